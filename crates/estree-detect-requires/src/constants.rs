@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use easter::expr::Expr;
+use easter::punc::{BinopTag, LogopTag, UnopTag};
+use estree_walk::ensure_stack;
+
+/// A value that a global identifier is known to have when the code runs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Null,
+    Undefined,
+}
+
+impl Constant {
+    /// Whether the value is truthy in JavaScript.
+    pub fn is_truthy(&self) -> bool {
+        match *self {
+            Constant::Bool(value) => value,
+            Constant::Number(value) => value != 0.0 && !value.is_nan(),
+            Constant::String(ref value) => !value.is_empty(),
+            Constant::Null | Constant::Undefined => false,
+        }
+    }
+}
+
+/// Known values of global identifiers, like `__DEV__`, or of member expressions on them, like
+/// `process.env.NODE_ENV`.
+pub type Constants = HashMap<String, Constant>;
+
+/// Evaluate an expression that only uses literals and known constants.
+/// Returns `None` if the value depends on anything else.
+pub fn evaluate(expr: &Expr, constants: &Constants) -> Option<Constant> {
+    ensure_stack(|| evaluate_expr(expr, constants))
+}
+
+fn evaluate_expr(expr: &Expr, constants: &Constants) -> Option<Constant> {
+    match *expr {
+        Expr::True(_) => Some(Constant::Bool(true)),
+        Expr::False(_) => Some(Constant::Bool(false)),
+        Expr::Null(_) => Some(Constant::Null),
+        Expr::Number(_, ref literal) => Some(Constant::Number(literal.value)),
+        Expr::String(_, ref literal) => Some(Constant::String(literal.value.clone())),
+        Expr::Id(_) | Expr::Dot(..) => {
+            let name = member_name(expr)?;
+            match constants.get(&name) {
+                Some(value) => Some(value.clone()),
+                None if name == "undefined" => Some(Constant::Undefined),
+                None => None,
+            }
+        },
+        Expr::Unop(_, ref op, ref argument) if op.tag == UnopTag::Not =>
+            Some(Constant::Bool(!evaluate(argument, constants)?.is_truthy())),
+        Expr::Binop(_, ref op, ref left, ref right) => {
+            let (left, right) = (evaluate(left, constants)?, evaluate(right, constants)?);
+            // Loose equality between different types needs conversions, so only compare values of the same type.
+            let same_type = match (&left, &right) {
+                (&Constant::Bool(_), &Constant::Bool(_)) | (&Constant::Number(_), &Constant::Number(_)) |
+                (&Constant::String(_), &Constant::String(_)) | (&Constant::Null, &Constant::Null) |
+                (&Constant::Undefined, &Constant::Undefined) => true,
+                _ => false,
+            };
+            match op.tag {
+                BinopTag::StrictEq => Some(Constant::Bool(left == right)),
+                BinopTag::StrictNEq => Some(Constant::Bool(left != right)),
+                BinopTag::Eq if same_type => Some(Constant::Bool(left == right)),
+                BinopTag::NEq if same_type => Some(Constant::Bool(left != right)),
+                _ => None,
+            }
+        },
+        Expr::Logop(_, ref op, ref left, ref right) => {
+            let left = evaluate(left, constants)?;
+            let short_circuits = match op.tag {
+                LogopTag::And => !left.is_truthy(),
+                LogopTag::Or => left.is_truthy(),
+            };
+            if short_circuits { Some(left) } else { evaluate(right, constants) }
+        },
+        _ => None,
+    }
+}
+
+/// The dotted name of an identifier or a chain of property accesses, like `process.env.NODE_ENV`.
+fn member_name(expr: &Expr) -> Option<String> {
+    match *expr {
+        Expr::Id(ref id) => Some(id.name.as_ref().to_string()),
+        Expr::Dot(_, ref object, ref key) => Some(format!("{}.{}", member_name(object)?, key.value)),
+        _ => None,
+    }
+}
@@ -1,13 +1,23 @@
 extern crate easter;
+extern crate estree_walk;
+extern crate joker;
+#[cfg(feature = "serialize")] extern crate serde;
+#[cfg(feature = "serialize")] #[macro_use] extern crate serde_derive;
 
-mod walk;
+mod constants;
 
+use std::collections::{BTreeSet, HashSet};
 use easter::expr::{Expr, ExprListItem};
 use easter::id::Id;
-use easter::stmt::Script;
-use walk::{Walker, Callbacks};
+use easter::punc::{BinopTag, LogopTag};
+use easter::stmt::{Script, Module, Stmt};
+use joker::track::Span;
+pub use constants::{Constant, Constants, evaluate};
+pub use estree_walk::{Walker, Callbacks, Control, NodeKind, NodePath, WalkerMut, CallbacksMut};
+pub use estree_walk::{Scope, ScopeChain, ScopeKind, Visitor, Visit, Location, LineIndex, Transformer, fold, ParallelWalker};
 
-/// Find require() calls in an ESTree Script node (from the easter crate).
+/// Find require() calls in an ESTree Script node (from the easter crate). Calls to a
+/// `require` that the script declares itself, like `function f(require) {}`, are ignored.
 ///
 /// # Examples
 ///
@@ -19,35 +29,515 @@ use walk::{Walker, Callbacks};
 /// assert_eq!(requires, vec!["y"]);
 /// ```
 pub fn detect(ast: &Script) -> Vec<String> {
-    let walker = Walker::new(ast, FindRequires::new());
-    let find = walker.walk();
+    detect_with_constants(ast, &Constants::new())
+}
+
+/// Find require() calls, except the ones in branches that never run because their condition
+/// only depends on known constants, like `if (__DEV__) require('./devtools')` when `__DEV__`
+/// is false.
+///
+/// # Examples
+///
+/// ```rust
+/// use esprit::script;
+/// use estree_detect_requires::{detect_with_constants, Constant, Constants};
+///
+/// let mut constants = Constants::new();
+/// constants.insert("process.env.NODE_ENV".into(), Constant::String("production".into()));
+/// let ast = script("if (process.env.NODE_ENV !== 'production') require('./dev'); else require('./prod')").unwrap();
+/// assert_eq!(detect_with_constants(&ast, &constants), vec!["./prod"]);
+/// ```
+pub fn detect_with_constants(ast: &Script, constants: &Constants) -> Vec<String> {
+    modules(&detect_calls_with_constants(ast, constants))
+}
+
+/// Find require() calls in an ES module. Imports are not included: walk the module with
+/// `Walker::new_module` and a `pre_import` callback to find those.
+pub fn detect_module(ast: &Module) -> Vec<String> {
+    let constants = Constants::new();
+    let walker = Walker::new_module(ast, FindRequires::new(&constants)).with_scopes();
+    modules(&walker.walk().calls)
+}
+
+/// Find require() calls that are only made inside `try` blocks, like
+/// `try { require('optional-peer') } catch (err) {}`. The code handles the module being
+/// missing, so the module is optional.
+///
+/// # Examples
+///
+/// ```rust
+/// use esprit::script;
+/// use estree_detect_requires::detect_optional;
+///
+/// let optional = detect_optional(&script("try { var a = require('a') } catch (err) {} require('b')").unwrap());
+/// assert_eq!(optional, vec!["a"]);
+/// ```
+pub fn detect_optional(ast: &Script) -> Vec<String> {
+    detect_optional_with_constants(ast, &Constants::new())
+}
+
+/// Like `detect_optional`, but skip branches that never run, like `detect_with_constants`.
+pub fn detect_optional_with_constants(ast: &Script, constants: &Constants) -> Vec<String> {
+    optional_modules(&detect_calls_with_constants(ast, constants))
+}
+
+/// A require() call with an argument that is only partially known,
+/// like `require('./locales/' + lang + '.json')`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct DynamicRequire {
+    /// The static string before the dynamic part, eg. `./locales/`.
+    pub prefix: String,
+    /// The static string after the dynamic part, eg. `.json`.
+    pub suffix: String,
+}
+
+/// Find require() calls with a string concatenation argument that starts with a string literal.
+///
+/// # Examples
+///
+/// ```rust
+/// use esprit::script;
+/// use estree_detect_requires::{detect_dynamic, DynamicRequire};
+///
+/// let requires = detect_dynamic(&script("require('./locales/' + lang + '.json')").unwrap());
+/// assert_eq!(requires, vec![DynamicRequire { prefix: "./locales/".into(), suffix: ".json".into() }]);
+/// ```
+pub fn detect_dynamic(ast: &Script) -> Vec<DynamicRequire> {
+    detect_dynamic_with_constants(ast, &Constants::new())
+}
+
+/// Like `detect_dynamic`, but skip branches that never run, like `detect_with_constants`.
+pub fn detect_dynamic_with_constants(ast: &Script, constants: &Constants) -> Vec<DynamicRequire> {
+    dynamic_modules(&detect_calls_with_constants(ast, constants))
+}
+
+/// What is known about the argument of a require() call.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(rename_all = "lowercase"))]
+pub enum Specifier {
+    /// A string literal, like `require('./a')`.
+    Static(String),
+    /// A string concatenation that starts with a string literal, like `require('./locales/' + lang)`.
+    Context(DynamicRequire),
+    /// Any other argument, like `require(name)`.
+    Dynamic,
+}
+
+/// The kind of call that refers to a module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(rename_all = "lowercase"))]
+pub enum RequireKind {
+    /// `require()`, which loads the module.
+    Require,
+    /// `require.resolve()`, which returns the path of the module instead of loading it.
+    Resolve,
+    /// `import()`, which loads the module asynchronously. esprit can not parse it yet, so it
+    /// is not reported.
+    Import,
+}
+
+/// Where a call is in the source file. Lines and columns start at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct RequireSpan {
+    pub start: Location,
+    /// The position just after the closing parenthesis.
+    pub end: Location,
+}
+
+impl RequireSpan {
+    fn from_span(span: &Span) -> RequireSpan {
+        RequireSpan {
+            start: Location { line: span.start.line + 1, column: span.start.column + 1 },
+            end: Location { line: span.end.line + 1, column: span.end.column + 1 },
+        }
+    }
+}
+
+/// A call that refers to a module, and where it is.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct RequireRecord {
+    pub kind: RequireKind,
+    pub specifier: Specifier,
+    /// Where the call is, if the parser tracked it.
+    pub span: Option<RequireSpan>,
+    /// The number of functions around the call. Calls at depth 0 run when the module is
+    /// loaded; deeper ones only run when a function is called, so they can be loaded lazily.
+    pub depth: u32,
+    /// Whether the call is inside a `try` block with a `catch` clause, in the same function,
+    /// so the code handles the module being missing.
+    pub guarded: bool,
+}
+
+/// Find all require() and `require.resolve()` calls, including dynamic ones, with their
+/// locations.
+///
+/// # Examples
+///
+/// ```rust
+/// use esprit::script;
+/// use estree_detect_requires::{detect_calls, RequireKind, Specifier};
+///
+/// let calls = detect_calls(&script("require('a'); function f() { require.resolve(b) }").unwrap());
+/// assert_eq!(calls[0].specifier, Specifier::Static("a".into()));
+/// assert_eq!((calls[1].kind, calls[1].depth), (RequireKind::Resolve, 1));
+/// ```
+pub fn detect_calls(ast: &Script) -> Vec<RequireRecord> {
+    detect_calls_with_constants(ast, &Constants::new())
+}
+
+/// Like `detect_calls`, but skip branches that never run, like `detect_with_constants`.
+/// The other `detect_*` functions all derive their results from these records, so callers
+/// that need several of them can walk the syntax tree once and use `modules`,
+/// `optional_modules`, `dynamic_modules` and `unanalyzable_calls`.
+pub fn detect_calls_with_constants(ast: &Script, constants: &Constants) -> Vec<RequireRecord> {
+    let walker = Walker::new(ast, FindRequires::new(constants)).with_scopes();
+    walker.walk().calls
+}
+
+/// The arguments of require() calls with a string literal argument, like `detect`.
+pub fn modules(calls: &[RequireRecord]) -> Vec<String> {
+    calls.iter()
+        .filter_map(|call| match (call.kind, &call.specifier) {
+            (RequireKind::Require, &Specifier::Static(ref value)) => Some(value.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The modules that are only required inside `try` blocks, like `detect_optional`.
+pub fn optional_modules(calls: &[RequireRecord]) -> Vec<String> {
+    let mut guarded = HashSet::new();
+    let mut unguarded = HashSet::new();
+    for call in calls {
+        if let (RequireKind::Require, &Specifier::Static(ref value)) = (call.kind, &call.specifier) {
+            if call.guarded { guarded.insert(value); } else { unguarded.insert(value); }
+        }
+    }
+    let mut optional = vec![];
+    for module in modules(calls) {
+        if guarded.contains(&module) && !unguarded.contains(&module) && !optional.contains(&module) {
+            optional.push(module);
+        }
+    }
+    optional
+}
+
+/// The partially known arguments of require() calls, like `detect_dynamic`.
+pub fn dynamic_modules(calls: &[RequireRecord]) -> Vec<DynamicRequire> {
+    calls.iter()
+        .filter_map(|call| match (call.kind, &call.specifier) {
+            (RequireKind::Require, &Specifier::Context(ref dynamic)) => Some(dynamic.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The require() calls with an argument that is not known at build time, like `detect_unanalyzable`.
+pub fn unanalyzable_calls(calls: &[RequireRecord]) -> Vec<RequireRecord> {
+    calls.iter()
+        .filter(|call| call.kind == RequireKind::Require && call.specifier == Specifier::Dynamic)
+        .cloned()
+        .collect()
+}
+
+/// Find require() calls with an argument that is not known at build time, like
+/// `require(name)` or `require(path.join(dir, name))`. The modules they load can not be
+/// bundled. Calls with a partially known argument are found by `detect_dynamic` instead.
+///
+/// # Examples
+///
+/// ```rust
+/// use esprit::script;
+/// use estree_detect_requires::detect_unanalyzable;
+///
+/// let calls = detect_unanalyzable(&script("require('a'); require(name)").unwrap());
+/// assert_eq!(calls.len(), 1);
+/// ```
+pub fn detect_unanalyzable(ast: &Script) -> Vec<RequireRecord> {
+    detect_unanalyzable_with_constants(ast, &Constants::new())
+}
+
+/// Like `detect_unanalyzable`, but skip branches that never run, like `detect_with_constants`.
+pub fn detect_unanalyzable_with_constants(ast: &Script, constants: &Constants) -> Vec<RequireRecord> {
+    unanalyzable_calls(&detect_calls_with_constants(ast, constants))
+}
+
+/// Find `require.resolve()` calls with a string literal argument. They are not included in
+/// `detect`, because they do not load the module.
+///
+/// # Examples
+///
+/// ```rust
+/// use esprit::script;
+/// use estree_detect_requires::detect_resolves;
+///
+/// let resolves = detect_resolves(&script("var worker = require.resolve('./worker'); require('a')").unwrap());
+/// assert_eq!(resolves, vec!["./worker"]);
+/// ```
+pub fn detect_resolves(ast: &Script) -> Vec<String> {
+    detect_calls(ast).into_iter()
+        .filter_map(|call| match (call.kind, call.specifier) {
+            (RequireKind::Resolve, Specifier::Static(value)) => Some(value),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Kinds of code that is evaluated at runtime.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum DynamicCodeKind {
+    /// A direct `eval(...)` call.
+    Eval,
+    /// `new Function(...)` or `Function(...)`.
+    FunctionConstructor,
+    /// A `with` statement.
+    With,
+}
+
+/// Code that can access or change local variables in ways that can not be seen in the syntax tree.
+/// Renaming or removing variables in a module that contains it is not safe.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct DynamicCode {
+    pub kind: DynamicCodeKind,
+    /// Where it occurs, if known. Lines and columns start at 1.
+    pub location: Option<Location>,
+}
+
+/// Find `eval(...)` calls, `Function` constructors and `with` statements.
+///
+/// # Examples
+///
+/// ```rust
+/// use esprit::script;
+/// use estree_detect_requires::{detect_dynamic_code, DynamicCodeKind};
+///
+/// let found = detect_dynamic_code(&script("eval(code)").unwrap());
+/// assert_eq!(found[0].kind, DynamicCodeKind::Eval);
+/// ```
+pub fn detect_dynamic_code(ast: &Script) -> Vec<DynamicCode> {
+    let walker = Walker::new(ast, FindDynamicCode { found: vec![] });
+    walker.walk().found
+}
 
-    find.get_modules()
+struct FindDynamicCode {
+    found: Vec<DynamicCode>,
+}
+
+impl FindDynamicCode {
+    fn push(&mut self, kind: DynamicCodeKind, span: &Option<Span>) -> () {
+        let location = span.map(|span| Location { line: span.start.line + 1, column: span.start.column + 1 });
+        self.found.push(DynamicCode { kind, location });
+    }
+}
+
+impl Callbacks for FindDynamicCode {
+    fn pre_stmt(&mut self, stmt: &Stmt, _path: &NodePath) -> Control {
+        if let Stmt::With(ref span, _, _) = *stmt {
+            self.push(DynamicCodeKind::With, span);
+        }
+        Control::Continue
+    }
+
+    fn pre_expr(&mut self, expr: &Expr, _path: &NodePath) -> Control {
+        let (span, callee) = match *expr {
+            Expr::Call(ref span, ref callee, _) | Expr::New(ref span, ref callee, _) => (span, callee),
+            _ => return Control::Continue,
+        };
+        if let Expr::Id(Id { ref name, .. }) = **callee {
+            match name.as_ref() {
+                "eval" => if let Expr::Call(..) = *expr { self.push(DynamicCodeKind::Eval, span) },
+                "Function" => self.push(DynamicCodeKind::FunctionConstructor, span),
+                _ => (),
+            }
+        }
+        Control::Continue
+    }
+}
+
+/// Find the free identifiers of a script: the variables it refers to, but does not declare,
+/// like globals and the `require` and `module` of a CommonJS wrapper. They are sorted by name.
+///
+/// # Examples
+///
+/// ```rust
+/// use esprit::script;
+/// use estree_detect_requires::free_identifiers;
+///
+/// let ast = script("var a = require('a'); function f(b) { return a + b + window.c }").unwrap();
+/// assert_eq!(free_identifiers(&ast), vec!["require", "window"]);
+/// ```
+pub fn free_identifiers(ast: &Script) -> Vec<String> {
+    let walker = Walker::new(ast, FindFreeIdentifiers { found: BTreeSet::new() }).with_scopes();
+    walker.walk().found.into_iter().collect()
+}
+
+struct FindFreeIdentifiers {
+    found: BTreeSet<String>,
+}
+
+impl Callbacks for FindFreeIdentifiers {
+    fn pre_id(&mut self, id: &Id, path: &NodePath) -> Control {
+        let name = id.name.as_ref();
+        // Every function has its own `arguments`.
+        let is_arguments = name == "arguments" && path.is_inside(NodeKind::Function);
+        if !is_arguments && !path.scopes().is_declared(name) {
+            self.found.insert(name.to_string());
+        }
+        Control::Continue
+    }
 }
 
 /// A tree walker that tracks require() calls.
-struct FindRequires {
-    modules: Vec<String>,
+struct FindRequires<'a> {
+    calls: Vec<RequireRecord>,
+    constants: &'a Constants,
+    /// Branches that never run. Nodes are identified by their address, which does not change
+    /// during the walk.
+    dead_stmts: HashSet<*const Stmt>,
+    dead_exprs: HashSet<*const Expr>,
 }
 
-impl FindRequires {
-    pub fn new() -> FindRequires {
-        FindRequires { modules: vec![] }
+impl<'a> FindRequires<'a> {
+    pub fn new(constants: &'a Constants) -> FindRequires<'a> {
+        FindRequires {
+            calls: vec![],
+            constants,
+            dead_stmts: HashSet::new(),
+            dead_exprs: HashSet::new(),
+        }
     }
-    pub fn get_modules(self) -> Vec<String> {
-        self.modules
+}
+
+impl<'a> FindRequires<'a> {
+    /// Whether a condition is known to be truthy or falsy.
+    fn condition(&self, test: &Expr) -> Option<bool> {
+        if self.constants.is_empty() {
+            return None;
+        }
+        evaluate(test, self.constants).map(|value| value.is_truthy())
     }
 }
 
-impl Callbacks for FindRequires {
-    fn pre_expr(&mut self, expr: &Expr) -> () {
-        if let Expr::Call(_, ref callee, ref args) = *expr {
-            if is_require_name(callee) {
-                if let Some(&ExprListItem::Expr(Expr::String(_, ref val))) = args.first() {
-                    self.modules.push(val.value.clone());
+impl<'a> Callbacks for FindRequires<'a> {
+    fn pre_stmt(&mut self, stmt: &Stmt, _path: &NodePath) -> Control {
+        if self.dead_stmts.contains(&(stmt as *const Stmt)) {
+            return Control::SkipChildren;
+        }
+        if let Stmt::If(_, ref test, ref cons, ref alt) = *stmt {
+            match self.condition(test) {
+                Some(true) => if let Some(ref alt) = *alt {
+                    self.dead_stmts.insert(&**alt as *const Stmt);
+                },
+                Some(false) => { self.dead_stmts.insert(&**cons as *const Stmt); },
+                None => (),
+            }
+        }
+        Control::Continue
+    }
+
+    fn pre_expr(&mut self, expr: &Expr, path: &NodePath) -> Control {
+        if self.dead_exprs.contains(&(expr as *const Expr)) {
+            return Control::SkipChildren;
+        }
+        match *expr {
+            Expr::Cond(_, ref test, ref cons, ref alt) => match self.condition(test) {
+                Some(true) => { self.dead_exprs.insert(&**alt as *const Expr); },
+                Some(false) => { self.dead_exprs.insert(&**cons as *const Expr); },
+                None => (),
+            },
+            Expr::Logop(_, ref op, ref left, ref right) => {
+                let skips_right = match (&op.tag, self.condition(left)) {
+                    (&LogopTag::And, Some(false)) | (&LogopTag::Or, Some(true)) => true,
+                    _ => false,
+                };
+                if skips_right {
+                    self.dead_exprs.insert(&**right as *const Expr);
                 }
+            },
+            _ => (),
+        }
+
+        if let Expr::Call(ref span, ref callee, ref args) = *expr {
+            // A `require` that is declared in the file, like a function parameter, is not Node's.
+            let kind = if is_require_name(callee) {
+                RequireKind::Require
+            } else if is_require_resolve(callee) {
+                RequireKind::Resolve
+            } else {
+                return Control::Continue;
+            };
+            if path.scopes().is_declared("require") {
+                return Control::Continue;
             }
+            let specifier = match args.first() {
+                Some(&ExprListItem::Expr(Expr::String(_, ref val))) => Specifier::Static(val.value.clone()),
+                Some(&ExprListItem::Expr(ref arg)) => match to_dynamic_require(arg) {
+                    Some(dynamic) => Specifier::Context(dynamic),
+                    None => Specifier::Dynamic,
+                },
+                _ => Specifier::Dynamic,
+            };
+            let depth = path.ancestors().iter().filter(|kind| **kind == NodeKind::Function).count() as u32;
+            self.calls.push(RequireRecord {
+                kind,
+                specifier,
+                span: span.as_ref().map(RequireSpan::from_span),
+                depth,
+                guarded: path.is_inside_in_function(NodeKind::Try),
+            });
         }
+        Control::Continue
+    }
+}
+
+/// A part of a string concatenation expression.
+enum ConcatPart {
+    Static(String),
+    Dynamic,
+}
+
+/// Flatten a chain of `+` operations into its parts.
+fn concat_parts(expr: &Expr, parts: &mut Vec<ConcatPart>) -> () {
+    match *expr {
+        Expr::Binop(_, ref op, ref left, ref right) if op.tag == BinopTag::Plus => {
+            concat_parts(left, parts);
+            concat_parts(right, parts);
+        },
+        Expr::String(_, ref val) => parts.push(ConcatPart::Static(val.value.clone())),
+        _ => parts.push(ConcatPart::Dynamic),
+    }
+}
+
+fn to_dynamic_require(arg: &Expr) -> Option<DynamicRequire> {
+    let mut parts = vec![];
+    concat_parts(arg, &mut parts);
+
+    let mut prefix = String::new();
+    let mut suffix = String::new();
+    let mut seen_dynamic = false;
+    for part in parts {
+        match part {
+            ConcatPart::Static(ref value) if !seen_dynamic => prefix.push_str(value),
+            // A static part between two dynamic parts can't be matched reliably, so start over.
+            ConcatPart::Static(ref value) => suffix.push_str(value),
+            ConcatPart::Dynamic => {
+                seen_dynamic = true;
+                suffix.clear();
+            },
+        }
+    }
+
+    if seen_dynamic && !prefix.is_empty() {
+        Some(DynamicRequire { prefix, suffix })
+    } else {
+        None
     }
 }
 
@@ -59,11 +549,28 @@ fn is_require_name(id: &Expr) -> bool {
     }
 }
 
+/// Whether a callee is `require.resolve`.
+fn is_require_resolve(callee: &Expr) -> bool {
+    match *callee {
+        Expr::Dot(_, ref object, ref key) => key.value == "resolve" && is_require_name(object),
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     extern crate esprit;
-    use self::esprit::script;
-    use ::detect;
+    use std::rc::Rc;
+    use self::esprit::{script, module};
+    use easter::decl::{Import, Export};
+    use easter::expr::{Expr, ExprListItem};
+    use easter::patt::Patt;
+    use easter::stmt::{Stmt, StmtListItem};
+    use joker::token::NumberLiteral;
+    use ::{is_require_name, Walker, Callbacks, Control, NodeKind, NodePath, WalkerMut, CallbacksMut};
+    use ::{Constant, Constants, detect_with_constants, ScopeKind, Location, LineIndex, Transformer, fold, ParallelWalker};
+    use ::{free_identifiers, detect_optional, detect, detect_module, detect_dynamic, DynamicRequire, detect_dynamic_code, DynamicCode, DynamicCodeKind, detect_calls, RequireRecord, RequireSpan, RequireKind, Specifier, detect_resolves};
+    use ::{detect_unanalyzable, detect_unanalyzable_with_constants};
 
     #[test]
     fn detects_var_require() {
@@ -92,8 +599,394 @@ mod tests {
         ").unwrap()), vec!["d", "e", "f", "g"]);
     }
 
+    #[test]
+    fn detects_require_in_patterns() {
+        assert_eq!(detect(&script("var { a, b: [c] } = require('x')").unwrap()), vec!["x"]);
+        assert_eq!(detect(&script("[a[require('y')], { b: c[require('z')] }] = arr").unwrap()), vec!["y", "z"]);
+    }
+
     #[test]
     fn detects_require_in_member_expression_object() {
         assert_eq!(detect(&script("require('util').inherits").unwrap()), vec!["util"]);
     }
+
+    #[test]
+    fn detects_dynamic_require_with_prefix_and_suffix() {
+        assert_eq!(detect_dynamic(&script("require('./locales/' + lang + '.json')").unwrap()), vec![
+            DynamicRequire { prefix: "./locales/".into(), suffix: ".json".into() },
+        ]);
+        assert_eq!(detect_dynamic(&script("require('./pages/' + name)").unwrap()), vec![
+            DynamicRequire { prefix: "./pages/".into(), suffix: "".into() },
+        ]);
+    }
+
+    #[test]
+    fn ignores_dynamic_require_without_static_prefix() {
+        assert_eq!(detect_dynamic(&script("require(name); require(dir + '/index.js')").unwrap()), vec![]);
+        assert_eq!(detect_dynamic(&script("require('./static')").unwrap()), vec![]);
+    }
+
+    #[test]
+    fn detects_dynamic_code() {
+        let kinds = |source| detect_dynamic_code(&script(source).unwrap()).into_iter()
+            .map(|found: DynamicCode| found.kind)
+            .collect::<Vec<DynamicCodeKind>>();
+        assert_eq!(kinds("eval('1'); new Function('a', 'return a'); Function('return this')()"), vec![
+            DynamicCodeKind::Eval,
+            DynamicCodeKind::FunctionConstructor,
+            DynamicCodeKind::FunctionConstructor,
+        ]);
+        assert_eq!(kinds("with (obj) { eval(x) }"), vec![DynamicCodeKind::With, DynamicCodeKind::Eval]);
+        assert_eq!(kinds("obj.eval(x); new Foo(function () { with (a) {} })"), vec![DynamicCodeKind::With]);
+
+        let found = detect_dynamic_code(&script("var a = 1;\n  eval(a)").unwrap());
+        assert_eq!(found[0].location, Some(Location { line: 2, column: 3 }));
+    }
+
+    #[test]
+    fn detects_require_in_new_and_with() {
+        assert_eq!(detect(&script("new Foo(require('a')); with (x) { require('b') }").unwrap()), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn detects_calls_with_locations() {
+        let span = |line, column, end_column| Some(RequireSpan {
+            start: Location { line, column },
+            end: Location { line, column: end_column },
+        });
+        assert_eq!(detect_calls(&script("require('a')\n  require('./b/' + c)\nrequire(d)").unwrap()), vec![
+            RequireRecord { kind: RequireKind::Require, specifier: Specifier::Static("a".into()), span: span(1, 1, 13), depth: 0, guarded: false },
+            RequireRecord {
+                kind: RequireKind::Require,
+                specifier: Specifier::Context(DynamicRequire { prefix: "./b/".into(), suffix: "".into() }),
+                span: span(2, 3, 22),
+                depth: 0,
+                guarded: false,
+            },
+            RequireRecord { kind: RequireKind::Require, specifier: Specifier::Dynamic, span: span(3, 1, 11), depth: 0, guarded: false },
+        ]);
+    }
+
+    #[test]
+    fn marks_guarded_calls() {
+        let calls = detect_calls(&script("try { require('a') } catch (e) {} try { require('b') } finally {} require('a')").unwrap());
+        assert_eq!(calls.iter().map(|call| call.guarded).collect::<Vec<bool>>(), vec![true, false, false]);
+    }
+
+    #[test]
+    fn detects_unanalyzable_requires() {
+        let ast = script("
+            require('a')
+            require('./locales/' + lang)
+            require(path.join(__dirname, 'b'))
+            require.resolve(name)
+            if (DEBUG) require(plugin)
+        ").unwrap();
+        let lines: Vec<u32> = detect_unanalyzable(&ast).into_iter()
+            .map(|call| call.span.unwrap().start.line)
+            .collect();
+        assert_eq!(lines, vec![4, 6]);
+
+        let mut constants = Constants::new();
+        constants.insert("DEBUG".into(), Constant::Bool(false));
+        assert_eq!(detect_unanalyzable_with_constants(&ast, &constants).len(), 1);
+    }
+
+    #[test]
+    fn counts_functions_around_calls() {
+        let depths: Vec<u32> = detect_calls(&script("
+            require('a')
+            function f() { require('b'); return function () { require('c') } }
+            if (x) { require('d') }
+        ").unwrap()).into_iter().map(|call| call.depth).collect();
+        assert_eq!(depths, vec![0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn changes_nodes_while_walking() {
+        struct PrefixRequires;
+        impl CallbacksMut for PrefixRequires {
+            fn pre_expr(&mut self, expr: &mut Expr) -> () {
+                if let Expr::Call(_, ref callee, ref mut args) = *expr {
+                    if !is_require_name(callee) {
+                        return;
+                    }
+                    if let Some(&mut ExprListItem::Expr(Expr::String(_, ref mut literal))) = args.first_mut() {
+                        literal.value = format!("./vendor/{}", literal.value);
+                    }
+                }
+            }
+        }
+
+        let mut ast = script("var a = require('a'); function f() { return [require('b')] }").unwrap();
+        WalkerMut::new(&mut ast, PrefixRequires).walk();
+        assert_eq!(detect(&ast), vec!["./vendor/a", "./vendor/b"]);
+    }
+
+    #[test]
+    fn passes_ancestors_to_callbacks() {
+        struct RequirePaths(Vec<Vec<NodeKind>>);
+        impl Callbacks for RequirePaths {
+            fn pre_expr(&mut self, expr: &Expr, path: &NodePath) -> Control {
+                if let Expr::Call(_, ref callee, _) = *expr {
+                    if is_require_name(callee) {
+                        self.0.push(path.ancestors().to_vec());
+                    }
+                }
+                Control::Continue
+            }
+        }
+
+        let ast = script("
+            require('a')
+            try { require('b') } catch (e) { a || require('c') }
+            function f() { if (x) require('d') }
+        ").unwrap();
+        assert_eq!(Walker::new(&ast, RequirePaths(vec![])).walk().0, vec![
+            vec![],
+            vec![NodeKind::Try],
+            vec![NodeKind::Catch, NodeKind::Conditional],
+            vec![NodeKind::Function, NodeKind::If],
+        ]);
+    }
+
+    #[test]
+    fn tracks_scopes() {
+        struct RequireScopes(Vec<(Vec<ScopeKind>, bool)>);
+        impl Callbacks for RequireScopes {
+            fn pre_expr(&mut self, expr: &Expr, path: &NodePath) -> Control {
+                if let Expr::Call(_, ref callee, _) = *expr {
+                    if is_require_name(callee) {
+                        let scopes = path.scopes();
+                        let kinds = scopes.scopes().iter().map(|scope| scope.kind()).collect();
+                        self.0.push((kinds, scopes.is_declared("require")));
+                    }
+                }
+                Control::Continue
+            }
+        }
+
+        let ast = script("
+            require('a')
+            function f(require) { require('b') }
+            function g() { require('c'); if (x) { var require = load } }
+            try {} catch (require) { require('d') }
+            { let require = load; require('e') }
+        ").unwrap();
+        assert_eq!(Walker::new(&ast, RequireScopes(vec![])).with_scopes().walk().0, vec![
+            (vec![ScopeKind::Script], false),
+            (vec![ScopeKind::Script, ScopeKind::Function], true),
+            (vec![ScopeKind::Script, ScopeKind::Function], true),
+            (vec![ScopeKind::Script, ScopeKind::Catch, ScopeKind::Block], true),
+            (vec![ScopeKind::Script, ScopeKind::Block], true),
+        ]);
+        assert_eq!(Walker::new(&ast, RequireScopes(vec![])).walk().0[1], (vec![], false));
+    }
+
+    #[test]
+    fn walks_modules() {
+        #[derive(Default)]
+        struct CountItems { imports: usize, exports: usize }
+        impl Callbacks for CountItems {
+            fn pre_import(&mut self, _import: &Import) -> Control {
+                self.imports += 1;
+                Control::Continue
+            }
+            fn pre_export(&mut self, _export: &Export) -> Control {
+                self.exports += 1;
+                Control::Continue
+            }
+        }
+
+        let ast = module("
+            import a from 'a'
+            import 'b'
+            export var c = require('c')
+            export function d() { return require('d') }
+            export default require('e')
+            require('f')
+        ").unwrap();
+        let counts = Walker::new_module(&ast, CountItems::default()).walk();
+        assert_eq!((counts.imports, counts.exports), (2, 3));
+        assert_eq!(detect_module(&ast), vec!["c", "d", "e", "f"]);
+    }
+
+    #[test]
+    fn collects_free_identifiers() {
+        let ast = script("
+            var a = 1
+            b = a
+            function f(c) { return c + d + arguments.length }
+            try { e() } catch (err) { console.error(err) }
+            ({ g: h, a, i: { j: k } })
+            l.m = n[o]
+        ").unwrap();
+        assert_eq!(free_identifiers(&ast), vec!["b", "console", "d", "e", "h", "k", "l", "n", "o"]);
+    }
+
+    #[test]
+    fn walks_setter_parameters() {
+        struct CountPatterns(usize);
+        impl Callbacks for CountPatterns {
+            fn pre_patt<T>(&mut self, _patt: &Patt<T>) -> Control {
+                self.0 += 1;
+                Control::Continue
+            }
+        }
+
+        let ast = script("({ get a() { return require('x') }, set a({ b, c: [d] }) { require('y') } })").unwrap();
+        assert_eq!(Walker::new(&ast, CountPatterns(0)).walk().0, 4);
+        assert_eq!(detect(&ast), vec!["x", "y"]);
+    }
+
+    #[test]
+    fn detects_require_resolve() {
+        let ast = script("
+            var a = require('a')
+            var b = require.resolve('./b')
+            require.resolve(name)
+            function f(require) { return require.resolve('c') }
+        ").unwrap();
+        assert_eq!(detect(&ast), vec!["a"]);
+        assert_eq!(detect_resolves(&ast), vec!["./b"]);
+        let calls = detect_calls(&ast);
+        assert_eq!(calls.len(), 3);
+        assert_eq!((calls[1].kind, &calls[1].specifier), (RequireKind::Resolve, &Specifier::Static("./b".into())));
+        assert_eq!(calls[1].span.unwrap().start, Location { line: 3, column: 21 });
+        assert_eq!(calls[2].specifier, Specifier::Dynamic);
+    }
+
+    #[test]
+    fn ignores_shadowed_require() {
+        let ast = script("
+            require('a')
+            function f(require) { require('b') }
+            function g() { require('c'); if (x) { var require = noop } }
+            try {} catch (require) { require('d') }
+            { let require = noop; require('e') }
+            (function () { require('f') })()
+        ").unwrap();
+        assert_eq!(detect(&ast), vec!["a", "f"]);
+        assert_eq!(detect(&script("var require = noop; require('a')").unwrap()), Vec::<String>::new());
+        assert!(detect_calls(&script("var require = noop; require(a)").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn detects_optional_requires() {
+        let ast = script("
+            try { require('a'); require('b') } catch (err) {}
+            require('b')
+            try { setup(function () { require('c') }) } finally {}
+            try {} catch (err) { require('d') }
+            function f() { try { require('e') } catch (err) {} }
+        ").unwrap();
+        assert_eq!(detect_optional(&ast), vec!["a", "e"]);
+    }
+
+    #[test]
+    fn does_not_treat_try_finally_as_optional() {
+        let ast = script("
+            try { require('a') } finally {}
+            try { try { require('b') } finally {} } catch (err) {}
+        ").unwrap();
+        assert_eq!(detect_optional(&ast), vec!["b"]);
+    }
+
+    #[test]
+    fn folds_nodes() {
+        struct RequireIds;
+        impl Transformer for RequireIds {
+            fn fold_stmt(&mut self, node: Stmt) -> Stmt {
+                match node {
+                    Stmt::Debugger(location, _) => Stmt::Empty(location),
+                    node => node,
+                }
+            }
+            fn fold_expr(&mut self, node: Expr) -> Expr {
+                match node {
+                    Expr::String(location, ref literal) if literal.value == "./x" =>
+                        Expr::Number(location, NumberLiteral { source: None, value: 3.0 }),
+                    node => node,
+                }
+            }
+        }
+
+        let ast = fold(script("require('./x'); require('./y'); debugger;").unwrap(), &mut RequireIds);
+        assert_eq!(detect(&ast), vec!["./y"]);
+        match ast.items[0] {
+            StmtListItem::Stmt(Stmt::Expr(_, Expr::Call(_, _, ref args), _)) => match args[0] {
+                ExprListItem::Expr(Expr::Number(_, ref literal)) => assert_eq!(literal.value, 3.0),
+                ref arg => panic!("expected a number, got {:?}", arg),
+            },
+            ref item => panic!("expected a call, got {:?}", item),
+        }
+        match ast.items[2] {
+            StmtListItem::Stmt(Stmt::Empty(_)) => (),
+            ref item => panic!("expected an empty statement, got {:?}", item),
+        }
+    }
+
+    #[test]
+    fn walks_in_parallel() {
+        #[derive(Default)]
+        struct CollectRequires(Vec<String>);
+        impl Callbacks for CollectRequires {
+            fn pre_expr(&mut self, expr: &Expr, path: &NodePath) -> Control {
+                if let Expr::Call(_, ref callee, ref args) = *expr {
+                    if let Some(&ExprListItem::Expr(Expr::String(_, ref literal))) = args.first() {
+                        if is_require_name(callee) && !path.scopes().is_declared("require") {
+                            self.0.push(literal.value.clone());
+                        }
+                    }
+                }
+                Control::Continue
+            }
+        }
+
+        let source = (0..100).map(|i| format!("require('./{}');", i)).collect::<Vec<String>>().join("\n");
+        let ast = script(&format!("{}\nfunction f(require) {{ require('x') }}", source)).unwrap();
+        let found = ParallelWalker::new(&ast, CollectRequires::default, |mut a, b| { a.0.extend(b.0); a })
+            .with_scopes()
+            .walk();
+        assert_eq!(found.0, (0..100).map(|i| format!("./{}", i)).collect::<Vec<String>>());
+    }
+
+    #[test]
+    fn passes_locations_to_callbacks() {
+        struct RequireLocations(Vec<Location>);
+        impl Callbacks for RequireLocations {
+            fn pre_expr(&mut self, expr: &Expr, path: &NodePath) -> Control {
+                if let Expr::Call(_, ref callee, _) = *expr {
+                    if is_require_name(callee) {
+                        self.0.push(path.location().unwrap());
+                    }
+                }
+                Control::Continue
+            }
+        }
+
+        let source = "var a = require('a')\n\nfunction f() {\n  return require('b')\n}";
+        let ast = script(source).unwrap();
+        let lines = Rc::new(LineIndex::new(source));
+        assert_eq!(Walker::new(&ast, RequireLocations(vec![])).with_line_index(lines).walk().0, vec![
+            Location { line: 1, column: 9 },
+            Location { line: 4, column: 10 },
+        ]);
+    }
+
+    #[test]
+    fn prunes_branches_with_known_constants() {
+        let mut constants = Constants::new();
+        constants.insert("__DEV__".into(), Constant::Bool(false));
+        constants.insert("process.env.NODE_ENV".into(), Constant::String("production".into()));
+        let ast = script("
+            if (__DEV__) require('a'); else require('b')
+            if (!__DEV__) { require('c') }
+            __DEV__ && require('d')
+            var e = process.env.NODE_ENV === 'production' ? require('e') : require('f')
+            if (unknown) require('g')
+        ").unwrap();
+        assert_eq!(detect_with_constants(&ast, &constants), vec!["b", "c", "e", "g"]);
+        assert_eq!(detect(&ast), vec!["a", "b", "c", "d", "e", "f", "g"]);
+    }
 }
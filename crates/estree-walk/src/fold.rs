@@ -0,0 +1,43 @@
+use std::mem;
+use easter::stmt::{Script, Stmt};
+use easter::expr::Expr;
+use walk_mut::{WalkerMut, CallbacksMut};
+
+/// A transform that can replace nodes by new ones, like `require('./x')` by `require(3)`.
+///
+/// Nodes are folded bottom-up: the children of a node have already been folded when the node
+/// is passed in. Each method receives the node by value and returns the node to put in its
+/// place, which may be the same node. All methods are optional.
+pub trait Transformer {
+    /// Called after the children of a Statement node were folded.
+    fn fold_stmt(&mut self, node: Stmt) -> Stmt { node }
+    /// Called after the children of an Expression node were folded.
+    fn fold_expr(&mut self, node: Expr) -> Expr { node }
+}
+
+/// Fold a Script with a `Transformer`, returning the rewritten Script.
+///
+/// # Examples
+/// ```rust,ignore
+/// let ast = fold(ast, &mut RequireIds::new(&ids));
+/// ```
+pub fn fold<T: Transformer>(mut ast: Script, transformer: &mut T) -> Script {
+    WalkerMut::new(&mut ast, Folder(transformer)).walk();
+    ast
+}
+
+/// Calls a `Transformer` from the post-order callbacks of a `WalkerMut`.
+struct Folder<'a, T: 'a + Transformer>(&'a mut T);
+
+impl<'a, T: Transformer> CallbacksMut for Folder<'a, T> {
+    fn post_stmt(&mut self, node: &mut Stmt) -> () {
+        // Move the node out, leaving a cheap placeholder until the replacement is put back.
+        let stmt = mem::replace(node, Stmt::Empty(None));
+        *node = self.0.fold_stmt(stmt);
+    }
+
+    fn post_expr(&mut self, node: &mut Expr) -> () {
+        let expr = mem::replace(node, Expr::Null(None));
+        *node = self.0.fold_expr(expr);
+    }
+}
@@ -0,0 +1,294 @@
+extern crate easter;
+extern crate joker;
+extern crate rayon;
+#[cfg(feature = "serialize")] extern crate serde;
+#[cfg(feature = "serialize")] #[macro_use] extern crate serde_derive;
+extern crate stacker;
+
+mod fold;
+mod location;
+mod parallel;
+mod scope;
+mod stack;
+mod visitor;
+mod walk;
+mod walk_mut;
+
+pub use fold::{Transformer, fold};
+pub use location::{Location, LineIndex};
+pub use parallel::ParallelWalker;
+pub use scope::{Scope, ScopeChain, ScopeKind};
+pub use stack::ensure_stack;
+pub use visitor::{Visitor, Visit};
+pub use walk::{Walker, Callbacks, Control, NodeKind, NodePath};
+pub use walk_mut::{WalkerMut, CallbacksMut};
+
+#[cfg(test)]
+mod tests {
+    extern crate esprit;
+    use std::collections::BTreeSet;
+    use self::esprit::script;
+    use easter::expr::Expr;
+    use easter::patt::Patt;
+    use easter::stmt::Stmt;
+    use rayon::ThreadPoolBuilder;
+    use ::{Walker, Callbacks, Control, NodePath, WalkerMut, CallbacksMut, Visitor, Visit, Location, LineIndex, ParallelWalker};
+
+    /// A script that contains every kind of statement and expression, some of them only in
+    /// places that are easy to forget, like loop heads and destructuring targets.
+    const EVERY_NODE: &str = "
+        ;
+        { label: for (var i = this; i < 10; i++) { if (i) continue label; else break label; } }
+        var a = [1, 'b', /c/g], o = { d: a, get f() { return -a }, set f(v) { new.target } };
+        let g = function () { return (a, o) };
+        const h = typeof a === 'x' && !o || void 0;
+        with (o) { g(++a, a++, --a, a--) }
+        switch (a) { case 1: throw new Error(a.b); default: debugger; }
+        try { a = o ? o[a] : a } catch (e) {} finally {}
+        while (a) a += 1;
+        do ; while (a);
+        for (x[true] in o) {}
+        for (x.y[false] of o) {}
+        [a[null]] = o;
+    ";
+
+    const STMT_KINDS: &[&str] = &[
+        "Empty", "Block", "Var", "Expr", "If", "Label", "Break", "Cont", "With", "Switch",
+        "Return", "Throw", "Try", "While", "DoWhile", "For", "ForIn", "ForOf", "Debugger",
+    ];
+
+    const EXPR_KINDS: &[&str] = &[
+        "This", "Id", "Arr", "Obj", "Fun", "Seq", "Unop", "Binop", "Logop", "PreInc", "PostInc",
+        "PreDec", "PostDec", "Assign", "BinAssign", "Cond", "Call", "New", "Dot", "Brack",
+        "NewTarget", "True", "False", "Null", "Number", "RegExp", "String",
+    ];
+
+    // These matches have no catch-all arm, so a new easter variant fails to compile here until
+    // it is added to the lists above and to `EVERY_NODE`.
+    fn stmt_kind(node: &Stmt) -> &'static str {
+        match *node {
+            Stmt::Empty(..) => "Empty",
+            Stmt::Block(..) => "Block",
+            Stmt::Var(..) => "Var",
+            Stmt::Expr(..) => "Expr",
+            Stmt::If(..) => "If",
+            Stmt::Label(..) => "Label",
+            Stmt::Break(..) => "Break",
+            Stmt::Cont(..) => "Cont",
+            Stmt::With(..) => "With",
+            Stmt::Switch(..) => "Switch",
+            Stmt::Return(..) => "Return",
+            Stmt::Throw(..) => "Throw",
+            Stmt::Try(..) => "Try",
+            Stmt::While(..) => "While",
+            Stmt::DoWhile(..) => "DoWhile",
+            Stmt::For(..) => "For",
+            Stmt::ForIn(..) => "ForIn",
+            Stmt::ForOf(..) => "ForOf",
+            Stmt::Debugger(..) => "Debugger",
+        }
+    }
+
+    fn expr_kind(node: &Expr) -> &'static str {
+        match *node {
+            Expr::This(..) => "This",
+            Expr::Id(..) => "Id",
+            Expr::Arr(..) => "Arr",
+            Expr::Obj(..) => "Obj",
+            Expr::Fun(..) => "Fun",
+            Expr::Seq(..) => "Seq",
+            Expr::Unop(..) => "Unop",
+            Expr::Binop(..) => "Binop",
+            Expr::Logop(..) => "Logop",
+            Expr::PreInc(..) => "PreInc",
+            Expr::PostInc(..) => "PostInc",
+            Expr::PreDec(..) => "PreDec",
+            Expr::PostDec(..) => "PostDec",
+            Expr::Assign(..) => "Assign",
+            Expr::BinAssign(..) => "BinAssign",
+            Expr::Cond(..) => "Cond",
+            Expr::Call(..) => "Call",
+            Expr::New(..) => "New",
+            Expr::Dot(..) => "Dot",
+            Expr::Brack(..) => "Brack",
+            Expr::NewTarget(..) => "NewTarget",
+            Expr::True(..) => "True",
+            Expr::False(..) => "False",
+            Expr::Null(..) => "Null",
+            Expr::Number(..) => "Number",
+            Expr::RegExp(..) => "RegExp",
+            Expr::String(..) => "String",
+        }
+    }
+
+    #[derive(Default)]
+    struct CollectKinds {
+        stmts: BTreeSet<&'static str>,
+        exprs: BTreeSet<&'static str>,
+    }
+
+    impl Callbacks for CollectKinds {
+        fn pre_stmt(&mut self, node: &Stmt, _path: &NodePath) -> Control {
+            self.stmts.insert(stmt_kind(node));
+            Control::Continue
+        }
+        fn pre_expr(&mut self, node: &Expr, _path: &NodePath) -> Control {
+            self.exprs.insert(expr_kind(node));
+            Control::Continue
+        }
+    }
+
+    impl CallbacksMut for CollectKinds {
+        fn pre_stmt(&mut self, node: &mut Stmt) -> () {
+            self.stmts.insert(stmt_kind(node));
+        }
+        fn pre_expr(&mut self, node: &mut Expr) -> () {
+            self.exprs.insert(expr_kind(node));
+        }
+    }
+
+    struct CountPatterns(usize);
+
+    impl Callbacks for CountPatterns {
+        fn pre_patt<T>(&mut self, _patt: &Patt<T>) -> Control {
+            self.0 += 1;
+            Control::Continue
+        }
+    }
+
+    fn count_patterns(source: &str) -> usize {
+        let ast = script(source).unwrap();
+        Walker::new(&ast, CountPatterns(0)).walk().0
+    }
+
+    fn assert_every_kind(found: &CollectKinds) {
+        let missing_stmts: Vec<_> = STMT_KINDS.iter().filter(|kind| !found.stmts.contains(*kind)).collect();
+        let missing_exprs: Vec<_> = EXPR_KINDS.iter().filter(|kind| !found.exprs.contains(*kind)).collect();
+        assert!(missing_stmts.is_empty(), "statements that were not walked: {:?}", missing_stmts);
+        assert!(missing_exprs.is_empty(), "expressions that were not walked: {:?}", missing_exprs);
+    }
+
+    #[test]
+    fn reaches_every_node_kind() {
+        let ast = script(EVERY_NODE).unwrap();
+        assert_every_kind(&Walker::new(&ast, CollectKinds::default()).walk());
+        assert_every_kind(&Walker::new(&ast, CollectKinds::default()).with_scopes().walk());
+    }
+
+    #[test]
+    fn reaches_every_node_kind_mutably() {
+        let mut ast = script(EVERY_NODE).unwrap();
+        assert_every_kind(&WalkerMut::new(&mut ast, CollectKinds::default()).walk());
+    }
+
+    #[test]
+    fn walks_loop_heads() {
+        assert_eq!(count_patterns("for (var [a] = b;;) {} for (var { c } in d) {} for (let [e, f] of g) {}"), 6);
+    }
+
+    #[test]
+    fn skips_children_and_stops_walking() {
+        struct CallNames(Vec<String>);
+        impl Callbacks for CallNames {
+            fn pre_expr(&mut self, expr: &Expr, _path: &NodePath) -> Control {
+                if let Expr::Call(_, ref callee, _) = *expr {
+                    if let Expr::Id(ref id) = **callee {
+                        let name: &str = id.name.as_ref();
+                        self.0.push(name.to_string());
+                        match name {
+                            "skip" => return Control::SkipChildren,
+                            "stop" => return Control::Stop,
+                            _ => (),
+                        }
+                    }
+                }
+                Control::Continue
+            }
+        }
+
+        let ast = script("a(skip(b())); c(); stop(d()); e()").unwrap();
+        assert_eq!(Walker::new(&ast, CallNames(vec![])).walk().0, vec!["a", "skip", "c", "stop"]);
+    }
+
+    #[test]
+    fn dispatches_to_visitor_methods() {
+        #[derive(Default)]
+        struct CountNodes { calls: usize, ifs: usize, other_exprs: usize }
+        impl Visitor for CountNodes {
+            fn visit_call(&mut self, _node: &Expr, _path: &NodePath) -> Control {
+                self.calls += 1;
+                Control::Continue
+            }
+            fn visit_if(&mut self, _node: &Stmt, _path: &NodePath) -> Control {
+                self.ifs += 1;
+                Control::Continue
+            }
+            fn visit_expr(&mut self, _node: &Expr, _path: &NodePath) -> Control {
+                self.other_exprs += 1;
+                Control::Continue
+            }
+        }
+
+        let ast = script("if (a) { b(c) } else if (d) e()").unwrap();
+        let counts = Walker::new(&ast, Visit(CountNodes::default())).walk().0;
+        assert_eq!((counts.calls, counts.ifs, counts.other_exprs), (2, 2, 5));
+    }
+
+    #[test]
+    fn walks_function_parameters() {
+        assert_eq!(count_patterns("function f(a, [b, { c }]) {} (function ({ d }) {})"), 7);
+    }
+
+    #[test]
+    fn walks_declarator_patterns_alike() {
+        assert_eq!(count_patterns("var a = b, c"), 0);
+        assert_eq!(count_patterns("let a = b, c"), 0);
+        assert_eq!(count_patterns("const a = b"), 0);
+        assert_eq!(count_patterns("var [a, { b }] = c"), 3);
+        assert_eq!(count_patterns("let [a, { b }] = c"), 3);
+        assert_eq!(count_patterns("const [a, { b }] = c"), 3);
+    }
+
+    #[test]
+    fn walks_catch_parameters() {
+        assert_eq!(count_patterns("try {} catch (err) {} try {} catch ({ code, errors: [first] }) {}"), 5);
+    }
+
+    #[test]
+    fn knows_hoisted_declarations_from_other_batches() {
+        #[derive(Default)]
+        struct CountRequireCalls(usize);
+        impl Callbacks for CountRequireCalls {
+            fn pre_expr(&mut self, expr: &Expr, path: &NodePath) -> Control {
+                if let Expr::Call(_, ref callee, _) = *expr {
+                    if let Expr::Id(ref id) = **callee {
+                        if id.name.as_ref() == "require" && !path.scopes().is_declared("require") {
+                            self.0 += 1;
+                        }
+                    }
+                }
+                Control::Continue
+            }
+        }
+
+        // With four threads, the call and the declaration are in different batches.
+        let ast = script("require('a'); b(); c(); function require() {}").unwrap();
+        let pool = ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        let found = pool.install(|| {
+            ParallelWalker::new(&ast, CountRequireCalls::default, |a, b| CountRequireCalls(a.0 + b.0))
+                .with_scopes()
+                .walk()
+        });
+        assert_eq!(found.0, 0);
+    }
+
+    #[test]
+    fn finds_line_and_column() {
+        let lines = LineIndex::new("a\r\nbc\rdé\u{2028}f");
+        assert_eq!(lines.line_count(), 4);
+        assert_eq!(lines.location(0), Location { line: 1, column: 1 });
+        assert_eq!(lines.location(4), Location { line: 2, column: 2 });
+        assert_eq!(lines.location(7), Location { line: 3, column: 2 });
+        assert_eq!(lines.location(9).to_string(), "4:1");
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+use joker::track::Span;
+
+/// A position in a source file, for diagnostics. Lines and columns start at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Location {
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Finds the line and column of offsets in a source file.
+///
+/// Syntax tree offsets count characters, not bytes, so the index does too. Build it once per
+/// file and share it, eg. with `Walker::with_line_index`.
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    /// The offset where each line starts.
+    line_starts: Vec<u32>,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> LineIndex {
+        let mut line_starts = vec![0];
+        let mut chars = source.chars().enumerate().peekable();
+        while let Some((offset, c)) = chars.next() {
+            let ends_line = match c {
+                '\r' => chars.peek().map_or(true, |&(_, next)| next != '\n'),
+                '\n' | '\u{2028}' | '\u{2029}' => true,
+                _ => false,
+            };
+            if ends_line {
+                line_starts.push(offset as u32 + 1);
+            }
+        }
+        LineIndex { line_starts }
+    }
+
+    /// The number of lines in the file.
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The location of a character offset.
+    pub fn location(&self, offset: u32) -> Location {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+        Location {
+            line: line as u32 + 1,
+            column: offset - self.line_starts[line] + 1,
+        }
+    }
+
+    /// The location where a span starts.
+    pub fn span_location(&self, span: &Span) -> Location {
+        self.location(span.start.offset)
+    }
+}
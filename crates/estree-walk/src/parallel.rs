@@ -0,0 +1,61 @@
+use rayon::prelude::*;
+use rayon::current_num_threads;
+use easter::stmt::Script;
+use scope::Scope;
+use walk::{Walker, Callbacks};
+
+/// Walks the top-level statements of a Script on the rayon thread pool, for very large files.
+///
+/// The statements are split into one batch per thread. Every batch is walked with its own
+/// callbacks, created by `new_callbacks`, and the callbacks of neighbouring batches are combined
+/// with `merge`, in source order. `pre_script` and `post_script` are not called, and
+/// `Control::Stop` only stops the batch that it was returned in. With scopes, the declarations
+/// of the whole script are collected once, and every batch starts with all of them, so a
+/// function that is declared in one batch is known in the others.
+///
+/// # Examples
+/// ```rust,ignore
+/// let found = ParallelWalker::new(&ast, FindRequires::default, FindRequires::merge).walk();
+/// ```
+pub struct ParallelWalker<'a, F, M> {
+    ast: &'a Script,
+    new_callbacks: F,
+    merge: M,
+    track_scopes: bool,
+}
+
+impl<'a, C, F, M> ParallelWalker<'a, F, M>
+    where C: Callbacks + Send,
+          F: Fn() -> C + Sync,
+          M: Fn(C, C) -> C + Sync
+{
+    pub fn new(ast: &'a Script, new_callbacks: F, merge: M) -> ParallelWalker<'a, F, M> {
+        ParallelWalker { ast, new_callbacks, merge, track_scopes: false }
+    }
+
+    /// Keep track of scopes, like `Walker::with_scopes`.
+    pub fn with_scopes(mut self) -> Self {
+        self.track_scopes = true;
+        self
+    }
+
+    /// Walk all batches and return the merged callbacks.
+    pub fn walk(self) -> C {
+        let ast = self.ast;
+        let batch_size = (ast.items.len() + current_num_threads() - 1) / current_num_threads();
+        let new_callbacks = &self.new_callbacks;
+        let merge = &self.merge;
+        let script_scope = if self.track_scopes { Some(Scope::script(&ast.items)) } else { None };
+        let script_scope = &script_scope;
+        ast.items.par_chunks(batch_size.max(1))
+            .map(|items| {
+                let walker = Walker::new_partial(ast, items, new_callbacks());
+                match *script_scope {
+                    Some(ref scope) => walker.with_scopes().with_script_scope(scope.clone()).walk(),
+                    None => walker.walk(),
+                }
+            })
+            .reduce_with(|a, b| merge(a, b))
+            .unwrap_or_else(new_callbacks)
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashSet;
+use easter::stmt::{Stmt, StmtListItem, ModItem, ForHead, ForInHead, ForOfHead};
+use easter::decl::{Decl, Dtor, Export};
+use easter::patt::{Patt, CompoundPatt};
+use easter::id::Id;
+
+/// A kind of node that has its own bindings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeKind {
+    /// The top level of the script.
+    Script,
+    /// The top level of an ES module.
+    Module,
+    /// A function body, with the function's parameters.
+    Function,
+    /// A `{ block }`, or the cases of a `switch` statement.
+    Block,
+    /// The parameter of a `catch` clause.
+    Catch,
+}
+
+/// A scope and the names that are declared in it.
+///
+/// `var` and function declarations are hoisted: they belong to the closest function, script or
+/// module scope, and they are known from the start of it, not just after the declaration.
+#[derive(Debug, Clone)]
+pub struct Scope {
+    kind: ScopeKind,
+    bindings: HashSet<String>,
+}
+
+impl Scope {
+    /// Create a scope without bindings.
+    pub fn new(kind: ScopeKind) -> Scope {
+        Scope { kind, bindings: HashSet::new() }
+    }
+
+    /// The scope of a script with these top-level statements.
+    pub fn script<'a, I>(items: I) -> Scope
+        where I: IntoIterator<Item = &'a StmtListItem> + Clone
+    {
+        let mut scope = Scope::new(ScopeKind::Script);
+        declare_vars(items.clone(), &mut scope.bindings);
+        declare_lexical(items, &mut scope.bindings);
+        scope
+    }
+
+    /// The scope of an ES module with these top-level items. Exported declarations are bound
+    /// in it, names bound by imports are not tracked yet.
+    pub fn module(items: &[ModItem]) -> Scope {
+        let mut scope = Scope::new(ScopeKind::Module);
+        let statements = items.iter().filter_map(|item| match *item {
+            ModItem::StmtListItem(ref item) => Some(item),
+            _ => None,
+        });
+        declare_vars(statements.clone(), &mut scope.bindings);
+        declare_lexical(statements, &mut scope.bindings);
+        for item in items {
+            match *item {
+                ModItem::Export(Export::Var(_, ref dtors)) => declare_dtors(dtors, &mut scope.bindings),
+                ModItem::Export(Export::Decl(ref decl)) => declare_decl(decl, &mut scope.bindings),
+                _ => (),
+            }
+        }
+        scope
+    }
+
+    /// The scope of a function with these parameters and body statements.
+    pub fn function<'a, I>(params: &[Patt<Id>], body: I) -> Scope
+        where I: IntoIterator<Item = &'a StmtListItem> + Clone
+    {
+        let mut scope = Scope::new(ScopeKind::Function);
+        for param in params {
+            declare_patt(param, &mut scope.bindings);
+        }
+        declare_vars(body.clone(), &mut scope.bindings);
+        declare_lexical(body, &mut scope.bindings);
+        scope
+    }
+
+    /// The scope of a block with these statements. Only `let`, `const` and function declarations
+    /// directly in the block are bound in it.
+    pub fn block<'a, I>(items: I) -> Scope
+        where I: IntoIterator<Item = &'a StmtListItem>
+    {
+        let mut scope = Scope::new(ScopeKind::Block);
+        declare_lexical(items, &mut scope.bindings);
+        scope
+    }
+
+    /// The scope of a `catch` clause with this parameter.
+    pub fn catch(param: &Patt<Id>) -> Scope {
+        let mut scope = Scope::new(ScopeKind::Catch);
+        declare_patt(param, &mut scope.bindings);
+        scope
+    }
+
+    pub fn kind(&self) -> ScopeKind {
+        self.kind
+    }
+
+    /// The names declared in this scope.
+    pub fn bindings(&self) -> &HashSet<String> {
+        &self.bindings
+    }
+
+    /// Whether a name is declared in this scope.
+    pub fn declares(&self, name: &str) -> bool {
+        self.bindings.contains(name)
+    }
+
+    /// Declare a name in this scope.
+    pub fn declare(&mut self, name: &str) {
+        self.bindings.insert(name.to_string());
+    }
+}
+
+/// The scopes around a node, outermost first.
+///
+/// The walker only tracks scopes if it was created with `Walker::with_scopes`. Otherwise the
+/// chain is always empty.
+#[derive(Debug, Clone, Default)]
+pub struct ScopeChain {
+    scopes: Vec<Scope>,
+}
+
+impl ScopeChain {
+    pub fn new() -> ScopeChain {
+        ScopeChain::default()
+    }
+
+    /// The scopes, outermost first.
+    pub fn scopes(&self) -> &[Scope] {
+        &self.scopes
+    }
+
+    /// The innermost scope, if there is one.
+    pub fn current(&self) -> Option<&Scope> {
+        self.scopes.last()
+    }
+
+    /// The innermost scope that declares a name. Returns `None` if the name is a global.
+    pub fn lookup(&self, name: &str) -> Option<&Scope> {
+        self.scopes.iter().rev().find(|scope| scope.declares(name))
+    }
+
+    /// Whether a name is declared in any scope, eg. whether `require` is shadowed by a local
+    /// variable.
+    pub fn is_declared(&self, name: &str) -> bool {
+        self.lookup(name).is_some()
+    }
+
+    /// Enter a scope.
+    pub fn push(&mut self, scope: Scope) {
+        self.scopes.push(scope);
+    }
+
+    /// Leave the innermost scope.
+    pub fn pop(&mut self) -> Option<Scope> {
+        self.scopes.pop()
+    }
+}
+
+/// Add the names bound by a pattern, like `a` and `b` in `[a, { x: b }]`.
+fn declare_patt(patt: &Patt<Id>, names: &mut HashSet<String>) {
+    match *patt {
+        Patt::Simple(ref id) => { names.insert(id.name.as_ref().to_string()); },
+        Patt::Compound(ref compound) => declare_compound_patt(compound, names),
+    }
+}
+
+fn declare_compound_patt(patt: &CompoundPatt<Id>, names: &mut HashSet<String>) {
+    match *patt {
+        CompoundPatt::Arr(_, ref elements) => {
+            for element in elements {
+                if let Some(ref element) = *element {
+                    declare_patt(element, names);
+                }
+            }
+        },
+        CompoundPatt::Obj(_, ref props) => {
+            for prop in props {
+                declare_patt(&prop.patt, names);
+            }
+        },
+    }
+}
+
+fn declare_dtors(dtors: &[Dtor], names: &mut HashSet<String>) {
+    for dtor in dtors {
+        match *dtor {
+            Dtor::Simple(_, ref id, _) => { names.insert(id.name.as_ref().to_string()); },
+            Dtor::Compound(_, ref patt, _) => declare_compound_patt(patt, names),
+        }
+    }
+}
+
+/// Add the `let`, `const` and function declarations in a list of statements.
+fn declare_lexical<'a, I>(items: I, names: &mut HashSet<String>)
+    where I: IntoIterator<Item = &'a StmtListItem>
+{
+    for item in items {
+        if let StmtListItem::Decl(ref decl) = *item {
+            declare_decl(decl, names);
+        }
+    }
+}
+
+fn declare_decl(decl: &Decl, names: &mut HashSet<String>) {
+    match *decl {
+        Decl::Fun(ref fun) => { names.insert(fun.id.name.as_ref().to_string()); },
+        Decl::Let(_, ref dtors, _) => declare_dtors(dtors, names),
+        Decl::Const(_, ref dtors, _) => {
+            for dtor in dtors {
+                declare_patt(&dtor.patt, names);
+            }
+        },
+    }
+}
+
+/// Add the `var` declarations in a list of statements, including the ones in nested blocks,
+/// but not the ones in nested functions.
+fn declare_vars<'a, I>(items: I, names: &mut HashSet<String>)
+    where I: IntoIterator<Item = &'a StmtListItem>
+{
+    for item in items {
+        if let StmtListItem::Stmt(ref stmt) = *item {
+            declare_stmt_vars(stmt, names);
+        }
+    }
+}
+
+fn declare_stmt_vars(stmt: &Stmt, names: &mut HashSet<String>) {
+    match *stmt {
+        Stmt::Var(_, ref dtors, _) => declare_dtors(dtors, names),
+        Stmt::Block(ref block) => declare_vars(&block.items, names),
+        Stmt::If(_, _, ref cons, ref alt) => {
+            declare_stmt_vars(cons.as_ref(), names);
+            if let Some(ref alt) = *alt { declare_stmt_vars(alt.as_ref(), names); }
+        },
+        Stmt::Label(_, _, ref body) | Stmt::While(_, _, ref body) |
+        Stmt::DoWhile(_, ref body, _, _) | Stmt::With(_, _, ref body) =>
+            declare_stmt_vars(body.as_ref(), names),
+        Stmt::Switch(_, _, ref cases) => {
+            for case in cases {
+                declare_vars(&case.body, names);
+            }
+        },
+        Stmt::Try(_, ref block, ref caught, ref finally) => {
+            declare_vars(&block.items, names);
+            if let Some(ref caught_block) = *caught { declare_vars(&caught_block.body.items, names); }
+            if let Some(ref finally_block) = *finally { declare_vars(&finally_block.items, names); }
+        },
+        Stmt::For(_, ref head, _, _, ref body) => {
+            if let Some(ref head) = *head {
+                if let ForHead::Var(_, ref dtors) = **head {
+                    declare_dtors(dtors, names);
+                }
+            }
+            declare_stmt_vars(body.as_ref(), names);
+        },
+        Stmt::ForIn(_, ref head, _, ref body) => {
+            match **head {
+                ForInHead::VarInit(_, ref id, _) => { names.insert(id.name.as_ref().to_string()); },
+                ForInHead::Var(_, ref patt) => declare_patt(patt, names),
+                _ => (),
+            }
+            declare_stmt_vars(body.as_ref(), names);
+        },
+        Stmt::ForOf(_, ref head, _, ref body) => {
+            if let ForOfHead::Var(_, ref patt) = **head {
+                declare_patt(patt, names);
+            }
+            declare_stmt_vars(body.as_ref(), names);
+        },
+        _ => (),
+    }
+}
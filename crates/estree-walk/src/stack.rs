@@ -0,0 +1,13 @@
+use stacker;
+
+/// Stack space that must be left before walking into a nested node.
+const RED_ZONE: usize = 64 * 1024;
+/// The size of the stack segments that are allocated when the red zone is reached.
+const SEGMENT_SIZE: usize = 1024 * 1024;
+
+/// Run a function that recurses into nested nodes. If the stack is nearly full, it runs on a
+/// newly allocated stack segment instead, so that deeply nested code, like thousands of nested
+/// `?:` expressions in minified code, can not overflow the stack.
+pub fn ensure_stack<R, F: FnOnce() -> R>(f: F) -> R {
+    stacker::maybe_grow(RED_ZONE, SEGMENT_SIZE, f)
+}
@@ -0,0 +1,137 @@
+use easter::stmt::Stmt;
+use easter::decl::Decl;
+use easter::expr::Expr;
+use walk::{Callbacks, Control, NodePath};
+
+/// Wraps a `Visitor` so that it can be passed to a `Walker`.
+///
+/// # Examples
+/// ```rust,ignore
+/// let visitor = Walker::new(&ast, Visit(FindCalls::default())).walk().0;
+/// ```
+pub struct Visit<V: Visitor>(pub V);
+
+/// Generate the `Visitor` trait, with one method per kind of node, and the `Callbacks`
+/// implementation that dispatches to them. Every method defaults to the coarse method for its
+/// node type, like `visit_call` to `visit_expr`.
+macro_rules! visitor {
+    (
+        $(
+            $node_type:ident => $coarse:ident, $pre:ident {
+                $( $(#[$doc:meta])* fn $method:ident => $($pattern:pat)|+, )*
+            }
+        )*
+    ) => {
+        /// Holds functions to be called on specific kinds of nodes, like `visit_call` for calls
+        /// and `visit_if` for `if` statements. They are called in preorder, like the `pre_*`
+        /// callbacks, and are all optional.
+        pub trait Visitor {
+            /// Called before a declaration is entered: a function declaration, `let` or `const`.
+            fn visit_decl(&mut self, _node: &Decl) -> Control { Control::Continue }
+            $(
+                /// Called before a node that does not have its own method is entered.
+                fn $coarse(&mut self, _node: &$node_type, _path: &NodePath) -> Control { Control::Continue }
+                $(
+                    $(#[$doc])*
+                    fn $method(&mut self, node: &$node_type, path: &NodePath) -> Control {
+                        self.$coarse(node, path)
+                    }
+                )*
+            )*
+        }
+
+        impl<V: Visitor> Callbacks for Visit<V> {
+            fn pre_decl(&mut self, node: &Decl) -> Control {
+                self.0.visit_decl(node)
+            }
+            $(
+                // Nodes that are added to easter later go to the coarse method.
+                #[allow(unreachable_patterns)]
+                fn $pre(&mut self, node: &$node_type, path: &NodePath) -> Control {
+                    match *node {
+                        $( $($pattern)|+ => self.0.$method(node, path), )*
+                        _ => self.0.$coarse(node, path),
+                    }
+                }
+            )*
+        }
+    }
+}
+
+visitor! {
+    Expr => visit_expr, pre_expr {
+        /// `this`.
+        fn visit_this => Expr::This(..),
+        /// A variable reference, like `a`.
+        fn visit_id => Expr::Id(..),
+        /// An array literal, like `[a, b]`.
+        fn visit_array => Expr::Arr(..),
+        /// An object literal, like `{ a: b }`.
+        fn visit_object => Expr::Obj(..),
+        /// A function expression.
+        fn visit_function => Expr::Fun(..),
+        /// A comma separated sequence, like `a, b`.
+        fn visit_sequence => Expr::Seq(..),
+        /// A unary operator, like `!a` or `typeof a`.
+        fn visit_unary => Expr::Unop(..),
+        /// A binary operator, like `a + b` or `a === b`.
+        fn visit_binary => Expr::Binop(..),
+        /// `a && b` or `a || b`.
+        fn visit_logical => Expr::Logop(..),
+        /// `++a`, `a++`, `--a` or `a--`.
+        fn visit_update => Expr::PreInc(..) | Expr::PostInc(..) | Expr::PreDec(..) | Expr::PostDec(..),
+        /// An assignment, like `a = b` or `a += b`.
+        fn visit_assign => Expr::Assign(..) | Expr::BinAssign(..),
+        /// `a ? b : c`.
+        fn visit_conditional => Expr::Cond(..),
+        /// A call, like `require('a')`.
+        fn visit_call => Expr::Call(..),
+        /// `new A()`.
+        fn visit_new => Expr::New(..),
+        /// A property access, like `a.b` or `a[b]`.
+        fn visit_member => Expr::Dot(..) | Expr::Brack(..),
+        /// `new.target`.
+        fn visit_new_target => Expr::NewTarget(..),
+        /// A literal value, like `true`, `null`, `1`, `/a/` or `'a'`.
+        fn visit_literal => Expr::True(..) | Expr::False(..) | Expr::Null(..) | Expr::Number(..) |
+            Expr::RegExp(..) | Expr::String(..),
+    }
+    Stmt => visit_stmt, pre_stmt {
+        /// An empty statement, `;`.
+        fn visit_empty => Stmt::Empty(..),
+        /// A `{ block }`.
+        fn visit_block => Stmt::Block(..),
+        /// A `var` declaration.
+        fn visit_var_decl => Stmt::Var(..),
+        /// An expression statement, like `a();`.
+        fn visit_expr_stmt => Stmt::Expr(..),
+        /// An `if` statement.
+        fn visit_if => Stmt::If(..),
+        /// A labelled statement, like `outer: for (;;) {}`.
+        fn visit_label => Stmt::Label(..),
+        /// A `break` statement.
+        fn visit_break => Stmt::Break(..),
+        /// A `continue` statement.
+        fn visit_continue => Stmt::Cont(..),
+        /// A `with` statement.
+        fn visit_with => Stmt::With(..),
+        /// A `switch` statement.
+        fn visit_switch => Stmt::Switch(..),
+        /// A `return` statement.
+        fn visit_return => Stmt::Return(..),
+        /// A `throw` statement.
+        fn visit_throw => Stmt::Throw(..),
+        /// A `try` statement.
+        fn visit_try => Stmt::Try(..),
+        /// A `while` or `do`/`while` loop.
+        fn visit_while => Stmt::While(..) | Stmt::DoWhile(..),
+        /// A `for (;;)` loop.
+        fn visit_for => Stmt::For(..),
+        /// A `for (a in b)` loop.
+        fn visit_for_in => Stmt::ForIn(..),
+        /// A `for (a of b)` loop.
+        fn visit_for_of => Stmt::ForOf(..),
+        /// A `debugger` statement.
+        fn visit_debugger => Stmt::Debugger(..),
+    }
+}
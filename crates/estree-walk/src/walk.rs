@@ -0,0 +1,768 @@
+use std::rc::Rc;
+use std::slice;
+use easter::stmt::{Script, Module, ModItem, StmtListItem, Stmt, ForHead, ForInHead, ForOfHead};
+use easter::decl::{Decl, Dtor, Import, Export};
+use easter::expr::{ExprListItem, Expr};
+use easter::patt::{Patt, CompoundPatt, AssignTarget};
+use easter::id::Id;
+use easter::obj::{Prop, PropVal};
+use easter::fun::Fun;
+use joker::track::{Span, TrackingRef};
+use location::{Location, LineIndex};
+use scope::{Scope, ScopeChain};
+use stack::ensure_stack;
+
+/// A kind of node that changes whether or how the code inside it runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    /// A function body. Code inside it only runs when the function is called.
+    Function,
+    /// The block of a `try` statement that has a `catch` clause. The block of a `try` statement
+    /// that only has a `finally` clause is not tracked, because its errors are not caught.
+    Try,
+    /// The `catch` clause of a `try` statement.
+    Catch,
+    /// The `finally` clause of a `try` statement.
+    Finally,
+    /// A branch of an `if` statement.
+    If,
+    /// A branch of a `?:`, `&&` or `||` expression.
+    Conditional,
+    /// The body of a loop.
+    Loop,
+    /// A case of a `switch` statement.
+    Switch,
+    /// The body of a `with` statement.
+    With,
+}
+
+/// Where a node is in the syntax tree: the kinds of its ancestors that matter for how it runs,
+/// the scopes around it, and where it is in the source file. The walker passes it to
+/// `pre_stmt`, `pre_expr` and `pre_id`.
+#[derive(Debug, Clone, Default)]
+pub struct NodePath {
+    ancestors: Vec<NodeKind>,
+    scopes: ScopeChain,
+    span: Option<Span>,
+    lines: Option<Rc<LineIndex>>,
+}
+
+impl NodePath {
+    pub fn new() -> NodePath {
+        NodePath::default()
+    }
+
+    /// The ancestors, outermost first.
+    pub fn ancestors(&self) -> &[NodeKind] {
+        &self.ancestors
+    }
+
+    /// The closest ancestor, if there is one.
+    pub fn parent(&self) -> Option<NodeKind> {
+        self.ancestors.last().cloned()
+    }
+
+    /// Whether any ancestor is of this kind, eg. whether a require() call is inside a `try` block.
+    pub fn is_inside(&self, kind: NodeKind) -> bool {
+        self.ancestors.contains(&kind)
+    }
+
+    /// Whether an ancestor of this kind is inside the closest function, or anywhere if the node
+    /// is not inside a function. Unlike `is_inside`, this is about what happens when the node
+    /// runs, eg. a require() call in a function that is defined in a `try` block is not caught
+    /// by it.
+    pub fn is_inside_in_function(&self, kind: NodeKind) -> bool {
+        self.ancestors.iter().rev()
+            .take_while(|ancestor| **ancestor != NodeKind::Function || kind == NodeKind::Function)
+            .any(|ancestor| *ancestor == kind)
+    }
+
+    /// Whether the node runs unconditionally when the script runs: it is not inside a function,
+    /// a branch, a loop or a `catch` clause.
+    pub fn is_top_level(&self) -> bool {
+        self.ancestors.iter().all(|kind| match *kind {
+            NodeKind::Try | NodeKind::Finally | NodeKind::With => true,
+            _ => false,
+        })
+    }
+
+    /// The scopes around the node, if the walker tracks scopes.
+    pub fn scopes(&self) -> &ScopeChain {
+        &self.scopes
+    }
+
+    /// Where the node is in the source file, if the parser tracked it.
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    /// The line index of the source file, if the walker was given one.
+    pub fn lines(&self) -> Option<&LineIndex> {
+        self.lines.as_ref().map(|lines| &**lines)
+    }
+
+    /// The line and column where the node starts, for diagnostics like `file.js:3:14`.
+    /// Uses the line index if there is one, or else the position that the parser tracked.
+    pub fn location(&self) -> Option<Location> {
+        let span = self.span?;
+        Some(match self.lines {
+            Some(ref lines) => lines.span_location(&span),
+            None => Location { line: span.start.line + 1, column: span.start.column + 1 },
+        })
+    }
+
+    fn push(&mut self, kind: NodeKind) {
+        self.ancestors.push(kind);
+    }
+
+    fn pop(&mut self) {
+        self.ancestors.pop();
+    }
+}
+
+/// The root node of a walk.
+enum Root<'a> {
+    Script(&'a Script),
+    Module(&'a Module),
+    /// Some of the top-level statements of a script.
+    Items(&'a Script, &'a [StmtListItem]),
+}
+
+/// An estree (easter crate) JavaScript AST walker.
+pub struct Walker<'a, C: Callbacks> {
+    ast: Root<'a>,
+    callbacks: C,
+    path: NodePath,
+    stopped: bool,
+    track_scopes: bool,
+    /// The scope of the whole script, for walks of some of its statements.
+    script_scope: Option<Scope>,
+}
+
+/// What the walker does after a pre-order callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    /// Walk the children of the node.
+    Continue,
+    /// Do not walk the children of the node. Its post-order callback is still called.
+    SkipChildren,
+    /// Stop walking. No more callbacks are called, not even the post-order ones of the
+    /// nodes that are being walked.
+    Stop,
+}
+
+/// Holds functions to be called on different types of nodes.
+/// There are functions for preorder traversal and postorder traversal.
+/// Preorder functions return a `Control` to skip the node's children or stop the walk.
+/// All callbacks are optional, implementations can pick and choose which they need.
+pub trait Callbacks {
+    /// Called before a top-level Script node is entered.
+    fn pre_script(&mut self, _node: &Script) -> Control { Control::Continue }
+    /// Called before a top-level Module node is entered.
+    fn pre_module(&mut self, _node: &Module) -> Control { Control::Continue }
+    /// Called before an import declaration is entered.
+    fn pre_import(&mut self, _node: &Import) -> Control { Control::Continue }
+    /// Called before an export declaration is entered.
+    fn pre_export(&mut self, _node: &Export) -> Control { Control::Continue }
+    /// Called before a Statement node is entered.
+    fn pre_stmt(&mut self, _node: &Stmt, _path: &NodePath) -> Control { Control::Continue }
+    /// Called before an Expression node is entered.
+    fn pre_expr(&mut self, _node: &Expr, _path: &NodePath) -> Control { Control::Continue }
+    /// Called before a Declaration node is entered.
+    fn pre_decl(&mut self, _node: &Decl) -> Control { Control::Continue }
+    /// Called before a Function node is entered.
+    fn pre_fun<Id>(&mut self, _node: &Fun<Id>) -> Control { Control::Continue }
+    /// Called before a destructuring or assignment Pattern node is entered.
+    /// The pattern of a `var`, `let` or `const` declarator is not a node of its own, so this is
+    /// only called on the patterns nested inside it, like `b` and `[c]` in `var { b, d: [c] } = e`.
+    fn pre_patt<T>(&mut self, _node: &Patt<T>) -> Control { Control::Continue }
+    /// Called on an identifier that refers to a variable, like `a` in `a.b = c`, but not on
+    /// declared names or property names.
+    fn pre_id(&mut self, _node: &Id, _path: &NodePath) -> Control { Control::Continue }
+    /// Called after a top-level Script node was handled.
+    fn post_script(&mut self, _node: &Script) -> () {}
+    /// Called after a top-level Module node was handled.
+    fn post_module(&mut self, _node: &Module) -> () {}
+    /// Called after an import declaration was handled.
+    fn post_import(&mut self, _node: &Import) -> () {}
+    /// Called after an export declaration was handled.
+    fn post_export(&mut self, _node: &Export) -> () {}
+    /// Called after a Statement node was handled.
+    fn post_stmt(&mut self, _node: &Stmt) -> () {}
+    /// Called after an Expression node was handled.
+    fn post_expr(&mut self, _node: &Expr) -> () {}
+    /// Called after a Declaration node was handled.
+    fn post_decl(&mut self, _node: &Decl) -> () {}
+    /// Called after a Function node was handled.
+    fn post_fun<Id>(&mut self, _node: &Fun<Id>) -> () {}
+    /// Called after a Pattern node was handled.
+    fn post_patt<T>(&mut self, _node: &Patt<T>) -> () {}
+    /// Called after an identifier reference was handled.
+    fn post_id(&mut self, _node: &Id) -> () {}
+}
+
+impl<'a, C: Callbacks> Walker<'a, C> {
+    /// Create a new Walker for a given ESTree Script, calling the
+    /// callbacks specified in `callbacks` on the relevant nodes.
+    pub fn new(ast: &'a Script, callbacks: C) -> Walker<'a, C> {
+        Walker { ast: Root::Script(ast), callbacks, path: NodePath::new(), stopped: false, track_scopes: false, script_scope: None }
+    }
+
+    /// Create a new Walker for an ES module, calling the callbacks specified in `callbacks` on
+    /// the relevant nodes, including `pre_import` and `pre_export` on import and export declarations.
+    pub fn new_module(ast: &'a Module, callbacks: C) -> Walker<'a, C> {
+        Walker { ast: Root::Module(ast), callbacks, path: NodePath::new(), stopped: false, track_scopes: false, script_scope: None }
+    }
+
+    /// Create a new Walker for some of the top-level statements of a Script, like a batch of
+    /// them in `ParallelWalker`. `pre_script` and `post_script` are not called. The script
+    /// scope still contains the declarations from all of the script, including hoisted `var`
+    /// and function declarations in the statements that are not walked.
+    pub fn new_partial(ast: &'a Script, items: &'a [StmtListItem], callbacks: C) -> Walker<'a, C> {
+        Walker { ast: Root::Items(ast, items), callbacks, path: NodePath::new(), stopped: false, track_scopes: false, script_scope: None }
+    }
+
+    /// Use this scope for the script in a partial walk, instead of collecting the declarations
+    /// of the whole script again. Walkers of several batches of statements can share it.
+    pub fn with_script_scope(mut self, scope: Scope) -> Self {
+        self.script_scope = Some(scope);
+        self
+    }
+
+    /// Keep track of the scopes and the names declared in them during the walk. Callbacks can
+    /// look them up with `path.scopes()`.
+    ///
+    /// # Examples
+    /// ```rust,ignore
+    /// let callbacks = Walker::new(&ast, FindRequires::default()).with_scopes().walk();
+    /// ```
+    pub fn with_scopes(mut self) -> Self {
+        self.track_scopes = true;
+        self
+    }
+
+    /// Look up the locations of nodes in this line index, so callbacks can get them with
+    /// `path.location()`. The index must be for the source of the syntax tree that is walked.
+    ///
+    /// # Examples
+    /// ```rust,ignore
+    /// let lines = Rc::new(LineIndex::new(&source));
+    /// let found = Walker::new(&ast, FindDebugger::new()).with_line_index(Rc::clone(&lines)).walk();
+    /// ```
+    pub fn with_line_index(mut self, lines: Rc<LineIndex>) -> Self {
+        self.path.lines = Some(lines);
+        self
+    }
+
+    /// Do a recursive walk, calling `callbacks` where relevant.
+    /// Returns the Callbacks instance, so that custom implementations
+    /// of this trait can contain state.
+    /// Consumes the walker—create a new one to do more than one walk.
+    pub fn walk(mut self) -> C {
+        match self.ast {
+            Root::Script(ast) => self.walk_script(ast),
+            Root::Module(ast) => self.walk_module(ast),
+            Root::Items(ast, items) => self.walk_items(ast, items),
+        }
+        self.callbacks
+    }
+
+    /// Kick off the walk at the top-level Script node.
+    fn walk_script(&mut self, ast: &Script) -> () {
+        let control = self.callbacks.pre_script(ast);
+        if self.enter(control) {
+            self.push_scope(|| Scope::script(&ast.items));
+            for item in &ast.items {
+                self.walk_stmt_item(item);
+            }
+            self.pop_scope();
+        }
+        if !self.stopped {
+            self.callbacks.post_script(ast);
+        }
+    }
+
+    /// Walk some of the top-level statements of a Script.
+    fn walk_items(&mut self, ast: &Script, items: &[StmtListItem]) -> () {
+        let script_scope = self.script_scope.take();
+        self.push_scope(|| script_scope.unwrap_or_else(|| Scope::script(&ast.items)));
+        for item in items {
+            self.walk_stmt_item(item);
+        }
+        self.pop_scope();
+    }
+
+    /// Kick off the walk at the top-level Module node.
+    fn walk_module(&mut self, ast: &Module) -> () {
+        let control = self.callbacks.pre_module(ast);
+        if self.enter(control) {
+            self.push_scope(|| Scope::module(&ast.items));
+            for item in &ast.items {
+                match *item {
+                    ModItem::Import(ref import) => self.walk_import(import),
+                    ModItem::Export(ref export) => self.walk_export(export),
+                    ModItem::StmtListItem(ref item) => self.walk_stmt_item(item),
+                }
+            }
+            self.pop_scope();
+        }
+        if !self.stopped {
+            self.callbacks.post_module(ast);
+        }
+    }
+
+    /// Walk an import declaration. It only contains names and a module specifier, so there are
+    /// no child nodes to walk.
+    fn walk_import(&mut self, import: &Import) -> () {
+        if self.stopped { return; }
+        let control = self.callbacks.pre_import(import);
+        self.enter(control);
+        if !self.stopped {
+            self.callbacks.post_import(import);
+        }
+    }
+
+    /// Walk an export declaration, like `export var a = 1` or `export default expr`.
+    fn walk_export(&mut self, export: &Export) -> () {
+        if self.stopped { return; }
+        let control = self.callbacks.pre_export(export);
+        if self.enter(control) {
+            match *export {
+                Export::Var(_, ref dtors) => self.walk_var(dtors),
+                Export::Decl(ref decl) => self.walk_decl(decl),
+                Export::Default(_, ref expr) => self.walk_expr(expr),
+                // Export lists and re-exports only contain names and module specifiers.
+                _ => (),
+            }
+        }
+        if !self.stopped {
+            self.callbacks.post_export(export);
+        }
+    }
+
+    /// Handle the result of a pre-order callback. Returns whether to walk the node's children.
+    fn enter(&mut self, control: Control) -> bool {
+        match control {
+            Control::Continue => true,
+            Control::SkipChildren => false,
+            Control::Stop => {
+                self.stopped = true;
+                false
+            },
+        }
+    }
+
+    /// Enter a scope, if scopes are tracked. `make_scope` collects its declarations.
+    fn push_scope<F>(&mut self, make_scope: F) -> ()
+        where F: FnOnce() -> Scope
+    {
+        if self.track_scopes {
+            self.path.scopes.push(make_scope());
+        }
+    }
+
+    fn pop_scope(&mut self) -> () {
+        if self.track_scopes {
+            self.path.scopes.pop();
+        }
+    }
+
+    /// Walk an item in a list of statements, like in { blocks; }.
+    fn walk_stmt_item(&mut self, item: &StmtListItem) -> () {
+        match *item {
+            StmtListItem::Stmt(ref stmt) => self.walk_stmt(stmt),
+            StmtListItem::Decl(ref decl) => self.walk_decl(decl),
+        }
+    }
+
+    /// Walk a statement.
+    fn walk_stmt(&mut self, stmt: &Stmt) -> () {
+        if self.stopped { return; }
+        self.path.span = *stmt.tracking_ref();
+        let control = self.callbacks.pre_stmt(stmt, &self.path);
+        if self.enter(control) {
+            ensure_stack(|| self.walk_stmt_children(stmt));
+        }
+        if !self.stopped {
+            self.callbacks.post_stmt(stmt);
+        }
+    }
+
+    fn walk_stmt_children(&mut self, stmt: &Stmt) -> () {
+        match *stmt {
+            Stmt::Block(ref block) => {
+                self.push_scope(|| Scope::block(&block.items));
+                for item in &block.items {
+                    self.walk_stmt_item(item);
+                }
+                self.pop_scope();
+            },
+            Stmt::Var(_, ref decls, _) => self.walk_var(decls),
+            Stmt::Expr(_, ref expr, _) => self.walk_expr(expr),
+            Stmt::If(_, ref cond, ref cons, ref alt) => {
+                self.walk_expr(cond);
+                self.path.push(NodeKind::If);
+                self.walk_stmt(cons.as_ref());
+                if let Some(ref node) = *alt { self.walk_stmt(node.as_ref()); }
+                self.path.pop();
+            },
+            Stmt::Label(_, _, ref block) => self.walk_stmt(block.as_ref()),
+            Stmt::Switch(_, ref cond, ref cases) => {
+                self.walk_expr(cond);
+                self.path.push(NodeKind::Switch);
+                self.push_scope(|| Scope::block(cases.iter().flat_map(|case| &case.body)));
+                for case in cases {
+                    if let Some(ref test) = case.test { self.walk_expr(test); }
+                    for item in &case.body {
+                        self.walk_stmt_item(item);
+                    }
+                }
+                self.pop_scope();
+                self.path.pop();
+            },
+            Stmt::Return(_, Some(ref arg), _) | Stmt::Throw(_, ref arg, _) =>
+                self.walk_expr(arg),
+            Stmt::Try(_, ref block, ref caught, ref finally) => {
+                if caught.is_some() {
+                    self.path.push(NodeKind::Try);
+                    self.walk_block_items(&block.items);
+                    self.path.pop();
+                } else {
+                    self.walk_block_items(&block.items);
+                }
+                if let Some(ref caught_block) = *caught {
+                    self.path.push(NodeKind::Catch);
+                    self.push_scope(|| Scope::catch(&caught_block.param));
+                    self.walk_patt(&caught_block.param, Self::walk_binding);
+                    self.walk_block_items(&caught_block.body.items);
+                    self.pop_scope();
+                    self.path.pop();
+                }
+                if let Some(ref finally_block) = *finally {
+                    self.path.push(NodeKind::Finally);
+                    self.walk_block_items(&finally_block.items);
+                    self.path.pop();
+                }
+            },
+            Stmt::While(_, ref cond, ref body) => {
+                self.walk_expr(cond);
+                self.walk_nested_stmt(NodeKind::Loop, body.as_ref());
+            },
+            Stmt::DoWhile(_, ref body, ref cond, _) => {
+                self.walk_nested_stmt(NodeKind::Loop, body.as_ref());
+                self.walk_expr(cond);
+            },
+            Stmt::For(_, ref head, ref cond, ref update, ref body) => {
+                if let Some(ref node) = *head { self.walk_for_head(node); }
+                if let Some(ref node) = *cond { self.walk_expr(node); }
+                self.path.push(NodeKind::Loop);
+                if let Some(ref node) = *update { self.walk_expr(node); }
+                self.walk_stmt(body.as_ref());
+                self.path.pop();
+            },
+            Stmt::ForIn(_, ref head, ref iterable, ref body) => {
+                self.walk_for_in_head(head);
+                self.walk_expr(iterable);
+                self.walk_nested_stmt(NodeKind::Loop, body.as_ref());
+            },
+            Stmt::ForOf(_, ref head, ref iterable, ref body) => {
+                self.walk_for_of_head(head);
+                self.walk_expr(iterable);
+                self.walk_nested_stmt(NodeKind::Loop, body.as_ref());
+            },
+            Stmt::With(_, ref object, ref body) => {
+                self.walk_expr(object);
+                self.walk_nested_stmt(NodeKind::With, body.as_ref());
+            },
+            _ => (),
+        }
+    }
+
+    /// Walk the initializer of a `for (;;)` loop.
+    fn walk_for_head(&mut self, head: &ForHead) -> () {
+        match *head {
+            ForHead::Var(_, ref dtors) | ForHead::Let(_, ref dtors) => self.walk_var(dtors),
+            ForHead::Expr(_, ref expr) => self.walk_expr(expr),
+        }
+    }
+
+    /// Walk the left-hand side of a `for (a in b)` loop.
+    fn walk_for_in_head(&mut self, head: &ForInHead) -> () {
+        match *head {
+            ForInHead::VarInit(_, _, ref init) => self.walk_expr(init),
+            ForInHead::Var(_, ref patt) | ForInHead::Let(_, ref patt) => self.walk_patt(patt, Self::walk_binding),
+            ForInHead::Expr(ref expr) => self.walk_expr(expr),
+        }
+    }
+
+    /// Walk the left-hand side of a `for (a of b)` loop.
+    fn walk_for_of_head(&mut self, head: &ForOfHead) -> () {
+        match *head {
+            ForOfHead::Var(_, ref patt) | ForOfHead::Let(_, ref patt) => self.walk_patt(patt, Self::walk_binding),
+            ForOfHead::Expr(ref expr) => self.walk_expr(expr),
+        }
+    }
+
+    /// Walk the statements of a block that is part of another statement, like a `try` block.
+    fn walk_block_items(&mut self, items: &[StmtListItem]) -> () {
+        self.push_scope(|| Scope::block(items));
+        for item in items {
+            self.walk_stmt_item(item);
+        }
+        self.pop_scope();
+    }
+
+    /// Walk a statement inside a node of the given kind, like the body of a loop.
+    fn walk_nested_stmt(&mut self, kind: NodeKind, stmt: &Stmt) -> () {
+        self.path.push(kind);
+        self.walk_stmt(stmt);
+        self.path.pop();
+    }
+
+    /// Walk a declaration node (function, let, const).
+    fn walk_decl(&mut self, decl: &Decl) -> () {
+        if self.stopped { return; }
+        let control = self.callbacks.pre_decl(decl);
+        if self.enter(control) {
+            self.walk_decl_children(decl);
+        }
+        if !self.stopped {
+            self.callbacks.post_decl(decl);
+        }
+    }
+
+    fn walk_decl_children(&mut self, decl: &Decl) -> () {
+        match *decl {
+            Decl::Fun(ref fun) => self.walk_fun(fun),
+            Decl::Let(_, ref dtors, _) => {
+                for dtor in dtors {
+                    self.walk_dtor(dtor);
+                }
+            },
+            Decl::Const(_, ref dtors, _) => {
+                for dtor in dtors {
+                    if let Patt::Compound(ref patt) = dtor.patt {
+                        self.walk_compound_patt(patt, Self::walk_binding);
+                    }
+                    self.walk_expr(&dtor.value);
+                }
+            },
+        }
+    }
+
+    /// Walk a var declaration.
+    fn walk_var(&mut self, decls: &[Dtor]) -> () {
+        for decl in decls {
+            self.walk_dtor(decl);
+        }
+    }
+
+    fn walk_dtor(&mut self, dtor: &Dtor) -> () {
+        match *dtor {
+            Dtor::Simple(_, _, Some(ref expr)) => self.walk_expr(expr),
+            Dtor::Simple(_, _, None) => (),
+            Dtor::Compound(_, ref patt, ref expr) => {
+                self.walk_compound_patt(patt, Self::walk_binding);
+                self.walk_expr(expr);
+            },
+        }
+    }
+
+    /// Walk an expression node.
+    fn walk_expr(&mut self, expr: &Expr) -> () {
+        if self.stopped { return; }
+        self.path.span = *expr.tracking_ref();
+        let control = self.callbacks.pre_expr(expr, &self.path);
+        if self.enter(control) {
+            ensure_stack(|| self.walk_expr_children(expr));
+        }
+        if !self.stopped {
+            self.callbacks.post_expr(expr);
+        }
+    }
+
+    fn walk_expr_children(&mut self, expr: &Expr) -> () {
+        match *expr {
+            Expr::Call(_, ref callee, ref args) => {
+                self.walk_expr(callee);
+                for arg in args {
+                    match *arg {
+                        ExprListItem::Expr(ref node) => self.walk_expr(node),
+                        ExprListItem::Spread(_, ref node) => self.walk_expr(node),
+                    }
+                }
+            },
+            Expr::New(_, ref callee, ref args) => {
+                self.walk_expr(callee);
+                if let Some(ref args) = *args {
+                    for arg in args {
+                        match *arg {
+                            ExprListItem::Expr(ref node) => self.walk_expr(node),
+                            ExprListItem::Spread(_, ref node) => self.walk_expr(node),
+                        }
+                    }
+                }
+            },
+            Expr::Seq(_, ref exprs) => {
+                for expr in exprs {
+                    self.walk_expr(expr);
+                }
+            }
+            Expr::Arr(_, ref elements) => {
+                for el in elements {
+                    match *el {
+                        Some(ExprListItem::Expr(ref node)) => self.walk_expr(node),
+                        Some(ExprListItem::Spread(_, ref node)) => self.walk_expr(node),
+                        None => (),
+                    }
+                }
+            },
+            Expr::Obj(_, ref properties) => {
+                for prop in properties {
+                    self.walk_prop(prop);
+                }
+            },
+            Expr::Id(ref id) => self.walk_id(id),
+            Expr::Fun(ref fun) => self.walk_fun(fun),
+            Expr::Binop(_, _, ref a, ref b) => {
+                self.walk_expr(a.as_ref());
+                self.walk_expr(b.as_ref());
+            },
+            Expr::Logop(_, _, ref a, ref b) => {
+                self.walk_expr(a.as_ref());
+                self.path.push(NodeKind::Conditional);
+                self.walk_expr(b.as_ref());
+                self.path.pop();
+            },
+            Expr::Unop(_, _, ref expr) => self.walk_expr(expr.as_ref()),
+            Expr::PreInc(_, ref target) | Expr::PostInc(_, ref target) |
+            Expr::PreDec(_, ref target) | Expr::PostDec(_, ref target) =>
+                self.walk_assign_target(target.as_ref()),
+            Expr::Assign(_, ref target, ref expr) => {
+                self.walk_patt(target, Self::walk_assign_target);
+                self.walk_expr(expr.as_ref());
+            },
+            Expr::BinAssign(_, _, ref target, ref expr) => {
+                self.walk_assign_target(target);
+                self.walk_expr(expr.as_ref());
+            },
+            Expr::Cond(_, ref cond, ref cons, ref alt) => {
+                self.walk_expr(cond.as_ref());
+                self.path.push(NodeKind::Conditional);
+                self.walk_expr(cons.as_ref());
+                self.walk_expr(alt.as_ref());
+                self.path.pop();
+            },
+            // a.b
+            Expr::Dot(_, ref object, ref _property) => self.walk_expr(object.as_ref()),
+            Expr::Brack(_, ref object, ref property) => {
+                self.walk_expr(object.as_ref());
+                self.walk_expr(property.as_ref());
+            },
+            _ => (),
+        }
+    }
+
+    /// Walk a function declaration or expression node.
+    fn walk_fun<Id>(&mut self, fun: &Fun<Id>) -> () {
+        if self.stopped { return; }
+        let control = self.callbacks.pre_fun(fun);
+        if self.enter(control) {
+            self.walk_fun_children(fun);
+        }
+        if !self.stopped {
+            self.callbacks.post_fun(fun);
+        }
+    }
+
+    fn walk_fun_children<Id>(&mut self, fun: &Fun<Id>) -> () {
+        self.walk_function(&fun.params.list, &fun.body.items);
+    }
+
+    /// Walk a pattern, calling `walk_target` on the identifiers or member expressions it assigns to.
+    fn walk_patt<T>(&mut self, patt: &Patt<T>, walk_target: fn(&mut Self, &T)) -> () {
+        if self.stopped { return; }
+        let control = self.callbacks.pre_patt(patt);
+        if self.enter(control) {
+            match *patt {
+                Patt::Simple(ref target) => walk_target(self, target),
+                Patt::Compound(ref compound) => self.walk_compound_patt(compound, walk_target),
+            }
+        }
+        if !self.stopped {
+            self.callbacks.post_patt(patt);
+        }
+    }
+
+    /// Walk the elements of an array or object pattern, like in `var [a, { b: c.d }] = arr`.
+    fn walk_compound_patt<T>(&mut self, patt: &CompoundPatt<T>, walk_target: fn(&mut Self, &T)) -> () {
+        match *patt {
+            CompoundPatt::Arr(_, ref elements) => {
+                for element in elements {
+                    if let Some(ref element) = *element {
+                        self.walk_patt(element, walk_target);
+                    }
+                }
+            },
+            CompoundPatt::Obj(_, ref props) => {
+                for prop in props {
+                    self.walk_patt(&prop.patt, walk_target);
+                }
+            },
+        }
+    }
+
+    /// Declared names do not contain anything to walk into.
+    fn walk_binding(&mut self, _id: &Id) -> () {}
+
+    /// Walk an identifier that refers to a variable.
+    fn walk_id(&mut self, id: &Id) -> () {
+        if self.stopped { return; }
+        self.path.span = id.location;
+        let control = self.callbacks.pre_id(id, &self.path);
+        self.enter(control);
+        if !self.stopped {
+            self.callbacks.post_id(id);
+        }
+    }
+
+    fn walk_assign_target(&mut self, target: &AssignTarget) -> () {
+        match *target {
+            AssignTarget::Id(ref id) => self.walk_id(id),
+            AssignTarget::Dot(_, ref object, ref _property) => self.walk_expr(object.as_ref()),
+            AssignTarget::Brack(_, ref object, ref property) => {
+                self.walk_expr(object.as_ref());
+                self.walk_expr(property.as_ref());
+            },
+        }
+    }
+
+    /// Walk the parameters and body of a function, getter or setter. The parameters are walked
+    /// first, inside the function scope.
+    fn walk_function(&mut self, params: &[Patt<Id>], body: &[StmtListItem]) -> () {
+        self.path.push(NodeKind::Function);
+        self.push_scope(|| Scope::function(params, body));
+        for param in params {
+            self.walk_patt(param, Self::walk_binding);
+        }
+        for item in body {
+            self.walk_stmt_item(item);
+        }
+        self.pop_scope();
+        self.path.pop();
+    }
+
+    fn walk_prop(&mut self, prop: &Prop) -> () {
+        match *prop {
+            Prop::Regular(_, _, ref val) => {
+                match *val {
+                    PropVal::Init(ref value) => self.walk_expr(value),
+                    PropVal::Get(_, ref body) => self.walk_function(&[], &body.items),
+                    PropVal::Set(_, ref param, ref body) => self.walk_function(slice::from_ref(param), &body.items),
+                }
+            },
+            Prop::Method(ref fun) => self.walk_fun(fun),
+            Prop::Shorthand(ref id) => self.walk_id(id),
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use easter::stmt::{Script, StmtListItem, Stmt, ForHead, ForInHead, ForOfHead};
+use easter::decl::{Decl, Dtor};
+use easter::expr::{ExprListItem, Expr};
+use easter::patt::{Patt, CompoundPatt, AssignTarget};
+use easter::id::Id;
+use easter::obj::{Prop, PropVal};
+use easter::fun::Fun;
+use stack::ensure_stack;
+
+/// An estree (easter crate) JavaScript AST walker that can change the nodes it visits.
+pub struct WalkerMut<'a, C: CallbacksMut> {
+    ast: &'a mut Script,
+    callbacks: C,
+}
+
+/// Like `Callbacks`, but the functions receive mutable nodes.
+/// A pre-order callback can replace a node; the walker then descends into the replacement.
+/// All callbacks are optional, implementations can pick and choose which they need.
+pub trait CallbacksMut {
+    /// Called before a top-level Script node is entered.
+    fn pre_script(&mut self, _node: &mut Script) -> () {}
+    /// Called before a Statement node is entered.
+    fn pre_stmt(&mut self, _node: &mut Stmt) -> () {}
+    /// Called before an Expression node is entered.
+    fn pre_expr(&mut self, _node: &mut Expr) -> () {}
+    /// Called before a Declaration node is entered.
+    fn pre_decl(&mut self, _node: &mut Decl) -> () {}
+    /// Called before a Function node is entered.
+    fn pre_fun<Id>(&mut self, _node: &mut Fun<Id>) -> () {}
+    /// Called before a destructuring or assignment Pattern node is entered.
+    /// Like in `Callbacks::pre_patt`, the pattern of a declarator itself is not included.
+    fn pre_patt<T>(&mut self, _node: &mut Patt<T>) -> () {}
+    /// Called after a top-level Script node was handled.
+    fn post_script(&mut self, _node: &mut Script) -> () {}
+    /// Called after a Statement node was handled.
+    fn post_stmt(&mut self, _node: &mut Stmt) -> () {}
+    /// Called after an Expression node was handled.
+    fn post_expr(&mut self, _node: &mut Expr) -> () {}
+    /// Called after a Declaration node was handled.
+    fn post_decl(&mut self, _node: &mut Decl) -> () {}
+    /// Called after a Function node was handled.
+    fn post_fun<Id>(&mut self, _node: &mut Fun<Id>) -> () {}
+    /// Called after a Pattern node was handled.
+    fn post_patt<T>(&mut self, _node: &mut Patt<T>) -> () {}
+}
+
+impl<'a, C: CallbacksMut> WalkerMut<'a, C> {
+    /// Create a new WalkerMut for a given ESTree Script, calling the
+    /// callbacks specified in `callbacks` on the relevant nodes.
+    pub fn new(ast: &'a mut Script, callbacks: C) -> WalkerMut<'a, C> {
+        WalkerMut { ast, callbacks }
+    }
+
+    /// Do a recursive walk, calling `callbacks` where relevant.
+    /// Returns the CallbacksMut instance, so that custom implementations
+    /// of this trait can contain state.
+    /// Consumes the walker—create a new one to do more than one walk.
+    pub fn walk(mut self) -> C {
+        let ast = self.ast;
+        self.callbacks.pre_script(ast);
+        for item in &mut ast.items {
+            walk_stmt_item(&mut self.callbacks, item);
+        }
+        self.callbacks.post_script(ast);
+        self.callbacks
+    }
+}
+
+/// Walk an item in a list of statements, like in { blocks; }.
+fn walk_stmt_item<C: CallbacksMut>(callbacks: &mut C, item: &mut StmtListItem) -> () {
+    match *item {
+        StmtListItem::Stmt(ref mut stmt) => walk_stmt(callbacks, stmt),
+        StmtListItem::Decl(ref mut decl) => walk_decl(callbacks, decl),
+    }
+}
+
+fn walk_stmt_items<C: CallbacksMut>(callbacks: &mut C, items: &mut [StmtListItem]) -> () {
+    for item in items {
+        walk_stmt_item(callbacks, item);
+    }
+}
+
+/// Walk a statement.
+fn walk_stmt<C: CallbacksMut>(callbacks: &mut C, stmt: &mut Stmt) -> () {
+    ensure_stack(|| walk_stmt_node(callbacks, stmt))
+}
+
+fn walk_stmt_node<C: CallbacksMut>(callbacks: &mut C, stmt: &mut Stmt) -> () {
+    callbacks.pre_stmt(stmt);
+    match *stmt {
+        Stmt::Block(ref mut block) => walk_stmt_items(callbacks, &mut block.items),
+        Stmt::Var(_, ref mut decls, _) => walk_var(callbacks, decls),
+        Stmt::Expr(_, ref mut expr, _) => walk_expr(callbacks, expr),
+        Stmt::If(_, ref mut cond, ref mut cons, ref mut alt) => {
+            walk_expr(callbacks, cond);
+            walk_stmt(callbacks, cons.as_mut());
+            if let Some(ref mut node) = *alt { walk_stmt(callbacks, node.as_mut()); }
+        },
+        Stmt::Label(_, _, ref mut block) => walk_stmt(callbacks, block.as_mut()),
+        Stmt::Switch(_, ref mut cond, ref mut cases) => {
+            walk_expr(callbacks, cond);
+            for case in cases {
+                if let Some(ref mut test) = case.test { walk_expr(callbacks, test); }
+                walk_stmt_items(callbacks, &mut case.body);
+            }
+        },
+        Stmt::Return(_, Some(ref mut arg), _) | Stmt::Throw(_, ref mut arg, _) =>
+            walk_expr(callbacks, arg),
+        Stmt::Try(_, ref mut block, ref mut caught, ref mut finally) => {
+            walk_stmt_items(callbacks, &mut block.items);
+            if let Some(ref mut caught_block) = *caught {
+                walk_patt(callbacks, &mut caught_block.param, walk_binding);
+                walk_stmt_items(callbacks, &mut caught_block.body.items);
+            }
+            if let Some(ref mut finally_block) = *finally {
+                walk_stmt_items(callbacks, &mut finally_block.items);
+            }
+        },
+        Stmt::While(_, ref mut cond, ref mut body) => {
+            walk_expr(callbacks, cond);
+            walk_stmt(callbacks, body.as_mut());
+        },
+        Stmt::DoWhile(_, ref mut body, ref mut cond, _) => {
+            walk_stmt(callbacks, body.as_mut());
+            walk_expr(callbacks, cond);
+        },
+        Stmt::For(_, ref mut head, ref mut cond, ref mut update, ref mut body) => {
+            if let Some(ref mut node) = *head { walk_for_head(callbacks, node); }
+            if let Some(ref mut node) = *cond { walk_expr(callbacks, node); }
+            if let Some(ref mut node) = *update { walk_expr(callbacks, node); }
+            walk_stmt(callbacks, body.as_mut());
+        },
+        Stmt::ForIn(_, ref mut head, ref mut iterable, ref mut body) => {
+            walk_for_in_head(callbacks, head);
+            walk_expr(callbacks, iterable);
+            walk_stmt(callbacks, body.as_mut());
+        },
+        Stmt::ForOf(_, ref mut head, ref mut iterable, ref mut body) => {
+            walk_for_of_head(callbacks, head);
+            walk_expr(callbacks, iterable);
+            walk_stmt(callbacks, body.as_mut());
+        },
+        Stmt::With(_, ref mut object, ref mut body) => {
+            walk_expr(callbacks, object);
+            walk_stmt(callbacks, body.as_mut());
+        },
+        _ => (),
+    }
+    callbacks.post_stmt(stmt);
+}
+
+/// Walk the initializer of a `for (;;)` loop.
+fn walk_for_head<C: CallbacksMut>(callbacks: &mut C, head: &mut ForHead) -> () {
+    match *head {
+        ForHead::Var(_, ref mut dtors) | ForHead::Let(_, ref mut dtors) => walk_var(callbacks, dtors),
+        ForHead::Expr(_, ref mut expr) => walk_expr(callbacks, expr),
+    }
+}
+
+/// Walk the left-hand side of a `for (a in b)` loop.
+fn walk_for_in_head<C: CallbacksMut>(callbacks: &mut C, head: &mut ForInHead) -> () {
+    match *head {
+        ForInHead::VarInit(_, _, ref mut init) => walk_expr(callbacks, init),
+        ForInHead::Var(_, ref mut patt) | ForInHead::Let(_, ref mut patt) => walk_patt(callbacks, patt, walk_binding),
+        ForInHead::Expr(ref mut expr) => walk_expr(callbacks, expr),
+    }
+}
+
+/// Walk the left-hand side of a `for (a of b)` loop.
+fn walk_for_of_head<C: CallbacksMut>(callbacks: &mut C, head: &mut ForOfHead) -> () {
+    match *head {
+        ForOfHead::Var(_, ref mut patt) | ForOfHead::Let(_, ref mut patt) => walk_patt(callbacks, patt, walk_binding),
+        ForOfHead::Expr(ref mut expr) => walk_expr(callbacks, expr),
+    }
+}
+
+/// Walk a declaration node (function, let, const).
+fn walk_decl<C: CallbacksMut>(callbacks: &mut C, decl: &mut Decl) -> () {
+    callbacks.pre_decl(decl);
+    match *decl {
+        Decl::Fun(ref mut fun) => walk_fun(callbacks, fun),
+        Decl::Let(_, ref mut dtors, _) => walk_var(callbacks, dtors),
+        Decl::Const(_, ref mut dtors, _) => {
+            for dtor in dtors {
+                if let Patt::Compound(ref mut patt) = dtor.patt {
+                    walk_compound_patt(callbacks, patt, walk_binding);
+                }
+                walk_expr(callbacks, &mut dtor.value);
+            }
+        },
+    }
+    callbacks.post_decl(decl);
+}
+
+/// Walk a var declaration.
+fn walk_var<C: CallbacksMut>(callbacks: &mut C, decls: &mut [Dtor]) -> () {
+    for decl in decls {
+        match *decl {
+            Dtor::Simple(_, _, Some(ref mut expr)) => walk_expr(callbacks, expr),
+            Dtor::Simple(_, _, None) => (),
+            Dtor::Compound(_, ref mut patt, ref mut expr) => {
+                walk_compound_patt(callbacks, patt, walk_binding);
+                walk_expr(callbacks, expr);
+            },
+        }
+    }
+}
+
+fn walk_expr_items<C: CallbacksMut>(callbacks: &mut C, items: &mut [ExprListItem]) -> () {
+    for item in items {
+        match *item {
+            ExprListItem::Expr(ref mut node) => walk_expr(callbacks, node),
+            ExprListItem::Spread(_, ref mut node) => walk_expr(callbacks, node),
+        }
+    }
+}
+
+/// Walk an expression node.
+fn walk_expr<C: CallbacksMut>(callbacks: &mut C, expr: &mut Expr) -> () {
+    ensure_stack(|| walk_expr_node(callbacks, expr))
+}
+
+fn walk_expr_node<C: CallbacksMut>(callbacks: &mut C, expr: &mut Expr) -> () {
+    callbacks.pre_expr(expr);
+    match *expr {
+        Expr::Call(_, ref mut callee, ref mut args) => {
+            walk_expr(callbacks, callee);
+            walk_expr_items(callbacks, args);
+        },
+        Expr::New(_, ref mut callee, ref mut args) => {
+            walk_expr(callbacks, callee);
+            if let Some(ref mut args) = *args {
+                walk_expr_items(callbacks, args);
+            }
+        },
+        Expr::Seq(_, ref mut exprs) => {
+            for expr in exprs {
+                walk_expr(callbacks, expr);
+            }
+        },
+        Expr::Arr(_, ref mut elements) => {
+            for el in elements {
+                match *el {
+                    Some(ExprListItem::Expr(ref mut node)) => walk_expr(callbacks, node),
+                    Some(ExprListItem::Spread(_, ref mut node)) => walk_expr(callbacks, node),
+                    None => (),
+                }
+            }
+        },
+        Expr::Obj(_, ref mut properties) => {
+            for prop in properties {
+                walk_prop(callbacks, prop);
+            }
+        },
+        Expr::Fun(ref mut fun) => walk_fun(callbacks, fun),
+        Expr::Binop(_, _, ref mut a, ref mut b) | Expr::Logop(_, _, ref mut a, ref mut b) => {
+            walk_expr(callbacks, a.as_mut());
+            walk_expr(callbacks, b.as_mut());
+        },
+        Expr::Unop(_, _, ref mut expr) => walk_expr(callbacks, expr.as_mut()),
+        Expr::PreInc(_, ref mut target) | Expr::PostInc(_, ref mut target) |
+        Expr::PreDec(_, ref mut target) | Expr::PostDec(_, ref mut target) =>
+            walk_assign_target(callbacks, target.as_mut()),
+        Expr::Assign(_, ref mut target, ref mut expr) => {
+            walk_patt(callbacks, target, walk_assign_target);
+            walk_expr(callbacks, expr.as_mut());
+        },
+        Expr::BinAssign(_, _, ref mut target, ref mut expr) => {
+            walk_assign_target(callbacks, target);
+            walk_expr(callbacks, expr.as_mut());
+        },
+        Expr::Cond(_, ref mut cond, ref mut cons, ref mut alt) => {
+            walk_expr(callbacks, cond.as_mut());
+            walk_expr(callbacks, cons.as_mut());
+            walk_expr(callbacks, alt.as_mut());
+        },
+        Expr::Dot(_, ref mut object, _) => walk_expr(callbacks, object.as_mut()),
+        Expr::Brack(_, ref mut object, ref mut property) => {
+            walk_expr(callbacks, object.as_mut());
+            walk_expr(callbacks, property.as_mut());
+        },
+        _ => (),
+    }
+    callbacks.post_expr(expr);
+}
+
+/// Walk a function declaration or expression node.
+fn walk_fun<C: CallbacksMut, Id>(callbacks: &mut C, fun: &mut Fun<Id>) -> () {
+    callbacks.pre_fun(fun);
+    for param in &mut fun.params.list {
+        walk_patt(callbacks, param, walk_binding);
+    }
+    walk_stmt_items(callbacks, &mut fun.body.items);
+    callbacks.post_fun(fun);
+}
+
+/// Walk a pattern, calling `walk_target` on the identifiers or member expressions it assigns to.
+fn walk_patt<C: CallbacksMut, T>(callbacks: &mut C, patt: &mut Patt<T>, walk_target: fn(&mut C, &mut T)) -> () {
+    callbacks.pre_patt(patt);
+    match *patt {
+        Patt::Simple(ref mut target) => walk_target(callbacks, target),
+        Patt::Compound(ref mut compound) => walk_compound_patt(callbacks, compound, walk_target),
+    }
+    callbacks.post_patt(patt);
+}
+
+/// Walk the elements of an array or object pattern.
+fn walk_compound_patt<C: CallbacksMut, T>(callbacks: &mut C, patt: &mut CompoundPatt<T>, walk_target: fn(&mut C, &mut T)) -> () {
+    match *patt {
+        CompoundPatt::Arr(_, ref mut elements) => {
+            for element in elements {
+                if let Some(ref mut element) = *element {
+                    walk_patt(callbacks, element, walk_target);
+                }
+            }
+        },
+        CompoundPatt::Obj(_, ref mut props) => {
+            for prop in props {
+                walk_patt(callbacks, &mut prop.patt, walk_target);
+            }
+        },
+    }
+}
+
+fn walk_binding<C: CallbacksMut>(_callbacks: &mut C, _id: &mut Id) -> () {}
+
+fn walk_assign_target<C: CallbacksMut>(callbacks: &mut C, target: &mut AssignTarget) -> () {
+    match *target {
+        AssignTarget::Id(_) => (),
+        AssignTarget::Dot(_, ref mut object, _) => walk_expr(callbacks, object.as_mut()),
+        AssignTarget::Brack(_, ref mut object, ref mut property) => {
+            walk_expr(callbacks, object.as_mut());
+            walk_expr(callbacks, property.as_mut());
+        },
+    }
+}
+
+fn walk_prop<C: CallbacksMut>(callbacks: &mut C, prop: &mut Prop) -> () {
+    match *prop {
+        Prop::Regular(_, _, ref mut val) => {
+            match *val {
+                PropVal::Init(ref mut value) => walk_expr(callbacks, value),
+                PropVal::Get(_, ref mut body) => walk_stmt_items(callbacks, &mut body.items),
+                PropVal::Set(_, ref mut param, ref mut body) => {
+                    walk_patt(callbacks, param, walk_binding);
+                    walk_stmt_items(callbacks, &mut body.items);
+                },
+            }
+        },
+        Prop::Method(ref mut fun) => walk_fun(callbacks, fun),
+        Prop::Shorthand(_) => (),
+    }
+}
@@ -0,0 +1,129 @@
+extern crate js_bundler;
+#[macro_use] extern crate quicli;
+
+use std::env;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use quicli::prelude::*;
+use js_bundler::config::{Config, CONFIG_FILE};
+use js_bundler::flat_pack::FlatPack;
+use js_bundler::pack::Pack;
+
+/// The entry point of a fixture that does not set one in its bundler.toml.
+pub const DEFAULT_ENTRY: &str = "./index.js";
+/// The file with the output that running a fixture's bundle must print.
+pub const EXPECTED_OUTPUT_FILE: &str = "expected-output.txt";
+/// The file with the bundle that a fixture built last time it was blessed.
+pub const SNAPSHOT_FILE: &str = "bundle.snap.js";
+
+/// A project under `tests/fixtures`. It is built with its own bundler.toml, if it has one.
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    pub name: String,
+    pub dir: PathBuf,
+}
+
+impl Fixture {
+    /// Build the bundle, like `js-bundler` would in the fixture directory.
+    pub fn build(&self) -> Result<String> {
+        let config_file = self.dir.join(CONFIG_FILE);
+        let mut config = if config_file.is_file() { Config::from_file(&config_file)? } else { Config::default() };
+        // Like on the command line, a bare entry is a file, not a package in node_modules.
+        let entry = config.entry.as_ref().map_or(DEFAULT_ENTRY.to_string(), |entry| to_relative(entry));
+        // The shims for builtin modules are found relative to the repository root, which the
+        // fixture is not.
+        config.no_builtins = true;
+        // Fixtures test a full build, and must not leave a cache directory behind.
+        config.no_cache = true;
+        let mut deps = config.to_deps_in(&self.dir)?;
+        deps.run(&entry)?;
+        if config.minimal_runtime {
+            return FlatPack::new(&deps).to_string();
+        }
+        Ok(Pack::new(&deps)
+            .with_registry(config.runtime_registry)
+            .with_injections(config.injections()?)
+            .with_polyfills(config.polyfills()?)
+            .to_string())
+    }
+
+    /// Build the bundle, run it, and compare the results with the expected output and the
+    /// snapshot. With `bless`, the snapshot is written instead.
+    pub fn check(&self, bless: bool) -> Result<()> {
+        let bundle = self.build()?;
+        // A fixture that is only compared with its snapshot could pass with a broken bundle.
+        let output = run_node(&bundle)?
+            .ok_or_else(|| format_err!("node is not installed, so the bundle can not be run"))?;
+        compare(&self.dir.join(EXPECTED_OUTPUT_FILE), &output, false)?;
+        compare(&self.dir.join(SNAPSHOT_FILE), &bundle, bless)
+    }
+}
+
+/// List the fixtures in a directory, sorted by name.
+pub fn fixtures(root: &Path) -> Result<Vec<Fixture>> {
+    let mut fixtures = vec![];
+    for entry in fs::read_dir(root)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            fixtures.push(Fixture {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                dir: entry.path(),
+            });
+        }
+    }
+    fixtures.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(fixtures)
+}
+
+/// Whether snapshots should be updated, because `BLESS=1` is set.
+pub fn bless() -> bool {
+    env::var_os("BLESS").map_or(false, |value| !value.is_empty() && value != "0")
+}
+
+/// Run a bundle with node and return what it printed to stdout.
+/// Returns `None` if node is not installed.
+pub fn run_node(bundle: &str) -> Result<Option<String>> {
+    let mut child = match Command::new("node").stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    child.stdin.take().unwrap().write_all(bundle.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!("The bundle failed with {}:\n{}", output.status, String::from_utf8_lossy(&output.stderr));
+    }
+    Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+}
+
+/// Compare `actual` with the contents of a file. With `bless`, the file is written instead.
+fn compare(path: &Path, actual: &str, bless: bool) -> Result<()> {
+    if bless {
+        if fs::read_to_string(path).ok().as_ref().map(|expected| expected.as_str()) != Some(actual) {
+            fs::write(path, actual)?;
+        }
+        return Ok(());
+    }
+    let expected = match fs::read_to_string(path) {
+        Ok(expected) => expected,
+        Err(ref err) if err.kind() == ErrorKind::NotFound =>
+            bail!("{} does not exist. Run with BLESS=1 to create it.", path.to_string_lossy()),
+        Err(err) => return Err(err.into()),
+    };
+    if expected != actual {
+        bail!("{} does not match. Run with BLESS=1 to update it if the change is expected.\n--- expected\n{}\n--- actual\n{}",
+              path.to_string_lossy(), expected, actual);
+    }
+    Ok(())
+}
+
+/// Make a bare entry point relative, like `js-bundler init` does.
+fn to_relative(entry: &str) -> String {
+    if entry.starts_with('.') || entry.starts_with('/') {
+        entry.to_string()
+    } else {
+        format!("./{}", entry)
+    }
+}
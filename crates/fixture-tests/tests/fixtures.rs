@@ -0,0 +1,15 @@
+extern crate fixture_tests;
+
+use std::path::Path;
+use fixture_tests::{fixtures, bless};
+
+/// Builds and runs every project in tests/fixtures, and reports all failures at once.
+#[test]
+fn fixtures_build_and_run() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../tests/fixtures");
+    let bless = bless();
+    let failures: Vec<String> = fixtures(&root).unwrap().iter()
+        .filter_map(|fixture| fixture.check(bless).err().map(|err| format!("{}: {}", fixture.name, err)))
+        .collect();
+    assert!(failures.is_empty(), "{} fixtures failed:\n\n{}", failures.len(), failures.join("\n\n"));
+}
@@ -0,0 +1,278 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use estree_detect_requires::{DynamicCode, DynamicRequire, RequireRecord};
+use quicli::prelude::*;
+use serde_json;
+use sha1::{Sha1, Digest};
+use emit::write_atomic;
+use graph::{Hash, SourceFile};
+use loader::ParseLimits;
+
+/// The directory inside the cache directory that the build cache uses.
+const BUILD_DIR: &str = "build";
+/// The file that records which version of the bundler wrote the cache.
+const VERSION_FILE: &str = "version";
+/// The file in the directory of each group of settings that records when it was last used.
+const LAST_USED_FILE: &str = "last-used";
+/// The directory in the directory of each group of settings that lists the entries each build used.
+const USED_DIR: &str = "used";
+/// Entries that none of this many recent builds used are removed.
+const KEEP_BUILDS: usize = 20;
+/// Entries for settings that were not used for this many seconds are removed.
+const STALE_AFTER_SECS: u64 = 7 * 24 * 60 * 60;
+/// Changes when the format of cache entries changes.
+const FORMAT_VERSION: u32 = 3;
+
+/// Loaded files from earlier builds, stored on disk: their source after transforms and the
+/// dependencies that were detected in it.
+///
+/// Entries are keyed by the path and contents of a file and by the settings that change how
+/// files are loaded, so changing either one misses the cache. Entries do not contain the syntax
+/// tree, so features that need it for every module can not use the cache. The cache can be read
+/// from any thread.
+///
+/// Each group of settings has its own directory. `prune` removes the entries that none of the
+/// recent builds used, and opening the cache removes the directories of settings that were not
+/// used for a week, so the cache does not keep growing. Builds of different entry points can
+/// share a cache directory.
+#[derive(Debug, Clone)]
+pub struct BuildCache {
+    /// The directory for the current settings, named after a hash of the bundler version and
+    /// the settings.
+    dir: PathBuf,
+    /// The entries that were read or written since the cache was opened.
+    used: Arc<Mutex<HashSet<CacheKey>>>,
+    /// When the cache was opened. Entries written after this may belong to another build that
+    /// runs at the same time.
+    opened: SystemTime,
+}
+
+/// Where a file is stored in the build cache.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey(String);
+
+#[derive(Serialize, Deserialize)]
+struct Entry<'a> {
+    source: Cow<'a, str>,
+    hash: Vec<u8>,
+    dependencies: Vec<String>,
+    dynamic_dependencies: Vec<DynamicRequire>,
+    optional_dependencies: Vec<String>,
+    dynamic_code: Vec<DynamicCode>,
+    unanalyzable_requires: Vec<RequireRecord>,
+}
+
+impl BuildCache {
+    /// Open the build cache in `cache_dir`. `settings` describes everything besides the file
+    /// itself that changes how it is loaded, like transforms and known constants. The entries
+    /// are removed if a different version of the bundler wrote them, and so are the entries
+    /// for settings that were not used for a week.
+    pub fn open(cache_dir: &Path, settings: &str) -> Result<BuildCache> {
+        let build_dir = cache_dir.join(BUILD_DIR);
+        let version = format!("{} {}", env!("CARGO_PKG_VERSION"), FORMAT_VERSION);
+        let version_file = build_dir.join(VERSION_FILE);
+        if fs::read_to_string(&version_file).ok().as_ref() != Some(&version) {
+            if build_dir.exists() {
+                fs::remove_dir_all(&build_dir)?;
+            }
+            fs::create_dir_all(&build_dir)?;
+            fs::write(&version_file, &version)?;
+        }
+        remove_stale_settings(&build_dir)?;
+        let dir = build_dir.join(format!("{:x}", Sha1::digest_str(&format!("{}\n{}", version, settings))));
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(LAST_USED_FILE), "")?;
+        Ok(BuildCache {
+            dir,
+            used: Arc::new(Mutex::new(HashSet::new())),
+            opened: SystemTime::now(),
+        })
+    }
+
+    /// Get the key of a file with this source code. Files that were parsed with different
+    /// limits get different keys, because a file that is cached did not exceed the limits.
+    pub fn key(&self, path: &Path, source: &str, limits: &ParseLimits) -> CacheKey {
+        let key = Sha1::digest_str(&format!("{:?}\0{}\0{}", limits, path.to_string_lossy(), source));
+        CacheKey(format!("{:x}", key))
+    }
+
+    /// Get a file from the cache. Entries that can not be read count as missing.
+    pub fn get(&self, key: &CacheKey, path: &Path) -> Option<SourceFile> {
+        self.used.lock().unwrap().insert(key.clone());
+        let entry = fs::read_to_string(self.entry_path(key)).ok()?;
+        let entry: Entry = serde_json::from_str(&entry).ok()?;
+        if entry.hash.len() != Hash::default().len() {
+            return None;
+        }
+        Some(SourceFile::CJS {
+            path: path.to_path_buf(),
+            source: Arc::from(entry.source.into_owned()),
+            hash: Hash::clone_from_slice(&entry.hash),
+            ast: None,
+            dependencies: entry.dependencies,
+            dynamic_dependencies: entry.dynamic_dependencies,
+            optional_dependencies: entry.optional_dependencies,
+            dynamic_code: entry.dynamic_code,
+            unanalyzable_requires: entry.unanalyzable_requires,
+        })
+    }
+
+    /// Store a loaded file. Only CommonJS files are stored; other files are loaded as CommonJS
+    /// by the builtin transforms.
+    pub fn put(&self, key: &CacheKey, file: &SourceFile) -> Result<()> {
+        let entry = match *file {
+            SourceFile::CJS { ref source, ref hash, ref dependencies, ref dynamic_dependencies, ref optional_dependencies, ref dynamic_code, ref unanalyzable_requires, .. } => Entry {
+                source: Cow::Borrowed(&**source),
+                hash: hash.to_vec(),
+                dependencies: dependencies.clone(),
+                dynamic_dependencies: dynamic_dependencies.clone(),
+                optional_dependencies: optional_dependencies.clone(),
+                dynamic_code: dynamic_code.clone(),
+                unanalyzable_requires: unanalyzable_requires.clone(),
+            },
+            SourceFile::JSON { .. } => return Ok(()),
+        };
+        self.used.lock().unwrap().insert(key.clone());
+        write_atomic(&self.entry_path(key), serde_json::to_string(&entry)?.as_bytes())
+    }
+
+    /// Record the entries that were used since the cache was opened, and remove the entries for
+    /// the current settings that none of the last 20 builds used. Call this after a complete
+    /// build: the entries that no recent build used are for files that changed or are no longer
+    /// part of any bundle.
+    pub fn prune(&self) -> Result<()> {
+        let used_dir = self.dir.join(USED_DIR);
+        fs::create_dir_all(&used_dir)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let list = self.used.lock().unwrap().iter().map(|key| key.0.clone()).collect::<Vec<String>>().join("\n");
+        // Named so that sorting the lists sorts them by time.
+        write_atomic(&used_dir.join(format!("{:020}{:09}-{}", now.as_secs(), now.subsec_nanos(), process::id())), list.as_bytes())?;
+
+        let mut lists = fs::read_dir(&used_dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<::std::result::Result<Vec<PathBuf>, _>>()?;
+        lists.retain(|path| !path.file_name().map_or(true, |name| name.to_string_lossy().starts_with('.')));
+        lists.sort();
+        let old_lists = lists.len().saturating_sub(KEEP_BUILDS);
+        for path in &lists[..old_lists] {
+            fs::remove_file(path)?;
+        }
+        let mut recently_used = HashSet::new();
+        for path in &lists[old_lists..] {
+            // Another build may have removed it already.
+            if let Ok(list) = fs::read_to_string(path) {
+                recently_used.extend(list.lines().map(|key| key.to_string()));
+            }
+        }
+
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let is_entry = path.extension().map_or(false, |ext| ext == "json");
+            let is_unused = path.file_stem().map_or(false, |stem| !recently_used.contains(&*stem.to_string_lossy()));
+            let is_old = fs::metadata(&path).and_then(|metadata| metadata.modified()).map_or(false, |modified| modified < self.opened);
+            if is_entry && is_unused && is_old {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn entry_path(&self, key: &CacheKey) -> PathBuf {
+        self.dir.join(format!("{}.json", key.0))
+    }
+}
+
+/// Remove the directories of settings that were not used for a while.
+fn remove_stale_settings(build_dir: &Path) -> Result<()> {
+    let now = SystemTime::now();
+    for entry in fs::read_dir(build_dir)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let last_used = fs::metadata(path.join(LAST_USED_FILE)).and_then(|metadata| metadata.modified()).ok();
+        let is_stale = last_used.map_or(true, |last_used| now.duration_since(last_used).map_or(false, |age| age.as_secs() > STALE_AFTER_SECS));
+        if is_stale {
+            fs::remove_dir_all(&path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use graph::SourceFile;
+    use loader::{ParseLimits, parse_cjs};
+    use super::{BuildCache, VERSION_FILE, BUILD_DIR, KEEP_BUILDS};
+
+    #[test]
+    fn stores_files_by_contents_and_settings() {
+        let dir = env::temp_dir().join(format!("js-bundler-cache-{}", ::std::process::id()));
+        let path = Path::new("/a.js");
+        let source = "require('./b'); require(name)";
+        let limits = ParseLimits::default();
+        let cache = BuildCache::open(&dir, "strip-console").unwrap();
+        let key = cache.key(path, source, &limits);
+        assert!(cache.get(&key, path).is_none());
+        let file = parse_cjs(path.to_path_buf(), source.to_string()).unwrap();
+        cache.put(&key, &file).unwrap();
+        match cache.get(&key, path) {
+            Some(SourceFile::CJS { ref source, ref hash, ast: None, ref dependencies, ref unanalyzable_requires, .. }) => {
+                assert_eq!(&**source, "require('./b'); require(name)");
+                assert_eq!(hash, file.hash());
+                assert_eq!(dependencies, &vec!["./b".to_string()]);
+                assert_eq!(unanalyzable_requires.len(), 1);
+            },
+            _ => panic!("expected a cached CommonJS file"),
+        }
+
+        assert_ne!(cache.key(path, "require('./c')", &limits), key);
+        assert_ne!(cache.key(path, source, &ParseLimits { max_nodes: Some(100), ..limits }), key);
+        assert!(BuildCache::open(&dir, "").unwrap().get(&key, path).is_none());
+
+        // Entries from another version of the bundler are thrown away.
+        fs::write(dir.join(BUILD_DIR).join(VERSION_FILE), "0.0.0 0").unwrap();
+        let cache = BuildCache::open(&dir, "strip-console").unwrap();
+        assert!(cache.get(&key, path).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_entries_of_other_entry_points() {
+        let dir = env::temp_dir().join(format!("js-bundler-cache-entries-{}", ::std::process::id()));
+        let limits = ParseLimits::default();
+        let (app, admin) = (Path::new("/app.js"), Path::new("/admin.js"));
+        let cache = BuildCache::open(&dir, "").unwrap();
+        let (key_app, key_admin) = (cache.key(app, "1", &limits), cache.key(admin, "2", &limits));
+        cache.put(&key_app, &parse_cjs(app.to_path_buf(), "1".to_string()).unwrap()).unwrap();
+        cache.prune().unwrap();
+
+        // Builds of app.js and admin.js take turns, with the same settings.
+        let cache = BuildCache::open(&dir, "").unwrap();
+        cache.put(&key_admin, &parse_cjs(admin.to_path_buf(), "2".to_string()).unwrap()).unwrap();
+        cache.prune().unwrap();
+        let cache = BuildCache::open(&dir, "").unwrap();
+        assert!(cache.get(&key_app, app).is_some());
+        cache.prune().unwrap();
+        assert!(BuildCache::open(&dir, "").unwrap().get(&key_admin, admin).is_some());
+
+        // Entries that no recent build used are removed.
+        for _ in 0..KEEP_BUILDS {
+            let cache = BuildCache::open(&dir, "").unwrap();
+            cache.get(&key_app, app);
+            cache.prune().unwrap();
+        }
+        let cache = BuildCache::open(&dir, "").unwrap();
+        assert!(cache.get(&key_app, app).is_some());
+        assert!(cache.get(&key_admin, admin).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
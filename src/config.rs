@@ -0,0 +1,888 @@
+use std::collections::{BTreeMap, HashMap};
+use std::cmp::min;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+use glob::Pattern;
+use estree_detect_requires::{Constant, Constants};
+use quicli::prelude::*;
+use serde::Serialize;
+use serde_json;
+use toml;
+use cache::BuildCache;
+use deps::{Deps, Limits};
+use diagnostics::Severity;
+use emit::{Emitter, EmitTarget, OutputDirs, CopyRule};
+use env::Env;
+use error::BundleError;
+use inject::Injection;
+use loader::{ParseLimits, Transform};
+use meta::{BuildMeta, META_MODULE};
+use path::{CaseSensitivity, NormalizedPath, is_inside};
+use remote::{RemoteModules, CACHE_DIR};
+use roots::{RootPrefix, TSCONFIG_FILE, tsconfig_roots};
+use rules::{Rule, Rules};
+use watch::{WatchIgnore, WatchOptions, Polling, DEFAULT_POLL_INTERVAL_MS};
+use package::{read_package_json, dependency_names};
+use polyfill::Polyfill;
+use transforms::{InlineEnv, StripAsserts, StripConsole, StripDebugger};
+
+/// The name of the config file that is used if none is given.
+pub const CONFIG_FILE: &str = "bundler.toml";
+
+/// Options that were renamed or removed, by their dotted name like `rules.name`, with what to
+/// use instead. Config files that still use them are reported, instead of ignoring the option.
+const DEPRECATED_OPTIONS: &[(&str, &str)] = &[];
+
+/// Bundler options, usually read from a bundler.toml file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    /// The entry point of the bundle.
+    pub entry: Option<String>,
+    /// Where to write the bundle. The bundle is written to stdout if this is not set.
+    pub outfile: Option<PathBuf>,
+    /// Write the bundle, copied files and a manifest to this directory, instead of to `outfile`.
+    pub outdir: Option<PathBuf>,
+    /// Remove files from `outdir` that the previous build wrote, but this one did not.
+    pub clean: bool,
+    /// The URL that `outdir` is served at, used for the URLs in the manifest. Defaults to `/`.
+    pub public_path: Option<String>,
+    /// Where to put the output files: `disk` writes them to `outdir`, `memory` keeps them in
+    /// memory for embedders, and the command line only prints what it would write.
+    pub emit: EmitTarget,
+    /// List every bundled module with its package, version and license in the manifest.
+    pub licenses: bool,
+    /// Copy the source of every bundled module, after transforms, to this directory for review.
+    pub emit_sources: Option<PathBuf>,
+    /// Exclude shims for builtin modules.
+    pub no_builtins: bool,
+    /// Specifier prefixes that refer to project directories, like `"~/" = "src"`, so
+    /// `require('~/components/button')` loads `src/components/button.js` from any file.
+    pub roots: BTreeMap<String, PathBuf>,
+    /// Also read root prefixes from the `paths` in ./tsconfig.json, like `"~/*": ["src/*"]`.
+    pub tsconfig_paths: bool,
+    /// Packages to keep as require() calls instead of bundling them.
+    pub external: Vec<String>,
+    /// Keep the peerDependencies from ./package.json external. With `outdir`, also write the
+    /// package.json and its TypeScript declaration files to it, pointing at the bundle.
+    pub library: bool,
+    /// In library mode, also keep the dependencies from ./package.json external.
+    pub external_dependencies: bool,
+    /// Packages to bundle even if library mode would keep them external.
+    pub no_external: Vec<String>,
+    /// Directories where partially dynamic require() calls are expanded to all matching files.
+    pub context_requires: Vec<PathBuf>,
+    /// Allow bundling modules from `https:` URLs. They are downloaded into the cache directory.
+    pub allow_https: bool,
+    /// Only use downloaded modules from the cache, and fail if one is missing.
+    pub offline: bool,
+    /// Where to store `data:` and downloaded modules, and the build cache. Defaults to
+    /// `.js-bundler-cache`.
+    pub cache_dir: Option<PathBuf>,
+    /// Parse and transform every file again, instead of loading unchanged files from the build
+    /// cache.
+    pub no_cache: bool,
+    /// Bundle code that can not be trusted: only load and copy files inside this directory, and
+    /// refuse to use `https:` modules, post-build commands or environment variables. The cache
+    /// directory and `emit-sources` must be inside it too; the cache directory defaults to
+    /// `.js-bundler-cache` inside it.
+    pub sandbox: Option<PathBuf>,
+    /// Glob patterns for files that must not be bundled.
+    pub exclude: Vec<String>,
+    /// Glob patterns for files that `--watch` does not rebuild for.
+    pub watch_ignore: Vec<String>,
+    /// Also do not rebuild for files that .gitignore lists.
+    pub watch_gitignore: bool,
+    /// Check files for changes by polling, for file systems where native watching does not work.
+    pub watch_poll: bool,
+    /// How often to poll, in milliseconds. Setting it enables polling.
+    pub watch_poll_interval: Option<u64>,
+    /// Glob patterns for the files to poll. Defaults to all files.
+    pub watch_poll_paths: Vec<String>,
+    /// Remove assertions from the bundle.
+    pub strip_asserts: bool,
+    /// Remove `console.*()` calls from the bundle.
+    pub strip_console: bool,
+    /// `console` methods to keep when using `strip-console`, eg. `["error", "warn"]`.
+    pub keep_console: Vec<String>,
+    /// Remove `debugger` statements from the bundle.
+    pub strip_debugger: bool,
+    /// Identifiers to declare in every module that uses them, bound to a module or one of its
+    /// exports, eg. `React = "react"` or `h = "preact#h"`.
+    pub inject: BTreeMap<String, String>,
+    /// Modules that define a global for older browsers, required by every module that uses
+    /// the global, eg. `fetch = "whatwg-fetch"`. Bundles that do not use the global do not
+    /// include the polyfill.
+    pub polyfills: BTreeMap<String, String>,
+    /// Pack modules without a module runtime. Only works for bundles with a single entry point,
+    /// no circular dependencies and no dynamic require() calls.
+    pub minimal_runtime: bool,
+    /// Expose a `__bundle` global that can add or replace modules at runtime.
+    pub runtime_registry: bool,
+    /// Shell commands to run after a successful build. They receive a JSON build report on stdin.
+    pub post_build: Vec<String>,
+    /// Shell commands to run after each successful build in watch mode. They receive the build
+    /// report on stdin and in `JS_BUNDLER_*` environment variables.
+    pub on_rebuild: Vec<String>,
+    /// What to do when a module uses `eval`, `Function` or `with`: `ignore`, `warn` or `error`.
+    pub dynamic_code: Severity,
+    /// What to do when a require() argument is not known at build time, like `require(name)`:
+    /// `ignore`, `warn` or `error`.
+    pub unanalyzable_requires: Severity,
+    /// Maximum number of modules in the bundle.
+    pub max_modules: Option<u32>,
+    /// Maximum size of a source file, in bytes.
+    pub max_file_size: Option<u64>,
+    /// Maximum length of a chain of require() calls.
+    pub max_depth: Option<usize>,
+    /// Skip files that nest parentheses, brackets and braces deeper than this.
+    pub max_nesting: Option<usize>,
+    /// Skip files with more syntax nodes than this.
+    pub max_ast_nodes: Option<usize>,
+    /// Skip files that take longer than this many milliseconds to parse.
+    pub max_parse_time: Option<u64>,
+    /// The build mode, exported by the `bundler:meta` module. Defaults to `production`.
+    pub mode: Option<String>,
+    /// Inline `process.env.NAME` for environment variables starting with this prefix, eg. `APP_`.
+    /// The variables are read from .env files and from the environment.
+    pub env_prefix: Option<String>,
+    /// Maximum number of bytes of source code that a package may add to the bundle.
+    pub package_budgets: BTreeMap<String, u64>,
+    /// Global identifiers with a known value when the bundle runs, eg. `__DEV__ = false`.
+    /// require() calls in branches that never run because of them are not bundled.
+    /// The bundle does not define them.
+    pub constants: BTreeMap<String, toml::Value>,
+    /// Constants exported by the `bundler:meta` module.
+    pub meta: BTreeMap<String, toml::Value>,
+    /// Where to put each kind of file in `outdir`.
+    pub output_dirs: OutputDirs,
+    /// Files to copy to `outdir` as they are.
+    pub copy: Vec<CopyRule>,
+    /// How to load and transform files, by path.
+    pub rules: Vec<Rule>,
+}
+
+impl Config {
+    /// Read options from a config file. Fails if the file has unknown keys or invalid values.
+    pub fn from_file(path: &Path) -> Result<Config> {
+        let source = read_file(path)?;
+        let issues = Config::check(&source);
+        if !issues.is_empty() {
+            let issues = issues.iter()
+                .map(|issue| issue.to_string_in(path))
+                .collect::<Vec<String>>()
+                .join("\n  ");
+            return Err(BundleError::config(format!("Invalid config file {}:\n  {}", path.to_string_lossy(), issues)).into());
+        }
+        Ok(toml::from_str(&source)?)
+    }
+
+    /// Check the source of a config file: whether it is valid TOML, whether every key is a
+    /// known option that is not deprecated, also in `[[rules]]`, `[[copy]]` and
+    /// `[output-dirs]`, and whether the values have the right types.
+    pub fn check(source: &str) -> Vec<ConfigIssue> {
+        check_with_deprecated(source, DEPRECATED_OPTIONS)
+    }
+
+    /// Format the options as TOML, including the defaults.
+    pub fn to_toml(&self) -> Result<String> {
+        // Go through a toml::Value, which puts tables after the other values, like TOML needs.
+        Ok(toml::to_string(&toml::Value::try_from(self)?)?)
+    }
+
+    /// Read options from ./bundler.toml if it exists, or use the defaults.
+    pub fn from_default_file() -> Result<Config> {
+        let path = Path::new(CONFIG_FILE);
+        if path.is_file() {
+            Config::from_file(path)
+        } else {
+            Ok(Config::default())
+        }
+    }
+
+    /// Create a dependency tree builder that uses these options, for a build in the current
+    /// directory.
+    pub fn to_deps(&self) -> Result<Deps> {
+        self.to_deps_in(Path::new("."))
+    }
+
+    /// Create a dependency tree builder for a build in `basedir`. The entry point, package.json,
+    /// tsconfig.json, `roots` and .env files are relative to it, so builds in different
+    /// directories can run in one process.
+    pub fn to_deps_in(&self, basedir: &Path) -> Result<Deps> {
+        self.check_sandbox()?;
+        let mut externals = self.external.clone();
+        if self.library {
+            let package = read_package_json(basedir)?;
+            externals.extend(dependency_names(&package, "peerDependencies"));
+            if self.external_dependencies {
+                externals.extend(dependency_names(&package, "dependencies"));
+            }
+        }
+        externals.retain(|name| !self.no_external.contains(name));
+
+        let excludes = self.exclude.iter()
+            .map(|pattern| Pattern::new(pattern)
+                 .map_err(|err| BundleError::config(format!("Invalid exclude pattern {}: {}", pattern, err)).into()))
+            .collect::<Result<Vec<Pattern>>>()?;
+
+        let mut deps = Deps::new()
+            .with_basedir(basedir.to_path_buf())
+            .include_builtins(!self.no_builtins)
+            .with_builtins_path("./crates/node-core-shims".into())
+            .with_externals(externals)
+            .with_context_requires(self.context_requires.clone())
+            .with_excludes(excludes)
+            .with_remote_modules(self.remote_modules())
+            .with_meta(self.build_meta()?)
+            .with_dynamic_code_severity(self.dynamic_code)
+            .with_unanalyzable_require_severity(self.unanalyzable_requires)
+            .with_injections(self.injections()?)
+            .with_polyfills(self.polyfills()?)
+            .with_roots(self.root_prefixes(basedir)?)
+            .with_rules(Rules::new(&self.rules, |name, options| self.make_transform(name, options, basedir))?)
+            .with_limits(Limits {
+                max_modules: self.max_modules,
+                max_file_size: self.max_file_size,
+                max_depth: self.max_depth,
+                parse: ParseLimits {
+                    max_nesting: self.max_nesting,
+                    max_nodes: self.max_ast_nodes,
+                    max_parse_time: self.max_parse_time,
+                },
+            });
+        if let Some(ref root) = self.sandbox {
+            deps = deps.with_sandbox_root(root.clone());
+        }
+        if let Some(cache) = self.build_cache(basedir)? {
+            deps = deps.with_cache(cache);
+        }
+        for (package, max_bytes) in &self.package_budgets {
+            deps = deps.with_package_budget(package, *max_bytes);
+        }
+        if self.strip_asserts {
+            deps = deps.with_transform(StripAsserts);
+        }
+        if self.strip_console {
+            deps = deps.with_transform(StripConsole::new(self.keep_console.clone()));
+        }
+        if self.strip_debugger {
+            deps = deps.with_transform(StripDebugger);
+        }
+        let mut constants = self.detection_constants()?;
+        if let Some(env) = self.load_env(basedir)? {
+            // The inlined variables are known too.
+            for (name, value) in &env.vars {
+                constants.insert(format!("process.env.{}", name), Constant::String(value.clone()));
+            }
+            deps = deps.with_transform(InlineEnv::new(env.vars));
+        }
+        Ok(deps.with_constants(constants))
+    }
+
+    /// Create an emitter for `outdir`, if it is set or if the output is kept in memory.
+    pub fn to_emitter(&self) -> Result<Option<Emitter>> {
+        let outdir = match (&self.outdir, self.emit) {
+            (&Some(ref outdir), _) => outdir.clone(),
+            (&None, EmitTarget::Memory) => PathBuf::from("."),
+            (&None, EmitTarget::Disk) if self.licenses =>
+                return Err(BundleError::config("`licenses` requires `outdir`, because they are listed in its manifest").into()),
+            (&None, EmitTarget::Disk) => return Ok(None),
+        };
+        if self.outfile.is_some() {
+            let message = match self.emit {
+                EmitTarget::Disk => "`outfile` and `outdir` can not be used together",
+                EmitTarget::Memory => "`outfile` can not be used with `emit = \"memory\"`",
+            };
+            return Err(BundleError::config(message).into());
+        }
+        let mut emitter = Emitter::new(outdir)
+            .with_dirs(self.output_dirs.clone())
+            .with_public_path(self.public_path.clone().unwrap_or_else(|| "/".to_string()))
+            .with_copy_rules(self.copy.clone())
+            .with_clean(self.clean)
+            .with_target(self.emit);
+        if self.library {
+            emitter = emitter.with_library_package(PathBuf::from("."));
+        }
+        match self.sandbox {
+            Some(ref root) => Ok(Some(emitter.with_sandbox_root(root.clone()))),
+            None => Ok(Some(emitter)),
+        }
+    }
+
+    /// The project root prefixes from `roots` and, with `tsconfig-paths`, from tsconfig.json.
+    /// Directories are relative to `basedir`.
+    pub fn root_prefixes(&self, basedir: &Path) -> Result<Vec<RootPrefix>> {
+        let basedir = basedir.canonicalize()?;
+        let mut roots = if self.tsconfig_paths {
+            tsconfig_roots(&basedir.join(TSCONFIG_FILE))?
+        } else {
+            vec![]
+        };
+        for (prefix, dir) in &self.roots {
+            roots.push(RootPrefix::new(prefix, basedir.join(dir))?);
+        }
+        Ok(roots)
+    }
+
+    /// The identifiers to inject into modules that use them.
+    pub fn injections(&self) -> Result<Vec<Injection>> {
+        self.inject.iter()
+            .map(|(name, value)| Injection::parse(name, value))
+            .collect()
+    }
+
+    /// The polyfills to require in modules that use their globals.
+    pub fn polyfills(&self) -> Result<Vec<Polyfill>> {
+        self.polyfills.iter()
+            .map(|(global, specifier)| Polyfill::new(global, specifier))
+            .collect()
+    }
+
+    /// Load the environment variables to inline, if `env-prefix` is set. The .env files are read
+    /// from `basedir`.
+    pub fn load_env(&self, basedir: &Path) -> Result<Option<Env>> {
+        match self.env_prefix {
+            Some(ref prefix) => Ok(Some(Env::load(basedir, self.mode(), prefix)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn mode(&self) -> &str {
+        self.mode.as_ref().map_or("production", |mode| mode.as_str())
+    }
+
+    fn build_meta(&self) -> Result<BuildMeta> {
+        let entry = self.entry.as_ref().map_or("", |entry| entry.as_str());
+        let mode = self.mode();
+        let target = if self.no_builtins { "node" } else { "browser" };
+        let constants = self.meta.iter()
+            .map(|(name, value)| Ok((name.clone(), serde_json::to_value(value)?)))
+            .collect::<Result<BTreeMap<String, serde_json::Value>>>()?;
+        BuildMeta::new(entry, mode, target)
+            .with_constants(constants)
+            .map_err(|name| BundleError::config(format!("Invalid [meta] constant {}: {} already exports a value with that name", name, META_MODULE)).into())
+    }
+
+    fn detection_constants(&self) -> Result<Constants> {
+        self.constants.iter()
+            .map(|(name, value)| {
+                let constant = match *value {
+                    toml::Value::Boolean(value) => Constant::Bool(value),
+                    toml::Value::Integer(value) => Constant::Number(value as f64),
+                    toml::Value::Float(value) => Constant::Number(value),
+                    toml::Value::String(ref value) => Constant::String(value.clone()),
+                    _ => return Err(BundleError::config(format!("Invalid constant {}: expected a boolean, number or string", name)).into()),
+                };
+                Ok((name.clone(), constant))
+            })
+            .collect()
+    }
+
+    fn check_sandbox(&self) -> Result<()> {
+        if self.sandbox.is_none() {
+            return Ok(());
+        }
+        if self.allow_https {
+            return Err(BundleError::config("https: modules can not be used in sandbox mode").into());
+        }
+        if !self.post_build.is_empty() {
+            return Err(BundleError::config("Post-build commands can not be used in sandbox mode").into());
+        }
+        if !self.on_rebuild.is_empty() {
+            return Err(BundleError::config("On-rebuild commands can not be used in sandbox mode").into());
+        }
+        if self.env_prefix.is_some() || inlines_env(&self.rules) {
+            return Err(BundleError::config("Environment variables can not be inlined in sandbox mode").into());
+        }
+        let root = self.sandbox.as_ref().unwrap();
+        if !root.is_dir() {
+            return Err(BundleError::config(format!("The sandbox root {} is not a directory", root.to_string_lossy())).into());
+        }
+        if let Some(ref dir) = self.emit_sources {
+            if !is_inside(dir, root)? {
+                return Err(BundleError::config("`emit-sources` must be inside the sandbox root").into());
+            }
+        }
+        if let Some(ref dir) = self.cache_dir {
+            if !is_inside(dir, root)? {
+                return Err(BundleError::config("`cache-dir` must be inside the sandbox root").into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a transform for a rule. Options in the rule override the top-level ones.
+    fn make_transform(&self, name: &str, options: &BTreeMap<String, toml::Value>, basedir: &Path) -> Result<Rc<Transform>> {
+        match name {
+            "strip-asserts" => Ok(Rc::new(StripAsserts)),
+            "strip-console" => {
+                let keep = match options.get("keep-console") {
+                    Some(value) => string_list(value)
+                        .ok_or_else(|| BundleError::config("The keep-console rule option must be a list of strings"))?,
+                    None => self.keep_console.clone(),
+                };
+                Ok(Rc::new(StripConsole::new(keep)))
+            },
+            "strip-debugger" => Ok(Rc::new(StripDebugger)),
+            "inline-env" => {
+                let prefix = match options.get("env-prefix") {
+                    Some(value) => value.as_str().map(|prefix| prefix.to_string())
+                        .ok_or_else(|| BundleError::config("The env-prefix rule option must be a string"))?,
+                    None => self.env_prefix.clone()
+                        .ok_or_else(|| BundleError::config("The inline-env transform needs an env-prefix"))?,
+                };
+                let env = Env::load(basedir, self.mode(), &prefix)?;
+                Ok(Rc::new(InlineEnv::new(env.vars)))
+            },
+            _ => Err(BundleError::config(format!("Unknown transform {} in rules. Expected strip-asserts, strip-console, strip-debugger or inline-env", name)).into()),
+        }
+    }
+
+    /// Files that `--watch` does not rebuild for. The output directory and the cache directory
+    /// are always ignored, because builds write to them.
+    pub fn watch_ignore(&self) -> Result<WatchIgnore> {
+        let mut patterns = self.watch_ignore.iter()
+            .map(|pattern| Pattern::new(pattern)
+                 .map_err(|err| BundleError::config(format!("Invalid watch-ignore pattern {}: {}", pattern, err)).into()))
+            .collect::<Result<Vec<Pattern>>>()?;
+        if let Some(ref outdir) = self.outdir {
+            let outdir = NormalizedPath::new(&Path::new(".").canonicalize()?.join(outdir), CaseSensitivity::platform_default());
+            patterns.push(Pattern::new(&format!("{}/**", Pattern::escape(outdir.as_str())))?);
+        }
+        let cache_dir = NormalizedPath::new(&Path::new(".").canonicalize()?.join(self.cache_dir()), CaseSensitivity::platform_default());
+        patterns.push(Pattern::new(&format!("{}/**", Pattern::escape(cache_dir.as_str())))?);
+        let ignore = WatchIgnore::new().with_patterns(patterns);
+        if self.watch_gitignore {
+            ignore.with_gitignore(Path::new(".gitignore"))
+        } else {
+            Ok(ignore)
+        }
+    }
+
+    /// How `--watch` finds out about changes, and which ones it ignores.
+    pub fn watch_options(&self) -> Result<WatchOptions> {
+        let options = WatchOptions::new().with_ignore(self.watch_ignore()?);
+        if !self.watch_poll && self.watch_poll_interval.is_none() {
+            if !self.watch_poll_paths.is_empty() {
+                return Err(BundleError::config("`watch-poll-paths` requires `watch-poll` or `watch-poll-interval`").into());
+            }
+            return Ok(options);
+        }
+        let interval = self.watch_poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL_MS);
+        if interval == 0 {
+            return Err(BundleError::config("`watch-poll-interval` must be at least 1 millisecond").into());
+        }
+        let paths = self.watch_poll_paths.iter()
+            .map(|pattern| Pattern::new(pattern)
+                 .map_err(|err| BundleError::config(format!("Invalid watch-poll-paths pattern {}: {}", pattern, err)).into()))
+            .collect::<Result<Vec<Pattern>>>()?;
+        Ok(options.with_polling(Polling::new(Duration::from_millis(interval)).with_paths(paths)))
+    }
+
+    /// The build cache, unless it is turned off. Cached files have no syntax tree, so it is
+    /// also off for the features that need the syntax tree of every module.
+    fn build_cache(&self, basedir: &Path) -> Result<Option<BuildCache>> {
+        if self.no_cache || self.minimal_runtime || !self.inject.is_empty() || !self.polyfills.is_empty() {
+            return Ok(None);
+        }
+        let settings = serde_json::to_string(&CacheSettings {
+            rules: &self.rules,
+            strip_asserts: self.strip_asserts,
+            strip_console: self.strip_console,
+            keep_console: &self.keep_console,
+            strip_debugger: self.strip_debugger,
+            constants: &self.constants,
+            env: self.inlined_env(basedir)?,
+        })?;
+        // The cache only makes builds faster, so a cache directory that can not be written to
+        // does not fail the build.
+        Ok(BuildCache::open(&self.cache_dir(), &settings).ok())
+    }
+
+    /// All environment variables that the top-level `env-prefix` and the rules can inline.
+    fn inlined_env(&self, basedir: &Path) -> Result<BTreeMap<String, String>> {
+        let mut prefixes = self.env_prefix.iter().cloned().collect::<Vec<String>>();
+        rule_env_prefixes(&self.rules, &mut prefixes);
+        let mut vars = BTreeMap::new();
+        for prefix in prefixes {
+            vars.extend(Env::load(basedir, self.mode(), &prefix)?.vars);
+        }
+        Ok(vars)
+    }
+
+    fn cache_dir(&self) -> PathBuf {
+        match (&self.cache_dir, &self.sandbox) {
+            (&Some(ref dir), _) => dir.clone(),
+            (&None, &Some(ref root)) => root.join(CACHE_DIR),
+            (&None, &None) => PathBuf::from(CACHE_DIR),
+        }
+    }
+
+    fn remote_modules(&self) -> RemoteModules {
+        RemoteModules::new(self.cache_dir())
+            .allow_https(self.allow_https)
+            .offline(self.offline)
+    }
+}
+
+/// The settings that change how files are loaded, which the build cache is keyed by.
+#[derive(Serialize)]
+struct CacheSettings<'a> {
+    rules: &'a [Rule],
+    strip_asserts: bool,
+    strip_console: bool,
+    keep_console: &'a [String],
+    strip_debugger: bool,
+    constants: &'a BTreeMap<String, toml::Value>,
+    env: BTreeMap<String, String>,
+}
+
+/// Whether any rule, or nested rule, uses the `inline-env` transform.
+fn inlines_env(rules: &[Rule]) -> bool {
+    rules.iter().any(|rule| rule.transforms.iter().any(|transform| transform == "inline-env") || inlines_env(&rule.one_of))
+}
+
+/// Collect the `env-prefix` options of rules and their nested rules.
+fn rule_env_prefixes(rules: &[Rule], prefixes: &mut Vec<String>) {
+    for rule in rules {
+        if let Some(prefix) = rule.options.get("env-prefix").and_then(|value| value.as_str()) {
+            prefixes.push(prefix.to_string());
+        }
+        rule_env_prefixes(&rule.one_of, prefixes);
+    }
+}
+
+fn check_with_deprecated(source: &str, deprecated: &[(&str, &str)]) -> Vec<ConfigIssue> {
+    let value = match source.parse::<toml::Value>() {
+        Ok(value) => value,
+        Err(err) => return vec![ConfigIssue::from_toml(&err)],
+    };
+    let mut check = KeyCheck { source, deprecated, tables: HashMap::new(), issues: vec![] };
+    if let Some(table) = value.as_table() {
+        check.check_config(table);
+    }
+    let mut issues = check.issues;
+    if let Err(err) = toml::from_str::<Config>(source) {
+        issues.push(ConfigIssue::from_toml(&err));
+    }
+    issues
+}
+
+/// A table in a config file whose keys are checked.
+struct Section<'a> {
+    /// The dotted name of the table, like `rules.one-of`.
+    name: &'a str,
+    /// The table header, like `[[rules]]`, for messages.
+    header: String,
+    /// The 0-based line of the table header, if the table has one.
+    line: Option<usize>,
+}
+
+/// Looks for unknown and deprecated keys in a config file and its nested tables.
+struct KeyCheck<'a> {
+    source: &'a str,
+    deprecated: &'a [(&'a str, &'a str)],
+    /// How many tables of each name were checked, to find the header of the next one.
+    tables: HashMap<String, usize>,
+    issues: Vec<ConfigIssue>,
+}
+
+impl<'a> KeyCheck<'a> {
+    fn check_config(&mut self, config: &toml::value::Table) {
+        self.check_keys(config, None, &keys_of(&Config::default()));
+        if let Some(rules) = config.get("rules").and_then(|rules| rules.as_array()) {
+            self.check_rules(rules, "rules");
+        }
+        if let Some(copy) = config.get("copy").and_then(|copy| copy.as_array()) {
+            let known = keys_of(&CopyRule { from: String::new(), to: None });
+            for rule in copy.iter().filter_map(|rule| rule.as_table()) {
+                let section = self.next_section("copy", true);
+                self.check_keys(rule, Some(&section), &known);
+            }
+        }
+        if let Some(dirs) = config.get("output-dirs").and_then(|dirs| dirs.as_table()) {
+            let section = self.next_section("output-dirs", false);
+            self.check_keys(dirs, Some(&section), &keys_of(&OutputDirs::default()));
+        }
+    }
+
+    fn check_rules(&mut self, rules: &[toml::Value], name: &str) {
+        let known = keys_of(&Rule::default());
+        let one_of = format!("{}.one-of", name);
+        for rule in rules.iter().filter_map(|rule| rule.as_table()) {
+            let section = self.next_section(name, true);
+            self.check_keys(rule, Some(&section), &known);
+            if let Some(rules) = rule.get("one-of").and_then(|rules| rules.as_array()) {
+                self.check_rules(rules, &one_of);
+            }
+        }
+    }
+
+    /// The next table called `name`. Tables are checked in the order they are in the file.
+    fn next_section<'n>(&mut self, name: &'n str, array: bool) -> Section<'n> {
+        let index = self.tables.entry(name.to_string()).or_insert(0);
+        let line = find_table(self.source, name, *index);
+        *index += 1;
+        let header = if array { format!("[[{}]]", name) } else { format!("[{}]", name) };
+        Section { name, header, line }
+    }
+
+    /// Check the keys of a table, or of the top level if `section` is `None`.
+    fn check_keys(&mut self, table: &toml::value::Table, section: Option<&Section>, known: &[String]) {
+        for key in table.keys() {
+            let (name, place) = match section {
+                Some(section) => (format!("{}.{}", section.name, key), format!(" in {}", section.header)),
+                None => (key.clone(), String::new()),
+            };
+            let message = match self.deprecated.iter().find(|&&(option, _)| option == name) {
+                Some(&(_, instead)) => format!("Deprecated option `{}`{}: {}", key, place, instead),
+                None if known.contains(key) => continue,
+                None => match closest_key(key, known) {
+                    Some(suggestion) => format!("Unknown option `{}`{}. Did you mean `{}`?", key, place, suggestion),
+                    None => format!("Unknown option `{}`{}", key, place),
+                },
+            };
+            let location = match section {
+                Some(&Section { line: Some(line), .. }) => find_key_in_table(self.source, line, key),
+                Some(_) => None,
+                None => find_key(self.source, key),
+            };
+            self.issues.push(ConfigIssue { message, location });
+        }
+    }
+}
+
+/// A problem in a config file, like an unknown key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigIssue {
+    pub message: String,
+    /// The 1-based line and column of the problem, if it is known.
+    pub location: Option<(usize, usize)>,
+}
+
+impl ConfigIssue {
+    fn from_toml(err: &toml::de::Error) -> ConfigIssue {
+        ConfigIssue {
+            message: err.to_string(),
+            location: err.line_col().map(|(line, column)| (line + 1, column + 1)),
+        }
+    }
+
+    /// Format the issue with the path of the config file, like `bundler.toml:3:1: message`.
+    pub fn to_string_in(&self, path: &Path) -> String {
+        match self.location {
+            Some((line, column)) => format!("{}:{}:{}: {}", path.to_string_lossy(), line, column, self.message),
+            None => format!("{}: {}", path.to_string_lossy(), self.message),
+        }
+    }
+}
+
+/// The keys of a config table, from the fields of its default value.
+fn keys_of<T: Serialize>(value: &T) -> Vec<String> {
+    serde_json::to_value(value).ok()
+        .and_then(|value| value.as_object().map(|object| object.keys().cloned().collect()))
+        .unwrap_or_default()
+}
+
+/// Suggest the known key that is closest to a misspelled one, if there is one that is close.
+fn closest_key<'a>(key: &str, known: &'a [String]) -> Option<&'a String> {
+    known.iter()
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|&(distance, _)| distance <= 2)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The number of characters to insert, remove or change to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &b_char) in b.iter().enumerate() {
+            let change = previous[j] + if a_char == b_char { 0 } else { 1 };
+            current.push(min(change, min(previous[j + 1], current[j]) + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Find the 1-based line and column where a top-level key is set, like `key = value` before
+/// the first table, or a `[key]`, `[key.name]` or `[[key]]` table header.
+fn find_key(source: &str, key: &str) -> Option<(usize, usize)> {
+    let mut in_table = false;
+    for (index, line) in source.lines().enumerate() {
+        let trimmed = line.trim_left();
+        let column = line.len() - trimmed.len() + 1;
+        if trimmed.starts_with('[') {
+            in_table = true;
+            let name = trimmed.trim_left_matches('[').trim_left();
+            if name.starts_with(key) && name[key.len()..].trim_left().starts_with(|c: char| c == ']' || c == '.') {
+                return Some((index + 1, column));
+            }
+        } else if !in_table && trimmed.starts_with(key) && trimmed[key.len()..].trim_left().starts_with('=') {
+            return Some((index + 1, column));
+        }
+    }
+    None
+}
+
+/// Find the 0-based line of the `index`th `[name]` or `[[name]]` table header.
+fn find_table(source: &str, name: &str, index: usize) -> Option<usize> {
+    source.lines().enumerate()
+        .filter(|&(_, line)| {
+            let trimmed = line.trim_left();
+            trimmed.starts_with('[') && trimmed.trim_left_matches('[').split(']').next().map(|header| header.trim()) == Some(name)
+        })
+        .nth(index)
+        .map(|(line, _)| line)
+}
+
+/// Find the 1-based line and column where a key is set in the table whose header is on the
+/// 0-based line `header`.
+fn find_key_in_table(source: &str, header: usize, key: &str) -> Option<(usize, usize)> {
+    for (index, line) in source.lines().enumerate().skip(header + 1) {
+        let trimmed = line.trim_left();
+        if trimmed.starts_with('[') {
+            break;
+        }
+        if trimmed.starts_with(key) && trimmed[key.len()..].trim_left().starts_with('=') {
+            return Some((index + 1, line.len() - trimmed.len() + 1));
+        }
+    }
+    None
+}
+
+fn string_list(value: &toml::Value) -> Option<Vec<String>> {
+    value.as_array()?.iter()
+        .map(|item| item.as_str().map(|item| item.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use rules::Rule;
+    use super::{Config, ConfigIssue, check_with_deprecated};
+
+    #[test]
+    fn reports_unknown_keys() {
+        let issues = Config::check("entry = \"./index.js\"\n  outfle = \"bundle.js\"\n\n[[rulez]]\ntest = \"*.js\"\n");
+        assert_eq!(issues, vec![
+            ConfigIssue { message: "Unknown option `outfle`. Did you mean `outfile`?".to_string(), location: Some((2, 3)) },
+            ConfigIssue { message: "Unknown option `rulez`. Did you mean `rules`?".to_string(), location: Some((4, 1)) },
+        ]);
+    }
+
+    #[test]
+    fn reports_unknown_keys_in_tables() {
+        let issues = Config::check("\
+entry = \"./index.js\"
+
+[output-dirs]
+js = \"js\"
+cs = \"css\"
+
+[[rules]]
+test = \"*.json\"
+
+[[rules]]
+tset = \"*.js\"
+
+[[rules.one-of]]
+transforms = [\"strip-console\"]
+  exclud = [\"vendor/**\"]
+
+[[copy]]
+form = \"static\"
+");
+        assert_eq!(issues, vec![
+            ConfigIssue { message: "Unknown option `tset` in [[rules]]. Did you mean `test`?".to_string(), location: Some((11, 1)) },
+            ConfigIssue { message: "Unknown option `exclud` in [[rules.one-of]]. Did you mean `exclude`?".to_string(), location: Some((15, 3)) },
+            ConfigIssue { message: "Unknown option `form` in [[copy]]. Did you mean `from`?".to_string(), location: Some((18, 1)) },
+            ConfigIssue { message: "Unknown option `cs` in [output-dirs]. Did you mean `css`?".to_string(), location: Some((5, 1)) },
+        ]);
+    }
+
+    #[test]
+    fn reports_deprecated_options() {
+        let deprecated = &[("strip", "use `strip-asserts` instead"), ("rules.loader", "use `type` instead")];
+        let issues = check_with_deprecated("strip = true\n\n[[rules]]\ntest = \"*.txt\"\nloader = \"text\"\n", deprecated);
+        assert_eq!(issues, vec![
+            ConfigIssue { message: "Deprecated option `strip`: use `strip-asserts` instead".to_string(), location: Some((1, 1)) },
+            ConfigIssue { message: "Deprecated option `loader` in [[rules]]: use `type` instead".to_string(), location: Some((5, 1)) },
+        ]);
+    }
+
+    #[test]
+    fn reports_type_mismatches() {
+        let issues = Config::check("entry = \"./index.js\"\nminimal-runtime = \"yes\"\n");
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message.contains("minimal-runtime"));
+    }
+
+    #[test]
+    fn accepts_valid_config() {
+        assert!(Config::check("entry = \"./index.js\"\nexternal = [\"react\"]\n\n[inject]\nh = \"preact#h\"\n").is_empty());
+    }
+
+    #[test]
+    fn prints_config_as_toml() {
+        let mut config = Config::default();
+        config.entry = Some("./index.js".to_string());
+        config.inject.insert("h".to_string(), "preact#h".to_string());
+        let printed = config.to_toml().unwrap();
+        assert!(printed.starts_with("allow-https = false\n"));
+        assert!(printed.contains("entry = \"./index.js\"\n"));
+        assert!(printed.contains("\n[inject]\nh = \"preact#h\"\n"));
+        assert!(Config::check(&printed).is_empty());
+    }
+
+    #[test]
+    fn reads_project_files_from_the_basedir() {
+        let dir = env::temp_dir().join(format!("js-bundler-basedir-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("package.json"), r#"{ "peerDependencies": { "react": "*" } }"#).unwrap();
+        fs::write(dir.join("tsconfig.json"), r#"{ "compilerOptions": { "paths": { "~/*": ["src/*"] } } }"#).unwrap();
+        fs::write(dir.join(".env"), "BASEDIR_TEST_NAME=app\n").unwrap();
+        let config = Config {
+            library: true,
+            tsconfig_paths: true,
+            env_prefix: Some("BASEDIR_TEST_".to_string()),
+            no_cache: true,
+            ..Config::default()
+        };
+        let src = dir.canonicalize().unwrap().join("src");
+        let deps = config.to_deps_in(&dir);
+        let roots = config.root_prefixes(&dir).unwrap();
+        let env = config.load_env(&dir).unwrap().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        // The working directory has no package.json, so this only works if it is read from `dir`.
+        assert!(deps.is_ok());
+        assert_eq!((roots[0].prefix.as_str(), &roots[0].dir), ("~/", &src));
+        assert_eq!(env.vars.get("BASEDIR_TEST_NAME").map(|value| value.as_str()), Some("app"));
+    }
+
+    #[test]
+    fn confines_sandboxed_builds() {
+        let root = env::temp_dir().join(format!("js-bundler-sandbox-{}", ::std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let sandboxed = || Config { sandbox: Some(root.clone()), ..Config::default() };
+        assert!(sandboxed().to_deps().is_ok());
+        assert!(root.join(".js-bundler-cache").is_dir());
+
+        assert!(Config { env_prefix: Some(String::new()), ..sandboxed() }.to_deps().is_err());
+        let rule = Rule { transforms: vec!["inline-env".to_string()], ..Rule::default() };
+        assert!(Config { rules: vec![rule], ..sandboxed() }.to_deps().is_err());
+
+        assert!(Config { emit_sources: Some(root.join("review")), ..sandboxed() }.to_deps().is_ok());
+        assert!(Config { emit_sources: Some(root.join("../review")), ..sandboxed() }.to_deps().is_err());
+        assert!(Config { cache_dir: Some(env::temp_dir()), ..sandboxed() }.to_deps().is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
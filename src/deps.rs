@@ -1,21 +1,85 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::Arc;
 use quicli::prelude::*; // TODO use `failure`?
+use glob::Pattern;
+use serde_json;
 use node_resolve::Resolver;
+use estree_detect_requires::{Constants, DynamicCodeKind, DynamicRequire};
 use builtins::{Builtins, NodeBuiltins, NoBuiltins};
+use cache::BuildCache;
+use diagnostics::Severity;
+use error::BundleError;
 use graph::{ModuleMap, Dependency, Dependencies, SourceFile, ModuleRecord};
-use loader::LoadFile;
+use inject::{Injection, used_injections};
+use polyfill::{Polyfill, PolyfillUse, used_polyfills};
+use loader::{LoadFile, LimitExceeded, ParseLimits, Parser, Transform, parse_cjs};
+use package::{package_name, package_from_path};
+use path::{NormalizedPath, CaseSensitivity};
+use prefetch::Prefetcher;
+use rules::Rules;
+use meta::{BuildMeta, META_MODULE};
+use pipeline::{Pipeline, Hook, Stage};
+use remote::{RemoteModules, CACHE_DIR, is_remote, strip_node_prefix};
+use roots::{RootPrefix, resolve_root};
+
+/// Safety limits for the size of the dependency tree.
+/// `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    /// Maximum number of modules in the tree.
+    pub max_modules: Option<u32>,
+    /// Maximum size of a single source file, in bytes.
+    pub max_file_size: Option<u64>,
+    /// Maximum length of a chain of require() calls, starting at the entry point.
+    pub max_depth: Option<usize>,
+    /// Limits for parsing a single file. Dependencies that exceed them are skipped with a warning,
+    /// and requiring them throws at runtime.
+    pub parse: ParseLimits,
+}
 
 /// Builds a dependency tree for Node modules.
 pub struct Deps {
     module_id: u32,
     resolver: Resolver,
-    loaded_files: HashSet<PathBuf>,
+    loaded_files: HashSet<NormalizedPath>,
+    /// The IDs of loaded modules, including the ones whose dependencies are still being read.
+    module_ids: HashMap<NormalizedPath, u32>,
+    case_sensitivity: CaseSensitivity,
     module_map: ModuleMap,
     include_builtins: bool,
     builtins: Box<Builtins>,
+    externals: HashSet<String>,
+    roots: Vec<RootPrefix>,
+    injections: Vec<Injection>,
+    polyfills: Vec<Polyfill>,
+    polyfill_uses: Vec<PolyfillUse>,
+    limits: Limits,
+    context_dirs: Vec<PathBuf>,
+    excludes: Vec<Pattern>,
+    budgets: HashMap<String, u64>,
+    package_sizes: HashMap<String, u64>,
+    package_chains: HashMap<String, String>,
+    transforms: Vec<Rc<Transform>>,
+    rules: Rules,
+    constants: Rc<Constants>,
+    parsers: HashMap<String, Arc<Parser>>,
+    pipeline: Rc<Pipeline>,
+    cache: Option<BuildCache>,
+    remote: RemoteModules,
+    meta: Option<BuildMeta>,
+    dynamic_code: Severity,
+    unanalyzable_requires: Severity,
+    sandbox_root: Option<PathBuf>,
+    basedir: PathBuf,
+    warnings: Vec<String>,
+    /// Files that are read and parsed ahead of time, by their resolved path.
+    prefetcher: Prefetcher,
+    /// The chain of modules that is currently being read, starting at the entry point.
+    chain: Vec<NormalizedPath>,
 }
 
 impl Deps {
@@ -33,8 +97,36 @@ impl Deps {
             module_map,
             module_id,
             loaded_files,
+            module_ids: HashMap::new(),
+            case_sensitivity: CaseSensitivity::platform_default(),
             include_builtins: true,
             builtins: Box::new(builtins),
+            externals: HashSet::new(),
+            roots: vec![],
+            injections: vec![],
+            polyfills: vec![],
+            polyfill_uses: vec![],
+            limits: Limits::default(),
+            context_dirs: vec![],
+            excludes: vec![],
+            budgets: HashMap::new(),
+            package_sizes: HashMap::new(),
+            package_chains: HashMap::new(),
+            transforms: vec![],
+            rules: Rules::default(),
+            constants: Rc::new(Constants::new()),
+            parsers: HashMap::new(),
+            pipeline: Rc::new(Pipeline::new()),
+            cache: None,
+            remote: RemoteModules::new(PathBuf::from(CACHE_DIR)),
+            meta: None,
+            dynamic_code: Severity::Warn,
+            unanalyzable_requires: Severity::Warn,
+            sandbox_root: None,
+            basedir: PathBuf::from("."),
+            warnings: vec![],
+            prefetcher: Prefetcher::new(),
+            chain: vec![],
         }
     }
 
@@ -43,11 +135,15 @@ impl Deps {
     /// # Examples
     ///
     /// ```
+    /// # extern crate js_bundler;
+    /// # extern crate node_resolve;
     /// use node_resolve::Resolver;
-    /// use deps::Deps;
+    /// use js_bundler::deps::Deps;
     ///
+    /// # fn main() {
     /// let deps = Deps::new()
     ///     .with_resolver(Resolver::new().preserve_symlinks(false));
+    /// # }
     /// ```
     pub fn with_resolver(mut self, resolver: Resolver) -> Self {
         self.resolver = resolver;
@@ -59,10 +155,10 @@ impl Deps {
     /// # Examples
     ///
     /// ```
-    /// use deps::Deps;
+    /// use js_bundler::deps::Deps;
     /// // Use builtin shims provided by the node-libs-browser package.
     /// let deps = Deps::new()
-    ///     .with_builtins_path("./node_modules/node-libs-browser".into())
+    ///     .with_builtins_path("./node_modules/node-libs-browser".into());
     /// ```
     pub fn with_builtins_path(mut self, path: PathBuf) -> Self {
         self.builtins = Box::new(NodeBuiltins::new(path));
@@ -85,25 +181,472 @@ impl Deps {
         self
     }
 
+    /// Mark packages as external.
+    /// Requires for external packages (and files inside them, like `react/dom`)
+    /// stay as `require()` calls, so the program that loads the bundle must provide them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use js_bundler::deps::Deps;
+    /// let deps = Deps::new()
+    ///     .with_externals(vec!["react".to_string(), "react-dom".to_string()]);
+    /// ```
+    pub fn with_externals<I: IntoIterator<Item = String>>(mut self, externals: I) -> Self {
+        self.externals.extend(externals);
+        self
+    }
+
+    /// Bind identifiers in the modules that use them, like `React` to the `react` package.
+    /// The injected modules are resolved like require() calls in each of those modules.
+    /// `Pack` must be given the same injections to declare the identifiers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use js_bundler::deps::Deps;
+    /// use js_bundler::inject::Injection;
+    /// let deps = Deps::new()
+    ///     .with_injections(vec![Injection::parse("h", "preact#h").unwrap()]);
+    /// ```
+    pub fn with_injections(mut self, injections: Vec<Injection>) -> Self {
+        self.injections = injections;
+        self
+    }
+
+    /// Require polyfills at the start of the modules that use their globals, like `fetch`.
+    /// The polyfill modules are resolved like require() calls in each of those modules.
+    /// `Pack` must be given the same polyfills to require them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use js_bundler::deps::Deps;
+    /// use js_bundler::polyfill::Polyfill;
+    /// let deps = Deps::new()
+    ///     .with_polyfills(vec![Polyfill::new("fetch", "whatwg-fetch").unwrap()]);
+    /// ```
+    pub fn with_polyfills(mut self, polyfills: Vec<Polyfill>) -> Self {
+        self.polyfills = polyfills;
+        self
+    }
+
+    /// Resolve specifiers that start with a project root prefix, like `~/components/button`,
+    /// inside the prefix's directory instead of in node_modules.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use js_bundler::deps::Deps;
+    /// use js_bundler::roots::RootPrefix;
+    /// let deps = Deps::new()
+    ///     .with_roots(vec![RootPrefix::new("~/", "./src".into()).unwrap()]);
+    /// ```
+    pub fn with_roots(mut self, roots: Vec<RootPrefix>) -> Self {
+        self.roots = roots;
+        self
+    }
+
+    /// Configure whether file paths that only differ in case refer to the same module.
+    /// Defaults to the usual behaviour of file systems on the current platform.
+    pub fn with_case_sensitivity(mut self, case_sensitivity: CaseSensitivity) -> Self {
+        self.case_sensitivity = case_sensitivity;
+        self
+    }
+
+    /// Stop with an error when the dependency tree grows beyond these limits.
+    /// This protects against accidentally bundling huge parts of node_modules.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use js_bundler::deps::{Deps, Limits};
+    /// let deps = Deps::new()
+    ///     .with_limits(Limits { max_modules: Some(5000), ..Limits::default() });
+    /// ```
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Bundle every file that a partially dynamic require() call could refer to,
+    /// if the call points into one of these directories.
+    ///
+    /// For example, `require('./locales/' + lang + '.json')` includes every .json file
+    /// in the `locales` directory, so the call works at runtime for any of them.
+    /// Subdirectories are not included.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use js_bundler::deps::Deps;
+    /// let deps = Deps::new()
+    ///     .with_context_requires(vec!["./src/locales".into()]);
+    /// ```
+    pub fn with_context_requires(mut self, dirs: Vec<PathBuf>) -> Self {
+        self.context_dirs.extend(dirs.into_iter()
+            .map(|dir| dir.canonicalize().unwrap_or(dir)));
+        self
+    }
+
+    /// Refuse to bundle files matching these patterns.
+    /// Requiring an excluded file is an error, which lists the chain of require() calls
+    /// that led to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate glob;
+    /// # extern crate js_bundler;
+    /// use glob::Pattern;
+    /// use js_bundler::deps::Deps;
+    /// # fn main() {
+    /// // No lodash in the client bundle.
+    /// let deps = Deps::new()
+    ///     .with_excludes(vec![Pattern::new("**/node_modules/lodash/**").unwrap()]);
+    /// # }
+    /// ```
+    pub fn with_excludes(mut self, patterns: Vec<Pattern>) -> Self {
+        self.excludes.extend(patterns);
+        self
+    }
+
+    /// Limit how many bytes of source code a package may add to the bundle.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use js_bundler::deps::Deps;
+    /// let deps = Deps::new()
+    ///     .with_package_budget("moment", 20_000);
+    /// ```
+    pub fn with_package_budget(mut self, package: &str, max_bytes: u64) -> Self {
+        self.budgets.insert(package.to_string(), max_bytes);
+        self
+    }
+
+    /// Run a transform on every source file.
+    /// Transforms run in the order they are added.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use js_bundler::deps::Deps;
+    /// use js_bundler::transforms::StripAsserts;
+    /// let deps = Deps::new()
+    ///     .with_transform(StripAsserts);
+    /// ```
+    pub fn with_transform<T: Transform + 'static>(mut self, transform: T) -> Self {
+        self.transforms.push(Rc::new(transform));
+        self
+    }
+
+    /// Choose the type and extra transforms of each file with path-based rules.
+    /// The rules' transforms run after the ones added with `with_transform`.
+    pub fn with_rules(mut self, rules: Rules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Global identifiers with a known value, like `__DEV__`. require() calls in branches that
+    /// never run because of them are not bundled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate estree_detect_requires;
+    /// # extern crate js_bundler;
+    /// use estree_detect_requires::{Constant, Constants};
+    /// use js_bundler::deps::Deps;
+    /// # fn main() {
+    /// let mut constants = Constants::new();
+    /// constants.insert("__DEV__".to_string(), Constant::Bool(false));
+    /// let deps = Deps::new()
+    ///     .with_constants(constants);
+    /// # }
+    /// ```
+    pub fn with_constants(mut self, constants: Constants) -> Self {
+        self.constants = Rc::new(constants);
+        self
+    }
+
+    /// Run extra steps before or after the stages of the build.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use js_bundler::deps::Deps;
+    /// use js_bundler::pipeline::Pipeline;
+    ///
+    /// let deps = Deps::new()
+    ///     .with_pipeline(Pipeline::new());
+    /// ```
+    pub fn with_pipeline(mut self, pipeline: Pipeline) -> Self {
+        self.pipeline = Rc::new(pipeline);
+        self
+    }
+
+    /// Configure how `data:` and `https:` modules are stored.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use js_bundler::deps::Deps;
+    /// use js_bundler::remote::RemoteModules;
+    ///
+    /// let deps = Deps::new()
+    ///     .with_remote_modules(RemoteModules::new("/tmp/cache".into()).allow_https(true));
+    /// ```
+    pub fn with_remote_modules(mut self, remote: RemoteModules) -> Self {
+        self.remote = remote;
+        self
+    }
+
+    /// Provide the `bundler:meta` module with information about this build.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use js_bundler::deps::Deps;
+    /// use js_bundler::meta::BuildMeta;
+    ///
+    /// let deps = Deps::new()
+    ///     .with_meta(BuildMeta::new("./app.js", "production", "browser"));
+    /// ```
+    pub fn with_meta(mut self, meta: BuildMeta) -> Self {
+        self.meta = Some(meta);
+        self
+    }
+
+    /// Set how to report uses of `eval`, `Function` and `with`. Defaults to a warning.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use js_bundler::deps::Deps;
+    /// use js_bundler::diagnostics::Severity;
+    ///
+    /// let deps = Deps::new()
+    ///     .with_dynamic_code_severity(Severity::Error);
+    /// ```
+    pub fn with_dynamic_code_severity(mut self, severity: Severity) -> Self {
+        self.dynamic_code = severity;
+        self
+    }
+
+    /// Set how to report require() calls with an argument that is not known at build time,
+    /// like `require(name)`. The modules they load are not bundled, so the bundle may be
+    /// incomplete. Defaults to a warning; `Severity::Error` is a strict mode that fails the build.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use js_bundler::deps::Deps;
+    /// use js_bundler::diagnostics::Severity;
+    ///
+    /// let deps = Deps::new()
+    ///     .with_unanalyzable_require_severity(Severity::Error);
+    /// ```
+    pub fn with_unanalyzable_require_severity(mut self, severity: Severity) -> Self {
+        self.unanalyzable_requires = severity;
+        self
+    }
+
+    /// Resolve the entry point relative to `basedir` instead of the working directory.
+    /// The working directory is shared by the whole process, so builds that run at the same
+    /// time for different projects must each set their own.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use js_bundler::deps::Deps;
+    ///
+    /// let deps = Deps::new()
+    ///     .with_basedir("/srv/builds/1234".into());
+    /// ```
+    pub fn with_basedir(mut self, basedir: PathBuf) -> Self {
+        self.basedir = basedir;
+        self
+    }
+
+    /// Parse files with the given extension, like `"js"`, with a different parser than esprit.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    /// use js_bundler::deps::Deps;
+    /// use js_bundler::loader::EspritParser;
+    ///
+    /// let deps = Deps::new()
+    ///     .with_parser("mjs", Arc::new(EspritParser));
+    /// ```
+    pub fn with_parser(mut self, extension: &str, parser: Arc<Parser>) -> Self {
+        self.parsers.insert(extension.to_string(), parser);
+        self
+    }
+
+    /// Load unchanged files from a build cache instead of parsing and transforming them again.
+    /// The settings of the cache must describe all transforms, rules and constants, because
+    /// cached files skip them. The cache is not used if the pipeline has steps that change source
+    /// files. Cached files have no syntax tree. Entries that a build did not use are removed
+    /// once it finishes.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::env;
+    /// use js_bundler::cache::BuildCache;
+    /// use js_bundler::deps::Deps;
+    ///
+    /// let cache = BuildCache::open(&env::temp_dir().join("js-bundler-cache-example"), "strip-console").unwrap();
+    /// let deps = Deps::new().with_cache(cache);
+    /// ```
+    pub fn with_cache(mut self, cache: BuildCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Refuse to load files outside of `root`, for bundling code that can not be trusted.
+    /// Symlinks are followed before checking, so they can not point outside of `root` either.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use js_bundler::deps::Deps;
+    ///
+    /// let deps = Deps::new()
+    ///     .with_sandbox_root("/srv/builds/1234".into());
+    /// ```
+    pub fn with_sandbox_root(mut self, root: PathBuf) -> Self {
+        self.sandbox_root = Some(root);
+        self
+    }
+
+    /// Get the warnings from the last run.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Get the polyfills that were added to modules in the last run, and the modules that use them.
+    pub fn polyfill_uses(&self) -> &[PolyfillUse] {
+        &self.polyfill_uses
+    }
+
+    /// Get the steps that were added to the build, to run the `Optimize` and `Emit` stages.
+    /// Pass them to `Emitter::with_pipeline` for the steps after `Emit`.
+    pub fn pipeline(&self) -> Rc<Pipeline> {
+        Rc::clone(&self.pipeline)
+    }
+
     /// Start dependency resolution at an entry file.
     pub fn run(&mut self, entry: &str) -> Result<()> {
-        let resolved = self.resolver.with_basedir(PathBuf::from("."))
-            .resolve(entry)?;
+        let resolved = self.resolver.with_basedir(self.basedir.clone())
+            .resolve(entry)
+            .map_err(|err| BundleError::resolve(entry, &self.basedir, err))?;
 
-        let source_file = LoadFile::new(resolved).run()?;
+        let source_file = self.load_file(resolved)?;
         let mut record = self.to_record(source_file, true)?;
-        let rec_path = path_to_string(&record.file.path());
-        self.loaded_files.insert(record.file.path().clone());
-        self.read_deps(&mut record)?;
-        self.add_module(&rec_path, record);
-        Ok(())
+        let rec_path = self.normalize(record.file.path());
+        self.loaded_files.insert(rec_path.clone());
+        self.module_ids.insert(rec_path.clone(), record.id);
+        self.track_package_size(&rec_path, &record);
+        self.read_deps(&mut record, 0)?;
+        self.add_module(rec_path, record);
+        self.pipeline.run_graph(Hook::Before(Stage::Link), &self.module_map)?;
+        self.check_budgets()?;
+        self.pipeline.run_graph(Hook::After(Stage::Link), &self.module_map)?;
+        if let Some(ref cache) = self.cache {
+            // Entries that no recent build used are for old versions of files.
+            let _ = cache.prune();
+        }
+        self.remote.save_lockfile()
+    }
+
+    fn load_file(&mut self, path: PathBuf) -> Result<SourceFile> {
+        match self.prefetcher.take(&path) {
+            Some((loader, file)) => loader.process(file?),
+            None => self.loader(path)?.run(),
+        }
+    }
+
+    /// Start reading and parsing the files that a module requires on the thread pool, so they
+    /// are ready, or closer to it, when the depth-first walk in `read_deps` gets to them. While
+    /// they are read, this thread goes on transforming and resolving the files before them.
+    ///
+    /// The path steps before `Load` run here, for all of the module's dependencies at once, so
+    /// they run ahead of the files that the walk gets to first. The steps after `Load`,
+    /// transforms and module ids still happen one file at a time on this thread, in the order
+    /// of the walk.
+    fn prefetch(&mut self, record: &ModuleRecord) -> () {
+        for dependency in record.dependencies.values() {
+            if let Some(ref resolved) = dependency.resolved {
+                let normalized = self.normalize(resolved);
+                if self.loaded_files.contains(&normalized) || self.excluded_by(&normalized).is_some() || self.prefetcher.contains(resolved) {
+                    continue;
+                }
+                // Errors are reported when the file is loaded in order.
+                if let Ok(loader) = self.loader(resolved.clone()) {
+                    self.prefetcher.start(resolved.clone(), loader);
+                }
+            }
+        }
+    }
+
+    /// Set up loading a file, with the parser, rules and transforms for its path.
+    fn loader(&self, path: PathBuf) -> Result<LoadFile> {
+        let path = self.pipeline.run_path(Hook::Before(Stage::Load), path)?;
+        self.check_sandbox(&path)?;
+        let parser = path.extension().and_then(|extension| self.parsers.get(&*extension.to_string_lossy())).cloned();
+        let rules = self.rules.matching(self.normalize(&path).as_str());
+        let load = match parser {
+            Some(parser) => LoadFile::new(path).with_parser(parser),
+            None => LoadFile::new(path),
+        };
+        Ok(self.transforms.iter()
+            .chain(rules.transforms.iter())
+            .fold(load, |load, transform| load.with_transform(Rc::clone(transform)))
+            .with_module_type(rules.module_type)
+            .with_constants(Rc::clone(&self.constants))
+            .with_max_size(self.limits.max_file_size)
+            .with_parse_limits(self.limits.parse)
+            .with_pipeline(Rc::clone(&self.pipeline))
+            .with_cache(self.cache.clone()))
     }
 
     fn to_record(&mut self, file: SourceFile, entry: bool) -> Result<ModuleRecord> {
         self.module_id += 1;
+        if let Some(max_modules) = self.limits.max_modules {
+            if self.module_id > max_modules {
+                bail!("Exceeded the maximum of {} modules while loading {}", max_modules, file.path().to_string_lossy());
+            }
+        }
+        self.check_dynamic_code(&file)?;
+        self.check_unanalyzable_requires(&file)?;
         let basedir = file.path().clone().parent().unwrap().to_path_buf();
         let dependencies = match file {
-            SourceFile::CJS { ref dependencies, .. } => self.resolve_deps(basedir, dependencies)?,
+            SourceFile::CJS { ref dependencies, ref dynamic_dependencies, ref optional_dependencies, .. } => {
+                let mut dependencies = dependencies.clone();
+                for injection in used_injections(&file, &self.injections) {
+                    if !dependencies.contains(&injection.specifier) {
+                        dependencies.push(injection.specifier.clone());
+                    }
+                }
+                for polyfill in used_polyfills(&file, &self.polyfills) {
+                    if !dependencies.contains(&polyfill.specifier) {
+                        dependencies.push(polyfill.specifier.clone());
+                    }
+                    self.polyfill_uses.push(PolyfillUse {
+                        global: polyfill.global.clone(),
+                        specifier: polyfill.specifier.clone(),
+                        path: file.path().clone(),
+                    });
+                }
+                for dynamic in dynamic_dependencies {
+                    dependencies.extend(self.expand_context(&basedir, dynamic)?);
+                }
+                self.resolve_deps(basedir, &dependencies, optional_dependencies)?
+            },
             _ => Dependencies::new(),
         };
         Ok(ModuleRecord {
@@ -114,51 +657,256 @@ impl Deps {
         })
     }
 
-    fn resolve_deps(&mut self, basedir: PathBuf, dependencies: &Vec<String>) -> Result<Dependencies> {
-        let resolver = self.resolver.with_basedir(basedir);
+    /// Resolve the dependencies of a module in `basedir`. Optional dependencies that can not be
+    /// found are kept unresolved, so requiring them throws at runtime.
+    fn resolve_deps(&mut self, basedir: PathBuf, dependencies: &Vec<String>, optional: &[String]) -> Result<Dependencies> {
+        let resolver = self.resolver.with_basedir(basedir.clone());
         let mut map = Dependencies::new();
         for dep_id in dependencies {
+            let specifier = self.pipeline.run_specifier(Hook::Before(Stage::Resolve), dep_id.clone(), &basedir)?;
+            let specifier = match self.remote.join(&basedir, &specifier)? {
+                Some(url) => url,
+                None => match resolve_root(&self.roots, &specifier) {
+                    Some(path) => path.to_string_lossy().into_owned(),
+                    None => strip_node_prefix(&specifier).map(|name| name.to_string()).unwrap_or(specifier),
+                },
+            };
             // TODO include core module shims
-            let path = if self.builtins.is_builtin(&dep_id) {
+            let path = if self.is_external(&specifier) {
+                None
+            } else if specifier == META_MODULE {
+                let (name, meta) = match self.meta {
+                    Some(ref meta) => (meta.file_stem(), serde_json::to_vec(meta)?),
+                    None => bail!("{} is not available in this build", META_MODULE),
+                };
+                Some(self.remote.replace("meta", &name, &meta, "json")?)
+            } else if self.builtins.is_builtin(&specifier) {
                 if self.include_builtins {
-                    self.builtins.resolve(&resolver, &dep_id)?
+                    self.builtins.resolve(&resolver, &specifier)
+                        .map_err(|err| BundleError::resolve(&specifier, &basedir, err))?
                 } else {
                     None
                 }
+            } else if is_remote(&specifier) {
+                Some(self.remote.fetch(&specifier)?)
             } else {
-                Some(resolver.resolve(&dep_id)?)
+                match resolver.resolve(&specifier) {
+                    Ok(resolved) => Some(resolved),
+                    Err(err) => if optional.contains(dep_id) {
+                        self.warnings.push(format!("Could not find optional dependency \"{}\" from {}, requiring it will throw",
+                                                   dep_id, basedir.to_string_lossy()));
+                        map.insert(dep_id.clone(), Dependency::uninitialized(dep_id.clone()).with_optional(true));
+                        continue;
+                    } else {
+                        return Err(BundleError::resolve(&specifier, &basedir, err).into());
+                    },
+                }
             };
-            path.map(|resolved| map.insert(dep_id.clone(), Dependency::resolved(dep_id.clone(), resolved)));
+            if let Some(resolved) = path {
+                let resolved = self.pipeline.run_path(Hook::After(Stage::Resolve), resolved)?;
+                map.insert(dep_id.clone(), Dependency::resolved(dep_id.clone(), resolved).with_optional(optional.contains(dep_id)));
+            }
         }
         Ok(map)
     }
 
-    fn read_deps(&mut self, record: &mut ModuleRecord) -> Result<()> {
+    /// Find the files that a partially dynamic require() call could refer to.
+    /// Returns the require() arguments that would load each of them.
+    fn expand_context(&self, basedir: &Path, dynamic: &DynamicRequire) -> Result<Vec<String>> {
+        if !dynamic.prefix.starts_with('.') {
+            return Ok(vec![]);
+        }
+        let (dir_prefix, file_prefix) = match dynamic.prefix.rfind('/') {
+            Some(index) => dynamic.prefix.split_at(index + 1),
+            None => return Ok(vec![]),
+        };
+        let dir = match basedir.join(dir_prefix).canonicalize() {
+            Ok(dir) => dir,
+            Err(_) => return Ok(vec![]),
+        };
+        if !self.context_dirs.iter().any(|context_dir| dir.starts_with(context_dir)) {
+            return Ok(vec![]);
+        }
+
+        let suffix = &dynamic.suffix;
+        let mut specifiers = vec![];
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let loadable = Path::new(&name).extension().map_or(false, |ext| ext == "js" || ext == "json");
+            if !loadable || name.len() < file_prefix.len() + suffix.len() || !name.starts_with(file_prefix) || !name.ends_with(suffix.as_str()) {
+                continue;
+            }
+            specifiers.push(format!("{}{}", dir_prefix, name));
+            // `require('./pages/' + name)` may be called without an extension.
+            if suffix.is_empty() {
+                if let Some(stem) = Path::new(&name).file_stem() {
+                    specifiers.push(format!("{}{}", dir_prefix, stem.to_string_lossy()));
+                }
+            }
+        }
+        specifiers.sort();
+        Ok(specifiers)
+    }
+
+    fn is_external(&self, dep_id: &str) -> bool {
+        self.externals.contains(package_name(dep_id))
+    }
+
+    fn read_deps(&mut self, record: &mut ModuleRecord, depth: usize) -> Result<()> {
+        if let Some(max_depth) = self.limits.max_depth {
+            if depth >= max_depth && !record.dependencies.is_empty() {
+                bail!("Exceeded the maximum require depth of {} in {}", max_depth, record.file.path().to_string_lossy());
+            }
+        }
+
+        self.prefetch(record);
+        self.chain.push(self.normalize(record.file.path()));
         for dependency in record.dependencies.values_mut() {
             let dep_record = if let Some(ref resolved) = dependency.resolved {
-                if !self.loaded_files.contains(resolved) {
-                    let source_file = LoadFile::new(resolved.clone()).run()?;
-                    let mut new_record = self.to_record(source_file, true)?;
-                    let new_path = path_to_string(&new_record.file.path());
-                    self.loaded_files.insert(new_record.file.path().to_path_buf());
-                    self.read_deps(&mut new_record)?;
-                    self.add_module(&new_path, new_record);
+                let resolved_path = self.normalize(resolved);
+                if let Some(pattern) = self.excluded_by(&resolved_path) {
+                    bail!("{} is excluded by the pattern {}, but it is required as \"{}\" by:\n  {}",
+                          resolved_path, pattern, dependency.name, self.format_chain());
                 }
-                self.module_map.get(&path_to_string(resolved)).map(|rc| rc.to_owned())
+                if !self.loaded_files.contains(&resolved_path) {
+                    match self.load_file(resolved.clone()) {
+                        Ok(source_file) => {
+                            let mut new_record = self.to_record(source_file, false)?;
+                            self.loaded_files.insert(resolved_path.clone());
+                            self.module_ids.insert(resolved_path.clone(), new_record.id);
+                            self.track_package_size(&resolved_path, &new_record);
+                            self.read_deps(&mut new_record, depth + 1)?;
+                            self.add_module(resolved_path.clone(), new_record);
+                        },
+                        Err(err) => {
+                            let exceeded = err.downcast::<LimitExceeded>()?;
+                            self.warnings.push(format!("Skipped {}, required as \"{}\" by {}", exceeded, dependency.name, record.file.path().to_string_lossy()));
+                            let stub = self.skipped_module(resolved.clone(), &exceeded)?;
+                            self.loaded_files.insert(resolved_path.clone());
+                            self.module_ids.insert(resolved_path.clone(), stub.id);
+                            self.add_module(resolved_path.clone(), stub);
+                        },
+                    }
+                }
+                // A module that is still being read, because it requires this one in a cycle, only
+                // has an ID so far.
+                dependency.id = self.module_ids.get(&resolved_path).cloned();
+                self.module_map.get(&resolved_path).map(|rc| rc.to_owned())
             } else {
                 None
             };
 
-            if dep_record.is_none() {
+            if dependency.id.is_none() && !dependency.is_missing() {
                 warn!("Could not resolve ModuleRecord for {} from {}", dependency.name, record.file.path().to_string_lossy());
             }
             dep_record.map(|d| dependency.set_record(&d));
         }
+        self.chain.pop();
+        Ok(())
+    }
+
+    /// A module that stands in for a file that exceeds a parse limit. Requiring it throws an
+    /// error that says why the file was left out, instead of the runtime failing to find it.
+    fn skipped_module(&mut self, path: PathBuf, exceeded: &LimitExceeded) -> Result<ModuleRecord> {
+        let message = format!("{}, so it was left out of the bundle", exceeded);
+        let source = format!("throw new Error({});", serde_json::to_string(&message)?);
+        self.to_record(parse_cjs(path, source)?, false)
+    }
+
+    fn excluded_by(&self, path: &NormalizedPath) -> Option<&Pattern> {
+        self.excludes.iter().find(|pattern| pattern.matches(path.as_str()))
+    }
+
+    fn format_chain(&self) -> String {
+        self.chain.iter()
+            .map(|path| path.as_str())
+            .collect::<Vec<&str>>()
+            .join("\n  -> ")
+    }
+
+    fn track_package_size(&mut self, path: &NormalizedPath, record: &ModuleRecord) -> () {
+        if let Some(package) = package_from_path(path.as_str()) {
+            *self.package_sizes.entry(package.to_string()).or_insert(0) += record.file.source().len() as u64;
+            if !self.package_chains.contains_key(package) {
+                let chain = self.format_chain();
+                self.package_chains.insert(package.to_string(), chain);
+            }
+        }
+    }
+
+    fn check_dynamic_code(&mut self, file: &SourceFile) -> Result<()> {
+        let dynamic_code = match *file {
+            SourceFile::CJS { ref dynamic_code, .. } => dynamic_code,
+            _ => return Ok(()),
+        };
+        for found in dynamic_code {
+            let what = match found.kind {
+                DynamicCodeKind::Eval => "eval()",
+                DynamicCodeKind::FunctionConstructor => "the Function constructor",
+                DynamicCodeKind::With => "a with statement",
+            };
+            let location = found.location.map_or(String::new(), |location| format!(":{}", location));
+            let message = format!("{}{} uses {}, which prevents optimizing the module",
+                                  file.path().to_string_lossy(), location, what);
+            match self.dynamic_code {
+                Severity::Ignore => (),
+                Severity::Warn => self.warnings.push(message),
+                Severity::Error => bail!("{}", message),
+            }
+        }
         Ok(())
     }
 
-    fn add_module(&mut self, rec_path: &str, record: ModuleRecord) -> () {
-        self.module_map.insert(rec_path.to_string(), Rc::new(record));
+    fn check_unanalyzable_requires(&mut self, file: &SourceFile) -> Result<()> {
+        let unanalyzable_requires = match *file {
+            SourceFile::CJS { ref unanalyzable_requires, .. } => unanalyzable_requires,
+            _ => return Ok(()),
+        };
+        for call in unanalyzable_requires {
+            let location = call.span.map_or(String::new(), |span| format!(":{}", span.start));
+            let message = format!("{}{} calls require() with an argument that can not be analyzed, so the module it loads is not bundled",
+                                  file.path().to_string_lossy(), location);
+            match self.unanalyzable_requires {
+                Severity::Ignore => (),
+                Severity::Warn => self.warnings.push(message),
+                Severity::Error => bail!("{}", message),
+            }
+        }
+        Ok(())
+    }
+
+    fn check_sandbox(&self, path: &Path) -> Result<()> {
+        if let Some(ref root) = self.sandbox_root {
+            let root = root.canonicalize()
+                .map_err(|err| format_err!("Invalid sandbox root {}: {}", root.to_string_lossy(), err))?;
+            if !path.canonicalize()?.starts_with(&root) {
+                bail!("Can not load {}, because it is outside of the sandbox root {}", path.to_string_lossy(), root.to_string_lossy());
+            }
+        }
+        Ok(())
+    }
+
+    fn check_budgets(&self) -> Result<()> {
+        for (package, max_bytes) in &self.budgets {
+            let size = self.package_sizes.get(package).cloned().unwrap_or(0);
+            if size > *max_bytes {
+                bail!("{} adds {} bytes to the bundle, more than its budget of {} bytes. It is first required by:\n  {}",
+                      package, size, max_bytes, self.package_chains[package]);
+            }
+        }
+        Ok(())
+    }
+
+    fn add_module(&mut self, rec_path: NormalizedPath, record: ModuleRecord) -> () {
+        self.module_map.insert(rec_path, Rc::new(record));
+    }
+
+    fn normalize(&self, path: &Path) -> NormalizedPath {
+        NormalizedPath::new(path, self.case_sensitivity)
     }
 }
 
@@ -169,6 +917,118 @@ impl Deref for Deps {
     }
 }
 
-fn path_to_string(path: &Path) -> String {
-    path.to_string_lossy().into_owned()
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+    use glob::Pattern;
+    use quicli::prelude::*;
+    use graph::SourceFile;
+    use loader::ParseLimits;
+    use pipeline::{Pipeline, Stage, Step};
+    use super::{Deps, Limits};
+
+    /// Write the files of a test project to a new temporary directory.
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("js-bundler-{}-{}", name, ::std::process::id()));
+        for &(path, source) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    fn run(dir: &Path, deps: Deps) -> (Deps, Result<()>) {
+        let mut deps = deps.include_builtins(false).with_basedir(dir.to_path_buf());
+        let result = deps.run("./index.js");
+        fs::remove_dir_all(dir).unwrap();
+        (deps, result)
+    }
+
+    /// Records the files that path and file steps see, in order.
+    struct RecordOrder(Rc<RefCell<Vec<String>>>);
+
+    impl RecordOrder {
+        fn push(&self, step: &str, path: &PathBuf) {
+            self.0.borrow_mut().push(format!("{} {}", step, path.file_name().unwrap().to_string_lossy()));
+        }
+    }
+
+    impl Step for RecordOrder {
+        fn path(&self, path: PathBuf) -> Result<PathBuf> {
+            self.push("path", &path);
+            Ok(path)
+        }
+
+        fn file(&self, file: SourceFile) -> Result<SourceFile> {
+            self.push("file", file.path());
+            Ok(file)
+        }
+    }
+
+    #[test]
+    fn runs_path_steps_when_prefetching() {
+        let dir = project("prefetch-order", &[
+            ("index.js", "require('./a'); require('./b')"),
+            ("a.js", "require('./c')"),
+            ("b.js", ""),
+            ("c.js", ""),
+        ]);
+        let order = Rc::new(RefCell::new(vec![]));
+        let pipeline = Pipeline::new()
+            .before(Stage::Load, RecordOrder(Rc::clone(&order)))
+            .after(Stage::Load, RecordOrder(Rc::clone(&order)));
+        run(&dir, Deps::new().with_pipeline(pipeline)).1.unwrap();
+        // The dependencies of a module get their path steps before the first one is loaded,
+        // but the files are loaded in the order of the depth-first walk.
+        assert_eq!(*order.borrow(), vec![
+            "path index.js", "file index.js",
+            "path a.js", "path b.js", "file a.js",
+            "path c.js", "file c.js",
+            "file b.js",
+        ]);
+    }
+
+    #[test]
+    fn fails_for_excluded_modules() {
+        let dir = project("excluded", &[("index.js", "require('./secret')"), ("secret.js", "")]);
+        let deps = Deps::new().with_excludes(vec![Pattern::new("**/secret.js").unwrap()]);
+        let message = run(&dir, deps).1.unwrap_err().to_string();
+        assert!(message.contains("secret.js is excluded by the pattern **/secret.js, but it is required as \"./secret\" by:"));
+    }
+
+    #[test]
+    fn fails_when_a_package_exceeds_its_budget() {
+        let files = &[
+            ("index.js", "require('big')"),
+            ("node_modules/big/index.js", "module.exports = '0123456789'"),
+        ];
+        let (deps, result) = run(&project("budget", files), Deps::new().with_package_budget("big", 100));
+        assert!(result.is_ok());
+        assert_eq!(deps.len(), 2);
+
+        let (_, result) = run(&project("budget", files), Deps::new().with_package_budget("big", 10));
+        let message = result.unwrap_err().to_string();
+        assert!(message.starts_with("big adds 29 bytes to the bundle, more than its budget of 10 bytes."));
+    }
+
+    #[test]
+    fn replaces_skipped_modules_with_a_stub() {
+        let dir = project("skipped", &[("index.js", "require('./big')"), ("big.js", "a; b; c; d; e; f; g; h; i; j; k")]);
+        let limits = Limits { parse: ParseLimits { max_nodes: Some(10), ..ParseLimits::default() }, ..Limits::default() };
+        let (deps, result) = run(&dir, Deps::new().with_limits(limits));
+        result.unwrap();
+        assert_eq!(deps.warnings().len(), 1);
+        assert!(deps.warnings()[0].starts_with("Skipped "));
+        let entry = deps.values().find(|record| record.entry).unwrap();
+        // The runtime gets a module to require, which throws an error that says what happened.
+        let stub = entry.dependencies["./big"].record.as_ref().unwrap();
+        assert_eq!(entry.dependencies["./big"].id, Some(stub.id));
+        assert!(stub.file.source().starts_with("throw new Error(\""));
+        assert!(stub.file.source().contains("big.js has more than the maximum of 10 syntax nodes, so it was left out of the bundle"));
+    }
 }
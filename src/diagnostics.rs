@@ -0,0 +1,29 @@
+/// How to handle a problem that does not always break the bundle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Do not report it.
+    Ignore,
+    /// Report it, but keep building.
+    Warn,
+    /// Fail the build.
+    Error,
+}
+
+impl Default for Severity {
+    fn default() -> Self {
+        Severity::Warn
+    }
+}
+
+impl ::std::str::FromStr for Severity {
+    type Err = String;
+    fn from_str(severity: &str) -> Result<Self, String> {
+        match severity {
+            "ignore" => Ok(Severity::Ignore),
+            "warn" => Ok(Severity::Warn),
+            "error" => Ok(Severity::Error),
+            _ => Err(format!("expected ignore, warn or error, got {}", severity)),
+        }
+    }
+}
@@ -0,0 +1,628 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use glob::glob;
+use quicli::prelude::*;
+use serde_json::{self, Value};
+use sha1::{Sha1, Digest};
+use licenses::ModuleLicense;
+use package::read_package_json;
+use path::is_inside;
+use pipeline::{Pipeline, Hook, Stage};
+
+/// The name of the manifest file in the output directory.
+pub const MANIFEST_FILE: &str = "manifest.json";
+/// The name of the package metadata file that library builds write to the output directory.
+pub const PACKAGE_FILE: &str = "package.json";
+
+/// The kinds of files that are written to the output directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtifactKind {
+    Js,
+    Css,
+    Asset,
+}
+
+/// Where the emitter puts the files it creates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmitTarget {
+    /// Write the files to the output directory.
+    Disk,
+    /// Keep the files in memory, for embedders that serve or upload them without a directory.
+    Memory,
+}
+
+impl Default for EmitTarget {
+    fn default() -> Self {
+        EmitTarget::Disk
+    }
+}
+
+/// Where to put each kind of file, relative to the output directory. The directories can not
+/// be absolute or contain `..`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputDirs {
+    pub js: PathBuf,
+    pub css: PathBuf,
+    pub assets: PathBuf,
+}
+
+impl Default for OutputDirs {
+    fn default() -> Self {
+        OutputDirs {
+            js: PathBuf::from("."),
+            css: PathBuf::from("."),
+            assets: PathBuf::from("."),
+        }
+    }
+}
+
+impl OutputDirs {
+    fn get(&self, kind: ArtifactKind) -> &Path {
+        match kind {
+            ArtifactKind::Js => &self.js,
+            ArtifactKind::Css => &self.css,
+            ArtifactKind::Asset => &self.assets,
+        }
+    }
+}
+
+/// Copy files to the output directory as they are.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CopyRule {
+    /// A directory or file to copy, or a glob pattern.
+    pub from: String,
+    /// Where to copy to, relative to the output directory. Defaults to the assets directory.
+    /// It can not be absolute or contain `..`, and two rules can not copy to the same file.
+    pub to: Option<PathBuf>,
+}
+
+/// A file in the output directory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub kind: ArtifactKind,
+    /// The path relative to the output directory, with forward slashes.
+    pub path: String,
+    /// The URL that the file is served at.
+    pub url: String,
+    /// The sha1 hash of the file contents, used to skip writing files that did not change.
+    #[serde(default)]
+    pub hash: String,
+}
+
+/// Lists the files that a build wrote to the output directory.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub files: Vec<ManifestEntry>,
+    /// The modules in each bundle, by the path of the bundle, with their packages and licenses.
+    /// Only recorded with `Emitter::with_module_licenses`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub modules: BTreeMap<String, Vec<ModuleLicense>>,
+}
+
+impl Manifest {
+    /// Read the manifest from an output directory.
+    pub fn from_dir(outdir: &Path) -> Result<Manifest> {
+        let source = read_file(outdir.join(MANIFEST_FILE))?;
+        Ok(serde_json::from_str(&source)?)
+    }
+}
+
+/// A file created by the emitter, with its contents.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Artifact {
+    pub entry: ManifestEntry,
+    pub contents: Vec<u8>,
+}
+
+/// The files of a build, and the manifest that lists them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Output {
+    pub manifest: Manifest,
+    pub artifacts: Vec<Artifact>,
+}
+
+impl Output {
+    /// Find a file by its path relative to the output directory, eg. `js/app.js`.
+    pub fn get(&self, path: &str) -> Option<&Artifact> {
+        self.artifacts.iter().find(|artifact| artifact.entry.path == path)
+    }
+}
+
+/// Writes bundles and copied files to an output directory, and records them in a manifest.
+pub struct Emitter {
+    outdir: PathBuf,
+    dirs: OutputDirs,
+    public_path: String,
+    copy: Vec<CopyRule>,
+    clean: bool,
+    target: EmitTarget,
+    licenses: Vec<ModuleLicense>,
+    pipeline: Rc<Pipeline>,
+    sandbox_root: Option<PathBuf>,
+    library_package: Option<PathBuf>,
+}
+
+impl Emitter {
+    pub fn new(outdir: PathBuf) -> Self {
+        Emitter {
+            outdir,
+            dirs: OutputDirs::default(),
+            public_path: String::from("/"),
+            copy: vec![],
+            clean: false,
+            target: EmitTarget::Disk,
+            licenses: vec![],
+            pipeline: Rc::new(Pipeline::new()),
+            sandbox_root: None,
+            library_package: None,
+        }
+    }
+
+    /// Put each kind of file in a different directory.
+    pub fn with_dirs(mut self, dirs: OutputDirs) -> Self {
+        self.dirs = dirs;
+        self
+    }
+
+    /// Set the URL that the output directory is served at, eg. `https://cdn.example.com/app/`.
+    pub fn with_public_path(mut self, public_path: String) -> Self {
+        self.public_path = public_path;
+        self
+    }
+
+    /// Copy files to the output directory as they are.
+    pub fn with_copy_rules(mut self, copy: Vec<CopyRule>) -> Self {
+        self.copy = copy;
+        self
+    }
+
+    /// Refuse to copy files from outside of `root`.
+    pub fn with_sandbox_root(mut self, root: PathBuf) -> Self {
+        self.sandbox_root = Some(root);
+        self
+    }
+
+    /// Remove files that the previous build wrote, but this one did not.
+    /// Only files listed in the previous manifest are removed.
+    pub fn with_clean(mut self, clean: bool) -> Self {
+        self.clean = clean;
+        self
+    }
+
+    /// Write files to disk, or keep them in memory with `emit_in_memory`.
+    pub fn with_target(mut self, target: EmitTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn target(&self) -> EmitTarget {
+        self.target
+    }
+
+    /// List the modules in the bundle, with their packages and licenses, in the manifest.
+    pub fn with_module_licenses(mut self, licenses: Vec<ModuleLicense>) -> Self {
+        self.licenses = licenses;
+        self
+    }
+
+    /// Run the steps that were added after the `Emit` stage, to rewrite the URLs in the manifest.
+    pub fn with_pipeline(mut self, pipeline: Rc<Pipeline>) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+
+    /// Emit the bundle as a library: write the package.json from `package_dir` to the output
+    /// directory with `main` pointing at the bundle, and copy the package's TypeScript
+    /// declaration files next to the bundle, with `types` pointing at them.
+    pub fn with_library_package(mut self, package_dir: PathBuf) -> Self {
+        self.library_package = Some(package_dir);
+        self
+    }
+
+    /// Write a bundle named `name` and the copied files, and the manifest that lists them.
+    pub fn emit(&self, name: &str, bundle: &str) -> Result<Manifest> {
+        if self.target == EmitTarget::Memory {
+            bail!("Can not write files with an in-memory emitter, use `emit_in_memory` instead");
+        }
+        let output = self.emit_in_memory(name, bundle)?;
+        // A missing or broken manifest only means that every file is written again.
+        let previous = Manifest::from_dir(&self.outdir).ok();
+        for artifact in &output.artifacts {
+            self.write(artifact, &previous)?;
+        }
+
+        let manifest = output.manifest;
+        if previous.as_ref() != Some(&manifest) {
+            write_atomic(&self.outdir.join(MANIFEST_FILE), serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+        }
+
+        if let (true, Some(previous)) = (self.clean, previous) {
+            for stale in previous.files.iter().filter(|file| !manifest.files.iter().any(|new_file| new_file.path == file.path)) {
+                if !is_inside_outdir(Path::new(&stale.path)) {
+                    bail!("Can not remove {}, because it is outside of the output directory", stale.path);
+                }
+                match fs::remove_file(self.outdir.join(&stale.path)) {
+                    Err(ref err) if err.kind() == ErrorKind::NotFound => (),
+                    result => result?,
+                }
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Create the bundle named `name`, the copied files and the manifest that lists them,
+    /// without writing anything to the output directory.
+    pub fn emit_in_memory(&self, name: &str, bundle: &str) -> Result<Output> {
+        for &(key, dir) in &[("js", &self.dirs.js), ("css", &self.dirs.css), ("assets", &self.dirs.assets)] {
+            if !is_inside_outdir(dir) {
+                bail!("The {} output directory must be inside the output directory, not {}", key, dir.to_string_lossy());
+            }
+        }
+        let mut artifacts = vec![];
+        let bundle_path = self.dirs.get(ArtifactKind::Js).join(format!("{}.js", name));
+        artifacts.push(self.artifact(ArtifactKind::Js, &bundle_path, bundle.as_bytes().to_vec()));
+        if let Some(ref package_dir) = self.library_package {
+            artifacts.extend(self.library_files(package_dir, &bundle_path)?);
+        }
+
+        for rule in &self.copy {
+            for (source, target) in self.copied_files(rule)? {
+                self.check_sandbox(&source)?;
+                let target_path = to_url_path(&target);
+                if target_path == MANIFEST_FILE || target_path == artifacts[0].entry.path {
+                    bail!("Can not copy {} to {}, because the bundle writes that file", source.to_string_lossy(), target_path);
+                }
+                if artifacts.iter().any(|artifact| artifact.entry.path == target_path) {
+                    bail!("Can not copy {} to {}, because another file is copied there", source.to_string_lossy(), target_path);
+                }
+                artifacts.push(self.artifact(ArtifactKind::Asset, &target, fs::read(&source)?));
+            }
+        }
+
+        for artifact in &mut artifacts {
+            let url = self.pipeline.run_url(Hook::After(Stage::Emit), artifact.entry.url.clone(), &artifact.entry)?;
+            artifact.entry.url = url;
+        }
+
+        let mut manifest = Manifest {
+            files: artifacts.iter().map(|artifact| artifact.entry.clone()).collect(),
+            modules: BTreeMap::new(),
+        };
+        if !self.licenses.is_empty() {
+            manifest.modules.insert(artifacts[0].entry.path.clone(), self.licenses.clone());
+        }
+        Ok(Output { manifest, artifacts })
+    }
+
+    /// The package.json of a library, with `main` and `types` pointing into the output directory,
+    /// and the declaration files from the directory of the `types` file.
+    fn library_files(&self, package_dir: &Path, bundle_path: &Path) -> Result<Vec<Artifact>> {
+        let mut package = read_package_json(package_dir)?;
+        if !package.is_object() {
+            bail!("Invalid {}: expected an object", package_dir.join(PACKAGE_FILE).to_string_lossy());
+        }
+        let mut artifacts = vec![];
+        let js_dir = self.dirs.get(ArtifactKind::Js);
+        // Older packages use `typings`, which works the same.
+        let types_key = ["types", "typings"].iter().cloned().find(|key| package[*key].is_string());
+        if let Some(key) = types_key {
+            let types_file = package_dir.join(package[key].as_str().unwrap());
+            if !types_file.is_file() {
+                bail!("The `{}` file {} does not exist", key, types_file.to_string_lossy());
+            }
+            let types_dir = types_file.parent().unwrap().to_path_buf();
+            for source in glob(&types_dir.join("**").join("*.d.ts").to_string_lossy())? {
+                let source = source?;
+                // Declarations of dependencies, and the ones an earlier build copied, are not
+                // the package's own.
+                if source.components().any(|component| component.as_os_str() == "node_modules")
+                    || (self.outdir.exists() && is_inside(&source, &self.outdir)?) {
+                    continue;
+                }
+                self.check_sandbox(&source)?;
+                let target = js_dir.join(source.strip_prefix(&types_dir)?);
+                artifacts.push(self.artifact(ArtifactKind::Asset, &target, fs::read(&source)?));
+            }
+            package[key] = Value::from(to_url_path(&js_dir.join(types_file.file_name().unwrap())));
+        }
+        package["main"] = Value::from(to_url_path(bundle_path));
+        let mut source = serde_json::to_string_pretty(&package)?;
+        source.push('\n');
+        artifacts.push(self.artifact(ArtifactKind::Asset, Path::new(PACKAGE_FILE), source.into_bytes()));
+        Ok(artifacts)
+    }
+
+    /// List the files that a copy rule matches, with their target path relative to the output directory.
+    fn copied_files(&self, rule: &CopyRule) -> Result<Vec<(PathBuf, PathBuf)>> {
+        if let Some(ref to) = rule.to {
+            if !is_inside_outdir(to) {
+                bail!("Copy rule {} must copy to a path inside the output directory, not {}", rule.from, to.to_string_lossy());
+            }
+        }
+        let to = rule.to.clone().unwrap_or_else(|| self.dirs.get(ArtifactKind::Asset).to_path_buf());
+        let from = Path::new(&rule.from);
+        if from.is_dir() {
+            let pattern = from.join("**").join("*");
+            let mut files = vec![];
+            for source in glob(&pattern.to_string_lossy())? {
+                let source = source?;
+                if source.is_file() {
+                    let target = to.join(source.strip_prefix(from)?);
+                    files.push((source, target));
+                }
+            }
+            return Ok(files);
+        }
+        let mut files = vec![];
+        for source in glob(&rule.from)? {
+            let source = source?;
+            if source.is_file() {
+                let target = to.join(source.file_name().unwrap());
+                files.push((source, target));
+            }
+        }
+        if files.is_empty() {
+            bail!("Copy rule {} did not match any files", rule.from);
+        }
+        Ok(files)
+    }
+
+    fn check_sandbox(&self, source: &Path) -> Result<()> {
+        if let Some(ref root) = self.sandbox_root {
+            if !is_inside(source, root)? {
+                bail!("Can not copy {}, because it is outside of the sandbox root {}", source.to_string_lossy(), root.to_string_lossy());
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a file, unless the previous build wrote the same contents to it.
+    /// Skipping unchanged files keeps their modification times, so other watchers do not
+    /// see a change.
+    fn write(&self, artifact: &Artifact, previous: &Option<Manifest>) -> Result<()> {
+        let full_path = self.outdir.join(&artifact.entry.path);
+        let unchanged = previous.as_ref()
+            .and_then(|previous| previous.files.iter().find(|file| file.path == artifact.entry.path))
+            .map_or(false, |file| file.hash == artifact.entry.hash)
+            && fs::metadata(&full_path).map(|metadata| metadata.len() == artifact.contents.len() as u64).unwrap_or(false);
+        if !unchanged {
+            fs::create_dir_all(full_path.parent().unwrap())?;
+            write_atomic(&full_path, &artifact.contents)?;
+        }
+        Ok(())
+    }
+
+    fn artifact(&self, kind: ArtifactKind, path: &Path, contents: Vec<u8>) -> Artifact {
+        Artifact { entry: self.entry(kind, path, &contents), contents }
+    }
+
+    fn entry(&self, kind: ArtifactKind, path: &Path, contents: &[u8]) -> ManifestEntry {
+        let path = to_url_path(path);
+        let url = if self.public_path.ends_with('/') {
+            format!("{}{}", self.public_path, path)
+        } else {
+            format!("{}/{}", self.public_path, path)
+        };
+        let hash = format!("{:x}", Sha1::digest(contents));
+        ManifestEntry { kind, path, url, hash }
+    }
+}
+
+/// Write a file, unless it already has these contents.
+pub fn write_if_changed(path: &Path, contents: &[u8]) -> Result<()> {
+    match fs::read(path) {
+        Ok(ref existing) if existing.as_slice() == contents => Ok(()),
+        _ => write_atomic(path, contents),
+    }
+}
+
+/// Numbers temporary files, so builds on different threads never write to the same one.
+static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Write a file by writing a temporary file next to it and renaming it, so a build that fails
+/// or is cancelled halfway never leaves a partially written file, and concurrent builds never
+/// read one.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let file_name = path.file_name()
+        .ok_or_else(|| format_err!("Can not write to {}", path.to_string_lossy()))?;
+    let temp_path = path.with_file_name(format!(".{}.{}.{}.tmp", file_name.to_string_lossy(), process::id(), TEMP_FILES.fetch_add(1, Ordering::SeqCst)));
+    if let Err(err) = fs::write(&temp_path, contents).and_then(|_| fs::rename(&temp_path, path)) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+/// Whether a path is relative to the output directory and stays inside it: it is not
+/// absolute and does not contain `..`.
+fn is_inside_outdir(path: &Path) -> bool {
+    path.components().all(|component| match component {
+        Component::Normal(_) | Component::CurDir => true,
+        _ => false,
+    })
+}
+
+/// Turn a relative path into a URL path, eg. `./js/app.js` becomes `js/app.js`.
+pub fn to_url_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::thread;
+    use serde_json;
+    use serde_json::Value;
+    use super::{Emitter, EmitTarget, ArtifactKind, CopyRule, Manifest, ManifestEntry, OutputDirs, MANIFEST_FILE, PACKAGE_FILE, to_url_path, write_atomic};
+
+    #[test]
+    fn creates_url_paths() {
+        assert_eq!(to_url_path(Path::new("./js/app.js")), "js/app.js");
+        assert_eq!(to_url_path(Path::new("app.js")), "app.js");
+    }
+
+    #[test]
+    fn creates_manifest_entries() {
+        let emitter = Emitter::new(PathBuf::from("dist"))
+            .with_public_path("https://cdn.example.com/app".to_string());
+        assert_eq!(emitter.entry(ArtifactKind::Js, Path::new("./js/app.js"), b"abc"), ManifestEntry {
+            kind: ArtifactKind::Js,
+            path: "js/app.js".to_string(),
+            url: "https://cdn.example.com/app/js/app.js".to_string(),
+            hash: "a9993e364706816aba3e25717850c26c9cd0d89d".to_string(),
+        });
+    }
+
+    #[test]
+    fn emits_in_memory() {
+        let outdir = env::temp_dir().join(format!("js-bundler-in-memory-{}", ::std::process::id()));
+        let emitter = Emitter::new(outdir.clone()).with_target(EmitTarget::Memory);
+        let output = emitter.emit_in_memory("app", "console.log(1)").unwrap();
+        assert_eq!(output.manifest.files.len(), 1);
+        assert_eq!(output.manifest.files[0].url, "/app.js");
+        assert_eq!(output.get("app.js").unwrap().contents, b"console.log(1)".to_vec());
+        assert!(emitter.emit("app", "console.log(1)").is_err());
+        assert!(!outdir.exists());
+    }
+
+    #[test]
+    fn writes_atomically_from_threads() {
+        let dir = env::temp_dir().join(format!("js-bundler-write-atomic-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.js");
+        let threads: Vec<_> = (0..8)
+            .map(|index| {
+                let path = path.clone();
+                thread::spawn(move || write_atomic(&path, format!("thread {}", index).as_bytes()).unwrap())
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(fs::read_to_string(&path).unwrap().starts_with("thread "));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn copies_only_files_inside_the_sandbox() {
+        let root = env::temp_dir().join(format!("js-bundler-copy-sandbox-{}", ::std::process::id()));
+        let outside = env::temp_dir().join(format!("js-bundler-copy-outside-{}.txt", ::std::process::id()));
+        fs::create_dir_all(root.join("public")).unwrap();
+        fs::write(root.join("public/robots.txt"), "User-agent: *").unwrap();
+        fs::write(&outside, "secret").unwrap();
+        let copy = |from: &Path| Emitter::new(root.join("dist"))
+            .with_target(EmitTarget::Memory)
+            .with_sandbox_root(root.clone())
+            .with_copy_rules(vec![CopyRule { from: from.to_string_lossy().into_owned(), to: None }])
+            .emit_in_memory("app", "");
+        let inside = copy(&root.join("public"));
+        let escaped = copy(&outside);
+        fs::remove_dir_all(&root).unwrap();
+        fs::remove_file(&outside).unwrap();
+        assert_eq!(inside.unwrap().artifacts.len(), 2);
+        assert!(escaped.is_err());
+    }
+
+    #[test]
+    fn copies_only_to_free_paths_in_the_output_directory() {
+        let dir = env::temp_dir().join(format!("js-bundler-copy-targets-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("app.js"), "console.log(1)").unwrap();
+        let copy = |to: &str| Emitter::new(dir.join("dist"))
+            .with_target(EmitTarget::Memory)
+            .with_copy_rules(vec![CopyRule { from: dir.join("app.js").to_string_lossy().into_owned(), to: Some(PathBuf::from(to)) }])
+            .emit_in_memory("app", "");
+        let results = ["static", "../static", "/tmp", "."].iter().map(|to| copy(to).map(|output| output.manifest.files[1].path.clone())).collect::<Vec<_>>();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(results[0].as_ref().unwrap(), "static/app.js");
+        assert!(results[1].is_err());
+        assert!(results[2].is_err());
+        // The bundle is also written to app.js.
+        assert!(results[3].is_err());
+    }
+
+    #[test]
+    fn copies_each_file_once() {
+        let dir = env::temp_dir().join(format!("js-bundler-copy-twice-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("robots.txt"), "User-agent: *").unwrap();
+        let rule = || CopyRule { from: dir.join("robots.txt").to_string_lossy().into_owned(), to: Some(PathBuf::from("static")) };
+        let result = Emitter::new(dir.join("dist"))
+            .with_target(EmitTarget::Memory)
+            .with_copy_rules(vec![rule(), rule()])
+            .emit_in_memory("app", "");
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn keeps_output_dirs_inside_the_output_directory() {
+        let emit = |dirs: OutputDirs| Emitter::new(PathBuf::from("dist"))
+            .with_target(EmitTarget::Memory)
+            .with_dirs(dirs)
+            .emit_in_memory("app", "");
+        assert!(emit(OutputDirs { js: PathBuf::from("./js"), ..OutputDirs::default() }).is_ok());
+        assert!(emit(OutputDirs { js: PathBuf::from("../js"), ..OutputDirs::default() }).is_err());
+        assert!(emit(OutputDirs { assets: PathBuf::from("/srv/static"), ..OutputDirs::default() }).is_err());
+    }
+
+    #[test]
+    fn emits_library_package_metadata_and_declarations() {
+        let dir = env::temp_dir().join(format!("js-bundler-library-{}", ::std::process::id()));
+        fs::create_dir_all(dir.join("types/util")).unwrap();
+        fs::create_dir_all(dir.join("types/node_modules/dep")).unwrap();
+        fs::write(dir.join(PACKAGE_FILE), r#"{ "name": "lib", "main": "src/index.js", "types": "./types/index.d.ts" }"#).unwrap();
+        fs::write(dir.join("types/index.d.ts"), "export * from './util/helpers';").unwrap();
+        fs::write(dir.join("types/util/helpers.d.ts"), "export declare function help(): void;").unwrap();
+        fs::write(dir.join("types/node_modules/dep/index.d.ts"), "").unwrap();
+        let output = Emitter::new(dir.join("dist"))
+            .with_target(EmitTarget::Memory)
+            .with_dirs(OutputDirs { js: PathBuf::from("lib"), ..OutputDirs::default() })
+            .with_library_package(dir.clone())
+            .emit_in_memory("index", "module.exports = 1");
+        fs::remove_dir_all(&dir).unwrap();
+        let output = output.unwrap();
+
+        let paths = output.manifest.files.iter().map(|file| file.path.as_str()).collect::<Vec<&str>>();
+        assert_eq!(paths, vec!["lib/index.js", "lib/index.d.ts", "lib/util/helpers.d.ts", "package.json"]);
+        assert_eq!(output.get("lib/util/helpers.d.ts").unwrap().contents, b"export declare function help(): void;".to_vec());
+        let package: Value = serde_json::from_slice(&output.get(PACKAGE_FILE).unwrap().contents).unwrap();
+        assert_eq!(package["name"], "lib");
+        assert_eq!(package["main"], "lib/index.js");
+        assert_eq!(package["types"], "lib/index.d.ts");
+    }
+
+    #[test]
+    fn cleans_only_files_inside_the_output_directory() {
+        let dir = env::temp_dir().join(format!("js-bundler-clean-{}", ::std::process::id()));
+        fs::create_dir_all(dir.join("dist")).unwrap();
+        fs::write(dir.join("keep.txt"), "").unwrap();
+        let previous = Manifest {
+            files: vec![ManifestEntry { kind: ArtifactKind::Asset, path: "../keep.txt".to_string(), url: "/keep.txt".to_string(), hash: String::new() }],
+            ..Manifest::default()
+        };
+        fs::write(dir.join("dist").join(MANIFEST_FILE), serde_json::to_string(&previous).unwrap()).unwrap();
+        let result = Emitter::new(dir.join("dist")).with_clean(true).emit("app", "");
+        let kept = dir.join("keep.txt").exists();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(result.is_err());
+        assert!(kept);
+    }
+}
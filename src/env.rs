@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use quicli::prelude::*;
+
+/// Environment variables to inline into the bundle.
+#[derive(Debug, Clone, Default)]
+pub struct Env {
+    pub vars: BTreeMap<String, String>,
+    /// The .env files that the variables were read from.
+    pub files: Vec<PathBuf>,
+}
+
+impl Env {
+    /// Load the variables starting with `prefix` from the .env files in `dir` and from the
+    /// environment.
+    ///
+    /// Later sources override earlier ones:
+    /// `.env`, `.env.local`, `.env.{mode}`, `.env.{mode}.local`, then the environment itself.
+    pub fn load(dir: &Path, mode: &str, prefix: &str) -> Result<Env> {
+        let mut result = Env::default();
+        let names = vec![
+            ".env".to_string(),
+            ".env.local".to_string(),
+            format!(".env.{}", mode),
+            format!(".env.{}.local", mode),
+        ];
+        for name in names {
+            let path = dir.join(name);
+            if !path.is_file() {
+                continue;
+            }
+            let vars = parse_env(&read_file(&path)?)
+                .map_err(|err| format_err!("Invalid env file {}: {}", path.to_string_lossy(), err))?;
+            result.vars.extend(vars.into_iter().filter(|&(ref name, _)| name.starts_with(prefix)));
+            result.files.push(path);
+        }
+        result.vars.extend(env::vars().filter(|&(ref name, _)| name.starts_with(prefix)));
+        Ok(result)
+    }
+}
+
+/// Parse the `NAME=value` lines of a .env file.
+/// Supports comments, an `export ` prefix, and single or double quoted values.
+pub fn parse_env(source: &str) -> Result<Vec<(String, String)>> {
+    let mut vars = vec![];
+    for (index, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = if line.starts_with("export ") { line["export ".len()..].trim_left() } else { line };
+        let equals = match line.find('=') {
+            Some(equals) => equals,
+            None => bail!("expected NAME=value on line {}", index + 1),
+        };
+        let name = line[..equals].trim();
+        let value = line[equals + 1..].trim();
+        let value = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+            value[1..value.len() - 1].replace("\\n", "\n").replace("\\\"", "\"")
+        } else if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+            value[1..value.len() - 1].to_string()
+        } else {
+            // Unquoted values can have a comment after them.
+            value.splitn(2, " #").next().unwrap().trim_right().to_string()
+        };
+        vars.push((name.to_string(), value));
+    }
+    Ok(vars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_env;
+
+    #[test]
+    fn parses_env_files() {
+        let vars = parse_env("# comment\n\nAPP_A=1\nexport APP_B = \"two\\nlines\"\nAPP_C='#not a comment'\nAPP_D=x # comment\n").unwrap();
+        assert_eq!(vars, vec![
+            ("APP_A".to_string(), "1".to_string()),
+            ("APP_B".to_string(), "two\nlines".to_string()),
+            ("APP_C".to_string(), "#not a comment".to_string()),
+            ("APP_D".to_string(), "x".to_string()),
+        ]);
+        assert!(parse_env("APP_A").is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use quicli::prelude::Error;
+
+/// The error that caused a `BundleError`.
+pub type Cause = Box<StdError + Send + Sync>;
+
+/// The ways a build can fail.
+///
+/// Library functions return `quicli` (`failure`) errors. When a build fails for one of these
+/// reasons, the error wraps a `BundleError`, so embedders can tell failures apart without
+/// parsing messages:
+///
+/// ```rust,ignore
+/// match err.downcast_ref::<BundleError>() {
+///     Some(&BundleError::Resolve { ref specifier, .. }) => eprintln!("missing: {}", specifier),
+///     _ => eprintln!("{}", err),
+/// }
+/// ```
+///
+/// The underlying error is available through `std::error::Error::cause`.
+#[derive(Debug)]
+pub enum BundleError {
+    /// A require() call could not be resolved.
+    Resolve { specifier: String, basedir: PathBuf, cause: Cause },
+    /// A file could not be parsed.
+    Parse { path: PathBuf, cause: Cause },
+    /// A transform failed to change a file.
+    Transform { path: PathBuf, cause: Cause },
+    /// A file could not be read.
+    Io { path: PathBuf, cause: io::Error },
+    /// The options are invalid or can not be used together.
+    Config { message: String },
+    /// Modules require each other, but the output format can not represent that.
+    Cycle { path: PathBuf },
+}
+
+impl BundleError {
+    pub fn resolve<E: Into<Error>>(specifier: &str, basedir: &Path, cause: E) -> BundleError {
+        BundleError::Resolve { specifier: specifier.to_string(), basedir: basedir.to_path_buf(), cause: to_cause(cause) }
+    }
+
+    pub fn parse<E: Into<Error>>(path: &Path, cause: E) -> BundleError {
+        BundleError::Parse { path: path.to_path_buf(), cause: to_cause(cause) }
+    }
+
+    pub fn transform<E: Into<Error>>(path: &Path, cause: E) -> BundleError {
+        BundleError::Transform { path: path.to_path_buf(), cause: to_cause(cause) }
+    }
+
+    pub fn io(path: &Path, cause: io::Error) -> BundleError {
+        BundleError::Io { path: path.to_path_buf(), cause }
+    }
+
+    pub fn config<S: Into<String>>(message: S) -> BundleError {
+        BundleError::Config { message: message.into() }
+    }
+
+    pub fn cycle(path: &Path) -> BundleError {
+        BundleError::Cycle { path: path.to_path_buf() }
+    }
+}
+
+fn to_cause<E: Into<Error>>(cause: E) -> Cause {
+    Box::new(cause.into().compat())
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BundleError::Resolve { ref specifier, ref basedir, ref cause } =>
+                write!(f, "Could not resolve \"{}\" from {}: {}", specifier, basedir.to_string_lossy(), cause),
+            // Parse errors from esprit already name the file.
+            BundleError::Parse { ref cause, .. } => write!(f, "{}", cause),
+            BundleError::Transform { ref path, ref cause } =>
+                write!(f, "Could not transform {}: {}", path.to_string_lossy(), cause),
+            BundleError::Io { ref path, ref cause } =>
+                write!(f, "Could not read {}: {}", path.to_string_lossy(), cause),
+            BundleError::Config { ref message } => write!(f, "{}", message),
+            BundleError::Cycle { ref path } =>
+                write!(f, "The minimal runtime does not support circular dependencies, but {} is part of a cycle", path.to_string_lossy()),
+        }
+    }
+}
+
+impl StdError for BundleError {
+    fn description(&self) -> &str {
+        match *self {
+            BundleError::Resolve { .. } => "could not resolve a module",
+            BundleError::Parse { .. } => "could not parse a file",
+            BundleError::Transform { .. } => "could not transform a file",
+            BundleError::Io { .. } => "could not read a file",
+            BundleError::Config { ref message } => message,
+            BundleError::Cycle { .. } => "circular dependency",
+        }
+    }
+
+    fn cause(&self) -> Option<&StdError> {
+        match *self {
+            BundleError::Resolve { ref cause, .. } |
+            BundleError::Parse { ref cause, .. } |
+            BundleError::Transform { ref cause, .. } => Some(&**cause),
+            BundleError::Io { ref cause, .. } => Some(cause),
+            BundleError::Config { .. } | BundleError::Cycle { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,773 @@
+use easter::decl::{Decl, Dtor};
+use easter::expr::{Expr, ExprListItem};
+use easter::id::Id;
+use easter::obj::{Prop, PropKey, PropVal};
+use easter::patt::{Patt, AssignTarget};
+use easter::stmt::{Block, Script, Stmt, StmtListItem, ForHead, ForInHead};
+use esprit::script;
+use joker::track::{Span, TrackingRef};
+use quicli::prelude::*;
+use serde_json::{Map, Value};
+
+/// Convert a syntax tree to ESTree JSON, for tools that are written in JavaScript.
+///
+/// Nodes get acorn-style `start` and `end` offsets, in UTF-16 code units like JavaScript string
+/// indices. Syntax that is not converted yet, like destructuring patterns and ES2015 syntax,
+/// becomes a `Raw` node that holds its source text in a `raw` property, so no code is lost.
+pub fn to_estree(ast: &Script, source: &str) -> Value {
+    let exporter = Exporter::new(source);
+    let body: Vec<Value> = ast.items.iter().map(|item| exporter.stmt_item(item)).collect();
+    exporter.node("Program", &None, map(vec![("body", Value::from(body)), ("sourceType", Value::from("script"))]))
+}
+
+/// Convert ESTree JSON back to a syntax tree.
+///
+/// This generates JavaScript code from the JSON and parses it again, so it accepts any ESTree
+/// that `generate` supports, not only JSON from `to_estree`. Locations in the result refer to
+/// the generated code.
+pub fn from_estree(value: &Value) -> Result<Script> {
+    let source = generate(value)?;
+    script(&source).map_err(|err| format_err!("Could not parse the code generated from ESTree JSON: {:?}", err))
+}
+
+/// Generate JavaScript code from ESTree JSON.
+/// Supports ES5 nodes and `Raw` nodes. Expressions are parenthesized liberally.
+pub fn generate(value: &Value) -> Result<String> {
+    let mut out = String::new();
+    gen_node(value, &mut out)?;
+    Ok(out)
+}
+
+fn map(entries: Vec<(&str, Value)>) -> Map<String, Value> {
+    entries.into_iter().map(|(key, value)| (key.to_string(), value)).collect()
+}
+
+struct Exporter<'a> {
+    source: &'a str,
+    /// The byte offset and the UTF-16 offset of every character, and of the end of the source.
+    /// Syntax tree offsets count characters.
+    offsets: Vec<(usize, usize)>,
+}
+
+impl<'a> Exporter<'a> {
+    fn new(source: &'a str) -> Exporter<'a> {
+        let mut offsets = Vec::with_capacity(source.len() + 1);
+        let mut utf16_offset = 0;
+        for (byte_offset, c) in source.char_indices() {
+            offsets.push((byte_offset, utf16_offset));
+            utf16_offset += c.len_utf16();
+        }
+        offsets.push((source.len(), utf16_offset));
+        Exporter { source, offsets }
+    }
+
+    fn offsets(&self, offset: u32) -> (usize, usize) {
+        self.offsets.get(offset as usize).cloned().unwrap_or_else(|| *self.offsets.last().unwrap())
+    }
+
+    fn node(&self, kind: &str, span: &Option<Span>, mut fields: Map<String, Value>) -> Value {
+        let mut object = Map::new();
+        object.insert("type".to_string(), Value::from(kind));
+        if let Some(ref span) = *span {
+            object.insert("start".to_string(), Value::from(self.offsets(span.start.offset).1));
+            object.insert("end".to_string(), Value::from(self.offsets(span.end.offset).1));
+        }
+        object.append(&mut fields);
+        Value::Object(object)
+    }
+
+    /// Keep a node that is not converted as its source text.
+    fn raw(&self, span: &Option<Span>) -> Value {
+        let raw = match *span {
+            Some(ref span) => &self.source[self.offsets(span.start.offset).0..self.offsets(span.end.offset).0],
+            None => "",
+        };
+        self.node("Raw", span, map(vec![("raw", Value::from(raw))]))
+    }
+
+    fn stmt_item(&self, item: &StmtListItem) -> Value {
+        match *item {
+            StmtListItem::Stmt(ref stmt) => self.stmt(stmt),
+            StmtListItem::Decl(ref decl) => {
+                let span = decl.tracking_ref();
+                match *decl {
+                    Decl::Fun(ref fun) => self.function("FunctionDeclaration", span, Some(&fun.id), &fun.params.list, &fun.body.items, fun.body.tracking_ref())
+                        .unwrap_or_else(|| self.raw(span)),
+                    _ => self.raw(span),
+                }
+            },
+        }
+    }
+
+    fn stmt_items(&self, items: &[StmtListItem]) -> Value {
+        Value::from(items.iter().map(|item| self.stmt_item(item)).collect::<Vec<Value>>())
+    }
+
+    fn stmt(&self, stmt: &Stmt) -> Value {
+        self.try_stmt(stmt).unwrap_or_else(|| self.raw(stmt.tracking_ref()))
+    }
+
+    fn try_stmt(&self, stmt: &Stmt) -> Option<Value> {
+        let span = stmt.tracking_ref();
+        Some(match *stmt {
+            Stmt::Block(ref block) => self.node("BlockStatement", span, map(vec![
+                ("body", self.stmt_items(&block.items)),
+            ])),
+            Stmt::Var(_, ref dtors, _) => self.var_declaration("var", span, dtors)?,
+            Stmt::Expr(_, ref expr, _) => self.node("ExpressionStatement", span, map(vec![
+                ("expression", self.expr(expr)),
+            ])),
+            Stmt::If(_, ref test, ref cons, ref alt) => self.node("IfStatement", span, map(vec![
+                ("test", self.expr(test)),
+                ("consequent", self.stmt(cons)),
+                ("alternate", alt.as_ref().map_or(Value::Null, |alt| self.stmt(alt))),
+            ])),
+            Stmt::Label(_, ref label, ref body) => self.node("LabeledStatement", span, map(vec![
+                ("label", self.id(label.name.as_ref(), label.tracking_ref())),
+                ("body", self.stmt(body)),
+            ])),
+            Stmt::Switch(_, ref discriminant, ref cases) => self.node("SwitchStatement", span, map(vec![
+                ("discriminant", self.expr(discriminant)),
+                ("cases", Value::from(cases.iter().map(|case| self.node("SwitchCase", case.tracking_ref(), map(vec![
+                    ("test", case.test.as_ref().map_or(Value::Null, |test| self.expr(test))),
+                    ("consequent", self.stmt_items(&case.body)),
+                ]))).collect::<Vec<Value>>())),
+            ])),
+            Stmt::Return(_, ref argument, _) => self.node("ReturnStatement", span, map(vec![
+                ("argument", argument.as_ref().map_or(Value::Null, |argument| self.expr(argument))),
+            ])),
+            Stmt::Throw(_, ref argument, _) => self.node("ThrowStatement", span, map(vec![
+                ("argument", self.expr(argument)),
+            ])),
+            Stmt::While(_, ref test, ref body) => self.node("WhileStatement", span, map(vec![
+                ("test", self.expr(test)),
+                ("body", self.stmt(body)),
+            ])),
+            Stmt::DoWhile(_, ref body, ref test, _) => self.node("DoWhileStatement", span, map(vec![
+                ("body", self.stmt(body)),
+                ("test", self.expr(test)),
+            ])),
+            Stmt::With(_, ref object, ref body) => self.node("WithStatement", span, map(vec![
+                ("object", self.expr(object)),
+                ("body", self.stmt(body)),
+            ])),
+            Stmt::For(_, ref head, ref test, ref update, ref body) => self.node("ForStatement", span, map(vec![
+                ("init", match *head {
+                    Some(ref head) => match **head {
+                        ForHead::Var(ref head_span, ref dtors) => self.var_declaration("var", head_span, dtors)?,
+                        ForHead::Let(ref head_span, ref dtors) => self.var_declaration("let", head_span, dtors)?,
+                        ForHead::Expr(_, ref expr) => self.expr(expr),
+                    },
+                    None => Value::Null,
+                }),
+                ("test", test.as_ref().map_or(Value::Null, |test| self.expr(test))),
+                ("update", update.as_ref().map_or(Value::Null, |update| self.expr(update))),
+                ("body", self.stmt(body)),
+            ])),
+            Stmt::ForIn(_, ref head, ref right, ref body) => self.node("ForInStatement", span, map(vec![
+                ("left", match **head {
+                    ForInHead::Var(ref head_span, Patt::Simple(ref id)) => self.binding("var", head_span, id),
+                    ForInHead::Let(ref head_span, Patt::Simple(ref id)) => self.binding("let", head_span, id),
+                    ForInHead::Expr(ref expr) => self.expr(expr),
+                    _ => return None,
+                }),
+                ("right", self.expr(right)),
+                ("body", self.stmt(body)),
+            ])),
+            Stmt::Try(_, ref block, ref handler, ref finalizer) => self.node("TryStatement", span, map(vec![
+                ("block", self.block(block)),
+                ("handler", match *handler {
+                    Some(ref handler) => match handler.param {
+                        Patt::Simple(ref param) => self.node("CatchClause", handler.tracking_ref(), map(vec![
+                            ("param", self.id(param.name.as_ref(), param.tracking_ref())),
+                            ("body", self.block(&handler.body)),
+                        ])),
+                        _ => return None,
+                    },
+                    None => Value::Null,
+                }),
+                ("finalizer", finalizer.as_ref().map_or(Value::Null, |finalizer| self.block(finalizer))),
+            ])),
+            _ => return None,
+        })
+    }
+
+    fn block(&self, block: &Block) -> Value {
+        self.node("BlockStatement", block.tracking_ref(), map(vec![("body", self.stmt_items(&block.items))]))
+    }
+
+    fn var_declaration(&self, kind: &str, span: &Option<Span>, dtors: &[Dtor]) -> Option<Value> {
+        let mut declarations = vec![];
+        for dtor in dtors {
+            match *dtor {
+                Dtor::Simple(ref dtor_span, ref id, ref init) => declarations.push(self.node("VariableDeclarator", dtor_span, map(vec![
+                    ("id", self.id(id.name.as_ref(), id.tracking_ref())),
+                    ("init", init.as_ref().map_or(Value::Null, |init| self.expr(init))),
+                ]))),
+                _ => return None,
+            }
+        }
+        Some(self.node("VariableDeclaration", span, map(vec![
+            ("kind", Value::from(kind)),
+            ("declarations", Value::from(declarations)),
+        ])))
+    }
+
+    /// A declaration of a single variable without an initializer, like in `for (var key in object)`.
+    fn binding(&self, kind: &str, span: &Option<Span>, id: &Id) -> Value {
+        self.node("VariableDeclaration", span, map(vec![
+            ("kind", Value::from(kind)),
+            ("declarations", Value::from(vec![self.node("VariableDeclarator", id.tracking_ref(), map(vec![
+                ("id", self.id(id.name.as_ref(), id.tracking_ref())),
+                ("init", Value::Null),
+            ]))])),
+        ]))
+    }
+
+    fn function(&self, kind: &str, span: &Option<Span>, id: Option<&Id>, params: &[Patt<Id>], body: &[StmtListItem], body_span: &Option<Span>) -> Option<Value> {
+        let mut param_nodes = vec![];
+        for param in params {
+            match *param {
+                Patt::Simple(ref param) => param_nodes.push(self.id(param.name.as_ref(), param.tracking_ref())),
+                _ => return None,
+            }
+        }
+        Some(self.node(kind, span, map(vec![
+            ("id", id.map_or(Value::Null, |id| self.id(id.name.as_ref(), id.tracking_ref()))),
+            ("params", Value::from(param_nodes)),
+            ("body", self.node("BlockStatement", body_span, map(vec![("body", self.stmt_items(body))]))),
+        ])))
+    }
+
+    fn id(&self, name: &str, span: &Option<Span>) -> Value {
+        self.node("Identifier", span, map(vec![("name", Value::from(name))]))
+    }
+
+    fn expr(&self, expr: &Expr) -> Value {
+        self.try_expr(expr).unwrap_or_else(|| self.raw(expr.tracking_ref()))
+    }
+
+    fn exprs(&self, items: &[ExprListItem]) -> Value {
+        Value::from(items.iter().map(|item| match *item {
+            ExprListItem::Expr(ref expr) => self.expr(expr),
+            ExprListItem::Spread(ref span, ref expr) => self.node("SpreadElement", span, map(vec![("argument", self.expr(expr))])),
+        }).collect::<Vec<Value>>())
+    }
+
+    fn assign_target(&self, target: &AssignTarget) -> Value {
+        match *target {
+            AssignTarget::Id(ref id) => self.id(id.name.as_ref(), id.tracking_ref()),
+            AssignTarget::Dot(ref span, ref object, ref key) => self.member(span, object, self.id(&key.value, key.tracking_ref()), false),
+            AssignTarget::Brack(ref span, ref object, ref property) => self.member(span, object, self.expr(property), true),
+        }
+    }
+
+    fn member(&self, span: &Option<Span>, object: &Expr, property: Value, computed: bool) -> Value {
+        self.node("MemberExpression", span, map(vec![
+            ("object", self.expr(object)),
+            ("property", property),
+            ("computed", Value::from(computed)),
+        ]))
+    }
+
+    fn prop(&self, prop: &Prop) -> Option<Value> {
+        match *prop {
+            Prop::Regular(ref span, ref key, PropVal::Init(ref value)) => {
+                let key = match *key {
+                    PropKey::Id(ref key_span, ref name) => self.id(name, key_span),
+                    PropKey::String(ref key_span, ref literal) => self.node("Literal", key_span, map(vec![("value", Value::from(literal.value.clone()))])),
+                    _ => return None,
+                };
+                Some(self.node("Property", span, map(vec![
+                    ("key", key),
+                    ("value", self.expr(value)),
+                    ("kind", Value::from("init")),
+                ])))
+            },
+            _ => None,
+        }
+    }
+
+    fn try_expr(&self, expr: &Expr) -> Option<Value> {
+        let span = expr.tracking_ref();
+        Some(match *expr {
+            Expr::This(_) => self.node("ThisExpression", span, Map::new()),
+            Expr::Id(ref id) => self.id(id.name.as_ref(), span),
+            Expr::Null(_) => self.node("Literal", span, map(vec![("value", Value::Null)])),
+            Expr::True(_) => self.node("Literal", span, map(vec![("value", Value::from(true))])),
+            Expr::False(_) => self.node("Literal", span, map(vec![("value", Value::from(false))])),
+            Expr::String(_, ref literal) => self.node("Literal", span, map(vec![("value", Value::from(literal.value.clone()))])),
+            Expr::Number(_, ref literal) => self.node("Literal", span, map(vec![("value", Value::from(literal.value))])),
+            Expr::Arr(_, ref elements) => self.node("ArrayExpression", span, map(vec![
+                ("elements", Value::from(elements.iter().map(|element| match *element {
+                    Some(ExprListItem::Expr(ref expr)) => self.expr(expr),
+                    Some(ExprListItem::Spread(ref spread_span, ref expr)) => self.node("SpreadElement", spread_span, map(vec![("argument", self.expr(expr))])),
+                    None => Value::Null,
+                }).collect::<Vec<Value>>())),
+            ])),
+            Expr::Obj(_, ref props) => {
+                let mut properties = vec![];
+                for prop in props {
+                    properties.push(self.prop(prop)?);
+                }
+                self.node("ObjectExpression", span, map(vec![("properties", Value::from(properties))]))
+            },
+            Expr::Seq(_, ref exprs) => self.node("SequenceExpression", span, map(vec![
+                ("expressions", Value::from(exprs.iter().map(|expr| self.expr(expr)).collect::<Vec<Value>>())),
+            ])),
+            Expr::Unop(_, ref op, ref argument) => self.node("UnaryExpression", span, map(vec![
+                ("operator", Value::from(op.tag.to_string())),
+                ("prefix", Value::from(true)),
+                ("argument", self.expr(argument)),
+            ])),
+            Expr::Binop(_, ref op, ref left, ref right) => self.node("BinaryExpression", span, map(vec![
+                ("operator", Value::from(op.tag.to_string())),
+                ("left", self.expr(left)),
+                ("right", self.expr(right)),
+            ])),
+            Expr::Logop(_, ref op, ref left, ref right) => self.node("LogicalExpression", span, map(vec![
+                ("operator", Value::from(op.tag.to_string())),
+                ("left", self.expr(left)),
+                ("right", self.expr(right)),
+            ])),
+            Expr::PreInc(_, ref target) | Expr::PostInc(_, ref target) |
+            Expr::PreDec(_, ref target) | Expr::PostDec(_, ref target) => {
+                let (operator, prefix) = match *expr {
+                    Expr::PreInc(..) => ("++", true),
+                    Expr::PostInc(..) => ("++", false),
+                    Expr::PreDec(..) => ("--", true),
+                    _ => ("--", false),
+                };
+                self.node("UpdateExpression", span, map(vec![
+                    ("operator", Value::from(operator)),
+                    ("prefix", Value::from(prefix)),
+                    ("argument", self.assign_target(target)),
+                ]))
+            },
+            Expr::Assign(_, Patt::Simple(ref target), ref right) => self.node("AssignmentExpression", span, map(vec![
+                ("operator", Value::from("=")),
+                ("left", self.assign_target(target)),
+                ("right", self.expr(right)),
+            ])),
+            Expr::BinAssign(_, ref op, ref target, ref right) => self.node("AssignmentExpression", span, map(vec![
+                ("operator", Value::from(op.tag.to_string())),
+                ("left", self.assign_target(target)),
+                ("right", self.expr(right)),
+            ])),
+            Expr::Cond(_, ref test, ref cons, ref alt) => self.node("ConditionalExpression", span, map(vec![
+                ("test", self.expr(test)),
+                ("consequent", self.expr(cons)),
+                ("alternate", self.expr(alt)),
+            ])),
+            Expr::Call(_, ref callee, ref args) => self.node("CallExpression", span, map(vec![
+                ("callee", self.expr(callee)),
+                ("arguments", self.exprs(args)),
+            ])),
+            Expr::New(_, ref callee, ref args) => self.node("NewExpression", span, map(vec![
+                ("callee", self.expr(callee)),
+                ("arguments", args.as_ref().map_or_else(|| Value::from(Vec::<Value>::new()), |args| self.exprs(args))),
+            ])),
+            Expr::Dot(_, ref object, ref key) => self.member(span, object, self.id(&key.value, key.tracking_ref()), false),
+            Expr::Brack(_, ref object, ref property) => self.member(span, object, self.expr(property), true),
+            Expr::Fun(ref fun) => self.function("FunctionExpression", span, fun.id.as_ref(), &fun.params.list, &fun.body.items, fun.body.tracking_ref())?,
+            _ => return None,
+        })
+    }
+}
+
+fn field<'v>(value: &'v Value, name: &str) -> Result<&'v Value> {
+    value.get(name).ok_or_else(|| format_err!("Missing `{}` in ESTree node {}", name, value))
+}
+
+fn str_field<'v>(value: &'v Value, name: &str) -> Result<&'v str> {
+    field(value, name)?.as_str().ok_or_else(|| format_err!("Expected `{}` to be a string in ESTree node {}", name, value))
+}
+
+fn array_field<'v>(value: &'v Value, name: &str) -> Result<&'v Vec<Value>> {
+    field(value, name)?.as_array().ok_or_else(|| format_err!("Expected `{}` to be an array in ESTree node {}", name, value))
+}
+
+fn gen_list(values: &[Value], separator: &str, out: &mut String) -> Result<()> {
+    for (index, value) in values.iter().enumerate() {
+        if index > 0 {
+            out.push_str(separator);
+        }
+        gen_node(value, out)?;
+    }
+    Ok(())
+}
+
+/// Generate a sub-expression, in parentheses unless it can never need them.
+fn gen_operand(value: &Value, out: &mut String) -> Result<()> {
+    match value.get("type").and_then(Value::as_str) {
+        Some("Identifier") | Some("Literal") | Some("ThisExpression") | Some("ArrayExpression") => gen_node(value, out),
+        _ => {
+            out.push('(');
+            gen_node(value, out)?;
+            out.push(')');
+            Ok(())
+        },
+    }
+}
+
+fn gen_optional(value: Option<&Value>, out: &mut String) -> Result<()> {
+    match value {
+        Some(value) if !value.is_null() => gen_node(value, out),
+        _ => Ok(()),
+    }
+}
+
+fn gen_node(value: &Value, out: &mut String) -> Result<()> {
+    let kind = str_field(value, "type")?;
+    match kind {
+        "Program" | "BlockStatement" => {
+            if kind == "BlockStatement" { out.push_str("{\n"); }
+            for stmt in array_field(value, "body")? {
+                gen_node(stmt, out)?;
+                out.push('\n');
+            }
+            if kind == "BlockStatement" { out.push('}'); }
+        },
+        "Raw" => out.push_str(str_field(value, "raw")?),
+        "EmptyStatement" => out.push(';'),
+        "DebuggerStatement" => out.push_str("debugger;"),
+        "ExpressionStatement" => {
+            gen_operand(field(value, "expression")?, out)?;
+            out.push(';');
+        },
+        "VariableDeclaration" => {
+            out.push_str(str_field(value, "kind")?);
+            out.push(' ');
+            gen_list(array_field(value, "declarations")?, ", ", out)?;
+            out.push(';');
+        },
+        "VariableDeclarator" => {
+            gen_node(field(value, "id")?, out)?;
+            if let Some(init) = value.get("init").filter(|init| !init.is_null()) {
+                out.push_str(" = ");
+                gen_operand(init, out)?;
+            }
+        },
+        "IfStatement" => {
+            out.push_str("if (");
+            gen_node(field(value, "test")?, out)?;
+            out.push_str(") ");
+            gen_node(field(value, "consequent")?, out)?;
+            if let Some(alternate) = value.get("alternate").filter(|alternate| !alternate.is_null()) {
+                out.push_str(" else ");
+                gen_node(alternate, out)?;
+            }
+        },
+        "LabeledStatement" => {
+            gen_node(field(value, "label")?, out)?;
+            out.push_str(": ");
+            gen_node(field(value, "body")?, out)?;
+        },
+        "BreakStatement" | "ContinueStatement" => {
+            out.push_str(if kind == "BreakStatement" { "break" } else { "continue" });
+            if let Some(label) = value.get("label").filter(|label| !label.is_null()) {
+                out.push(' ');
+                gen_node(label, out)?;
+            }
+            out.push(';');
+        },
+        "SwitchStatement" => {
+            out.push_str("switch (");
+            gen_node(field(value, "discriminant")?, out)?;
+            out.push_str(") {\n");
+            for case in array_field(value, "cases")? {
+                match case.get("test").filter(|test| !test.is_null()) {
+                    Some(test) => {
+                        out.push_str("case ");
+                        gen_node(test, out)?;
+                        out.push_str(":\n");
+                    },
+                    None => out.push_str("default:\n"),
+                }
+                for stmt in array_field(case, "consequent")? {
+                    gen_node(stmt, out)?;
+                    out.push('\n');
+                }
+            }
+            out.push('}');
+        },
+        "ReturnStatement" | "ThrowStatement" => {
+            out.push_str(if kind == "ReturnStatement" { "return " } else { "throw " });
+            gen_optional(value.get("argument"), out)?;
+            out.push(';');
+        },
+        "TryStatement" => {
+            out.push_str("try ");
+            gen_node(field(value, "block")?, out)?;
+            if let Some(handler) = value.get("handler").filter(|handler| !handler.is_null()) {
+                out.push_str(" catch (");
+                gen_node(field(handler, "param")?, out)?;
+                out.push_str(") ");
+                gen_node(field(handler, "body")?, out)?;
+            }
+            if let Some(finalizer) = value.get("finalizer").filter(|finalizer| !finalizer.is_null()) {
+                out.push_str(" finally ");
+                gen_node(finalizer, out)?;
+            }
+        },
+        "WhileStatement" | "WithStatement" => {
+            out.push_str(if kind == "WhileStatement" { "while (" } else { "with (" });
+            gen_node(field(value, if kind == "WhileStatement" { "test" } else { "object" })?, out)?;
+            out.push_str(") ");
+            gen_node(field(value, "body")?, out)?;
+        },
+        "DoWhileStatement" => {
+            out.push_str("do ");
+            gen_node(field(value, "body")?, out)?;
+            out.push_str(" while (");
+            gen_node(field(value, "test")?, out)?;
+            out.push_str(");");
+        },
+        "ForStatement" => {
+            out.push_str("for (");
+            match value.get("init").filter(|init| !init.is_null()) {
+                // The declaration already ends in a semicolon.
+                Some(init) if init.get("type").and_then(Value::as_str) == Some("VariableDeclaration") => gen_node(init, out)?,
+                init => {
+                    gen_optional(init, out)?;
+                    out.push(';');
+                },
+            }
+            out.push(' ');
+            gen_optional(value.get("test"), out)?;
+            out.push_str("; ");
+            gen_optional(value.get("update"), out)?;
+            out.push_str(") ");
+            gen_node(field(value, "body")?, out)?;
+        },
+        "ForInStatement" => {
+            out.push_str("for (");
+            let left = field(value, "left")?;
+            if left.get("type").and_then(Value::as_str) == Some("VariableDeclaration") {
+                out.push_str(str_field(left, "kind")?);
+                out.push(' ');
+                gen_list(array_field(left, "declarations")?, ", ", out)?;
+            } else {
+                gen_node(left, out)?;
+            }
+            out.push_str(" in ");
+            gen_node(field(value, "right")?, out)?;
+            out.push_str(") ");
+            gen_node(field(value, "body")?, out)?;
+        },
+        "FunctionDeclaration" | "FunctionExpression" => {
+            out.push_str("function ");
+            gen_optional(value.get("id"), out)?;
+            out.push('(');
+            gen_list(array_field(value, "params")?, ", ", out)?;
+            out.push_str(") ");
+            gen_node(field(value, "body")?, out)?;
+        },
+        "Identifier" => out.push_str(str_field(value, "name")?),
+        "Literal" => match value.get("regex") {
+            Some(regex) => {
+                out.push('/');
+                out.push_str(str_field(regex, "pattern")?);
+                out.push('/');
+                out.push_str(str_field(regex, "flags")?);
+            },
+            // JSON literals are valid JavaScript, apart from line and paragraph separators in strings.
+            None => out.push_str(&field(value, "value")?.to_string().replace('\u{2028}', "\\u2028").replace('\u{2029}', "\\u2029")),
+        },
+        "ThisExpression" => out.push_str("this"),
+        "ArrayExpression" => {
+            out.push('[');
+            for element in array_field(value, "elements")? {
+                if !element.is_null() {
+                    gen_operand(element, out)?;
+                }
+                out.push_str(", ");
+            }
+            out.push(']');
+        },
+        "ObjectExpression" => {
+            out.push('{');
+            for (index, property) in array_field(value, "properties")?.iter().enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                let key = field(property, "key")?;
+                let computed = property.get("computed").and_then(Value::as_bool).unwrap_or(false);
+                let kind = property.get("kind").and_then(Value::as_str).unwrap_or("init");
+                if kind != "init" {
+                    // get and set properties are functions without the `function` keyword.
+                    let function = field(property, "value")?;
+                    out.push_str(kind);
+                    out.push(' ');
+                    gen_node(key, out)?;
+                    out.push('(');
+                    gen_list(array_field(function, "params")?, ", ", out)?;
+                    out.push_str(") ");
+                    gen_node(field(function, "body")?, out)?;
+                    continue;
+                }
+                if computed { out.push('['); }
+                gen_node(key, out)?;
+                if computed { out.push(']'); }
+                out.push_str(": ");
+                gen_operand(field(property, "value")?, out)?;
+            }
+            out.push('}');
+        },
+        "SequenceExpression" => {
+            for (index, expr) in array_field(value, "expressions")?.iter().enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                gen_operand(expr, out)?;
+            }
+        },
+        "UnaryExpression" => {
+            out.push_str(str_field(value, "operator")?);
+            out.push(' ');
+            gen_operand(field(value, "argument")?, out)?;
+        },
+        "UpdateExpression" => {
+            let prefix = value.get("prefix").and_then(Value::as_bool).unwrap_or(false);
+            if prefix { out.push_str(str_field(value, "operator")?); }
+            gen_operand(field(value, "argument")?, out)?;
+            if !prefix { out.push_str(str_field(value, "operator")?); }
+        },
+        "BinaryExpression" | "LogicalExpression" | "AssignmentExpression" => {
+            let left = field(value, "left")?;
+            // Assignment targets can not be parenthesized in every engine, and never need to be.
+            if kind == "AssignmentExpression" { gen_node(left, out)?; } else { gen_operand(left, out)?; }
+            out.push(' ');
+            out.push_str(str_field(value, "operator")?);
+            out.push(' ');
+            gen_operand(field(value, "right")?, out)?;
+        },
+        "ConditionalExpression" => {
+            gen_operand(field(value, "test")?, out)?;
+            out.push_str(" ? ");
+            gen_operand(field(value, "consequent")?, out)?;
+            out.push_str(" : ");
+            gen_operand(field(value, "alternate")?, out)?;
+        },
+        "CallExpression" | "NewExpression" => {
+            if kind == "NewExpression" { out.push_str("new "); }
+            gen_operand(field(value, "callee")?, out)?;
+            out.push('(');
+            for (index, argument) in array_field(value, "arguments")?.iter().enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                gen_operand(argument, out)?;
+            }
+            out.push(')');
+        },
+        "SpreadElement" => {
+            out.push_str("...");
+            gen_operand(field(value, "argument")?, out)?;
+        },
+        "MemberExpression" => {
+            let object = field(value, "object")?;
+            // `1.toString()` would be a syntax error.
+            if object.get("value").map_or(false, Value::is_number) {
+                out.push('(');
+                gen_node(object, out)?;
+                out.push(')');
+            } else {
+                gen_operand(object, out)?;
+            }
+            let property = field(value, "property")?;
+            if value.get("computed").and_then(Value::as_bool).unwrap_or(false) {
+                out.push('[');
+                gen_node(property, out)?;
+                out.push(']');
+            } else {
+                out.push('.');
+                gen_node(property, out)?;
+            }
+        },
+        _ => bail!("Can not generate code for ESTree nodes of type {}", kind),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use esprit::script;
+    use estree_detect_requires::detect;
+    use serde_json::{self, Value};
+    use super::{to_estree, from_estree, generate};
+
+    #[test]
+    fn exports_estree() {
+        let source = "var a = require('b'), c;\nif (!a) { a.d[0] += 1 + 2 } else throw new Error('x')";
+        let estree = to_estree(&script(source).unwrap(), source);
+        assert_eq!(estree["type"], "Program");
+        let declaration = &estree["body"][0]["declarations"][0];
+        assert_eq!(declaration["id"]["name"], "a");
+        assert_eq!(declaration["init"]["type"], "CallExpression");
+        assert_eq!(declaration["init"]["arguments"][0]["value"], "b");
+        assert_eq!(estree["body"][0]["declarations"][1]["init"], Value::Null);
+        let statement = &estree["body"][1];
+        assert_eq!(statement["test"]["operator"], "!");
+        assert_eq!(statement["consequent"]["body"][0]["expression"]["operator"], "+=");
+        assert_eq!(statement["consequent"]["body"][0]["expression"]["left"]["computed"], true);
+        assert_eq!(statement["alternate"]["argument"]["type"], "NewExpression");
+        assert_eq!(statement["start"], 25);
+    }
+
+    #[test]
+    fn exports_functions_loops_and_try() {
+        let source = "function f(a, b) { for (var i = 0; i < a; i++) {} for (var k in b) {} }\n\
+                      module.exports = function (x) { try { f(x) } catch (err) { throw err } finally {} }";
+        let estree = to_estree(&script(source).unwrap(), source);
+        let declaration = &estree["body"][0];
+        assert_eq!(declaration["type"], "FunctionDeclaration");
+        assert_eq!(declaration["id"]["name"], "f");
+        assert_eq!(declaration["params"][1]["name"], "b");
+        assert_eq!(declaration["body"]["body"][0]["type"], "ForStatement");
+        assert_eq!(declaration["body"]["body"][0]["init"]["declarations"][0]["id"]["name"], "i");
+        assert_eq!(declaration["body"]["body"][0]["update"]["type"], "UpdateExpression");
+        assert_eq!(declaration["body"]["body"][1]["type"], "ForInStatement");
+        assert_eq!(declaration["body"]["body"][1]["left"]["declarations"][0]["id"]["name"], "k");
+        let function = &estree["body"][1]["expression"]["right"];
+        assert_eq!(function["type"], "FunctionExpression");
+        assert_eq!(function["id"], Value::Null);
+        let statement = &function["body"]["body"][0];
+        assert_eq!(statement["type"], "TryStatement");
+        assert_eq!(statement["handler"]["param"]["name"], "err");
+        assert_eq!(statement["finalizer"]["type"], "BlockStatement");
+        assert_eq!(detect(&from_estree(&estree).unwrap()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn keeps_unsupported_syntax_as_raw_source() {
+        let source = "var s = '\u{1F600}';\nlet a = [s]";
+        let estree = to_estree(&script(source).unwrap(), source);
+        let declaration = &estree["body"][1];
+        assert_eq!(declaration["type"], "Raw");
+        assert_eq!(declaration["raw"], "let a = [s]");
+        // Offsets count UTF-16 code units, like JavaScript strings. The emoji is two of them.
+        assert_eq!(declaration["start"], 14);
+    }
+
+    #[test]
+    fn parenthesizes_numbers_in_member_expressions() {
+        let source = "(1).toString()";
+        let generated = generate(&to_estree(&script(source).unwrap(), source)).unwrap();
+        assert!(generated.contains("(1).toString"), "{}", generated);
+        assert!(script(&generated).is_ok());
+    }
+
+    #[test]
+    fn imports_estree() {
+        let source = "var a = require('b');\nswitch (a.c) { case 1: a = [1, , 2]; break; default: f(a ? {x: 1} : null, -a, typeof a) }";
+        let exported = to_estree(&script(source).unwrap(), source);
+        let imported = from_estree(&exported).unwrap();
+        assert_eq!(detect(&imported), vec!["b"]);
+
+        let generated = generate(&exported).unwrap();
+        assert!(generated.starts_with("var a = (require(\"b\"));"));
+        let unknown: Value = serde_json::from_str(r#"{ "type": "Program", "body": [{ "type": "Unknown" }] }"#).unwrap();
+        assert!(from_estree(&unknown).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use easter::stmt::{Script, Stmt};
+use easter::decl::{Decl, Dtor};
+use easter::expr::Expr;
+use easter::obj::Prop;
+use easter::patt::Patt;
+use estree_detect_requires::{Walker, Callbacks, Control, NodePath};
+use graph::{ModuleMap, SourceFile};
+use package::package_from_path;
+use sources::APP_PACKAGE;
+
+/// A language feature that is newer than ES5, so older browsers can not run code that uses it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Feature {
+    /// `let` and `const` declarations.
+    LetConst,
+    /// Array and object patterns, like `var { a } = b`. Nested patterns are counted too.
+    Destructuring,
+    /// `for (a of b)` loops.
+    ForOf,
+    /// Shorthand properties, like `{ a }`.
+    ShorthandProperties,
+    /// `new.target`.
+    NewTarget,
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Feature::LetConst => "let/const",
+            Feature::Destructuring => "destructuring",
+            Feature::ForOf => "for-of",
+            Feature::ShorthandProperties => "shorthand properties",
+            Feature::NewTarget => "new.target",
+        })
+    }
+}
+
+/// How often each feature is used. Features that are not used are not included.
+pub type FeatureCounts = BTreeMap<Feature, usize>;
+
+/// Count the uses of each feature in a file.
+pub fn count_features(ast: &Script) -> FeatureCounts {
+    Walker::new(ast, CountFeatures::default()).walk().counts
+}
+
+/// Count the uses of each feature per package, to see which packages need to be compiled for
+/// older browsers. Files that are not inside a node_modules folder are counted as the `app`
+/// package. Every package with JavaScript files is included, even if it uses no features.
+pub fn feature_stats(modules: &ModuleMap) -> BTreeMap<String, FeatureCounts> {
+    let mut stats: BTreeMap<String, FeatureCounts> = BTreeMap::new();
+    for (path, record) in modules.iter() {
+        let ast = match record.file {
+            SourceFile::CJS { ast: Some(ref ast), .. } => ast,
+            _ => continue,
+        };
+        let package = package_from_path(path.as_str()).unwrap_or(APP_PACKAGE);
+        let counts = stats.entry(package.to_string()).or_insert_with(FeatureCounts::new);
+        for (feature, count) in count_features(ast) {
+            *counts.entry(feature).or_insert(0) += count;
+        }
+    }
+    stats
+}
+
+#[derive(Default)]
+struct CountFeatures {
+    counts: FeatureCounts,
+}
+
+impl CountFeatures {
+    fn add(&mut self, feature: Feature) {
+        *self.counts.entry(feature).or_insert(0) += 1;
+    }
+
+    fn add_dtors(&mut self, dtors: &[Dtor]) {
+        for dtor in dtors {
+            if let Dtor::Compound(..) = *dtor {
+                self.add(Feature::Destructuring);
+            }
+        }
+    }
+}
+
+impl Callbacks for CountFeatures {
+    fn pre_stmt(&mut self, stmt: &Stmt, _path: &NodePath) -> Control {
+        match *stmt {
+            Stmt::Var(_, ref dtors, _) => self.add_dtors(dtors),
+            Stmt::ForOf(..) => self.add(Feature::ForOf),
+            _ => (),
+        }
+        Control::Continue
+    }
+
+    fn pre_decl(&mut self, decl: &Decl) -> Control {
+        match *decl {
+            Decl::Let(_, ref dtors, _) => {
+                self.add(Feature::LetConst);
+                self.add_dtors(dtors);
+            },
+            Decl::Const(_, ref dtors, _) => {
+                self.add(Feature::LetConst);
+                for dtor in dtors {
+                    if let Patt::Compound(_) = dtor.patt {
+                        self.add(Feature::Destructuring);
+                    }
+                }
+            },
+            Decl::Fun(_) => (),
+        }
+        Control::Continue
+    }
+
+    fn pre_expr(&mut self, expr: &Expr, _path: &NodePath) -> Control {
+        match *expr {
+            Expr::NewTarget(..) => self.add(Feature::NewTarget),
+            Expr::Obj(_, ref props) => {
+                for prop in props {
+                    if let Prop::Shorthand(_) = *prop {
+                        self.add(Feature::ShorthandProperties);
+                    }
+                }
+            },
+            _ => (),
+        }
+        Control::Continue
+    }
+
+    fn pre_patt<T>(&mut self, patt: &Patt<T>) -> Control {
+        if let Patt::Compound(_) = *patt {
+            self.add(Feature::Destructuring);
+        }
+        Control::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use esprit::script;
+    use super::{count_features, Feature};
+
+    #[test]
+    fn counts_features() {
+        let ast = script("
+            const { a, b: [c] } = require('a');
+            let d;
+            var [e] = f, g = { a, d };
+            for (var h of e) {}
+            function i() { return new.target }
+            [a, c] = [c, a];
+        ").unwrap();
+        let counts = count_features(&ast);
+        assert_eq!(counts.get(&Feature::LetConst), Some(&2));
+        assert_eq!(counts.get(&Feature::Destructuring), Some(&4));
+        assert_eq!(counts.get(&Feature::ForOf), Some(&1));
+        assert_eq!(counts.get(&Feature::ShorthandProperties), Some(&2));
+        assert_eq!(counts.get(&Feature::NewTarget), Some(&1));
+    }
+
+    #[test]
+    fn leaves_out_unused_features() {
+        let ast = script("var a = function () { return [1, 2] }").unwrap();
+        assert!(count_features(&ast).is_empty());
+    }
+}
@@ -0,0 +1,253 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use easter::expr::{Expr, ExprListItem};
+use easter::id::Id;
+use estree_detect_requires::{Walker, Callbacks, Control, NodePath};
+use joker::track::Span;
+use quicli::prelude::*;
+use serde_json;
+use error::BundleError;
+use graph::{ModuleMap, ModuleRecord, SourceFile};
+use ident::{UniqueNames, identifier_words};
+use transforms::Patch;
+
+/// Pack a `ModuleMap` into a bundle without a module runtime.
+///
+/// Modules run in dependency order, each in its own function, and require() calls are
+/// replaced by the `module.exports` of the required module. This only works for graphs with
+/// a single entry point, no cycles and only static require() calls. Unlike with a module
+/// runtime, every module runs up front, even if it is only required conditionally.
+pub struct FlatPack<'a> {
+    modules: &'a ModuleMap,
+}
+
+impl<'a> FlatPack<'a> {
+    pub fn new(modules: &ModuleMap) -> FlatPack {
+        FlatPack { modules }
+    }
+
+    pub fn to_string(&self) -> Result<String> {
+        let mut entries = self.modules.values().filter(|record| record.entry);
+        let entry = match (entries.next(), entries.next()) {
+            (Some(entry), None) => entry,
+            _ => bail!("The minimal runtime only supports bundles with a single entry point"),
+        };
+
+        let mut order = vec![];
+        sort_modules(entry, &mut HashSet::new(), &mut HashSet::new(), &mut order)?;
+
+        let names = module_names(&order);
+        let mut string = String::from("(function () {\n");
+        for record in order {
+            let name = &names[&record.id];
+            string.push_str(&format!("var {name} = {{exports: {{}}}};\n(function (module, exports) {{\n", name = name));
+            string.push_str(&link(record, &names)?);
+            string.push_str(&format!("\n}}).call({name}.exports, {name}, {name}.exports);\n", name = name));
+        }
+        string.push_str("})();");
+        Ok(string)
+    }
+}
+
+/// Name the variable for each module after its file.
+///
+/// The variables are visible inside every module, so the names must not shadow anything that
+/// a module uses, or the names that the module wrapper passes in.
+fn module_names(order: &[&Rc<ModuleRecord>]) -> HashMap<u32, String> {
+    let mut taken: HashSet<String> = ["module", "exports", "require"].iter().map(|name| name.to_string()).collect();
+    for record in order {
+        taken.extend(identifier_words(record.file.source()));
+    }
+    let mut names = UniqueNames::new(taken);
+    order.iter()
+        .map(|record| {
+            let path = record.file.path();
+            let stem = path.file_stem().map_or_else(|| "module".to_string(), |stem| stem.to_string_lossy().into_owned());
+            (record.id, names.name(&stem))
+        })
+        .collect()
+}
+
+/// Collect the modules in the order they must run: dependencies before the modules that require them.
+fn sort_modules<'r>(record: &'r Rc<ModuleRecord>, visiting: &mut HashSet<u32>, done: &mut HashSet<u32>, order: &mut Vec<&'r Rc<ModuleRecord>>) -> Result<()> {
+    if done.contains(&record.id) {
+        return Ok(());
+    }
+    if !visiting.insert(record.id) {
+        return Err(BundleError::cycle(record.file.path()).into());
+    }
+    for dependency in record.dependencies.values() {
+        match dependency.record {
+            Some(ref dep_record) => sort_modules(dep_record, visiting, done, order)?,
+            // Only modules that were still being loaded when they were required have no record.
+            None if dependency.id.is_some() => return Err(BundleError::cycle(record.file.path()).into()),
+            None => (),
+        }
+    }
+    visiting.remove(&record.id);
+    done.insert(record.id);
+    order.push(record);
+    Ok(())
+}
+
+/// Replace the require() calls in a module by references to the required modules.
+fn link<'r>(record: &'r ModuleRecord, module_names: &HashMap<u32, String>) -> Result<Cow<'r, str>> {
+    let ast = match record.file {
+        SourceFile::CJS { ast: Some(ref ast), .. } => ast,
+        _ => return Ok(Cow::Borrowed(&**record.file.source())),
+    };
+    let missing: HashSet<&str> = record.dependencies.iter()
+        .filter(|&(_, dependency)| dependency.is_missing())
+        .map(|(id, _)| id.as_str())
+        .collect();
+    let names: HashMap<&str, &String> = record.dependencies.iter()
+        .filter_map(|(id, dependency)| dependency.record.as_ref().map(|dep_record| (id.as_str(), &module_names[&dep_record.id])))
+        .collect();
+
+    let found = Walker::new(ast, FindRequireCalls::new()).with_scopes().walk();
+    let mut patch = Patch::new();
+    for (span, id) in found.calls {
+        match id {
+            // `require('x')()` calls the export without a `this` value, so keep it that way.
+            Some(ref id) => match names.get(id.as_str()) {
+                Some(name) if found.callees.iter().any(|callee| callee.start.offset == span.start.offset) =>
+                    patch.replace(&span, &format!("(0, {}.exports)", name)),
+                Some(name) => patch.replace(&span, &format!("{}.exports", name)),
+                // An optional dependency that was not found: throw where it is required, like Node does.
+                None if missing.contains(id.as_str()) => patch.replace(&span, &format!(
+                    "(function () {{ var err = new Error({message}); err.code = 'MODULE_NOT_FOUND'; throw err }})()",
+                    message = serde_json::to_string(&format!("Cannot find module '{}'", id)).unwrap(),
+                )),
+                None => bail!("The minimal runtime can not link require('{}') in {}, because it is not bundled", id, record.file.path().to_string_lossy()),
+            },
+            None => bail!("The minimal runtime does not support dynamic require() calls, like the one in {} at {}:{}",
+                          record.file.path().to_string_lossy(), span.start.line, span.start.column),
+        }
+    }
+    if patch.is_empty() {
+        return Ok(Cow::Borrowed(&**record.file.source()));
+    }
+    Ok(Cow::Owned(patch.apply(record.file.source())))
+}
+
+/// Find all calls to the CommonJS require(), with their argument if it is a string literal.
+struct FindRequireCalls {
+    calls: Vec<(Span, Option<String>)>,
+    /// require() calls whose result is called directly.
+    callees: Vec<Span>,
+}
+
+impl FindRequireCalls {
+    fn new() -> Self {
+        FindRequireCalls { calls: vec![], callees: vec![] }
+    }
+}
+
+fn is_require_call(expr: &Expr) -> bool {
+    match *expr {
+        Expr::Call(_, ref callee, _) => match **callee {
+            Expr::Id(Id { ref name, .. }) => name.as_ref() == "require",
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+impl Callbacks for FindRequireCalls {
+    fn pre_expr(&mut self, expr: &Expr, path: &NodePath) -> Control {
+        // Calls to a `require` that the module declares itself are left alone, like in `detect`.
+        if path.scopes().is_declared("require") {
+            return Control::Continue;
+        }
+        if let Expr::Call(Some(span), ref callee, ref args) = *expr {
+            if let Expr::Call(Some(callee_span), _, _) = **callee {
+                if is_require_call(callee) {
+                    self.callees.push(callee_span);
+                }
+            }
+            if !is_require_call(expr) {
+                return Control::Continue;
+            }
+            let id = match args.first() {
+                Some(&ExprListItem::Expr(Expr::String(_, ref literal))) => Some(literal.value.clone()),
+                _ => None,
+            };
+            self.calls.push((span, id));
+        }
+        Control::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+    use deps::Deps;
+    use graph::{Dependencies, Dependency, ModuleMap, ModuleRecord};
+    use loader::parse_cjs;
+    use path::NormalizedPath;
+    use super::FlatPack;
+
+    fn record(id: u32, path: &str, source: &str, entry: bool, dependencies: Dependencies) -> Rc<ModuleRecord> {
+        Rc::new(ModuleRecord {
+            id,
+            file: parse_cjs(PathBuf::from(path), source.to_string()).unwrap(),
+            entry,
+            dependencies,
+        })
+    }
+
+    #[test]
+    fn links_modules_in_dependency_order() {
+        let dir = env::temp_dir().join(format!("js-bundler-flat-pack-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.js"), "console.log(require('./b'))").unwrap();
+        fs::write(dir.join("b.js"), "module.exports = 1").unwrap();
+
+        let mut deps = Deps::new().include_builtins(false).with_basedir(dir.clone());
+        deps.run("./a.js").unwrap();
+        let bundle = FlatPack::new(&deps).to_string();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(bundle.unwrap(), "(function () {\n\
+            var b$1 = {exports: {}};\n(function (module, exports) {\nmodule.exports = 1\n}).call(b$1.exports, b$1, b$1.exports);\n\
+            var a = {exports: {}};\n(function (module, exports) {\nconsole.log(b$1.exports)\n}).call(a.exports, a, a.exports);\n\
+            })();");
+    }
+
+    #[test]
+    fn avoids_unsafe_module_names() {
+        let class = record(2, "/class.js", "module.exports = function () { return this }", false, Dependencies::new());
+        let mut dependencies = Dependencies::new();
+        dependencies.insert("./class".to_string(), Dependency::resolved("./class".to_string(), PathBuf::from("/class.js")).with_record(&class));
+        let index = record(1, "/index.js", "var _class = 1; require('./class')()", true, dependencies);
+
+        let mut modules = ModuleMap::new();
+        modules.insert(NormalizedPath::from(Path::new("/index.js")), index);
+        modules.insert(NormalizedPath::from(Path::new("/class.js")), class);
+        let bundle = FlatPack::new(&modules).to_string().unwrap();
+        assert!(bundle.contains("var _class$1 = {exports: {}};"));
+        assert!(bundle.contains("var _class = 1; (0, _class$1.exports)()"));
+    }
+
+    #[test]
+    fn throws_for_missing_optional_dependencies() {
+        let mut dependencies = Dependencies::new();
+        dependencies.insert("peer".to_string(), Dependency::uninitialized("peer".to_string()).with_optional(true));
+        let a = record(1, "/a.js", "try { require('peer') } catch (err) {}", true, dependencies);
+        let mut modules = ModuleMap::new();
+        modules.insert(NormalizedPath::from(Path::new("/a.js")), a);
+        let bundle = FlatPack::new(&modules).to_string().unwrap();
+        assert!(bundle.contains("try { (function () { var err = new Error(\"Cannot find module 'peer'\"); err.code = 'MODULE_NOT_FOUND'; throw err })() }"));
+    }
+
+    #[test]
+    fn rejects_dynamic_requires() {
+        let a = record(1, "/a.js", "require(name)", true, Dependencies::new());
+        let mut modules = ModuleMap::new();
+        modules.insert(NormalizedPath::from(Path::new("/a.js")), a);
+        assert!(FlatPack::new(&modules).to_string().is_err());
+    }
+}
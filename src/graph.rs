@@ -2,10 +2,13 @@ use std::cmp::Ordering;
 use std::collections::{HashMap, BTreeMap};
 use std::path::PathBuf;
 use std::rc::Rc;
+use std::sync::Arc;
 use digest::generic_array::GenericArray;
 use digest::generic_array::typenum::U20;
 use easter::stmt::Script;
+use estree_detect_requires::{DynamicCode, DynamicRequire, RequireRecord};
 use serde_json::Value;
+use path::NormalizedPath;
 
 /// Map dependency IDs used inside require() to their full paths.
 pub type Dependencies = BTreeMap<String, Dependency>;
@@ -17,21 +20,31 @@ pub enum SourceFile {
     CJS {
         /// Path to the file.
         path: PathBuf,
-        /// The file source content.
-        source: String,
+        /// The file source content. It is shared, so keeping a copy of it is cheap.
+        source: Arc<str>,
         /// Hash of the source content.
         hash: Hash,
         /// Syntax tree.
         ast: Option<Script>,
         /// Dependencies.
         dependencies: Vec<String>,
+        /// Dependencies that are only partially known, like `require('./locales/' + lang)`.
+        dynamic_dependencies: Vec<DynamicRequire>,
+        /// Dependencies that are only required inside `try` blocks. If they can not be found,
+        /// requiring them throws at runtime instead of failing the build.
+        optional_dependencies: Vec<String>,
+        /// Uses of `eval`, `Function` and `with`.
+        dynamic_code: Vec<DynamicCode>,
+        /// require() calls with an argument that is not known at build time, like `require(name)`.
+        /// The modules they load are not bundled.
+        unanalyzable_requires: Vec<RequireRecord>,
     },
     /// A JSON source file on disk.
     JSON {
         /// Path to the file.
         path: PathBuf,
-        /// The file source content.
-        source: String,
+        /// The file source content. It is shared, so keeping a copy of it is cheap.
+        source: Arc<str>,
         /// Hash of the source content.
         hash: Hash,
         /// The JSON object.
@@ -48,7 +61,7 @@ impl SourceFile {
         }
     }
 
-    pub fn source(&self) -> &String {
+    pub fn source(&self) -> &Arc<str> {
         match *self {
             SourceFile::CJS { ref source, .. } => source,
             SourceFile::JSON { ref source, .. } => source,
@@ -93,7 +106,12 @@ impl ModuleRecord {
 pub struct Dependency {
     pub name: String,
     pub resolved: Option<PathBuf>,
+    /// The ID of the required module. Unlike `record`, it is also set when the module was still
+    /// being loaded when it was required, like in circular dependencies.
+    pub id: Option<u32>,
     pub record: Option<Rc<ModuleRecord>>,
+    /// Whether the module handles this dependency being missing.
+    pub optional: bool,
 }
 
 impl Dependency {
@@ -101,7 +119,9 @@ impl Dependency {
         Dependency {
             name,
             resolved: None,
+            id: None,
             record: None,
+            optional: false,
         }
     }
 
@@ -109,19 +129,33 @@ impl Dependency {
         Dependency {
             name,
             resolved: Some(resolved),
+            id: None,
             record: None,
+            optional: false,
         }
     }
 
+    pub fn with_optional(mut self, optional: bool) -> Self {
+        self.optional = optional;
+        self
+    }
+
+    /// Whether requiring this dependency throws at runtime, because it is optional and it
+    /// could not be found.
+    pub fn is_missing(&self) -> bool {
+        self.optional && self.resolved.is_none()
+    }
+
     pub fn with_record(mut self, record: &Rc<ModuleRecord>) -> Self {
         self.set_record(record);
         self
     }
 
     pub fn set_record(&mut self, record: &Rc<ModuleRecord>) -> () {
+        self.id = Some(record.id);
         self.record = Some(Rc::clone(record));
     }
 }
 
 /// Keeps track of modules.
-pub type ModuleMap = HashMap<String, Rc<ModuleRecord>>;
+pub type ModuleMap = HashMap<NormalizedPath, Rc<ModuleRecord>>;
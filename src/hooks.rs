@@ -0,0 +1,174 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use quicli::prelude::*;
+use serde_json;
+use emit::ManifestEntry;
+
+/// Information about a finished build. Post-build commands receive it as JSON on stdin.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildReport {
+    /// The size of the bundle in bytes.
+    pub bytes: usize,
+    /// The number of modules in the bundle.
+    pub modules: usize,
+    pub duration_ms: i64,
+    pub outfile: Option<PathBuf>,
+    pub outdir: Option<PathBuf>,
+    /// The files in `outdir`, from its manifest.
+    pub files: Vec<ManifestEntry>,
+}
+
+/// Run shell commands after a successful build, eg. to upload source maps.
+/// Every command runs, even if an earlier one fails. The failures are returned together.
+///
+/// The commands receive the build report as JSON on stdin, and its sizes and paths in
+/// environment variables, for commands that do not read JSON:
+///
+///  - `JS_BUNDLER_BYTES`, `JS_BUNDLER_MODULES` and `JS_BUNDLER_DURATION_MS`
+///  - `JS_BUNDLER_OUTFILE` and `JS_BUNDLER_OUTDIR`, if they are set
+pub fn run_post_build(commands: &[String], report: &BuildReport) -> Result<()> {
+    run_commands("post-build", commands, report, &report_env(report))
+}
+
+/// Run shell commands after each successful build in watch mode, eg. to re-run tests.
+/// They receive the same input as post-build commands, and two more environment variables:
+///
+///  - `JS_BUNDLER_BUILD`: the number of the build, starting at 1 for the first one
+///  - `JS_BUNDLER_CHANGED`: the file whose change started the build, or empty for the first one
+pub fn run_on_rebuild(commands: &[String], report: &BuildReport, build: u32, changed: Option<&Path>) -> Result<()> {
+    let mut env = vec![
+        ("JS_BUNDLER_BUILD", build.to_string()),
+        ("JS_BUNDLER_CHANGED", changed.map_or(String::new(), |path| path.to_string_lossy().into_owned())),
+    ];
+    env.extend(report_env(report));
+    run_commands("on-rebuild", commands, report, &env)
+}
+
+/// The environment variables that describe a build report.
+fn report_env(report: &BuildReport) -> Vec<(&'static str, String)> {
+    let mut env = vec![
+        ("JS_BUNDLER_BYTES", report.bytes.to_string()),
+        ("JS_BUNDLER_MODULES", report.modules.to_string()),
+        ("JS_BUNDLER_DURATION_MS", report.duration_ms.to_string()),
+    ];
+    if let Some(ref outfile) = report.outfile {
+        env.push(("JS_BUNDLER_OUTFILE", outfile.to_string_lossy().into_owned()));
+    }
+    if let Some(ref outdir) = report.outdir {
+        env.push(("JS_BUNDLER_OUTDIR", outdir.to_string_lossy().into_owned()));
+    }
+    env
+}
+
+/// Run every command, even if an earlier one fails. The failures are returned together.
+fn run_commands(kind: &str, commands: &[String], report: &BuildReport, env: &[(&str, String)]) -> Result<()> {
+    let report = serde_json::to_string(report)?;
+    let failures = commands.iter()
+        .filter_map(|command| run_command(command, &report, env).err()
+            .map(|err| format!("{} command `{}` failed: {}", kind, command, err)))
+        .collect::<Vec<String>>();
+    if !failures.is_empty() {
+        bail!("{}", failures.join("\n"));
+    }
+    Ok(())
+}
+
+fn run_command(command: &str, report: &str, env: &[(&str, String)]) -> Result<()> {
+    let mut child = shell(command)
+        .envs(env.iter().map(|&(name, ref value)| (name, value)))
+        .stdin(Stdio::piped())
+        .spawn()?;
+    {
+        let stdin = child.stdin.as_mut().unwrap();
+        // The command may exit without reading its input.
+        let _ = stdin.write_all(report.as_bytes());
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("{}", status);
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(all(test, not(windows)))]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use serde_json::{self, Value};
+    use std::path::Path;
+    use super::{BuildReport, run_on_rebuild, run_post_build};
+
+    fn report() -> BuildReport {
+        BuildReport {
+            bytes: 1234,
+            modules: 5,
+            duration_ms: 67,
+            outfile: Some(PathBuf::from("dist/app.js")),
+            outdir: None,
+            files: vec![],
+        }
+    }
+
+    #[test]
+    fn passes_the_report_to_post_build_commands() {
+        let dir = env::temp_dir().join(format!("js-bundler-post-build-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let commands = vec![
+            format!("cat > '{}'", dir.join("report.json").to_string_lossy()),
+            format!("echo \"$JS_BUNDLER_BYTES $JS_BUNDLER_MODULES $JS_BUNDLER_OUTFILE ${{JS_BUNDLER_OUTDIR-none}}\" > '{}'", dir.join("env.txt").to_string_lossy()),
+        ];
+        run_post_build(&commands, &report()).unwrap();
+        let report: Value = serde_json::from_str(&fs::read_to_string(dir.join("report.json")).unwrap()).unwrap();
+        let env = fs::read_to_string(dir.join("env.txt")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(report["bytes"], 1234);
+        assert_eq!(report["durationMs"], 67);
+        assert_eq!(report["outfile"], "dist/app.js");
+        assert_eq!(env, "1234 5 dist/app.js none\n");
+    }
+
+    #[test]
+    fn reports_failing_post_build_commands() {
+        let err = run_post_build(&["exit 3".to_string(), "true".to_string(), "false".to_string()], &report()).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("post-build command `exit 3` failed: exit code: 3"));
+        assert!(message.contains("post-build command `false` failed"));
+        assert!(!message.contains("`true`"));
+    }
+
+    #[test]
+    fn passes_the_build_number_and_changed_file_to_on_rebuild_commands() {
+        let dir = env::temp_dir().join(format!("js-bundler-on-rebuild-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let command = format!("echo \"$JS_BUNDLER_BUILD [$JS_BUNDLER_CHANGED] $JS_BUNDLER_BYTES\" >> '{}'", dir.join("env.txt").to_string_lossy());
+        run_on_rebuild(&[command.clone()], &report(), 1, None).unwrap();
+        run_on_rebuild(&[command], &report(), 2, Some(Path::new("src/app.js"))).unwrap();
+        let env = fs::read_to_string(dir.join("env.txt")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(env, "1 [] 1234\n2 [src/app.js] 1234\n");
+    }
+
+    #[test]
+    fn reports_every_failing_on_rebuild_command() {
+        let err = run_on_rebuild(&["exit 1".to_string(), "exit 2".to_string()], &report(), 3, None).unwrap_err();
+        assert_eq!(err.to_string(), "on-rebuild command `exit 1` failed: exit code: 1\n\
+                                     on-rebuild command `exit 2` failed: exit code: 2");
+    }
+}
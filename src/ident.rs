@@ -0,0 +1,165 @@
+use std::collections::HashSet;
+
+/// Words that can not be used as variable names, in strict and sloppy mode, including literals
+/// and the names that strict mode does not allow to be bound.
+const RESERVED_WORDS: &[&str] = &[
+    // Keywords.
+    "break", "case", "catch", "class", "const", "continue", "debugger", "default", "delete", "do",
+    "else", "export", "extends", "finally", "for", "function", "if", "import", "in", "instanceof",
+    "new", "return", "super", "switch", "this", "throw", "try", "typeof", "var", "void", "while",
+    "with", "yield",
+    // Future reserved words.
+    "enum", "await", "implements", "interface", "let", "package", "private", "protected",
+    "public", "static",
+    // Literals.
+    "null", "true", "false",
+    // Not reserved, but can not be bound in strict mode or have special meaning.
+    "arguments", "eval", "undefined", "NaN", "Infinity",
+];
+
+/// Check if a name is a reserved word, or otherwise unsafe to declare as a variable.
+pub fn is_reserved_word(name: &str) -> bool {
+    RESERVED_WORDS.contains(&name)
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c == '$' || c == '_' || c.is_ascii_alphabetic() || (!c.is_ascii() && c.is_alphabetic())
+}
+
+fn is_identifier_part(c: char) -> bool {
+    is_identifier_start(c) || c.is_ascii_digit() || (!c.is_ascii() && c.is_alphanumeric())
+}
+
+/// Check if a name can be used as a variable name.
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if is_identifier_start(first) => chars.all(is_identifier_part) && !is_reserved_word(name),
+        _ => false,
+    }
+}
+
+/// Turn an arbitrary string, like a file name, into a valid variable name.
+/// Invalid characters become `_`, and names that start with a digit or are reserved get a `_` prefix.
+pub fn to_identifier(name: &str) -> String {
+    let mut result: String = name.chars()
+        .map(|c| if is_identifier_part(c) { c } else { '_' })
+        .collect();
+    let needs_prefix = match result.chars().next() {
+        Some(first) => !is_identifier_start(first) || is_reserved_word(&result),
+        None => true,
+    };
+    if needs_prefix {
+        result.insert(0, '_');
+    }
+    result
+}
+
+/// Collect every word in a source string that could be an identifier.
+///
+/// This also finds words in strings and comments, so the result is a superset of the
+/// identifiers that the code uses. That is what we want when picking names that must not
+/// shadow anything.
+pub fn identifier_words(source: &str) -> HashSet<String> {
+    let mut words = HashSet::new();
+    let mut word = String::new();
+    for c in source.chars() {
+        if is_identifier_part(c) {
+            word.push(c);
+        } else if !word.is_empty() {
+            words.insert(word.clone());
+            word.clear();
+        }
+    }
+    if !word.is_empty() {
+        words.insert(word);
+    }
+    words
+}
+
+/// Hands out variable names that do not collide with each other or with a set of taken names.
+#[derive(Debug, Default)]
+pub struct UniqueNames {
+    taken: HashSet<String>,
+}
+
+impl UniqueNames {
+    /// Avoid the names in `taken`.
+    pub fn new(taken: HashSet<String>) -> Self {
+        UniqueNames { taken }
+    }
+
+    /// Get a valid, unused variable name based on `base`.
+    pub fn name(&mut self, base: &str) -> String {
+        let base = to_identifier(base);
+        let mut name = base.clone();
+        let mut suffix = 1;
+        while self.taken.contains(&name) {
+            name = format!("{}${}", base, suffix);
+            suffix += 1;
+        }
+        self.taken.insert(name.clone());
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::{RESERVED_WORDS, is_identifier, is_reserved_word, to_identifier, identifier_words, UniqueNames};
+
+    #[test]
+    fn rejects_every_reserved_word() {
+        for word in RESERVED_WORDS {
+            assert!(is_reserved_word(word), "{} should be reserved", word);
+            assert!(!is_identifier(word), "{} should not be an identifier", word);
+            let safe = to_identifier(word);
+            assert!(is_identifier(&safe), "{} should be safe", safe);
+            assert_eq!(safe, format!("_{}", word));
+        }
+    }
+
+    #[test]
+    fn accepts_identifiers() {
+        for name in &["a", "$", "_", "$jquery", "_private", "camelCase", "a1", "ünïcödé", "let_", "classes", "Null"] {
+            assert!(is_identifier(name), "{} should be an identifier", name);
+            assert_eq!(to_identifier(name), *name);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_identifiers() {
+        for name in &["", "1a", "a-b", "a.b", "a b", "@scope", "a/b", "\u{2028}"] {
+            assert!(!is_identifier(name), "{:?} should not be an identifier", name);
+            assert!(is_identifier(&to_identifier(name)), "{:?} should become an identifier", to_identifier(name));
+        }
+    }
+
+    #[test]
+    fn converts_file_names() {
+        assert_eq!(to_identifier("index"), "index");
+        assert_eq!(to_identifier("lodash.debounce"), "lodash_debounce");
+        assert_eq!(to_identifier("2d-context"), "_2d_context");
+        assert_eq!(to_identifier("@scope/pkg"), "_scope_pkg");
+        assert_eq!(to_identifier(""), "_");
+        assert_eq!(to_identifier("class"), "_class");
+        assert_eq!(to_identifier("arguments"), "_arguments");
+    }
+
+    #[test]
+    fn finds_identifier_words() {
+        let words = identifier_words("var a = b.c('d e') // f\n$g_1");
+        let expected: HashSet<String> = ["var", "a", "b", "c", "d", "e", "f", "$g_1"].iter().map(|word| word.to_string()).collect();
+        assert_eq!(words, expected);
+    }
+
+    #[test]
+    fn hands_out_unique_names() {
+        let mut names = UniqueNames::new(["index", "index$1"].iter().map(|word| word.to_string()).collect());
+        assert_eq!(names.name("index"), "index$2");
+        assert_eq!(names.name("index"), "index$3");
+        assert_eq!(names.name("class"), "_class");
+        assert_eq!(names.name("class"), "_class$1");
+        assert_eq!(names.name("utils"), "utils");
+    }
+}
@@ -0,0 +1,138 @@
+use std::path::Path;
+use quicli::prelude::*;
+use serde_json::Value;
+use toml;
+use package::{read_package_json, dependency_names};
+
+/// Inspect a project and generate the contents of a starting bundler.toml for it.
+///
+/// Settings are taken from package.json and from browserify commands in its `scripts`.
+/// Settings that can not be migrated are listed as comments.
+pub fn generate_config(project_dir: &Path) -> Result<String> {
+    let package = if project_dir.join("package.json").is_file() {
+        Some(read_package_json(project_dir)?)
+    } else {
+        None
+    };
+
+    let mut settings = vec![];
+    let mut notes = vec![];
+
+    let browserify_args = package.as_ref()
+        .and_then(find_browserify_command)
+        .map(|command| parse_browserify_command(&command))
+        .unwrap_or_default();
+
+    let entry = browserify_args.entry.clone()
+        .or_else(|| package.as_ref().and_then(|package| {
+            package.get("browser").and_then(|browser| browser.as_str())
+                .or_else(|| package.get("main").and_then(|main| main.as_str()))
+                .map(|entry| entry.to_string())
+        }))
+        .unwrap_or_else(|| "./index.js".to_string());
+    settings.push(format!("entry = {}", toml_string(&to_relative(&entry))));
+
+    if let Some(ref outfile) = browserify_args.outfile {
+        settings.push(format!("outfile = {}", toml_string(outfile)));
+    }
+    if browserify_args.no_builtins {
+        settings.push("no-builtins = true".to_string());
+    }
+    if !browserify_args.external.is_empty() {
+        let external = browserify_args.external.iter()
+            .map(|name| toml_string(name))
+            .collect::<Vec<String>>()
+            .join(", ");
+        settings.push(format!("external = [{}]", external));
+    }
+
+    if let Some(ref package) = package {
+        if !dependency_names(package, "peerDependencies").is_empty() {
+            settings.push("# package.json has peerDependencies, so they are kept external.".to_string());
+            settings.push("library = true".to_string());
+        }
+        if let Some(browser) = package.get("browser").and_then(|browser| browser.as_object()) {
+            let keys = browser.keys().cloned().collect::<Vec<String>>().join(", ");
+            notes.push(format!("the \"browser\" field replacements in package.json are not supported yet: {}", keys));
+        }
+        if let Some(transforms) = package.pointer("/browserify/transform").and_then(|transform| transform.as_array()) {
+            let names = transforms.iter()
+                .filter_map(|transform| transform.as_str().or_else(|| transform.get(0).and_then(|name| name.as_str())))
+                .collect::<Vec<&str>>()
+                .join(", ");
+            notes.push(format!("browserify transforms are not supported yet: {}", names));
+        }
+    }
+    if project_dir.join("webpack.config.js").is_file() {
+        notes.push("found webpack.config.js. Its settings have to be migrated by hand.".to_string());
+    }
+
+    let mut config = String::from("# Generated by `js-bundler init`.\n");
+    for setting in settings {
+        config.push_str(&setting);
+        config.push('\n');
+    }
+    if !notes.is_empty() {
+        config.push('\n');
+        for note in notes {
+            config.push_str(&format!("# NOTE: {}\n", note));
+        }
+    }
+    Ok(config)
+}
+
+/// Options from a browserify command line.
+#[derive(Debug, Default)]
+struct BrowserifyArgs {
+    entry: Option<String>,
+    outfile: Option<String>,
+    external: Vec<String>,
+    no_builtins: bool,
+}
+
+/// Find a browserify command in the package.json `scripts`.
+/// Prefers the `build` script if there are several.
+fn find_browserify_command(package: &Value) -> Option<String> {
+    let scripts = package.get("scripts").and_then(|scripts| scripts.as_object())?;
+    let is_browserify = |script: &&Value| script.as_str().map_or(false, |command| command.contains("browserify "));
+    scripts.get("build").filter(is_browserify)
+        .or_else(|| scripts.values().find(is_browserify))
+        .and_then(|script| script.as_str())
+        .map(|command| command.to_string())
+}
+
+fn parse_browserify_command(command: &str) -> BrowserifyArgs {
+    let mut args = BrowserifyArgs::default();
+    let mut words = command.split_whitespace()
+        .skip_while(|word| !word.ends_with("browserify"))
+        .skip(1)
+        // Stop at the next command, eg. in `browserify app.js | uglifyjs`.
+        .take_while(|word| !["|", "&&", ";", ">"].contains(word));
+    while let Some(word) = words.next() {
+        match word {
+            "-o" | "--outfile" => args.outfile = words.next().map(|outfile| outfile.to_string()),
+            "-x" | "--external" => args.external.extend(words.next().map(|name| name.to_string())),
+            "--node" | "--no-builtins" | "--bare" => args.no_builtins = true,
+            // Skip the values of other options that take one.
+            "-t" | "--transform" | "-p" | "--plugin" | "-r" | "--require" | "-s" | "--standalone" => { words.next(); },
+            _ if word.starts_with('-') => (),
+            _ => if args.entry.is_none() {
+                args.entry = Some(word.to_string());
+            },
+        }
+    }
+    args
+}
+
+/// Make sure an entry point is resolved as a file path and not as a package name.
+fn to_relative(entry: &str) -> String {
+    if entry.starts_with('.') || entry.starts_with('/') {
+        entry.to_string()
+    } else {
+        format!("./{}", entry)
+    }
+}
+
+fn toml_string(value: &str) -> String {
+    toml::Value::String(value.to_string()).to_string()
+}
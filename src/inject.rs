@@ -0,0 +1,239 @@
+use std::collections::HashSet;
+use easter::decl::{Decl, Dtor};
+use easter::expr::Expr;
+use easter::patt::Patt;
+use easter::stmt::{Script, Stmt, StmtListItem};
+use estree_detect_requires::{Walker, Callbacks, Control, NodePath};
+use quicli::prelude::*;
+use serde_json;
+use error::BundleError;
+use graph::SourceFile;
+use ident::is_identifier;
+
+/// An identifier that is bound in the wrapper of every module that uses it, like a global that
+/// is only defined where it is needed.
+///
+/// `React = "react"` makes `React` the exports of the `react` module, and `h = "preact#h"`
+/// makes `h` the `h` export of `preact`. The module is resolved as if the module that uses the
+/// identifier required it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Injection {
+    pub name: String,
+    pub specifier: String,
+    pub export: Option<String>,
+}
+
+impl Injection {
+    /// Parse an injection from a name and a `module` or `module#export` string.
+    pub fn parse(name: &str, value: &str) -> Result<Injection> {
+        if !is_identifier(name) {
+            return Err(BundleError::config(format!("Invalid inject name {}: it must be a JavaScript identifier", name)).into());
+        }
+        let mut parts = value.splitn(2, '#');
+        let specifier = parts.next().unwrap_or("");
+        if specifier.is_empty() {
+            return Err(BundleError::config(format!("Invalid inject value for {}: expected a module like \"react\" or \"preact#h\"", name)).into());
+        }
+        Ok(Injection {
+            name: name.to_string(),
+            specifier: specifier.to_string(),
+            export: parts.next().map(|export| export.to_string()),
+        })
+    }
+
+    /// The statement that binds the identifier, eg. `var h = require("preact")["h"];`.
+    pub fn to_declaration(&self) -> String {
+        let specifier = serde_json::to_string(&self.specifier).unwrap();
+        match self.export {
+            Some(ref export) => format!("var {} = require({})[{}];", self.name, specifier, serde_json::to_string(export).unwrap()),
+            None => format!("var {} = require({});", self.name, specifier),
+        }
+    }
+}
+
+/// Find the injections that a file uses: the ones it refers to, but does not declare itself.
+///
+/// Declarations are not tracked per scope, so a module that declares the name anywhere, with
+/// `var`, `let`, `const` or `function`, does not get the injection. That keeps the module
+/// that provides an identifier from requiring itself.
+pub fn used_injections<'a>(file: &SourceFile, injections: &'a [Injection]) -> Vec<&'a Injection> {
+    if injections.is_empty() {
+        return vec![];
+    }
+    let names = match *file {
+        SourceFile::CJS { ast: Some(ref ast), .. } => Walker::new(ast, FindNames::default()).walk(),
+        _ => return vec![],
+    };
+    injections.iter()
+        .filter(|injection| names.used.contains(&injection.name) && !names.declared.contains(&injection.name))
+        .collect()
+}
+
+/// The declarations of the injections a file uses, on a single line so that line numbers in the
+/// module stay the same.
+pub fn injection_prelude(file: &SourceFile, injections: &[Injection]) -> String {
+    used_injections(file, injections).iter()
+        .map(|injection| injection.to_declaration())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Put a prelude at the start of a module: the text between the `{` of its wrapper and the
+/// closing `\n}`. The prelude goes after the directive prologue, so `'use strict'` still
+/// applies to the module, and on the same line as the last directive or the wrapper, so line
+/// numbers in the module stay the same.
+pub fn insert_prelude(file: &SourceFile, prelude: &str) -> String {
+    let mut string = String::new();
+    push_with_prelude(&mut string, file, prelude);
+    string
+}
+
+/// Append a module with a prelude to `string`, like `insert_prelude`, without copying the
+/// source of the module more than once.
+pub fn push_with_prelude(string: &mut String, file: &SourceFile, prelude: &str) {
+    let source = file.source();
+    let end = match *file {
+        SourceFile::CJS { ast: Some(ref ast), .. } if !prelude.is_empty() => directive_prologue_end(ast, source),
+        _ => None,
+    };
+    match end {
+        Some(end) => {
+            // A directive does not need a semicolon if it is followed by a newline.
+            let separator = if source[..end].ends_with(';') { " " } else { "; " };
+            string.push('\n');
+            string.push_str(&source[..end]);
+            string.push_str(separator);
+            string.push_str(prelude);
+            string.push_str(&source[end..]);
+        },
+        None => {
+            string.push_str(prelude);
+            string.push('\n');
+            string.push_str(source);
+        },
+    }
+}
+
+/// Find the byte offset where the directives at the start of a script, like `'use strict'`, end.
+fn directive_prologue_end(ast: &Script, source: &str) -> Option<usize> {
+    // Syntax tree offsets count characters, not bytes.
+    let byte_offset = |offset: u32| source.char_indices().nth(offset as usize)
+        .map(|(index, _)| index)
+        .unwrap_or_else(|| source.len());
+    let mut end = None;
+    for item in &ast.items {
+        match *item {
+            StmtListItem::Stmt(Stmt::Expr(Some(ref span), Expr::String(..), _)) => {
+                // `('use strict')` is an expression, not a directive.
+                if !source[byte_offset(span.start.offset)..].starts_with(|c: char| c == '\'' || c == '"') {
+                    break;
+                }
+                end = Some(byte_offset(span.end.offset));
+            },
+            _ => break,
+        }
+    }
+    end
+}
+
+#[derive(Default)]
+struct FindNames {
+    used: HashSet<String>,
+    declared: HashSet<String>,
+}
+
+impl FindNames {
+    fn declare_all(&mut self, dtors: &[Dtor]) {
+        for dtor in dtors {
+            if let Dtor::Simple(_, ref id, _) = *dtor {
+                self.declared.insert(id.name.as_ref().to_string());
+            }
+        }
+    }
+}
+
+impl Callbacks for FindNames {
+    fn pre_stmt(&mut self, stmt: &Stmt, _path: &NodePath) -> Control {
+        if let Stmt::Var(_, ref dtors, _) = *stmt {
+            self.declare_all(dtors);
+        }
+        Control::Continue
+    }
+
+    fn pre_decl(&mut self, decl: &Decl) -> Control {
+        match *decl {
+            Decl::Fun(ref fun) => { self.declared.insert(fun.id.name.as_ref().to_string()); },
+            Decl::Let(_, ref dtors, _) => self.declare_all(dtors),
+            Decl::Const(_, ref dtors, _) => {
+                for dtor in dtors {
+                    if let Patt::Simple(ref id) = dtor.patt {
+                        self.declared.insert(id.name.as_ref().to_string());
+                    }
+                }
+            },
+        }
+        Control::Continue
+    }
+
+    fn pre_expr(&mut self, expr: &Expr, _path: &NodePath) -> Control {
+        if let Expr::Id(ref id) = *expr {
+            self.used.insert(id.name.as_ref().to_string());
+        }
+        Control::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use loader::parse_cjs;
+    use super::{Injection, injection_prelude, insert_prelude};
+
+    #[test]
+    fn parses_injections() {
+        assert_eq!(Injection::parse("h", "preact#h").unwrap(), Injection {
+            name: "h".into(),
+            specifier: "preact".into(),
+            export: Some("h".into()),
+        });
+        assert_eq!(Injection::parse("React", "react").unwrap().export, None);
+        assert!(Injection::parse("not-a-name", "react").is_err());
+        assert!(Injection::parse("h", "#h").is_err());
+    }
+
+    #[test]
+    fn injects_used_names_only() {
+        let injections = vec![
+            Injection::parse("h", "preact#h").unwrap(),
+            Injection::parse("React", "react").unwrap(),
+        ];
+        let file = |source: &str| parse_cjs(PathBuf::from("/a.js"), source.to_string()).unwrap();
+        assert_eq!(injection_prelude(&file("module.exports = h('div')"), &injections), "var h = require(\"preact\")[\"h\"];");
+        assert_eq!(injection_prelude(&file("React.createElement(h)"), &injections),
+                   "var h = require(\"preact\")[\"h\"]; var React = require(\"react\");");
+        assert_eq!(injection_prelude(&file("function h() {} exports.h = h"), &injections), "");
+        assert_eq!(injection_prelude(&file("var x = 1"), &injections), "");
+    }
+
+    #[test]
+    fn inserts_the_prelude_after_directives() {
+        let injections = vec![Injection::parse("h", "preact#h").unwrap()];
+        let body = |source: &str| {
+            let file = parse_cjs(PathBuf::from("/a.js"), source.to_string()).unwrap();
+            insert_prelude(&file, &injection_prelude(&file, &injections))
+        };
+        assert_eq!(body("h('div')"), "var h = require(\"preact\")[\"h\"];\nh('div')");
+        assert_eq!(body("var x = 1"), "\nvar x = 1");
+
+        for source in &["'use strict';\nh('div')", "\"use strict\"\nh('div')", "'use strict'; 'use asm'\nh('div')"] {
+            let body = body(source);
+            let lines = body.lines().collect::<Vec<&str>>();
+            assert_eq!(lines.len(), 3, "{:?}", body);
+            assert_eq!(lines[0], "");
+            assert!(lines[1].starts_with(&source[..12]), "{:?}", body);
+            assert!(lines[1].ends_with("var h = require(\"preact\")[\"h\"];"), "{:?}", body);
+            assert_eq!(lines[2], "h('div')");
+        }
+        assert!(body("('use strict');\nh('div')").starts_with("var h"));
+    }
+}
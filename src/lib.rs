@@ -0,0 +1,55 @@
+extern crate base64;
+extern crate digest;
+extern crate easter;
+extern crate esprit;
+extern crate glob;
+extern crate joker;
+extern crate node_resolve;
+extern crate notify;
+extern crate rayon;
+extern crate reqwest;
+extern crate serde;
+#[macro_use] extern crate serde_derive;
+extern crate serde_json;
+extern crate sha1;
+extern crate sha2;
+extern crate estree_detect_requires;
+extern crate node_core_shims;
+extern crate time;
+extern crate toml;
+#[macro_use] extern crate quicli;
+
+pub mod builtins;
+pub mod cache;
+pub mod config;
+pub mod deps;
+pub mod diagnostics;
+pub mod emit;
+pub mod env;
+pub mod error;
+pub mod estree;
+pub mod features;
+pub mod flat_pack;
+pub mod graph;
+pub mod hooks;
+pub mod ident;
+pub mod inject;
+pub mod init;
+pub mod licenses;
+pub mod loader;
+pub mod meta;
+pub mod pack;
+pub mod package;
+pub mod path;
+pub mod pipeline;
+pub mod polyfill;
+pub mod prefetch;
+pub mod remote;
+pub mod roots;
+pub mod rules;
+pub mod sourcemap;
+pub mod sources;
+pub mod symbolicate;
+pub mod transforms;
+pub mod ui;
+pub mod watch;
@@ -0,0 +1,270 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use quicli::prelude::*;
+use serde_json;
+use sha1::{Sha1, Digest};
+use time;
+use graph::ModuleMap;
+use sources::{PackageInfo, APP_PACKAGE, package_root};
+
+/// A bundled module, and the package it comes from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModuleLicense {
+    pub package: String,
+    pub version: Option<String>,
+    /// Where the module was loaded from.
+    pub path: PathBuf,
+    /// The license of the package, as an SPDX expression like `MIT`, from its package.json.
+    pub license: Option<String>,
+}
+
+/// List the package and license of every bundled module, sorted by path. Files outside of
+/// node_modules belong to the package.json in the current directory, or to `app`.
+pub fn module_licenses(modules: &ModuleMap) -> Result<Vec<ModuleLicense>> {
+    let app = PackageInfo::read(&Path::new(".").canonicalize()?, APP_PACKAGE);
+    let mut packages: HashMap<PathBuf, PackageInfo> = HashMap::new();
+    let mut licenses = vec![];
+    for record in modules.values() {
+        let path = record.file.path();
+        let package = match package_root(path) {
+            Some((root, name)) => packages.entry(root.clone())
+                .or_insert_with(|| PackageInfo::read(&root, &name))
+                .clone(),
+            None => app.clone(),
+        };
+        licenses.push(ModuleLicense {
+            package: package.name,
+            version: package.version,
+            path: path.clone(),
+            license: package.license,
+        });
+    }
+    licenses.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(licenses)
+}
+
+/// Formats for a software bill of materials.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SbomFormat {
+    /// CycloneDX 1.4 JSON.
+    CycloneDx,
+    /// SPDX 2.2 JSON.
+    Spdx,
+}
+
+impl ::std::str::FromStr for SbomFormat {
+    type Err = String;
+    fn from_str(format: &str) -> ::std::result::Result<Self, String> {
+        match format {
+            "cyclonedx" => Ok(SbomFormat::CycloneDx),
+            "spdx" => Ok(SbomFormat::Spdx),
+            _ => Err(format!("expected cyclonedx or spdx, got {}", format)),
+        }
+    }
+}
+
+/// Create a software bill of materials that lists each bundled package once, as JSON.
+/// `name` names the document, eg. after the entry point.
+pub fn to_sbom(licenses: &[ModuleLicense], format: SbomFormat, name: &str) -> Result<String> {
+    let packages = unique_packages(licenses);
+    Ok(match format {
+        SbomFormat::CycloneDx => serde_json::to_string_pretty(&to_cyclonedx(&packages))?,
+        SbomFormat::Spdx => {
+            let created = time::now_utc().rfc3339().to_string();
+            serde_json::to_string_pretty(&to_spdx(&packages, name, &created)?)?
+        },
+    })
+}
+
+/// A package, and its license.
+type Packages<'a> = BTreeMap<(&'a str, Option<&'a str>), Option<&'a str>>;
+
+fn unique_packages(licenses: &[ModuleLicense]) -> Packages {
+    licenses.iter()
+        .map(|module| ((module.package.as_str(), module.version.as_ref().map(|version| version.as_str())),
+                       module.license.as_ref().map(|license| license.as_str())))
+        .collect()
+}
+
+/// The package URL of an npm package, eg. `pkg:npm/%40babel/core@7.0.0`.
+fn purl(name: &str, version: Option<&str>) -> String {
+    let name = name.replace('@', "%40");
+    match version {
+        Some(version) => format!("pkg:npm/{}@{}", name, version),
+        None => format!("pkg:npm/{}", name),
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CycloneDx {
+    bom_format: &'static str,
+    spec_version: &'static str,
+    version: u32,
+    components: Vec<CycloneDxComponent>,
+}
+
+#[derive(Debug, Serialize)]
+struct CycloneDxComponent {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    purl: String,
+    licenses: Vec<CycloneDxLicense>,
+}
+
+#[derive(Debug, Serialize)]
+enum CycloneDxLicense {
+    /// A single SPDX license ID.
+    #[serde(rename = "license")]
+    Id { id: String },
+    /// An SPDX expression, like `(MIT OR Apache-2.0)`.
+    #[serde(rename = "expression")]
+    Expression(String),
+}
+
+fn to_cyclonedx(packages: &Packages) -> CycloneDx {
+    let components = packages.iter()
+        .map(|(&(name, version), license)| CycloneDxComponent {
+            kind: "library",
+            name: name.to_string(),
+            version: version.map(|version| version.to_string()),
+            purl: purl(name, version),
+            licenses: license.iter()
+                .map(|license| if license.contains(|c: char| c == ' ' || c == '(') {
+                    CycloneDxLicense::Expression(license.to_string())
+                } else {
+                    CycloneDxLicense::Id { id: license.to_string() }
+                })
+                .collect(),
+        })
+        .collect();
+    CycloneDx { bom_format: "CycloneDX", spec_version: "1.4", version: 1, components }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Spdx {
+    spdx_version: &'static str,
+    data_license: &'static str,
+    #[serde(rename = "SPDXID")]
+    spdx_id: &'static str,
+    name: String,
+    document_namespace: String,
+    creation_info: SpdxCreationInfo,
+    packages: Vec<SpdxPackage>,
+}
+
+#[derive(Debug, Serialize)]
+struct SpdxCreationInfo {
+    created: String,
+    creators: Vec<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxPackage {
+    name: String,
+    #[serde(rename = "SPDXID")]
+    spdx_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version_info: Option<String>,
+    download_location: &'static str,
+    files_analyzed: bool,
+    license_concluded: &'static str,
+    license_declared: String,
+    copyright_text: &'static str,
+    external_refs: Vec<SpdxExternalRef>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxExternalRef {
+    reference_category: &'static str,
+    reference_type: &'static str,
+    reference_locator: String,
+}
+
+/// Nothing is known about a field, in SPDX.
+const NOASSERTION: &str = "NOASSERTION";
+
+fn to_spdx(packages: &Packages, name: &str, created: &str) -> Result<Spdx> {
+    let packages: Vec<SpdxPackage> = packages.iter()
+        .enumerate()
+        .map(|(index, (&(name, version), license))| SpdxPackage {
+            name: name.to_string(),
+            spdx_id: format!("SPDXRef-Package-{}", index + 1),
+            version_info: version.map(|version| version.to_string()),
+            download_location: NOASSERTION,
+            files_analyzed: false,
+            license_concluded: NOASSERTION,
+            license_declared: license.unwrap_or(NOASSERTION).to_string(),
+            copyright_text: NOASSERTION,
+            external_refs: vec![SpdxExternalRef {
+                reference_category: "PACKAGE-MANAGER",
+                reference_type: "purl",
+                reference_locator: purl(name, version),
+            }],
+        })
+        .collect();
+    // The namespace must be unique per document, so derive it from the contents.
+    let hash = Sha1::digest(serde_json::to_string(&packages)?.as_bytes());
+    Ok(Spdx {
+        spdx_version: "SPDX-2.2",
+        data_license: "CC0-1.0",
+        spdx_id: "SPDXRef-DOCUMENT",
+        name: name.to_string(),
+        document_namespace: format!("https://spdx.org/spdxdocs/{}-{:x}", name, hash),
+        creation_info: SpdxCreationInfo { created: created.to_string(), creators: vec!["Tool: js-bundler"] },
+        packages,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use serde_json;
+    use super::{ModuleLicense, purl, unique_packages, to_cyclonedx, to_spdx};
+
+    fn module(package: &str, version: &str, path: &str, license: &str) -> ModuleLicense {
+        ModuleLicense {
+            package: package.to_string(),
+            version: Some(version.to_string()),
+            path: PathBuf::from(path),
+            license: Some(license.to_string()),
+        }
+    }
+
+    #[test]
+    fn creates_package_urls() {
+        assert_eq!(purl("react", Some("16.4.1")), "pkg:npm/react@16.4.1");
+        assert_eq!(purl("@babel/core", Some("7.0.0")), "pkg:npm/%40babel/core@7.0.0");
+        assert_eq!(purl("app", None), "pkg:npm/app");
+    }
+
+    #[test]
+    fn lists_each_package_once() {
+        let licenses = vec![
+            module("react", "16.4.1", "/app/node_modules/react/index.js", "MIT"),
+            module("react", "16.4.1", "/app/node_modules/react/cjs/react.js", "MIT"),
+            module("dual", "1.0.0", "/app/node_modules/dual/index.js", "(MIT OR Apache-2.0)"),
+        ];
+        let packages = unique_packages(&licenses);
+        assert_eq!(packages.len(), 2);
+
+        let bom = serde_json::to_value(&to_cyclonedx(&packages)).unwrap();
+        assert_eq!(bom["bomFormat"], "CycloneDX");
+        assert_eq!(bom["components"][0]["name"], "dual");
+        assert_eq!(bom["components"][0]["licenses"][0]["expression"], "(MIT OR Apache-2.0)");
+        assert_eq!(bom["components"][1]["purl"], "pkg:npm/react@16.4.1");
+        assert_eq!(bom["components"][1]["licenses"][0]["license"]["id"], "MIT");
+
+        let spdx = serde_json::to_value(&to_spdx(&packages, "app", "2018-08-01T00:00:00Z").unwrap()).unwrap();
+        assert_eq!(spdx["SPDXID"], "SPDXRef-DOCUMENT");
+        assert_eq!(spdx["packages"][1]["SPDXID"], "SPDXRef-Package-2");
+        assert_eq!(spdx["packages"][1]["licenseDeclared"], "MIT");
+        assert_eq!(spdx["packages"][1]["externalRefs"][0]["referenceLocator"], "pkg:npm/react@16.4.1");
+    }
+}
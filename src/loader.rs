@@ -2,14 +2,27 @@ use std::error::Error as StdError;
 use std::fmt;
 use std::fs::File;
 use std::io::{Read, BufReader};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
 use esprit::script;
 use esprit::error::Error as EspritError;
-use estree_detect_requires::detect;
-use quicli::prelude::Result; // TODO use `failure`?
+use easter::decl::Decl;
+use easter::expr::Expr;
+use easter::stmt::{Script, Stmt};
+use estree_detect_requires::{Walker, Callbacks, Control, NodePath, Constants, detect_calls_with_constants, detect_dynamic_code};
+use estree_detect_requires::{modules, optional_modules, dynamic_modules, unanalyzable_calls};
+use quicli::prelude::{Result, bail}; // TODO use `failure`?
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use serde_json;
 use sha1::{Sha1, Digest};
+use cache::{BuildCache, CacheKey};
+use error::BundleError;
 use graph::{Hash, SourceFile};
+use pipeline::{Pipeline, Hook, Stage};
 
 #[derive(Debug)]
 pub struct ParseError {
@@ -65,7 +78,89 @@ impl StdError for ParseError {
     }
 }
 
-trait Transform {
+/// A file exceeded one of the `ParseLimits`.
+/// Dependencies that exceed a limit are skipped with a warning, instead of failing the build.
+#[derive(Debug)]
+pub struct LimitExceeded {
+    filename: PathBuf,
+    reason: String,
+}
+
+impl LimitExceeded {
+    fn new(filename: &Path, reason: String) -> LimitExceeded {
+        LimitExceeded { filename: filename.to_path_buf(), reason }
+    }
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", &self.filename.to_string_lossy(), self.reason)
+    }
+}
+
+impl StdError for LimitExceeded {
+    fn description(&self) -> &str {
+        &self.reason
+    }
+}
+
+/// Limits on the work that parsing a single file may take, so that a pathological or
+/// malicious file can not hang or crash the build. `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParseLimits {
+    /// Maximum nesting depth of parentheses, brackets and braces.
+    /// The parser is recursive, so very deeply nested code would overflow the stack.
+    pub max_nesting: Option<usize>,
+    /// Maximum number of statements, expressions and declarations in the syntax tree.
+    pub max_nodes: Option<usize>,
+    /// Maximum time to spend parsing a file, in milliseconds.
+    pub max_parse_time: Option<u64>,
+}
+
+/// The stack size of the threads that parse files.
+const PARSER_STACK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Create a thread pool to read and parse files on. The parser is recursive, so its threads
+/// get the same large stack as the thread that parses with a time limit, instead of the default
+/// stack of rayon's global pool.
+pub fn parser_pool() -> Result<ThreadPool> {
+    Ok(ThreadPoolBuilder::new()
+        .stack_size(PARSER_STACK_SIZE)
+        .thread_name(|index| format!("js-bundler-parser-{}", index))
+        .build()?)
+}
+
+/// Turns source code into a syntax tree.
+///
+/// Everything after parsing works on the easter syntax tree, so a different parser can be used
+/// for some files without changing the rest of the build. Transforms that change the code parse
+/// it again with esprit. Parsers must be `Send` so that they can run on a separate thread when
+/// there is a time limit.
+pub trait Parser: Send + Sync {
+    fn parse(&self, path: &Path, source: &str) -> Result<Script>;
+}
+
+/// The default parser, esprit.
+pub struct EspritParser;
+
+impl Parser for EspritParser {
+    fn parse(&self, path: &Path, source: &str) -> Result<Script> {
+        Ok(script(source).map_err(|e| ParseError::new(&path.to_path_buf(), e))?)
+    }
+}
+
+/// How a file is loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModuleType {
+    /// A CommonJS module.
+    Js,
+    /// A JSON file, which becomes a module that exports its value.
+    Json,
+}
+
+/// Changes a source file after it is loaded.
+pub trait Transform {
     fn transform(&self, file: SourceFile) -> Result<SourceFile>;
 }
 
@@ -77,10 +172,15 @@ impl Transform for JSONTransform {
             SourceFile::CJS { .. } => Ok(file),
             SourceFile::JSON { path, source, hash, .. } => Ok(SourceFile::CJS {
                 path,
-                source: format!("module.exports = {}", &source),
+                // JSON allows line and paragraph separators in strings, but older JavaScript engines do not.
+                source: Arc::from(format!("module.exports = {}", source.replace('\u{2028}', "\\u2028").replace('\u{2029}', "\\u2029"))),
                 hash,
                 ast: None,
                 dependencies: vec![],
+                dynamic_dependencies: vec![],
+                optional_dependencies: vec![],
+                dynamic_code: vec![],
+                unanalyzable_requires: vec![],
             }),
         }
     }
@@ -88,33 +188,215 @@ impl Transform for JSONTransform {
 
 pub struct LoadFile {
     path: PathBuf,
-    transforms: Vec<Box<Transform>>,
+    transforms: Vec<Rc<Transform>>,
+    max_size: Option<u64>,
+    parse_limits: ParseLimits,
+    parser: Arc<Parser>,
+    module_type: Option<ModuleType>,
+    constants: Rc<Constants>,
+    pipeline: Rc<Pipeline>,
+    cache: Option<BuildCache>,
 }
 
 impl LoadFile {
     pub fn new(path: PathBuf) -> Self {
         LoadFile {
             path,
-            transforms: vec![Box::new(JSONTransform)],
+            transforms: vec![Rc::new(JSONTransform)],
+            max_size: None,
+            parse_limits: ParseLimits::default(),
+            parser: Arc::new(EspritParser),
+            module_type: None,
+            constants: Rc::new(Constants::new()),
+            pipeline: Rc::new(Pipeline::new()),
+            cache: None,
         }
     }
 
+    /// Refuse to load files larger than `max_size` bytes.
+    pub fn with_max_size(mut self, max_size: Option<u64>) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Refuse to parse files that exceed these limits.
+    pub fn with_parse_limits(mut self, parse_limits: ParseLimits) -> Self {
+        self.parse_limits = parse_limits;
+        self
+    }
+
+    /// Parse the file with a different parser than esprit.
+    pub fn with_parser(mut self, parser: Arc<Parser>) -> Self {
+        self.parser = parser;
+        self
+    }
+
+    /// Load the file as this type. By default, `.json` files are loaded as JSON and all other
+    /// files as JavaScript.
+    pub fn with_module_type(mut self, module_type: Option<ModuleType>) -> Self {
+        self.module_type = module_type;
+        self
+    }
+
+    /// Leave out require() calls in branches that never run, given these known constants.
+    pub fn with_constants(mut self, constants: Rc<Constants>) -> Self {
+        self.constants = constants;
+        self
+    }
+
+    /// Run a transform after the builtin ones.
+    pub fn with_transform(mut self, transform: Rc<Transform>) -> Self {
+        self.transforms.push(transform);
+        self
+    }
+
+    /// Run the steps that were added to the pipeline around the `Load`, `Transform` and `Detect` stages.
+    pub fn with_pipeline(mut self, pipeline: Rc<Pipeline>) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+
+    /// Use files from the build cache, and store the file in it once it is loaded. Cached files
+    /// skip parsing and transforms. Pipeline steps can not be part of the cache key, so the
+    /// cache is not used if there are steps that change source files.
+    pub fn with_cache(mut self, cache: Option<BuildCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
     pub fn run(&self) -> Result<SourceFile> {
-        self.read_file()
-            .and_then(|file| self.transform(file))
+        let file = self.reader().run()?;
+        self.process(file)
+    }
+
+    /// Get the part of loading that only reads and parses the file. Unlike `LoadFile`, it can
+    /// be sent to another thread.
+    pub fn reader(&self) -> ReadFile {
+        ReadFile {
+            path: self.path.clone(),
+            max_size: self.max_size,
+            parse_limits: self.parse_limits,
+            parser: Arc::clone(&self.parser),
+            module_type: self.module_type,
+            cache: if self.pipeline.has_file_steps() { None } else { self.cache.clone() },
+        }
+    }
+
+    /// Run the pipeline steps and transforms on a file that was read by `reader()`.
+    pub fn process(&self, read: ReadOutput) -> Result<SourceFile> {
+        let (file, key) = match read {
+            ReadOutput::Cached(file) => return Ok(file),
+            ReadOutput::Parsed { file, key } => (file, key),
+        };
+        let file = self.pipeline.run_file(Hook::After(Stage::Load), file)?;
+        let file = self.pipeline.run_file(Hook::Before(Stage::Transform), file)?;
+        let file = self.transform(file)?;
+        let file = self.pipeline.run_file(Hook::After(Stage::Transform), file)?;
+        let file = self.pipeline.run_file(Hook::Before(Stage::Detect), file)?;
+        let file = self.detect_dependencies(file);
+        let file = self.pipeline.run_file(Hook::After(Stage::Detect), file)?;
+        if let (&Some(ref cache), Some(key)) = (&self.cache, key) {
+            // The cache only makes builds faster, so failing to write to it does not fail the build.
+            let _ = cache.put(&key, &file);
+        }
+        Ok(file)
     }
 
-    fn read_file(&self) -> Result<SourceFile> {
-        let file = File::open(&self.path)?;
+    /// Dependencies are detected when a file is parsed. Detect them again once the transforms
+    /// and the steps before `Detect` are done, if there are known constants that prune them or
+    /// steps that may have changed the syntax tree.
+    fn detect_dependencies(&self, file: SourceFile) -> SourceFile {
+        if self.constants.is_empty() && !self.pipeline.has_steps(Hook::Before(Stage::Detect)) {
+            return file;
+        }
+        match file {
+            SourceFile::CJS { path, source, hash, ast: Some(ast), .. } => {
+                let calls = detect_calls_with_constants(&ast, &self.constants);
+                SourceFile::CJS {
+                    dependencies: modules(&calls),
+                    dynamic_dependencies: dynamic_modules(&calls),
+                    optional_dependencies: optional_modules(&calls),
+                    unanalyzable_requires: unanalyzable_calls(&calls),
+                    dynamic_code: detect_dynamic_code(&ast),
+                    path,
+                    source,
+                    hash,
+                    ast: Some(ast),
+                }
+            },
+            file => file,
+        }
+    }
+
+    fn transform(&self, file: SourceFile) -> Result<SourceFile> {
+        self.transforms.iter()
+            .fold(Ok(file), |res, transform| {
+                res.and_then(|file| {
+                    let path = file.path().clone();
+                    transform.transform(file).map_err(|err| BundleError::transform(&path, err).into())
+                })
+            })
+    }
+}
+
+/// A file that `ReadFile` read.
+#[derive(Debug)]
+pub enum ReadOutput {
+    /// A parsed file, which still needs to be transformed. It is stored in the build cache at
+    /// `key` afterwards.
+    Parsed { file: SourceFile, key: Option<CacheKey> },
+    /// A file from the build cache, which was transformed already.
+    Cached(SourceFile),
+}
+
+/// Reads and parses a file, and detects its dependencies. Created by `LoadFile::reader`.
+#[derive(Clone)]
+pub struct ReadFile {
+    path: PathBuf,
+    max_size: Option<u64>,
+    parse_limits: ParseLimits,
+    parser: Arc<Parser>,
+    module_type: Option<ModuleType>,
+    cache: Option<BuildCache>,
+}
+
+impl ReadFile {
+    pub fn run(&self) -> Result<ReadOutput> {
+        let file = File::open(&self.path).map_err(|err| BundleError::io(&self.path, err))?;
+        if let Some(max_size) = self.max_size {
+            let size = file.metadata().map_err(|err| BundleError::io(&self.path, err))?.len();
+            if size > max_size {
+                bail!("{} is {} bytes, more than the maximum file size of {} bytes", self.path.to_string_lossy(), size, max_size);
+            }
+        }
         let mut reader = BufReader::new(file);
         let mut source = String::new();
-        reader.read_to_string(&mut source)?;
+        reader.read_to_string(&mut source).map_err(|err| BundleError::io(&self.path, err))?;
+        // This is the only copy of the source: the parser, transforms that do not change it, the
+        // cache and the packers share or borrow it.
+        let source: Arc<str> = Arc::from(source);
 
-        let hash = Sha1::digest_str(&source) as Hash;
+        let key = match self.cache {
+            Some(ref cache) => {
+                let key = cache.key(&self.path, &source, &self.parse_limits);
+                if let Some(file) = cache.get(&key, &self.path) {
+                    return Ok(ReadOutput::Cached(file));
+                }
+                Some(key)
+            },
+            None => None,
+        };
+        Ok(ReadOutput::Parsed { file: self.parse(source)?, key })
+    }
 
-        let is_json = self.path.extension().map_or(false, |ext| ext == "json");
+    fn parse(&self, source: Arc<str>) -> Result<SourceFile> {
+        let is_json = match self.module_type {
+            Some(module_type) => module_type == ModuleType::Json,
+            None => self.path.extension().map_or(false, |ext| ext == "json"),
+        };
         if is_json {
-            let value = serde_json::from_str(&source)?;
+            let hash = Sha1::digest_str(&source) as Hash;
+            let value = serde_json::from_str(&source).map_err(|err| BundleError::parse(&self.path, err))?;
             Ok(SourceFile::JSON {
                 path: self.path.clone(),
                 source,
@@ -122,23 +404,218 @@ impl LoadFile {
                 value,
             })
         } else {
-            let ast = script(&source)
-                .map_err(|e| ParseError::new(&self.path, e))?;
-            let dependencies = detect(&ast);
-            Ok(SourceFile::CJS {
-                path: self.path.clone(),
-                source,
-                hash,
-                ast: Some(ast),
-                dependencies,
-            })
+            parse_with_limits(Arc::clone(&self.parser), self.path.clone(), source, self.parse_limits)
         }
     }
+}
 
-    fn transform(&self, file: SourceFile) -> Result<SourceFile> {
-        self.transforms.iter()
-            .fold(Ok(file), |res, transform| {
-                res.and_then(|file| transform.transform(file))
+/// Parse a CommonJS source file, unless it exceeds one of the limits.
+fn parse_with_limits(parser: Arc<Parser>, path: PathBuf, source: Arc<str>, limits: ParseLimits) -> Result<SourceFile> {
+    if let Some(max_nesting) = limits.max_nesting {
+        let nesting = nesting_depth(&source);
+        if nesting > max_nesting {
+            return Err(LimitExceeded::new(&path, format!("is nested {} levels deep, more than the maximum of {}", nesting, max_nesting)).into());
+        }
+    }
+
+    let file = match limits.max_parse_time {
+        Some(max_parse_time) => {
+            let (sender, receiver) = mpsc::channel();
+            let thread_path = path.clone();
+            thread::Builder::new()
+                .stack_size(PARSER_STACK_SIZE)
+                .spawn(move || {
+                    let _ = sender.send(parse_cjs_with(&*parser, thread_path, source));
+                })?;
+            match receiver.recv_timeout(Duration::from_millis(max_parse_time)) {
+                Ok(result) => result?,
+                // The parser thread keeps running until it is done, but the build does not wait for it.
+                Err(RecvTimeoutError::Timeout) =>
+                    return Err(LimitExceeded::new(&path, format!("took more than the maximum of {}ms to parse", max_parse_time)).into()),
+                Err(RecvTimeoutError::Disconnected) => bail!("The parser crashed while parsing {}", path.to_string_lossy()),
+            }
+        },
+        None => parse_cjs_with(&*parser, path.clone(), source)?,
+    };
+
+    if let (Some(max_nodes), &SourceFile::CJS { ast: Some(ref ast), .. }) = (limits.max_nodes, &file) {
+        let nodes = Walker::new(ast, CountNodes { nodes: 0, max: max_nodes }).walk().nodes;
+        if nodes > max_nodes {
+            return Err(LimitExceeded::new(&path, format!("has more than the maximum of {} syntax nodes", max_nodes)).into());
+        }
+    }
+    Ok(file)
+}
+
+/// Find the deepest nesting of parentheses, brackets and braces, ignoring strings and comments.
+/// Template literals are skipped as a whole and regular expressions are not recognised,
+/// so this is an approximation.
+fn nesting_depth(source: &str) -> usize {
+    let mut chars = source.chars().peekable();
+    let mut depth = 0usize;
+    let mut max_depth = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '(' | '[' | '{' => {
+                depth += 1;
+                max_depth = max_depth.max(depth);
+            },
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            '"' | '\'' | '`' => {
+                while let Some(next) = chars.next() {
+                    if next == '\\' {
+                        chars.next();
+                    } else if next == c || (c != '`' && next == '\n') {
+                        break;
+                    }
+                }
+            },
+            '/' if chars.peek() == Some(&'/') => {
+                while let Some(next) = chars.next() {
+                    if next == '\n' {
+                        break;
+                    }
+                }
+            },
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                while let Some(next) = chars.next() {
+                    if previous == '*' && next == '/' {
+                        break;
+                    }
+                    previous = next;
+                }
+            },
+            _ => (),
+        }
+    }
+    max_depth
+}
+
+/// Counts syntax nodes, and stops as soon as there are more than `max`.
+struct CountNodes {
+    nodes: usize,
+    max: usize,
+}
+
+impl CountNodes {
+    fn count(&mut self) -> Control {
+        self.nodes += 1;
+        if self.nodes > self.max { Control::Stop } else { Control::Continue }
+    }
+}
+
+impl Callbacks for CountNodes {
+    fn pre_stmt(&mut self, _node: &Stmt, _path: &NodePath) -> Control {
+        self.count()
+    }
+    fn pre_expr(&mut self, _node: &Expr, _path: &NodePath) -> Control {
+        self.count()
+    }
+    fn pre_decl(&mut self, _node: &Decl) -> Control {
+        self.count()
+    }
+}
+
+/// Parse a CommonJS source file and detect its dependencies.
+/// Transforms that change the source code can use this to update the syntax tree.
+pub fn parse_cjs<S: Into<Arc<str>>>(path: PathBuf, source: S) -> Result<SourceFile> {
+    parse_cjs_with(&EspritParser, path, source)
+}
+
+/// Parse a CommonJS source file with a specific parser and detect its dependencies.
+/// The source is not copied if it is an `Arc<str>` already.
+pub fn parse_cjs_with<S: Into<Arc<str>>>(parser: &Parser, path: PathBuf, source: S) -> Result<SourceFile> {
+    let source = source.into();
+    let hash = Sha1::digest_str(&source) as Hash;
+    let ast = parser.parse(&path, &source).map_err(|err| BundleError::parse(&path, err))?;
+    let calls = detect_calls_with_constants(&ast, &Constants::new());
+    Ok(SourceFile::CJS {
+        path,
+        source,
+        hash,
+        dependencies: modules(&calls),
+        dynamic_dependencies: dynamic_modules(&calls),
+        optional_dependencies: optional_modules(&calls),
+        dynamic_code: detect_dynamic_code(&ast),
+        unanalyzable_requires: unanalyzable_calls(&calls),
+        ast: Some(ast),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::rc::Rc;
+    use std::sync::Arc;
+    use quicli::prelude::Result;
+    use error::BundleError;
+    use graph::SourceFile;
+    use pipeline::{Pipeline, Stage, Step};
+    use super::{EspritParser, LimitExceeded, LoadFile, ParseLimits, nesting_depth, parse_cjs, parse_with_limits};
+
+    #[test]
+    fn measures_nesting_depth() {
+        assert_eq!(nesting_depth("a"), 0);
+        assert_eq!(nesting_depth("f(a[0], { b: [1] })"), 3);
+        assert_eq!(nesting_depth("f('((((', \"[[\\\"[[\", `{{{`) // ((((\n/* [[[[ */"), 1);
+    }
+
+    #[test]
+    fn enforces_parse_limits() {
+        let source = format!("var a = {}1{}", "(".repeat(50), ")".repeat(50));
+        let limits = ParseLimits { max_nesting: Some(10), ..ParseLimits::default() };
+        let err = parse_with_limits(Arc::new(EspritParser), PathBuf::from("/a.js"), Arc::from(source.clone()), limits).unwrap_err();
+        assert!(err.downcast_ref::<LimitExceeded>().is_some());
+
+        let limits = ParseLimits { max_nodes: Some(10), ..ParseLimits::default() };
+        assert!(parse_with_limits(Arc::new(EspritParser), PathBuf::from("/a.js"), "a; b; c; d; e; f; g; h; i; j; k".into(), limits).is_err());
+        assert!(parse_with_limits(Arc::new(EspritParser), PathBuf::from("/a.js"), "a; b".into(), limits).is_ok());
+
+        let limits = ParseLimits { max_parse_time: Some(10000), ..ParseLimits::default() };
+        assert!(parse_with_limits(Arc::new(EspritParser), PathBuf::from("/a.js"), Arc::from(source), limits).is_ok());
+    }
+
+    #[test]
+    fn wraps_parse_errors() {
+        let err = parse_cjs(PathBuf::from("/a.js"), "var = 1".to_string()).unwrap_err();
+        match err.downcast_ref::<BundleError>() {
+            Some(&BundleError::Parse { ref path, .. }) => assert_eq!(path, &PathBuf::from("/a.js")),
+            _ => panic!("Expected a parse error, got {}", err),
+        }
+    }
+
+    /// Replaces the syntax tree of a file, without changing the dependencies that were found.
+    struct ReplaceSyntaxTree(&'static str);
+    impl Step for ReplaceSyntaxTree {
+        fn file(&self, file: SourceFile) -> Result<SourceFile> {
+            let replacement = match parse_cjs(file.path().clone(), self.0.to_string())? {
+                SourceFile::CJS { ast, .. } => ast,
+                _ => unreachable!(),
+            };
+            Ok(match file {
+                SourceFile::CJS { path, source, hash, dependencies, dynamic_dependencies, optional_dependencies, dynamic_code, unanalyzable_requires, .. } =>
+                    SourceFile::CJS { path, source, hash, ast: replacement, dependencies, dynamic_dependencies, optional_dependencies, dynamic_code, unanalyzable_requires },
+                file => file,
             })
+        }
+    }
+
+    #[test]
+    fn detects_dependencies_after_steps_before_detect() {
+        let path = env::temp_dir().join(format!("js-bundler-detect-{}.js", ::std::process::id()));
+        fs::write(&path, "require('./a')").unwrap();
+        let dependencies = |pipeline: Pipeline| match LoadFile::new(path.clone()).with_pipeline(Rc::new(pipeline)).run().unwrap() {
+            SourceFile::CJS { dependencies, .. } => dependencies,
+            _ => panic!("expected a CommonJS file"),
+        };
+        let before = dependencies(Pipeline::new().before(Stage::Detect, ReplaceSyntaxTree("require('./b')")));
+        let after = dependencies(Pipeline::new().after(Stage::Detect, ReplaceSyntaxTree("require('./b')")));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(before, vec!["./b".to_string()]);
+        assert_eq!(after, vec!["./a".to_string()]);
     }
 }
@@ -1,45 +1,543 @@
-extern crate digest;
-extern crate easter;
-extern crate esprit;
-extern crate node_resolve;
-extern crate serde_json;
-extern crate sha1;
+extern crate js_bundler;
 extern crate estree_detect_requires;
-extern crate node_core_shims;
+extern crate serde_json;
 extern crate time;
 #[macro_use] extern crate quicli;
 
-mod builtins;
-mod deps;
-mod graph;
-mod loader;
-mod pack;
-
-use std::io::{Write, stdout};
+use std::io::{Read, Write, stdin, stdout};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use time::PreciseTime;
 use quicli::prelude::*;
-use deps::Deps;
-use pack::Pack;
+use estree_detect_requires::detect_calls;
+use js_bundler::config::{Config, CONFIG_FILE};
+use js_bundler::hooks::{run_post_build, run_on_rebuild, BuildReport};
+use js_bundler::init::generate_config;
+use js_bundler::licenses::{module_licenses, to_sbom, SbomFormat};
+use js_bundler::diagnostics::Severity;
+use js_bundler::emit::{write_if_changed, EmitTarget, ManifestEntry};
+use js_bundler::estree::to_estree;
+use js_bundler::features::feature_stats;
+use js_bundler::flat_pack::FlatPack;
+use js_bundler::graph::SourceFile;
+use js_bundler::loader::parse_cjs;
+use js_bundler::pack::Pack;
+use js_bundler::pipeline::{Hook, Stage};
+use js_bundler::polyfill::polyfill_report;
+use js_bundler::sourcemap::SourceMap;
+use js_bundler::sources::emit_sources;
+use js_bundler::ui::WatchUi;
+use js_bundler::watch::{watch, Build, WatchEvent};
 
 #[derive(Debug, StructOpt)]
 struct Options {
-    entry: String,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+    #[structopt(help = "The entry point of the bundle. Defaults to the `entry` from bundler.toml.")]
+    entry: Option<String>,
+    #[structopt(long = "config", short = "c", help = "Read options from this file instead of ./bundler.toml.", parse(from_os_str))]
+    config: Option<PathBuf>,
     #[structopt(long = "no-builtins", help = "Exclude shims for builtin modules. Useful when generating a bundle for Node.")]
     no_builtins: bool,
+    #[structopt(long = "external", help = "Keep require() calls for this package instead of bundling it. Can be given multiple times.")]
+    external: Vec<String>,
+    #[structopt(long = "library", help = "Bundle as a library: keep the peerDependencies from ./package.json external.")]
+    library: bool,
+    #[structopt(long = "external-dependencies", help = "With --library, also keep the dependencies from ./package.json external.")]
+    external_dependencies: bool,
+    #[structopt(long = "no-external", help = "Bundle this package even if --library would keep it external. Can be given multiple times.")]
+    no_external: Vec<String>,
+    #[structopt(long = "outfile", short = "o", help = "Write the bundle to a file instead of stdout.", parse(from_os_str))]
+    outfile: Option<PathBuf>,
+    #[structopt(long = "outdir", short = "d", help = "Write the bundle, copied files and a manifest.json to this directory.", parse(from_os_str))]
+    outdir: Option<PathBuf>,
+    #[structopt(long = "clean", help = "Remove files from --outdir that the previous build wrote, but this one did not.")]
+    clean: bool,
+    #[structopt(long = "public-path", help = "The URL that --outdir is served at, for the URLs in manifest.json. Defaults to /.")]
+    public_path: Option<String>,
+    #[structopt(long = "licenses", help = "List every bundled module with its package, version and license in manifest.json. Requires --outdir.")]
+    licenses: bool,
+    #[structopt(long = "emit-sources", help = "Copy the source of every bundled module, after transforms, to this directory, grouped by package and version, with an index.json.", parse(from_os_str))]
+    emit_sources: Option<PathBuf>,
+    #[structopt(long = "context-require", help = "Bundle all files that a require('./dir/' + name) call could load, if it points into this directory. Can be given multiple times.", parse(from_os_str))]
+    context_require: Vec<PathBuf>,
+    #[structopt(long = "allow-https", help = "Allow require() calls of https: URLs. The modules are downloaded into the cache directory.")]
+    allow_https: bool,
+    #[structopt(long = "offline", help = "Do not download https: modules, only use the ones in the cache.")]
+    offline: bool,
+    #[structopt(long = "no-cache", help = "Parse and transform every file again, instead of loading unchanged files from the build cache.")]
+    no_cache: bool,
+    #[structopt(long = "exclude", help = "Fail if a file matching this glob pattern is required. Can be given multiple times.")]
+    exclude: Vec<String>,
+    #[structopt(long = "package-budget", help = "Fail if a package adds more source code to the bundle than this, eg. moment=20000. Can be given multiple times.", parse(try_from_str = "parse_budget"))]
+    package_budget: Vec<(String, u64)>,
+    #[structopt(long = "strip-asserts", help = "Remove assert() and console.assert() calls, and the assert module if it is no longer used.")]
+    strip_asserts: bool,
+    #[structopt(long = "strip-console", help = "Remove console.*() calls.")]
+    strip_console: bool,
+    #[structopt(long = "keep-console", help = "Keep calls to this console method when using --strip-console, eg. error. Can be given multiple times.")]
+    keep_console: Vec<String>,
+    #[structopt(long = "strip-debugger", help = "Remove debugger statements.")]
+    strip_debugger: bool,
+    #[structopt(long = "minimal-runtime", help = "Pack modules without a module runtime, for the smallest output. Requires a single entry point, no circular dependencies and no dynamic require() calls.")]
+    minimal_runtime: bool,
+    #[structopt(long = "runtime-registry", help = "Expose __bundle.register(id, factory) and __bundle.invalidate(id), so the page can add or replace modules at runtime.")]
+    runtime_registry: bool,
+    #[structopt(long = "mode", help = "The build mode that the bundler:meta module exports. Defaults to production.")]
+    mode: Option<String>,
+    #[structopt(long = "post-build", help = "Run this shell command after a successful build, with a JSON build report on stdin. Can be given multiple times.")]
+    post_build: Vec<String>,
+    #[structopt(long = "on-rebuild", help = "Run this shell command after each successful build in --watch mode, eg. to re-run tests. It gets the build report on stdin and in JS_BUNDLER_* environment variables. Can be given multiple times.")]
+    on_rebuild: Vec<String>,
+    #[structopt(long = "env-prefix", help = "Inline process.env.NAME for variables starting with this prefix, eg. APP_. Reads .env, .env.local, .env.{mode} and .env.{mode}.local, then the environment.")]
+    env_prefix: Option<String>,
+    #[structopt(long = "dynamic-code", help = "What to do when a module uses eval, Function or with: ignore, warn or error. Defaults to warn.")]
+    dynamic_code: Option<Severity>,
+    #[structopt(long = "unanalyzable-requires", help = "What to do when a require() argument is not known at build time, like require(name): ignore, warn or error. Defaults to warn.")]
+    unanalyzable_requires: Option<Severity>,
+    #[structopt(long = "max-modules", help = "Fail if the bundle would contain more than this many modules.")]
+    max_modules: Option<u32>,
+    #[structopt(long = "max-file-size", help = "Fail if a source file is larger than this many bytes.")]
+    max_file_size: Option<u64>,
+    #[structopt(long = "max-depth", help = "Fail if a chain of require() calls from the entry point is longer than this.")]
+    max_depth: Option<usize>,
+    #[structopt(long = "sandbox", help = "Only load, copy and write files inside this directory, and refuse to use https: modules, environment variables or post-build commands. For bundling code that can not be trusted.", parse(from_os_str))]
+    sandbox: Option<PathBuf>,
+    #[structopt(long = "max-nesting", help = "Skip files that nest parentheses, brackets and braces deeper than this, with a warning.")]
+    max_nesting: Option<usize>,
+    #[structopt(long = "max-ast-nodes", help = "Skip files with more syntax nodes than this, with a warning.")]
+    max_ast_nodes: Option<usize>,
+    #[structopt(long = "max-parse-time", help = "Skip files that take longer than this many milliseconds to parse, with a warning.")]
+    max_parse_time: Option<u64>,
+    #[structopt(long = "watch", short = "w", help = "Rebuild when one of the bundled files changes. Requires --outfile or --outdir.")]
+    watch: bool,
+    #[structopt(long = "ui", help = "Show the build status, recent build times, bundle sizes and warnings on a screen that is redrawn after every rebuild, in --watch mode.")]
+    ui: bool,
+    #[structopt(long = "watch-ignore", help = "Do not rebuild when files matching this glob pattern change, eg. **/coverage/**. Can be given multiple times.")]
+    watch_ignore: Vec<String>,
+    #[structopt(long = "watch-gitignore", help = "Do not rebuild when files that .gitignore lists change.")]
+    watch_gitignore: bool,
+    #[structopt(long = "poll", help = "Poll files for changes in --watch mode, for file systems where native watching does not work, like Docker volumes and network drives.")]
+    poll: bool,
+    #[structopt(long = "poll-interval", help = "How often to poll files for changes, in milliseconds. Implies --poll. Defaults to 1000.")]
+    poll_interval: Option<u64>,
+    #[structopt(long = "poll-path", help = "Only poll files matching this glob pattern, and watch the others natively. Can be given multiple times.")]
+    poll_path: Vec<String>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    #[structopt(name = "init", about = "Generate a bundler.toml for the project in the current directory, based on its package.json.")]
+    Init {
+        #[structopt(long = "force", help = "Overwrite an existing bundler.toml.")]
+        force: bool,
+    },
+    #[structopt(name = "symbolicate", about = "Rewrite a stack trace from a bundle to original source positions, using source maps. Reads the stack trace from stdin.")]
+    Symbolicate {
+        #[structopt(long = "map", help = "A source map for a generated file. The generated file name is taken from the map's `file` field, or from the map file name without `.map`. Can be given multiple times.", parse(from_os_str))]
+        maps: Vec<PathBuf>,
+    },
+    #[structopt(name = "parse", about = "Parse a file and print its syntax tree as ESTree JSON.")]
+    Parse {
+        #[structopt(help = "The file to parse.", parse(from_os_str))]
+        file: PathBuf,
+    },
+    #[structopt(name = "detect", about = "Print the require() calls in a file as JSON, with their kinds and locations.")]
+    Detect {
+        #[structopt(help = "The file to read.", parse(from_os_str))]
+        file: PathBuf,
+    },
+    #[structopt(name = "config", about = "Check or print the config file.")]
+    Config {
+        #[structopt(subcommand)]
+        command: ConfigCommand,
+    },
+    #[structopt(name = "features", about = "Count the uses of language features that are newer than ES5 in each bundled package.")]
+    Features {
+        #[structopt(help = "The entry point. Defaults to the `entry` from bundler.toml.")]
+        entry: Option<String>,
+        #[structopt(long = "json", help = "Print the counts as JSON.")]
+        json: bool,
+    },
+    #[structopt(name = "sbom", about = "Print a software bill of materials for the packages in the bundle, with their licenses.")]
+    Sbom {
+        #[structopt(help = "The entry point. Defaults to the `entry` from bundler.toml.")]
+        entry: Option<String>,
+        #[structopt(long = "format", help = "The format: cyclonedx or spdx. Defaults to cyclonedx.")]
+        format: Option<SbomFormat>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum ConfigCommand {
+    #[structopt(name = "check", about = "Report unknown options and invalid values in the config file, with their locations.")]
+    Check {},
+    #[structopt(name = "print", about = "Print the options from the config file as TOML, including the defaults.")]
+    Print {
+        #[structopt(long = "resolved", help = "Apply the command line flags on top of the config file, like a build does.")]
+        resolved: bool,
+    },
+}
+
+fn parse_budget(budget: &str) -> ::std::result::Result<(String, u64), String> {
+    let mut parts = budget.splitn(2, '=');
+    match (parts.next(), parts.next().map(|bytes| bytes.parse())) {
+        (Some(package), Some(Ok(bytes))) => Ok((package.to_string(), bytes)),
+        _ => Err(format!("expected a budget like package=bytes, got {}", budget)),
+    }
+}
+
+/// Apply command line flags on top of the options from the config file.
+fn merge_args(mut config: Config, args: &Options) -> Config {
+    if args.entry.is_some() { config.entry = args.entry.clone(); }
+    if args.outfile.is_some() { config.outfile = args.outfile.clone(); }
+    if args.outdir.is_some() { config.outdir = args.outdir.clone(); }
+    config.clean |= args.clean;
+    config.licenses |= args.licenses;
+    if args.public_path.is_some() { config.public_path = args.public_path.clone(); }
+    if args.emit_sources.is_some() { config.emit_sources = args.emit_sources.clone(); }
+    config.no_builtins |= args.no_builtins;
+    config.external.extend(args.external.iter().cloned());
+    config.library |= args.library;
+    config.external_dependencies |= args.external_dependencies;
+    config.no_external.extend(args.no_external.iter().cloned());
+    config.context_requires.extend(args.context_require.iter().cloned());
+    config.allow_https |= args.allow_https;
+    config.offline |= args.offline;
+    config.no_cache |= args.no_cache;
+    config.exclude.extend(args.exclude.iter().cloned());
+    config.watch_ignore.extend(args.watch_ignore.iter().cloned());
+    config.watch_gitignore |= args.watch_gitignore;
+    config.watch_poll |= args.poll;
+    if args.poll_interval.is_some() { config.watch_poll_interval = args.poll_interval; }
+    config.watch_poll_paths.extend(args.poll_path.iter().cloned());
+    config.package_budgets.extend(args.package_budget.iter().cloned());
+    config.strip_asserts |= args.strip_asserts;
+    config.strip_console |= args.strip_console;
+    config.keep_console.extend(args.keep_console.iter().cloned());
+    config.strip_debugger |= args.strip_debugger;
+    config.minimal_runtime |= args.minimal_runtime;
+    config.runtime_registry |= args.runtime_registry;
+    config.post_build.extend(args.post_build.iter().cloned());
+    config.on_rebuild.extend(args.on_rebuild.iter().cloned());
+    if args.mode.is_some() { config.mode = args.mode.clone(); }
+    if args.env_prefix.is_some() { config.env_prefix = args.env_prefix.clone(); }
+    if let Some(severity) = args.dynamic_code { config.dynamic_code = severity; }
+    if let Some(severity) = args.unanalyzable_requires { config.unanalyzable_requires = severity; }
+    if args.max_modules.is_some() { config.max_modules = args.max_modules; }
+    if args.max_file_size.is_some() { config.max_file_size = args.max_file_size; }
+    if args.max_depth.is_some() { config.max_depth = args.max_depth; }
+    if args.sandbox.is_some() { config.sandbox = args.sandbox.clone(); }
+    if args.max_nesting.is_some() { config.max_nesting = args.max_nesting; }
+    if args.max_ast_nodes.is_some() { config.max_ast_nodes = args.max_ast_nodes; }
+    if args.max_parse_time.is_some() { config.max_parse_time = args.max_parse_time; }
+    config
+}
+
+fn entry(config: &Config) -> Result<&String> {
+    config.entry.as_ref()
+        .ok_or_else(|| format_err!("No entry point given. Pass one on the command line or set `entry` in {}.", CONFIG_FILE))
+}
+
+fn read_config(args: &Options) -> Result<Config> {
+    match args.config {
+        Some(ref path) => Config::from_file(path),
+        None => Config::from_default_file(),
+    }
+}
+
+/// Read the config file and apply the command line flags on top of it.
+fn load_config(args: &Options) -> Result<Config> {
+    Ok(merge_args(read_config(args)?, args))
+}
+
+fn build(config: &Config) -> Result<Build> {
+    let entry = entry(config)?;
+    let mut deps = config.to_deps()?;
+    deps.run(entry)?;
+    for line in polyfill_report(deps.polyfill_uses()) {
+        eprint!("added polyfill {}\n", line);
+    }
+    if let Some(ref dir) = config.emit_sources {
+        emit_sources(&deps, dir)?;
+    }
+    let pipeline = deps.pipeline();
+    let bundle = if config.minimal_runtime {
+        if config.runtime_registry {
+            bail!("--minimal-runtime can not be used with --runtime-registry");
+        }
+        if !config.inject.is_empty() {
+            bail!("--minimal-runtime can not be used with `inject`");
+        }
+        if !config.polyfills.is_empty() {
+            bail!("--minimal-runtime can not be used with `polyfills`");
+        }
+        FlatPack::new(&deps).to_string()?
+    } else {
+        Pack::new(&deps)
+            .with_registry(config.runtime_registry)
+            .with_injections(config.injections()?)
+            .with_polyfills(config.polyfills()?)
+            .to_string()
+    };
+    let bundle = pipeline.run_bundle(Hook::Before(Stage::Optimize), bundle)?;
+    let bundle = pipeline.run_bundle(Hook::After(Stage::Optimize), bundle)?;
+    let bundle = pipeline.run_bundle(Hook::Before(Stage::Emit), bundle)?;
+    Ok(Build {
+        bundle,
+        files: deps.values().map(|record| record.file.path().clone()).collect(),
+        warnings: deps.warnings().to_vec(),
+        licenses: if config.licenses { module_licenses(&deps)? } else { vec![] },
+        pipeline,
+    })
+}
+
+/// Write the bundle. Returns the files in the output directory, if there is one.
+/// With `quiet`, nothing is printed, because the watch UI shows the build.
+fn write_bundle(config: &Config, build: &Build, quiet: bool) -> Result<Vec<ManifestEntry>> {
+    if let Some(emitter) = config.to_emitter()? {
+        let emitter = emitter
+            .with_module_licenses(build.licenses.clone())
+            .with_pipeline(Rc::clone(&build.pipeline));
+        let entry = config.entry.as_ref().map_or("bundle", |entry| entry.as_str());
+        let name = Path::new(entry).file_stem().map_or("bundle".into(), |stem| stem.to_string_lossy());
+        if emitter.target() == EmitTarget::Memory {
+            // Nothing is written, so show what would have been.
+            let output = emitter.emit_in_memory(&name, &build.bundle)?;
+            if !quiet {
+                for artifact in &output.artifacts {
+                    eprint!("emitted {} ({} bytes) in memory\n", artifact.entry.path, artifact.contents.len());
+                }
+            }
+            return Ok(output.manifest.files);
+        }
+        return Ok(emitter.emit(&name, &build.bundle)?.files);
+    }
+    match config.outfile {
+        Some(ref path) => write_if_changed(path, build.bundle.as_bytes())?,
+        None => stdout().write_all(build.bundle.as_bytes())?,
+    }
+    Ok(vec![])
+}
+
+/// Write the bundle, print the warnings and a summary and run the post-build commands.
+/// With `quiet`, nothing is printed, because the watch UI shows the warnings and the summary.
+fn finish(config: &Config, build: &Build, start: PreciseTime, quiet: bool) -> Result<BuildReport> {
+    if !quiet {
+        for warning in &build.warnings {
+            eprintln!("warning: {}", warning);
+        }
+    }
+    let files = write_bundle(config, build, quiet)?;
+    let duration_ms = start.to(PreciseTime::now()).num_milliseconds();
+    if !quiet {
+        eprint!("wrote {} bytes containing {} modules, took {}ms\n", build.bundle.len(), build.files.len(), duration_ms);
+    }
+    let report = BuildReport {
+        bytes: build.bundle.len(),
+        modules: build.files.len(),
+        duration_ms,
+        outfile: config.outfile.clone(),
+        outdir: config.outdir.clone(),
+        files,
+    };
+    if !config.post_build.is_empty() {
+        run_post_build(&config.post_build, &report)?;
+    }
+    Ok(report)
+}
+
+fn bundle(args: &Options) -> Result<()> {
+    let config = load_config(args)?;
+
+    if let Some(env) = config.load_env(Path::new("."))? {
+        let names = env.vars.keys().cloned().collect::<Vec<String>>();
+        let files = env.files.iter().map(|file| file.to_string_lossy().into_owned()).collect::<Vec<String>>();
+        eprint!("inlining environment variables: {} (from {})\n",
+                if names.is_empty() { "none".to_string() } else { names.join(", ") },
+                if files.is_empty() { "the environment".to_string() } else { files.join(", ") + " and the environment" });
+    }
+
+    if args.ui && !args.watch {
+        bail!("--ui requires --watch");
+    }
+    if args.watch {
+        if config.outfile.is_none() && config.outdir.is_none() {
+            bail!("--watch requires --outfile or --outdir");
+        }
+
+        let entries: Vec<PathBuf> = config.entry.iter().map(PathBuf::from).collect();
+        let mut start = PreciseTime::now();
+        let mut builds = 0;
+        let mut changed = None;
+        let mut ui = if args.ui { Some(WatchUi::new()) } else { None };
+        let options = config.watch_options()?;
+        watch(&entries, &options, || build(&config), |event| {
+            let mut errors = vec![];
+            let mut hook_error = None;
+            match *event {
+                WatchEvent::BuildStarted => start = PreciseTime::now(),
+                WatchEvent::BuildFinished { result: Ok(ref build) } => match finish(&config, build, start, ui.is_some()) {
+                    Ok(report) => {
+                        builds += 1;
+                        let changed: Option<PathBuf> = changed.take();
+                        if !config.on_rebuild.is_empty() {
+                            if let Err(err) = run_on_rebuild(&config.on_rebuild, &report, builds, changed.as_ref().map(|path| path.as_path())) {
+                                hook_error = Some(err.to_string());
+                            }
+                        }
+                    },
+                    Err(err) => errors.push(err.to_string()),
+                },
+                WatchEvent::BuildFinished { result: Err(ref err) } => if ui.is_none() {
+                    eprintln!("{}", err);
+                },
+                WatchEvent::FileChanged(ref path) => {
+                    if ui.is_none() {
+                        eprintln!("{} changed, rebuilding", path.to_string_lossy());
+                    }
+                    changed = Some(path.clone());
+                },
+                WatchEvent::Diagnostic(ref message) => if ui.is_none() {
+                    eprintln!("{}", message);
+                },
+            }
+            match ui {
+                Some(ref mut ui) => {
+                    ui.handle(event);
+                    for error in errors {
+                        ui.error(error);
+                    }
+                    if let Some(message) = hook_error {
+                        ui.hook_error(message);
+                    }
+                    let _ = ui.draw();
+                },
+                None => for error in errors.into_iter().chain(hook_error) {
+                    eprintln!("{}", error);
+                },
+            }
+        })?;
+    } else {
+        let start = PreciseTime::now();
+        let build = build(&config)?;
+        finish(&config, &build, start, false)?;
+    }
+    Ok(())
+}
+
+fn init(force: bool) -> Result<()> {
+    let path = Path::new(CONFIG_FILE);
+    if path.exists() && !force {
+        bail!("{} already exists. Use --force to overwrite it.", CONFIG_FILE);
+    }
+    let config = generate_config(Path::new("."))?;
+    write_to_file(path, &config)?;
+    eprint!("wrote {}\n", CONFIG_FILE);
+    Ok(())
+}
+
+fn symbolicate(map_paths: &[PathBuf]) -> Result<()> {
+    let mut maps = vec![];
+    for path in map_paths {
+        let map = SourceMap::from_file(path)?;
+        let file = match map.file() {
+            Some(file) => file.to_string(),
+            None => path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default(),
+        };
+        maps.push((file, map));
+    }
+    let mut trace = String::new();
+    stdin().read_to_string(&mut trace)?;
+    println!("{}", js_bundler::symbolicate::symbolicate(&trace, &maps));
+    Ok(())
+}
+
+fn parse_file(path: &Path) -> Result<SourceFile> {
+    let source = read_file(path)?;
+    parse_cjs(path.to_path_buf(), source)
+}
+
+fn parse(path: &Path) -> Result<()> {
+    if let SourceFile::CJS { ast: Some(ref ast), ref source, .. } = parse_file(path)? {
+        println!("{}", serde_json::to_string_pretty(&to_estree(ast, source))?);
+    }
+    Ok(())
+}
+
+fn detect(path: &Path) -> Result<()> {
+    let ast = match parse_file(path)? {
+        SourceFile::CJS { ast: Some(ast), .. } => ast,
+        _ => return Ok(()),
+    };
+    println!("{}", serde_json::to_string_pretty(&detect_calls(&ast))?);
+    Ok(())
+}
+
+fn check_config(args: &Options) -> Result<()> {
+    let path = args.config.clone().unwrap_or_else(|| PathBuf::from(CONFIG_FILE));
+    let issues = Config::check(&read_file(&path)?);
+    if !issues.is_empty() {
+        for issue in &issues {
+            eprintln!("{}", issue.to_string_in(&path));
+        }
+        bail!("Invalid config file {}", path.to_string_lossy());
+    }
+    eprint!("{} is valid\n", path.to_string_lossy());
+    Ok(())
+}
+
+fn print_config(args: &Options, resolved: bool) -> Result<()> {
+    let config = if resolved { load_config(args)? } else { read_config(args)? };
+    print!("{}", config.to_toml()?);
+    Ok(())
+}
+
+fn features(args: &Options, entry_arg: Option<&String>, json: bool) -> Result<()> {
+    let mut config = load_config(args)?;
+    if entry_arg.is_some() { config.entry = entry_arg.cloned(); }
+    // Features are counted in the syntax tree, which cached files do not have.
+    config.no_cache = true;
+    let mut deps = config.to_deps()?;
+    deps.run(entry(&config)?)?;
+    let stats = feature_stats(&deps);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+    for (package, counts) in &stats {
+        println!("{}", package);
+        if counts.is_empty() {
+            println!("  none");
+        }
+        for (feature, count) in counts {
+            println!("  {}: {}", feature, count);
+        }
+    }
+    Ok(())
+}
+
+fn sbom(args: &Options, entry_arg: Option<&String>, format: SbomFormat) -> Result<()> {
+    let mut config = load_config(args)?;
+    if entry_arg.is_some() { config.entry = entry_arg.cloned(); }
+    let mut deps = config.to_deps()?;
+    let entry = entry(&config)?;
+    deps.run(entry)?;
+    let name = Path::new(entry).file_stem().map_or("bundle".into(), |stem| stem.to_string_lossy());
+    println!("{}", to_sbom(&module_licenses(&deps)?, format, &name)?);
+    Ok(())
 }
 
 main!(|args: Options| {
-    let start = PreciseTime::now();
-    let mut deps = Deps::new()
-        .include_builtins(!args.no_builtins)
-        .with_builtins_path("./crates/node-core-shims".into());
-
-    deps.run(&args.entry)?;
-    let mut out = stdout();
-    let num_modules = deps.len();
-    let bundle = Pack::new(&deps).to_string();
-    let size = bundle.len();
-    out.write_all(bundle.as_bytes())?;
-    let end = PreciseTime::now();
-    eprint!("wrote {} bytes containing {} modules, took {}ms\n", size, num_modules, start.to(end).num_milliseconds());
+    match args.command {
+        Some(Command::Init { force }) => init(force)?,
+        Some(Command::Symbolicate { ref maps }) => symbolicate(maps)?,
+        Some(Command::Parse { ref file }) => parse(file)?,
+        Some(Command::Detect { ref file }) => detect(file)?,
+        Some(Command::Config { command: ConfigCommand::Check {} }) => check_config(&args)?,
+        Some(Command::Config { command: ConfigCommand::Print { resolved } }) => print_config(&args, resolved)?,
+        Some(Command::Features { ref entry, json }) => features(&args, entry.as_ref(), json)?,
+        Some(Command::Sbom { ref entry, format }) => sbom(&args, entry.as_ref(), format.unwrap_or(SbomFormat::CycloneDx))?,
+        None => bundle(&args)?,
+    }
 });
@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use serde_json::Value;
+use sha1::{Sha1, Digest};
+use time;
+
+/// The ID of the module that exports information about the build.
+pub const META_MODULE: &str = "bundler:meta";
+
+/// Information about the build, exported by the `bundler:meta` module.
+///
+/// ```js
+/// var meta = require('bundler:meta')
+/// console.log(meta.buildId, meta.mode, meta.target, meta.entry)
+/// ```
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildMeta {
+    /// A unique ID for this build.
+    pub build_id: String,
+    /// When the build started, in milliseconds since the Unix epoch.
+    pub timestamp: i64,
+    /// The build mode, eg. `production`.
+    pub mode: String,
+    /// The environment the bundle is for, `browser` or `node`.
+    pub target: String,
+    /// The name of the entry point, eg. `app` for `./src/app.js`.
+    pub entry: String,
+    /// Constants from the `[meta]` table in bundler.toml.
+    #[serde(flatten)]
+    pub constants: BTreeMap<String, Value>,
+}
+
+impl BuildMeta {
+    pub fn new(entry: &str, mode: &str, target: &str) -> Self {
+        let now = time::get_time();
+        let timestamp = now.sec * 1000 + i64::from(now.nsec / 1_000_000);
+        let build_id = format!("{:x}", Sha1::digest_str(&format!("{}.{}:{}", now.sec, now.nsec, entry)));
+        BuildMeta {
+            build_id: build_id[..16].to_string(),
+            timestamp,
+            mode: mode.to_string(),
+            target: target.to_string(),
+            entry: Path::new(entry).file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            constants: BTreeMap::new(),
+        }
+    }
+
+    /// Add user-defined constants. Names that are already used by the builtin fields are returned
+    /// as an error.
+    pub fn with_constants(mut self, constants: BTreeMap<String, Value>) -> ::std::result::Result<Self, String> {
+        const RESERVED: &[&str] = &["buildId", "timestamp", "mode", "target", "entry"];
+        if let Some(name) = constants.keys().find(|name| RESERVED.contains(&name.as_str())) {
+            return Err(name.clone());
+        }
+        self.constants = constants;
+        Ok(self)
+    }
+
+    /// A name for the file that the module is stored in. It is the same for every build of the
+    /// same entry point, mode and target, so each build replaces the file of the one before.
+    pub fn file_stem(&self) -> String {
+        format!("{:x}", Sha1::digest_str(&format!("{}\0{}\0{}", self.entry, self.mode, self.target)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use serde_json::{self, Value};
+    use super::BuildMeta;
+
+    #[test]
+    fn exports_entry_name_and_constants() {
+        let mut constants = BTreeMap::new();
+        constants.insert("apiUrl".to_string(), Value::from("https://example.com"));
+        let meta = BuildMeta::new("./src/app.js", "production", "browser")
+            .with_constants(constants).unwrap();
+        let json = serde_json::to_value(&meta).unwrap();
+        assert_eq!(json["entry"], "app");
+        assert_eq!(json["mode"], "production");
+        assert_eq!(json["apiUrl"], "https://example.com");
+        assert_eq!(json["buildId"].as_str().unwrap().len(), 16);
+    }
+
+    #[test]
+    fn uses_one_file_per_output() {
+        let meta = BuildMeta::new("./src/app.js", "production", "browser");
+        assert_eq!(BuildMeta::new("./src/app.js", "production", "browser").file_stem(), meta.file_stem());
+        assert_ne!(BuildMeta::new("./src/app.js", "development", "browser").file_stem(), meta.file_stem());
+        assert_ne!(BuildMeta::new("./src/admin.js", "production", "browser").file_stem(), meta.file_stem());
+    }
+
+    #[test]
+    fn rejects_reserved_constant_names() {
+        let mut constants = BTreeMap::new();
+        constants.insert("mode".to_string(), Value::from("test"));
+        assert_eq!(BuildMeta::new("./app.js", "production", "browser").with_constants(constants).unwrap_err(), "mode");
+    }
+}
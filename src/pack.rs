@@ -1,22 +1,68 @@
 use std::collections::BTreeMap;
 use std::rc::Rc;
-use serde_json;
+use serde_json::{self, Value};
 use graph::{ModuleMap, ModuleRecord};
+use inject::{Injection, injection_prelude, push_with_prelude};
+use polyfill::{Polyfill, polyfill_prelude};
 
 /// Pack a `ModuleMap` into a browserify-style javascript bundle.
 pub struct Pack<'a> {
     modules: &'a ModuleMap,
+    registry: bool,
+    injections: Vec<Injection>,
+    polyfills: Vec<Polyfill>,
 }
 
 impl<'a> Pack<'a> {
     pub fn new(modules: &ModuleMap) -> Pack {
-        Pack { modules }
+        Pack { modules, registry: false, injections: vec![], polyfills: vec![] }
+    }
+
+    /// Expose a `__bundle` global with `register(id, factory)` and `invalidate(id)` methods,
+    /// so the host page can add or replace modules after the bundle has loaded.
+    pub fn with_registry(mut self, registry: bool) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Declare these identifiers in the wrappers of the modules that use them.
+    /// They must be the same as the ones given to `Deps`, so the injected modules are bundled.
+    pub fn with_injections(mut self, injections: Vec<Injection>) -> Self {
+        self.injections = injections;
+        self
+    }
+
+    /// Require these polyfills at the start of the modules that use their globals.
+    /// They must be the same as the ones given to `Deps`, so the polyfill modules are bundled.
+    pub fn with_polyfills(mut self, polyfills: Vec<Polyfill>) -> Self {
+        self.polyfills = polyfills;
+        self
     }
 
     pub fn to_string(&self) -> String {
-        let mut string = String::from("_require = ");
+        if !self.registry {
+            let mut string = String::from("_require = ");
+            string.push_str(include_str!("./runtime.js"));
+            string.push_str("(");
+            self.push_modules(&mut string);
+            string.push_str(");");
+            return string;
+        }
+
+        // The registry has to exist before the entry points run, so they can use it.
+        let mut string = String::from("(function (modules, cache, entry) {\n__bundle = ");
+        string.push_str(include_str!("./registry.js"));
+        string.push_str("(modules, cache);\n_require = __bundle.require = ");
         string.push_str(include_str!("./runtime.js"));
-        string.push_str("({\n");
+        string.push_str("(modules, cache, entry);\n})(");
+        self.push_modules(&mut string);
+        string.push_str(");");
+        string
+    }
+
+    /// Append the module map, cache and entry point arguments for the runtime to `string`.
+    fn push_modules(&self, string: &mut String) {
+        string.push_str("{\n");
 
         let mut first = true;
         let mut entries = vec![];
@@ -24,17 +70,27 @@ impl<'a> Pack<'a> {
         modules.sort_unstable_by(|a, b| a.hash_cmp(b));
         for record in modules {
             if !first { string.push_str(",\n"); }
+            string.push_str(&format!("{id}:[function(require,module,exports){{", id = serde_json::to_string(&record.id).unwrap()));
+            // Polyfills run first, so injected modules can use the globals too.
+            let prelude = [polyfill_prelude(&record.file, &self.polyfills), injection_prelude(&record.file, &self.injections)]
+                .iter()
+                .filter(|prelude| !prelude.is_empty())
+                .cloned()
+                .collect::<Vec<String>>()
+                .join(" ");
+            push_with_prelude(string, &record.file, &prelude);
             string.push_str(&format!(
-                "{id}:[function(require,exports,module){{\n{source}\n}},{deps}]",
-                id = serde_json::to_string(&record.id).unwrap(),
-                source = record.file.source(),
+                "\n}},{deps}]",
+                // Optional dependencies that could not be found are `false`, so requiring them
+                // throws a MODULE_NOT_FOUND error that the module can catch.
                 deps = serde_json::to_string(
                     &record.dependencies.iter()
-                        .map(|(key, val)| (key, match val.record {
-                             Some(ref rec) => Some(rec.id),
-                             None => None,
+                        .map(|(key, val)| (key, match val.id {
+                             Some(id) => Value::from(id),
+                             None if val.is_missing() => Value::Bool(false),
+                             None => Value::Null,
                          }))
-                        .collect::<BTreeMap<&String, Option<u32>>>()
+                        .collect::<BTreeMap<&String, Value>>()
                 ).unwrap(),
             ));
             first = false;
@@ -46,7 +102,5 @@ impl<'a> Pack<'a> {
 
         string.push_str("},{},");
         string.push_str(&serde_json::to_string(&entries).unwrap());
-        string.push_str(");");
-        string
     }
 }
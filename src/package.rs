@@ -0,0 +1,35 @@
+use std::path::Path;
+use quicli::prelude::*;
+use serde_json::{self, Value};
+
+/// Read the package.json file in a package directory.
+pub fn read_package_json(package_dir: &Path) -> Result<Value> {
+    let source = read_file(package_dir.join("package.json"))?;
+    Ok(serde_json::from_str(&source)?)
+}
+
+/// Get the package names listed in a dependency field of a package.json, eg. `peerDependencies`.
+pub fn dependency_names(package: &Value, field: &str) -> Vec<String> {
+    package.get(field)
+        .and_then(|deps| deps.as_object())
+        .map(|deps| deps.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Get the package name part of a module ID.
+/// `@scope/pkg/lib/file.js` becomes `@scope/pkg`, `pkg/file.js` becomes `pkg`.
+pub fn package_name(module_id: &str) -> &str {
+    let end = if module_id.starts_with('@') {
+        module_id.match_indices('/').nth(1).map(|(i, _)| i)
+    } else {
+        module_id.find('/')
+    };
+    &module_id[..end.unwrap_or(module_id.len())]
+}
+
+/// Get the name of the package that contains a file, if it is inside a node_modules folder.
+/// The path must use forward slashes.
+pub fn package_from_path(path: &str) -> Option<&str> {
+    path.rfind("/node_modules/")
+        .map(|index| package_name(&path[index + "/node_modules/".len()..]))
+}
@@ -0,0 +1,156 @@
+use std::cmp::Ordering;
+use std::env;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::{Component, Path};
+use quicli::prelude::Result;
+
+/// How to treat paths that only differ in case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseSensitivity {
+    /// `a.js` and `A.js` are different files, like on most Linux file systems.
+    Sensitive,
+    /// `a.js` and `A.js` are the same file, like on Windows and macOS by default.
+    Insensitive,
+}
+
+impl CaseSensitivity {
+    /// The usual case sensitivity of file systems on the current platform.
+    pub fn platform_default() -> Self {
+        if cfg!(any(windows, target_os = "macos")) {
+            CaseSensitivity::Insensitive
+        } else {
+            CaseSensitivity::Sensitive
+        }
+    }
+}
+
+/// A file path in a platform independent form.
+///
+/// Normalized paths always use forward slashes and never have a Windows verbatim (`\\?\`) prefix,
+/// so the same project produces the same module paths on every platform.
+/// With `CaseSensitivity::Insensitive`, paths that only differ in case compare equal,
+/// but the original spelling is kept for display.
+#[derive(Debug, Clone)]
+pub struct NormalizedPath {
+    path: String,
+    key: String,
+}
+
+impl NormalizedPath {
+    pub fn new(path: &Path, case: CaseSensitivity) -> Self {
+        let path = normalize_separators(&path.to_string_lossy());
+        let key = match case {
+            CaseSensitivity::Sensitive => path.clone(),
+            CaseSensitivity::Insensitive => path.to_lowercase(),
+        };
+        NormalizedPath { path, key }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.path
+    }
+}
+
+impl<'a> From<&'a Path> for NormalizedPath {
+    fn from(path: &'a Path) -> Self {
+        NormalizedPath::new(path, CaseSensitivity::platform_default())
+    }
+}
+
+impl fmt::Display for NormalizedPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.path)
+    }
+}
+
+impl PartialEq for NormalizedPath {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for NormalizedPath {}
+
+impl Hash for NormalizedPath {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+    }
+}
+
+impl PartialOrd for NormalizedPath {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for NormalizedPath {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+/// Strip Windows verbatim prefixes and use forward slashes.
+fn normalize_separators(path: &str) -> String {
+    let path = if path.starts_with(r"\\?\UNC\") {
+        format!(r"\\{}", &path[8..])
+    } else if path.starts_with(r"\\?\") {
+        path[4..].to_string()
+    } else {
+        path.to_string()
+    };
+    path.replace('\\', "/")
+}
+
+/// Whether `path` is inside `root`, after following symlinks. A path that does not exist yet
+/// is checked by its closest existing parent, and must not leave it with `..`.
+pub fn is_inside(path: &Path, root: &Path) -> Result<bool> {
+    let root = root.canonicalize()?;
+    let absolute = env::current_dir()?.join(path);
+    let mut existing = absolute.as_path();
+    while !existing.exists() {
+        existing = match existing.parent() {
+            Some(parent) => parent,
+            None => return Ok(false),
+        };
+    }
+    let rest = absolute.strip_prefix(existing)?;
+    if rest.components().any(|component| match component { Component::Normal(_) => false, _ => true }) {
+        return Ok(false);
+    }
+    Ok(existing.canonicalize()?.starts_with(&root))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use super::{NormalizedPath, CaseSensitivity};
+
+    fn normalize(path: &str, case: CaseSensitivity) -> NormalizedPath {
+        NormalizedPath::new(Path::new(path), case)
+    }
+
+    #[test]
+    fn uses_forward_slashes() {
+        assert_eq!(normalize(r"C:\project\src\index.js", CaseSensitivity::Sensitive).as_str(), "C:/project/src/index.js");
+        assert_eq!(normalize("/project/src/index.js", CaseSensitivity::Sensitive).as_str(), "/project/src/index.js");
+    }
+
+    #[test]
+    fn strips_verbatim_prefixes() {
+        assert_eq!(normalize(r"\\?\C:\project\index.js", CaseSensitivity::Sensitive).as_str(), "C:/project/index.js");
+        assert_eq!(normalize(r"\\?\UNC\server\share\index.js", CaseSensitivity::Sensitive).as_str(), "//server/share/index.js");
+    }
+
+    #[test]
+    fn compares_case_insensitively() {
+        let lower = normalize("/project/index.js", CaseSensitivity::Insensitive);
+        let upper = normalize("/Project/Index.js", CaseSensitivity::Insensitive);
+        assert_eq!(lower, upper);
+        assert_eq!(upper.as_str(), "/Project/Index.js");
+
+        let lower = normalize("/project/index.js", CaseSensitivity::Sensitive);
+        let upper = normalize("/Project/Index.js", CaseSensitivity::Sensitive);
+        assert_ne!(lower, upper);
+    }
+}
@@ -0,0 +1,247 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use quicli::prelude::*;
+use emit::ManifestEntry;
+use graph::{ModuleMap, SourceFile};
+
+/// The named stages of a build, in the order that they run.
+///
+/// For each module, the bundler resolves its path, loads the file, runs transforms and detects
+/// its dependencies. Once the module graph is complete, it is linked, optimized and emitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Turn a require() argument into a file path.
+    Resolve,
+    /// Read and parse a file.
+    Load,
+    /// Run transforms on a file.
+    Transform,
+    /// Find the dependencies of a file.
+    Detect,
+    /// Connect the modules into a graph.
+    Link,
+    /// Change the packed bundle.
+    Optimize,
+    /// Write the bundle. Whoever writes the bundle runs the steps around this stage.
+    Emit,
+}
+
+/// A point in the pipeline where steps can run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Hook {
+    Before(Stage),
+    After(Stage),
+}
+
+/// An extra step that runs at a point in the pipeline.
+///
+/// What a step receives depends on where it is added:
+///
+///  - before `Resolve`: each require() argument, in `specifier`.
+///  - after `Resolve` and before `Load`: each resolved file path, in `path`. The files that a
+///    module requires are read ahead of time, so this runs for all of them before any is loaded.
+///  - after `Load`, and around `Transform` and `Detect`: each source file, in `file`. If there
+///    are steps before `Detect`, dependencies are detected again from the syntax tree they return.
+///  - around `Link`: the complete module graph, in `graph`.
+///  - around `Optimize`, and before `Emit`: the bundle source, in `bundle`.
+///  - after `Emit`: the URL of each file written to the output directory, including the bundle,
+///    in `url`, before the manifest is written.
+///
+/// The default implementations pass their input through unchanged.
+pub trait Step {
+    /// Rewrite a require() argument before it is resolved.
+    fn specifier(&self, specifier: String, _basedir: &Path) -> Result<String> {
+        Ok(specifier)
+    }
+
+    /// Change the path of a file before it is loaded.
+    fn path(&self, path: PathBuf) -> Result<PathBuf> {
+        Ok(path)
+    }
+
+    /// Change a source file.
+    fn file(&self, file: SourceFile) -> Result<SourceFile> {
+        Ok(file)
+    }
+
+    /// Inspect the module graph. Returning an error fails the build.
+    fn graph(&self, _modules: &ModuleMap) -> Result<()> {
+        Ok(())
+    }
+
+    /// Change the bundle source.
+    fn bundle(&self, bundle: String) -> Result<String> {
+        Ok(bundle)
+    }
+
+    /// Rewrite the URL of a written file, eg. to route it through a signing service.
+    /// The manifest lists the rewritten URL. `entry` has the file's path, hash and original URL.
+    fn url(&self, url: String, _entry: &ManifestEntry) -> Result<String> {
+        Ok(url)
+    }
+}
+
+/// The steps that plugins added to the build, like browserify's labeled pipeline.
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    steps: Vec<(Hook, Rc<Step>)>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    /// Run a step before a stage. Steps at the same point run in the order they were added.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate js_bundler;
+    /// # extern crate quicli;
+    /// use quicli::prelude::*;
+    /// use js_bundler::graph::SourceFile;
+    /// use js_bundler::pipeline::{Pipeline, Stage, Step};
+    ///
+    /// struct LogFiles;
+    ///
+    /// impl Step for LogFiles {
+    ///     fn file(&self, file: SourceFile) -> Result<SourceFile> {
+    ///         eprintln!("loaded {}", file.path().to_string_lossy());
+    ///         Ok(file)
+    ///     }
+    /// }
+    ///
+    /// # fn main() {
+    /// let pipeline = Pipeline::new()
+    ///     .before(Stage::Detect, LogFiles);
+    /// # }
+    /// ```
+    pub fn before<S: Step + 'static>(mut self, stage: Stage, step: S) -> Self {
+        self.steps.push((Hook::Before(stage), Rc::new(step)));
+        self
+    }
+
+    /// Run a step after a stage. Steps at the same point run in the order they were added.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate js_bundler;
+    /// # extern crate quicli;
+    /// use quicli::prelude::*;
+    /// use js_bundler::pipeline::{Pipeline, Stage, Step};
+    ///
+    /// struct AddLicenseHeader;
+    ///
+    /// impl Step for AddLicenseHeader {
+    ///     fn bundle(&self, bundle: String) -> Result<String> {
+    ///         Ok(format!("/*! MIT License */\n{}", bundle))
+    ///     }
+    /// }
+    ///
+    /// # fn main() {
+    /// let pipeline = Pipeline::new()
+    ///     .after(Stage::Optimize, AddLicenseHeader);
+    /// # }
+    /// ```
+    pub fn after<S: Step + 'static>(mut self, stage: Stage, step: S) -> Self {
+        self.steps.push((Hook::After(stage), Rc::new(step)));
+        self
+    }
+
+    /// Whether there are steps at a point in the pipeline.
+    pub fn has_steps(&self, hook: Hook) -> bool {
+        self.steps_at(hook).next().is_some()
+    }
+
+    /// Whether there are steps that receive source files, which a build cache would skip.
+    pub fn has_file_steps(&self) -> bool {
+        self.steps.iter().any(|&(hook, _)| match hook {
+            Hook::After(Stage::Load) => true,
+            Hook::Before(stage) | Hook::After(stage) => stage == Stage::Transform || stage == Stage::Detect,
+        })
+    }
+
+    fn steps_at<'a>(&'a self, hook: Hook) -> impl Iterator<Item = &'a Rc<Step>> + 'a {
+        self.steps.iter()
+            .filter(move |&&(step_hook, _)| step_hook == hook)
+            .map(|&(_, ref step)| step)
+    }
+
+    pub fn run_specifier(&self, hook: Hook, specifier: String, basedir: &Path) -> Result<String> {
+        self.steps_at(hook).fold(Ok(specifier), |res, step| res.and_then(|specifier| step.specifier(specifier, basedir)))
+    }
+
+    pub fn run_path(&self, hook: Hook, path: PathBuf) -> Result<PathBuf> {
+        self.steps_at(hook).fold(Ok(path), |res, step| res.and_then(|path| step.path(path)))
+    }
+
+    pub fn run_file(&self, hook: Hook, file: SourceFile) -> Result<SourceFile> {
+        self.steps_at(hook).fold(Ok(file), |res, step| res.and_then(|file| step.file(file)))
+    }
+
+    pub fn run_graph(&self, hook: Hook, modules: &ModuleMap) -> Result<()> {
+        self.steps_at(hook).map(|step| step.graph(modules)).collect()
+    }
+
+    pub fn run_bundle(&self, hook: Hook, bundle: String) -> Result<String> {
+        self.steps_at(hook).fold(Ok(bundle), |res, step| res.and_then(|bundle| step.bundle(bundle)))
+    }
+
+    pub fn run_url(&self, hook: Hook, url: String, entry: &ManifestEntry) -> Result<String> {
+        self.steps_at(hook).fold(Ok(url), |res, step| res.and_then(|url| step.url(url, entry)))
+    }
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.steps.iter().map(|&(hook, _)| hook)).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quicli::prelude::*;
+    use emit::{ArtifactKind, ManifestEntry};
+    use super::{Pipeline, Stage, Hook, Step};
+
+    struct Append(&'static str);
+    impl Step for Append {
+        fn bundle(&self, bundle: String) -> Result<String> {
+            Ok(bundle + self.0)
+        }
+    }
+
+    #[test]
+    fn runs_steps_at_their_hook_in_order() {
+        let pipeline = Pipeline::new()
+            .after(Stage::Optimize, Append("c"))
+            .before(Stage::Optimize, Append("a"))
+            .before(Stage::Optimize, Append("b"))
+            .before(Stage::Emit, Append("d"));
+        assert_eq!(pipeline.run_bundle(Hook::Before(Stage::Optimize), String::new()).unwrap(), "ab");
+        assert_eq!(pipeline.run_bundle(Hook::After(Stage::Optimize), String::new()).unwrap(), "c");
+        assert_eq!(pipeline.run_bundle(Hook::After(Stage::Emit), String::new()).unwrap(), "");
+    }
+
+    struct Tenant;
+    impl Step for Tenant {
+        fn url(&self, url: String, entry: &ManifestEntry) -> Result<String> {
+            Ok(format!("{}?tenant=42&hash={}", url, entry.hash))
+        }
+    }
+
+    #[test]
+    fn rewrites_urls_after_emit() {
+        let pipeline = Pipeline::new().after(Stage::Emit, Tenant);
+        let entry = ManifestEntry {
+            kind: ArtifactKind::Js,
+            path: "app.js".to_string(),
+            url: "/app.js".to_string(),
+            hash: "abc".to_string(),
+        };
+        assert_eq!(pipeline.run_url(Hook::After(Stage::Emit), entry.url.clone(), &entry).unwrap(), "/app.js?tenant=42&hash=abc");
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use estree_detect_requires::free_identifiers;
+use quicli::prelude::*;
+use serde_json;
+use error::BundleError;
+use graph::SourceFile;
+use ident::is_identifier;
+use package::{package_name, package_from_path};
+
+/// A module that defines a global for environments that do not have it, like
+/// `fetch = "whatwg-fetch"`. It is required at the start of every module that uses the global
+/// without declaring it, so bundles that never use the global do not include it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polyfill {
+    pub global: String,
+    pub specifier: String,
+}
+
+impl Polyfill {
+    pub fn new(global: &str, specifier: &str) -> Result<Polyfill> {
+        if !is_identifier(global) {
+            return Err(BundleError::config(format!("Invalid polyfill global {}: it must be a JavaScript identifier", global)).into());
+        }
+        if specifier.is_empty() {
+            return Err(BundleError::config(format!("Invalid polyfill for {}: expected a module like \"whatwg-fetch\"", global)).into());
+        }
+        Ok(Polyfill { global: global.to_string(), specifier: specifier.to_string() })
+    }
+
+    /// The statement that loads the polyfill, eg. `require("whatwg-fetch");`.
+    pub fn to_statement(&self) -> String {
+        format!("require({});", serde_json::to_string(&self.specifier).unwrap())
+    }
+}
+
+/// A polyfill that was added to a module, because the module uses its global.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolyfillUse {
+    pub global: String,
+    pub specifier: String,
+    pub path: PathBuf,
+}
+
+/// Find the polyfills that a file needs: the ones for globals that it refers to, but that are
+/// not declared in any scope around the reference. Files inside the polyfill's own package do
+/// not get it, so a polyfill that checks for the global does not require itself.
+pub fn used_polyfills<'a>(file: &SourceFile, polyfills: &'a [Polyfill]) -> Vec<&'a Polyfill> {
+    if polyfills.is_empty() {
+        return vec![];
+    }
+    let globals = match *file {
+        SourceFile::CJS { ast: Some(ref ast), .. } => free_identifiers(ast),
+        _ => return vec![],
+    };
+    let path = file.path().to_string_lossy().replace('\\', "/");
+    let package = package_from_path(&path);
+    polyfills.iter()
+        .filter(|polyfill| globals.contains(&polyfill.global))
+        .filter(|polyfill| package != Some(package_name(&polyfill.specifier)))
+        .collect()
+}
+
+/// The statements that load the polyfills a file needs, on a single line so that line numbers
+/// in the module stay the same.
+pub fn polyfill_prelude(file: &SourceFile, polyfills: &[Polyfill]) -> String {
+    used_polyfills(file, polyfills).iter()
+        .map(|polyfill| polyfill.to_statement())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Describe which polyfills were added and why, one line per polyfill, like
+/// `whatwg-fetch, for fetch in src/api.js and src/user.js`.
+pub fn polyfill_report(uses: &[PolyfillUse]) -> Vec<String> {
+    let mut by_specifier: BTreeMap<&str, (Vec<&str>, Vec<String>)> = BTreeMap::new();
+    for polyfill_use in uses {
+        let entry = by_specifier.entry(polyfill_use.specifier.as_str()).or_insert_with(|| (vec![], vec![]));
+        if !entry.0.contains(&polyfill_use.global.as_str()) {
+            entry.0.push(polyfill_use.global.as_str());
+        }
+        entry.1.push(polyfill_use.path.to_string_lossy().into_owned());
+    }
+    by_specifier.into_iter()
+        .map(|(specifier, (globals, paths))| format!("{}, for {} in {}", specifier, globals.join(", "), paths.join(" and ")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use loader::parse_cjs;
+    use inject::insert_prelude;
+    use super::{Polyfill, PolyfillUse, polyfill_prelude, polyfill_report};
+
+    #[test]
+    fn parses_polyfills() {
+        assert_eq!(Polyfill::new("fetch", "whatwg-fetch").unwrap().to_statement(), "require(\"whatwg-fetch\");");
+        assert!(Polyfill::new("not-a-global", "x").is_err());
+        assert!(Polyfill::new("URL", "").is_err());
+    }
+
+    #[test]
+    fn polyfills_used_globals_only() {
+        let polyfills = vec![
+            Polyfill::new("fetch", "whatwg-fetch").unwrap(),
+            Polyfill::new("URL", "url-polyfill").unwrap(),
+        ];
+        let file = |path: &str, source: &str| parse_cjs(PathBuf::from(path), source.to_string()).unwrap();
+        assert_eq!(polyfill_prelude(&file("/app/a.js", "fetch('/api')"), &polyfills), "require(\"whatwg-fetch\");");
+        assert_eq!(polyfill_prelude(&file("/app/a.js", "new URL(fetch.name)"), &polyfills),
+                   "require(\"whatwg-fetch\"); require(\"url-polyfill\");");
+        assert_eq!(polyfill_prelude(&file("/app/a.js", "function f(fetch) { return fetch() }"), &polyfills), "");
+        assert_eq!(polyfill_prelude(&file("/app/node_modules/whatwg-fetch/fetch.js", "if (!self.fetch) self.fetch = fetch"), &polyfills), "");
+    }
+
+    #[test]
+    fn keeps_strict_mode() {
+        let polyfills = vec![Polyfill::new("fetch", "whatwg-fetch").unwrap()];
+        let file = parse_cjs(PathBuf::from("/app/a.js"), "'use strict'\nfetch('/api')".to_string()).unwrap();
+        let body = insert_prelude(&file, &polyfill_prelude(&file, &polyfills));
+        assert!(body.starts_with("\n'use strict'"), "{:?}", body);
+        assert!(body.ends_with("require(\"whatwg-fetch\");\nfetch('/api')"), "{:?}", body);
+    }
+
+    #[test]
+    fn reports_polyfills() {
+        let polyfill_use = |global: &str, specifier: &str, path: &str| PolyfillUse {
+            global: global.to_string(),
+            specifier: specifier.to_string(),
+            path: PathBuf::from(path),
+        };
+        assert_eq!(polyfill_report(&[
+            polyfill_use("fetch", "whatwg-fetch", "/app/a.js"),
+            polyfill_use("URL", "url-polyfill", "/app/a.js"),
+            polyfill_use("fetch", "whatwg-fetch", "/app/b.js"),
+        ]), vec![
+            "url-polyfill, for URL in /app/a.js",
+            "whatwg-fetch, for fetch in /app/a.js and /app/b.js",
+        ]);
+    }
+}
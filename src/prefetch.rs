@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Sender, Receiver};
+use quicli::prelude::*;
+use rayon::ThreadPool;
+use loader::{LoadFile, ReadOutput, parser_pool};
+
+/// Reads and parses files on a thread pool while the build continues.
+///
+/// The build starts reading a file as soon as it knows that the file is required, and picks
+/// up the result when it gets to the file in its depth-first walk. Results arrive over a
+/// channel in whatever order the reads finish; the ones that are not needed yet wait in
+/// `ready`. The `LoadFile` of each file stays on the build thread, because its transforms and
+/// pipeline steps are not thread safe.
+///
+/// The pool is created when the first file is started, with the large stack that the parser
+/// needs. If it can not be created, files are read on the build thread instead.
+pub struct Prefetcher {
+    pool: Option<ThreadPool>,
+    sender: Sender<(PathBuf, Result<ReadOutput>)>,
+    receiver: Receiver<(PathBuf, Result<ReadOutput>)>,
+    /// Files that are being read, or were read, and have not been taken yet.
+    loaders: HashMap<PathBuf, LoadFile>,
+    ready: HashMap<PathBuf, Result<ReadOutput>>,
+}
+
+impl Prefetcher {
+    pub fn new() -> Prefetcher {
+        let (sender, receiver) = channel();
+        Prefetcher {
+            pool: None,
+            sender,
+            receiver,
+            loaders: HashMap::new(),
+            ready: HashMap::new(),
+        }
+    }
+
+    /// Whether a file is being read or waiting to be taken.
+    pub fn contains(&self, path: &Path) -> bool {
+        self.loaders.contains_key(path)
+    }
+
+    /// Start reading a file. `path` is the key to take it with later.
+    pub fn start(&mut self, path: PathBuf, loader: LoadFile) -> () {
+        let reader = loader.reader();
+        let sender = self.sender.clone();
+        let key = path.clone();
+        if self.pool.is_none() {
+            self.pool = parser_pool().ok();
+        }
+        // The receiver only goes away when the build is over.
+        match self.pool {
+            Some(ref pool) => pool.spawn(move || {
+                let _ = sender.send((key, reader.run()));
+            }),
+            None => { let _ = sender.send((key, reader.run())); },
+        }
+        self.loaders.insert(path, loader);
+    }
+
+    /// Take a file that was started, waiting for it to be read if necessary.
+    /// Returns `None` if the file was never started.
+    pub fn take(&mut self, path: &Path) -> Option<(LoadFile, Result<ReadOutput>)> {
+        let loader = self.loaders.remove(path)?;
+        loop {
+            if let Some(file) = self.ready.remove(path) {
+                return Some((loader, file));
+            }
+            // `self.sender` is never dropped while waiting, so this can not fail.
+            let (key, file) = self.receiver.recv().expect("prefetch channel closed");
+            self.ready.insert(key, file);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use graph::SourceFile;
+    use loader::{LoadFile, ReadOutput};
+    use super::Prefetcher;
+
+    #[test]
+    fn takes_files_in_any_order() {
+        let dir = env::temp_dir().join(format!("js-bundler-prefetch-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.js"), dir.join("b.js"));
+        fs::write(&a, "require('./b')").unwrap();
+        fs::write(&b, "module.exports = 1").unwrap();
+
+        let mut prefetcher = Prefetcher::new();
+        prefetcher.start(a.clone(), LoadFile::new(a.clone()));
+        prefetcher.start(b.clone(), LoadFile::new(b.clone()));
+        assert!(prefetcher.contains(&a));
+        let (loader, file) = prefetcher.take(&b).unwrap();
+        assert_eq!(loader.process(file.unwrap()).unwrap().path(), &b);
+        match prefetcher.take(&a).unwrap().1.unwrap() {
+            ReadOutput::Parsed { file: SourceFile::CJS { ref dependencies, .. }, .. } => assert_eq!(dependencies, &vec!["./b".to_string()]),
+            _ => panic!("expected a CommonJS file"),
+        }
+        assert!(!prefetcher.contains(&a));
+        assert!(prefetcher.take(Path::new("c.js")).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,21 @@
+(function () {
+  // Lets the host page add and replace modules after the bundle has loaded.
+  // Module factories are called like bundled modules: factory(require, module, exports).
+  return function registry(modules, cache) {
+    return {
+      register: function (id, factory, dependencies) {
+        modules[id] = [function (require, module, exports) {
+          factory.apply(this, arguments);
+        }, dependencies || {}];
+        delete cache[id];
+      },
+      // Modules that already required `id` keep their old copy.
+      invalidate: function (id) {
+        delete cache[id];
+      },
+      has: function (id) {
+        return Object.prototype.hasOwnProperty.call(modules, id);
+      }
+    };
+  };
+})()
@@ -0,0 +1,314 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use base64;
+use quicli::prelude::*;
+use reqwest::{self, Url};
+use sha1::{Sha1, Digest};
+use sha2::Sha256;
+use toml;
+use emit::write_atomic;
+
+/// The directory where downloaded and inlined modules are stored, if no other is given.
+pub const CACHE_DIR: &str = ".js-bundler-cache";
+
+/// The lockfile that records the integrity hashes of downloaded modules, if no other is given.
+pub const LOCKFILE: &str = "remote-modules.lock";
+
+/// The contents of a remote-modules.lock file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Lockfile {
+    /// Maps URLs to the integrity hash of their contents, eg. `sha256-...`.
+    modules: BTreeMap<String, String>,
+}
+
+/// Compute a subresource integrity string for a downloaded module.
+fn integrity(source: &[u8]) -> String {
+    format!("sha256-{}", base64::encode(&Sha256::digest(source)))
+}
+
+/// Remove the `node:` prefix from a builtin module ID, eg. `node:path` becomes `path`.
+pub fn strip_node_prefix(module_id: &str) -> Option<&str> {
+    if module_id.starts_with("node:") {
+        Some(&module_id["node:".len()..])
+    } else {
+        None
+    }
+}
+
+/// Check if a module ID is a URL that has to be stored in the cache before it can be bundled.
+pub fn is_remote(module_id: &str) -> bool {
+    module_id.starts_with("data:") || module_id.starts_with("https:") || module_id.starts_with("http:")
+}
+
+/// Stores `data:` and `https:` modules as files in a cache directory, so they can be bundled
+/// like any other file.
+///
+/// Downloaded modules are stored in a directory per URL, so relative require() calls in them
+/// resolve against their own URL, even if another URL has the same contents. The hashes of
+/// their contents are recorded in a lockfile, and a download whose contents do not match the
+/// lockfile fails the build.
+pub struct RemoteModules {
+    cache_dir: PathBuf,
+    allow_https: bool,
+    offline: bool,
+    lockfile_path: PathBuf,
+    lockfile: Option<Lockfile>,
+    lockfile_changed: bool,
+    /// Maps cache directories of downloaded modules to their URL, to resolve relative require()
+    /// calls inside them.
+    urls: HashMap<PathBuf, Url>,
+}
+
+impl RemoteModules {
+    pub fn new(cache_dir: PathBuf) -> Self {
+        RemoteModules {
+            cache_dir,
+            allow_https: false,
+            offline: false,
+            lockfile_path: PathBuf::from(LOCKFILE),
+            lockfile: None,
+            lockfile_changed: false,
+            urls: HashMap::new(),
+        }
+    }
+
+    /// Never download modules: only use the ones in the cache that match the lockfile.
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Use a different lockfile than ./remote-modules.lock.
+    pub fn with_lockfile(mut self, lockfile_path: PathBuf) -> Self {
+        self.lockfile_path = lockfile_path;
+        self
+    }
+
+    fn lockfile(&mut self) -> Result<&mut Lockfile> {
+        if self.lockfile.is_none() {
+            let lockfile = if self.lockfile_path.is_file() {
+                let source = read_file(&self.lockfile_path)?;
+                toml::from_str(&source)
+                    .map_err(|err| format_err!("Invalid lockfile {}: {}", self.lockfile_path.to_string_lossy(), err))?
+            } else {
+                Lockfile::default()
+            };
+            self.lockfile = Some(lockfile);
+        }
+        Ok(self.lockfile.as_mut().unwrap())
+    }
+
+    /// Write the lockfile if new modules were downloaded.
+    pub fn save_lockfile(&mut self) -> Result<()> {
+        if !self.lockfile_changed {
+            return Ok(());
+        }
+        if let Some(ref lockfile) = self.lockfile {
+            let mut source = String::from("# Integrity hashes of modules downloaded by js-bundler. Commit this file.\n");
+            source.push_str(&toml::to_string(lockfile)?);
+            write_atomic(&self.lockfile_path, source.as_bytes())?;
+        }
+        self.lockfile_changed = false;
+        Ok(())
+    }
+
+    /// Allow downloading modules from `https:` URLs. Off by default, because it makes builds
+    /// depend on the network.
+    pub fn allow_https(mut self, allow_https: bool) -> Self {
+        self.allow_https = allow_https;
+        self
+    }
+
+    /// Get the URL that a relative require() call in a downloaded module refers to.
+    /// Returns `None` if `basedir` is not the cache directory of a downloaded module.
+    pub fn join(&self, basedir: &Path, module_id: &str) -> Result<Option<String>> {
+        let is_relative = module_id.starts_with("./") || module_id.starts_with("../") || module_id.starts_with('/');
+        match self.urls.get(basedir) {
+            Some(url) if is_relative => Ok(Some(url.join(module_id)?.into_string())),
+            _ => Ok(None),
+        }
+    }
+
+    /// Store a `data:` or `https:` module in the cache, and return the path to the cached file.
+    pub fn fetch(&mut self, module_id: &str) -> Result<PathBuf> {
+        if module_id.starts_with("data:") {
+            let (media_type, source) = decode_data_url(module_id)?;
+            let extension = if media_type == "application/json" { "json" } else { "js" };
+            return self.store("data", &source, extension);
+        }
+
+        if !self.allow_https {
+            bail!("Cannot bundle {}: downloading modules is disabled. Use --allow-https to enable it.", module_id);
+        }
+        let url = Url::parse(module_id)?;
+        if url.scheme() != "https" {
+            bail!("Cannot bundle {}: modules can only be downloaded over https.", module_id);
+        }
+
+        let locked = self.lockfile()?.modules.get(url.as_str()).cloned();
+        let cached = match locked {
+            Some(ref expected) => {
+                let path = self.download_path(&url);
+                // Check the cached file too, in case it was edited.
+                match fs::read(&path) {
+                    Ok(ref source) if integrity(source) == *expected => Some(path),
+                    _ => None,
+                }
+            },
+            None => None,
+        };
+        let path = match cached {
+            Some(path) => path,
+            None if self.offline => bail!("Cannot bundle {}: it is not in the cache, and downloading is disabled in offline mode.", url),
+            None => {
+                let source = download(&url)?;
+                let actual = integrity(&source);
+                if let Some(expected) = locked {
+                    if actual != expected {
+                        bail!("The contents of {} changed since it was added to {}.\n  expected: {}\n  actual: {}\nIf the change is expected, remove the URL from the lockfile and build again.",
+                              url, self.lockfile_path.to_string_lossy(), expected, actual);
+                    }
+                } else {
+                    self.lockfile()?.modules.insert(url.to_string(), actual.clone());
+                    self.lockfile_changed = true;
+                }
+                let path = self.download_path(&url);
+                fs::create_dir_all(path.parent().unwrap())?;
+                write_atomic(&path, &source)?;
+                path
+            },
+        };
+        self.urls.insert(path.parent().unwrap().to_path_buf(), url);
+        Ok(path)
+    }
+
+    /// Store a generated module in the cache, and return the path to the cached file.
+    /// `extension` determines how the module is loaded, eg. `json` or `js`.
+    pub fn store(&self, kind: &str, source: &[u8], extension: &str) -> Result<PathBuf> {
+        let path = self.cache_dir.join(kind).join(format!("{:x}.{}", Sha1::digest(source), extension));
+        if !path.is_file() {
+            fs::create_dir_all(path.parent().unwrap())?;
+            write_atomic(&path, source)?;
+        }
+        Ok(path)
+    }
+
+    /// Store a generated module that changes on every build under a fixed `name`, replacing the
+    /// previous version, and return the path to the cached file.
+    pub fn replace(&self, kind: &str, name: &str, source: &[u8], extension: &str) -> Result<PathBuf> {
+        let path = self.cache_dir.join(kind).join(format!("{}.{}", name, extension));
+        fs::create_dir_all(path.parent().unwrap())?;
+        write_atomic(&path, source)?;
+        Ok(path)
+    }
+
+    /// Get the cache location of a module downloaded from `url`.
+    fn download_path(&self, url: &Url) -> PathBuf {
+        let dir_name = format!("{:x}", Sha1::digest(url.as_str().as_bytes()));
+        self.cache_dir.join("https").join(dir_name).join(file_name(url))
+    }
+}
+
+fn download(url: &Url) -> Result<Vec<u8>> {
+    let mut response = reqwest::get(url.clone())?;
+    if !response.status().is_success() {
+        bail!("Could not download {}: {}", url, response.status());
+    }
+    let mut source = vec![];
+    response.read_to_end(&mut source)?;
+    Ok(source)
+}
+
+/// Pick a file name for a downloaded module, so that the loader knows what kind of file it is.
+fn file_name(url: &Url) -> &'static str {
+    if url.path().ends_with(".json") { "module.json" } else { "module.js" }
+}
+
+/// Decode a `data:` URL into its media type and contents.
+fn decode_data_url(url: &str) -> Result<(String, Vec<u8>)> {
+    let comma = match url.find(',') {
+        Some(index) => index,
+        None => bail!("Invalid data URL {}: missing a comma", url),
+    };
+    let header = &url["data:".len()..comma];
+    let data = &url[comma + 1..];
+
+    let mut params = header.split(';');
+    let media_type = params.next()
+        .filter(|media_type| !media_type.is_empty())
+        .unwrap_or("text/plain")
+        .to_lowercase();
+    let is_base64 = params.any(|param| param == "base64");
+
+    let source = if is_base64 {
+        base64::decode(&percent_decode(data)?)
+            .map_err(|err| format_err!("Invalid data URL {}: {}", url, err))?
+    } else {
+        percent_decode(data)?
+    };
+    Ok((media_type, source))
+}
+
+fn percent_decode(input: &str) -> Result<Vec<u8>> {
+    let bytes = input.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let byte = input.get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            match byte {
+                Some(byte) => output.push(byte),
+                None => bail!("Invalid percent escape in data URL at position {}", i),
+            }
+            i += 3;
+        } else {
+            output.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use reqwest::Url;
+    use super::{RemoteModules, strip_node_prefix, decode_data_url, integrity};
+
+    #[test]
+    fn strips_node_prefix() {
+        assert_eq!(strip_node_prefix("node:path"), Some("path"));
+        assert_eq!(strip_node_prefix("path"), None);
+        assert_eq!(strip_node_prefix("./node:path"), None);
+    }
+
+    #[test]
+    fn decodes_data_urls() {
+        assert_eq!(decode_data_url("data:text/javascript,module.exports%20%3D%201").unwrap(),
+                   ("text/javascript".to_string(), b"module.exports = 1".to_vec()));
+        assert_eq!(decode_data_url("data:application/json;base64,eyJhIjoxfQ==").unwrap(),
+                   ("application/json".to_string(), b"{\"a\":1}".to_vec()));
+        assert_eq!(decode_data_url("data:,hi").unwrap(),
+                   ("text/plain".to_string(), b"hi".to_vec()));
+        assert!(decode_data_url("data:text/javascript").is_err());
+        assert!(decode_data_url("data:,%zz").is_err());
+    }
+
+    #[test]
+    fn computes_sri_hashes() {
+        assert_eq!(integrity(b"alert('Hello, world.');"), "sha256-qznLcsROx4GACP2dm0UCKCzCG+HiZ1guq6ZZDob/Tng=");
+    }
+
+    #[test]
+    fn stores_downloads_by_url() {
+        let remote = RemoteModules::new(PathBuf::from("cache"));
+        let a = remote.download_path(&Url::parse("https://example.com/a/index.js").unwrap());
+        let b = remote.download_path(&Url::parse("https://example.com/b/index.js").unwrap());
+        assert_ne!(a.parent(), b.parent());
+        assert_eq!(remote.download_path(&Url::parse("https://example.com/a/index.js").unwrap()), a);
+        assert!(remote.download_path(&Url::parse("https://example.com/data.json").unwrap()).ends_with("module.json"));
+    }
+}
@@ -0,0 +1,106 @@
+use std::path::{Path, PathBuf};
+use quicli::prelude::*;
+use serde_json::{self, Value};
+use error::BundleError;
+
+/// The name of the TypeScript config file that `paths` can be read from.
+pub const TSCONFIG_FILE: &str = "tsconfig.json";
+
+/// A specifier prefix that refers to a project directory, like `~/` for `src/`, so that
+/// `require('~/components/button')` loads the same file from anywhere in the project.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RootPrefix {
+    pub prefix: String,
+    pub dir: PathBuf,
+}
+
+impl RootPrefix {
+    pub fn new(prefix: &str, dir: PathBuf) -> Result<RootPrefix> {
+        if !prefix.ends_with('/') {
+            return Err(BundleError::config(format!("Invalid root prefix {}: it must end with a /, like \"~/\"", prefix)).into());
+        }
+        if prefix.starts_with("./") || prefix.starts_with("../") {
+            return Err(BundleError::config(format!("Invalid root prefix {}: relative paths can not be used as a prefix", prefix)).into());
+        }
+        Ok(RootPrefix { prefix: prefix.to_string(), dir })
+    }
+}
+
+/// Find the file that a specifier refers to if it starts with a root prefix. The longest
+/// matching prefix is used, so `@/lib/` can point somewhere else than `@/`.
+pub fn resolve_root(roots: &[RootPrefix], specifier: &str) -> Option<PathBuf> {
+    roots.iter()
+        .filter(|root| specifier.starts_with(root.prefix.as_str()))
+        .max_by_key(|root| root.prefix.len())
+        .map(|root| root.dir.join(&specifier[root.prefix.len()..]))
+}
+
+/// Read root prefixes from the `compilerOptions.paths` of a tsconfig.json file, like
+/// `"~/*": ["src/*"]`. Only the first target of each pattern is used.
+pub fn tsconfig_roots(path: &Path) -> Result<Vec<RootPrefix>> {
+    let source = read_file(path)
+        .map_err(|err| BundleError::config(format!("Could not read {}: {}", path.to_string_lossy(), err)))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    parse_tsconfig_roots(&source, dir)
+        .map_err(|err| BundleError::config(format!("Invalid {}: {}", path.to_string_lossy(), err)).into())
+}
+
+fn parse_tsconfig_roots(source: &str, dir: &Path) -> Result<Vec<RootPrefix>> {
+    let tsconfig: Value = serde_json::from_str(source)?;
+    let options = match tsconfig.get("compilerOptions") {
+        Some(options) => options,
+        None => return Ok(vec![]),
+    };
+    let base_url = dir.join(options.get("baseUrl").and_then(|value| value.as_str()).unwrap_or("."));
+    let paths = match options.get("paths").and_then(|paths| paths.as_object()) {
+        Some(paths) => paths,
+        None => return Ok(vec![]),
+    };
+    let mut roots = vec![];
+    for (pattern, targets) in paths {
+        let target = targets.as_array()
+            .and_then(|targets| targets.first())
+            .and_then(|target| target.as_str());
+        match (pattern.ends_with("/*"), target) {
+            (true, Some(target)) if target.ends_with("/*") => {
+                let prefix = &pattern[..pattern.len() - 1];
+                roots.push(RootPrefix::new(prefix, base_url.join(&target[..target.len() - 2]))?);
+            },
+            _ => bail!("the path {} is not supported, only patterns like \"~/*\": [\"src/*\"] are", pattern),
+        }
+    }
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use super::{RootPrefix, resolve_root, parse_tsconfig_roots};
+
+    #[test]
+    fn resolves_root_prefixes() {
+        let roots = vec![
+            RootPrefix::new("~/", PathBuf::from("/app/src")).unwrap(),
+            RootPrefix::new("@/", PathBuf::from("/app/src")).unwrap(),
+            RootPrefix::new("@/lib/", PathBuf::from("/app/vendor")).unwrap(),
+        ];
+        assert_eq!(resolve_root(&roots, "~/components/Button"), Some(PathBuf::from("/app/src/components/Button")));
+        assert_eq!(resolve_root(&roots, "@/utils/x"), Some(PathBuf::from("/app/src/utils/x")));
+        assert_eq!(resolve_root(&roots, "@/lib/y"), Some(PathBuf::from("/app/vendor/y")));
+        assert_eq!(resolve_root(&roots, "@scope/pkg"), None);
+        assert_eq!(resolve_root(&roots, "./a"), None);
+        assert!(RootPrefix::new("~", PathBuf::from("/app/src")).is_err());
+        assert!(RootPrefix::new("./", PathBuf::from("/app/src")).is_err());
+    }
+
+    #[test]
+    fn reads_tsconfig_paths() {
+        let roots = parse_tsconfig_roots(r#"{
+            "compilerOptions": { "baseUrl": "src", "paths": { "~/*": ["./*"], "@lib/*": ["../lib/*", "other/*"] } }
+        }"#, Path::new("/app")).unwrap();
+        assert_eq!(resolve_root(&roots, "~/a"), Some(PathBuf::from("/app/src/./a")));
+        assert_eq!(resolve_root(&roots, "@lib/b"), Some(PathBuf::from("/app/src/../lib/b")));
+        assert!(parse_tsconfig_roots(r#"{ "compilerOptions": { "paths": { "jquery": ["vendor/jquery.js"] } } }"#, Path::new("/app")).is_err());
+        assert_eq!(parse_tsconfig_roots("{}", Path::new("/app")).unwrap(), vec![]);
+    }
+}
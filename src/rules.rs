@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use glob::Pattern;
+use quicli::prelude::*;
+use toml;
+use error::BundleError;
+use loader::{ModuleType, Transform};
+
+/// A `[[rules]]` entry in the config file: how to load and transform the files it matches.
+///
+/// A rule matches a file if the file path matches `test`, matches one of the `include` patterns
+/// and none of the `exclude` patterns. Patterns that are not given always pass. Every matching
+/// rule applies, in order: transforms are added up and the last `type` wins. Of a rule's
+/// `one-of` rules, only the first one that matches applies.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Rule {
+    /// A glob pattern for the files this rule applies to, eg. `**/*.json`.
+    pub test: Option<String>,
+    /// Only apply to files matching one of these glob patterns.
+    pub include: Vec<String>,
+    /// Do not apply to files matching any of these glob patterns.
+    pub exclude: Vec<String>,
+    /// Load the files as this type, instead of choosing by file extension.
+    #[serde(rename = "type")]
+    pub module_type: Option<ModuleType>,
+    /// Transforms to run on the files, after the ones that run on every file.
+    pub transforms: Vec<String>,
+    /// Options for the transforms, eg. `keep-console = ["error"]`.
+    pub options: BTreeMap<String, toml::Value>,
+    /// Rules of which only the first matching one applies.
+    pub one_of: Vec<Rule>,
+}
+
+/// What the rules say about a file.
+#[derive(Default)]
+pub struct RuleMatch {
+    pub module_type: Option<ModuleType>,
+    pub transforms: Vec<Rc<Transform>>,
+}
+
+/// A rule with compiled patterns and transforms.
+struct CompiledRule {
+    test: Option<Pattern>,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    module_type: Option<ModuleType>,
+    transforms: Vec<Rc<Transform>>,
+    one_of: Vec<CompiledRule>,
+}
+
+impl CompiledRule {
+    fn matches(&self, path: &str) -> bool {
+        self.test.as_ref().map_or(true, |test| test.matches(path))
+            && (self.include.is_empty() || self.include.iter().any(|pattern| pattern.matches(path)))
+            && !self.exclude.iter().any(|pattern| pattern.matches(path))
+    }
+
+    fn apply(&self, path: &str, result: &mut RuleMatch) {
+        if self.module_type.is_some() {
+            result.module_type = self.module_type;
+        }
+        result.transforms.extend(self.transforms.iter().cloned());
+        if let Some(rule) = self.one_of.iter().find(|rule| rule.matches(path)) {
+            rule.apply(path, result);
+        }
+    }
+}
+
+/// The rules for loading and transforming files, in order.
+#[derive(Default)]
+pub struct Rules {
+    rules: Vec<CompiledRule>,
+}
+
+impl Rules {
+    /// Compile config rules. `make_transform` creates a transform from its name and the rule's options.
+    pub fn new<F>(rules: &[Rule], make_transform: F) -> Result<Rules>
+        where F: Fn(&str, &BTreeMap<String, toml::Value>) -> Result<Rc<Transform>>
+    {
+        Ok(Rules {
+            rules: compile_all(rules, &make_transform)?,
+        })
+    }
+
+    /// Find the type and transforms for a file.
+    pub fn matching(&self, path: &str) -> RuleMatch {
+        let mut result = RuleMatch::default();
+        for rule in self.rules.iter().filter(|rule| rule.matches(path)) {
+            rule.apply(path, &mut result);
+        }
+        result
+    }
+}
+
+fn compile_all<F>(rules: &[Rule], make_transform: &F) -> Result<Vec<CompiledRule>>
+    where F: Fn(&str, &BTreeMap<String, toml::Value>) -> Result<Rc<Transform>>
+{
+    rules.iter().map(|rule| compile(rule, make_transform)).collect()
+}
+
+fn compile<F>(rule: &Rule, make_transform: &F) -> Result<CompiledRule>
+    where F: Fn(&str, &BTreeMap<String, toml::Value>) -> Result<Rc<Transform>>
+{
+    Ok(CompiledRule {
+        test: match rule.test {
+            Some(ref test) => Some(pattern(test)?),
+            None => None,
+        },
+        include: rule.include.iter().map(|include| pattern(include)).collect::<Result<Vec<Pattern>>>()?,
+        exclude: rule.exclude.iter().map(|exclude| pattern(exclude)).collect::<Result<Vec<Pattern>>>()?,
+        module_type: rule.module_type,
+        transforms: rule.transforms.iter()
+            .map(|name| make_transform(name, &rule.options))
+            .collect::<Result<Vec<Rc<Transform>>>>()?,
+        one_of: compile_all(&rule.one_of, make_transform)?,
+    })
+}
+
+fn pattern(pattern: &str) -> Result<Pattern> {
+    Pattern::new(pattern)
+        .map_err(|err| BundleError::config(format!("Invalid rule pattern {}: {}", pattern, err)).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::rc::Rc;
+    use quicli::prelude::*;
+    use toml;
+    use graph::SourceFile;
+    use loader::{ModuleType, Transform};
+    use super::{Rule, Rules};
+
+    struct Noop;
+    impl Transform for Noop {
+        fn transform(&self, file: SourceFile) -> Result<SourceFile> {
+            Ok(file)
+        }
+    }
+
+    fn make_transform(name: &str, _options: &BTreeMap<String, toml::Value>) -> Result<Rc<Transform>> {
+        match name {
+            "noop" => Ok(Rc::new(Noop)),
+            _ => bail!("Unknown transform {}", name),
+        }
+    }
+
+    #[test]
+    fn applies_matching_rules_in_order() {
+        let rules = vec![
+            Rule { test: Some("**/*.data".into()), module_type: Some(ModuleType::Json), ..Rule::default() },
+            Rule {
+                test: Some("**/*.js".into()),
+                exclude: vec!["**/node_modules/**".into()],
+                transforms: vec!["noop".into()],
+                one_of: vec![
+                    Rule { include: vec!["/app/src/**".into()], transforms: vec!["noop".into()], ..Rule::default() },
+                    Rule { transforms: vec!["noop".into(), "noop".into()], ..Rule::default() },
+                ],
+                ..Rule::default()
+            },
+        ];
+        let rules = Rules::new(&rules, make_transform).unwrap();
+
+        assert_eq!(rules.matching("/app/config.data").module_type, Some(ModuleType::Json));
+        assert_eq!(rules.matching("/app/src/index.js").transforms.len(), 2);
+        assert_eq!(rules.matching("/app/lib/index.js").transforms.len(), 3);
+        assert_eq!(rules.matching("/app/node_modules/a/index.js").transforms.len(), 0);
+        assert_eq!(rules.matching("/app/node_modules/a/index.js").module_type, None);
+    }
+
+    #[test]
+    fn rejects_unknown_transforms() {
+        let rules = vec![Rule { transforms: vec!["minify".into()], ..Rule::default() }];
+        assert!(Rules::new(&rules, make_transform).is_err());
+    }
+}
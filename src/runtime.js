@@ -2,6 +2,12 @@
   function outer(modules, cache, entry) {
     var previousRequire = typeof require == 'function' && require;
 
+    function notFound(name) {
+      var err = new Error('Cannot find module \'' + name + '\'');
+      err.code = 'MODULE_NOT_FOUND';
+      return err;
+    }
+
     function newRequire(name, jumped){
       if(!cache[name]) {
         if(!modules[name]) {
@@ -9,13 +15,13 @@
           if (!jumped && currentRequire) return currentRequire(name, true);
 
           if (previousRequire) return previousRequire(name, true);
-          var err = new Error('Cannot find module \'' + name + '\'');
-          err.code = 'MODULE_NOT_FOUND';
-          throw err;
+          throw notFound(name);
         }
         var m = cache[name] = {exports:{}};
         modules[name][0].call(m.exports, function(x){
           var id = modules[name][1][x];
+          // An optional dependency that was not found when bundling.
+          if (id === false) throw notFound(x);
           return newRequire(id ? id : x);
         },m,m.exports,outer,modules,cache,entry);
       }
@@ -0,0 +1,199 @@
+use std::path::Path;
+use quicli::prelude::*;
+use serde_json;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSourceMap {
+    version: u32,
+    file: Option<String>,
+    source_root: Option<String>,
+    sources: Vec<Option<String>>,
+    #[serde(default)]
+    names: Vec<String>,
+    mappings: String,
+}
+
+/// A mapping from a position in the generated file to a position in a source file.
+#[derive(Debug, Clone, Copy)]
+struct Mapping {
+    generated_column: u32,
+    source: u32,
+    line: u32,
+    column: u32,
+    name: Option<u32>,
+}
+
+/// A position in an original source file. Lines and columns start at 0.
+#[derive(Debug, PartialEq)]
+pub struct OriginalPosition<'a> {
+    pub source: &'a str,
+    pub line: u32,
+    pub column: u32,
+    pub name: Option<&'a str>,
+}
+
+/// A parsed version 3 source map, for looking up original positions.
+#[derive(Debug)]
+pub struct SourceMap {
+    file: Option<String>,
+    sources: Vec<String>,
+    names: Vec<String>,
+    /// Mappings for each generated line, sorted by generated column.
+    lines: Vec<Vec<Mapping>>,
+}
+
+impl SourceMap {
+    pub fn from_json(source: &str) -> Result<SourceMap> {
+        let raw: RawSourceMap = serde_json::from_str(source)?;
+        if raw.version != 3 {
+            bail!("Unsupported source map version {}", raw.version);
+        }
+        let source_root = raw.source_root.unwrap_or_default();
+        let sources = raw.sources.into_iter()
+            .map(|source| {
+                let source = source.unwrap_or_default();
+                if source_root.is_empty() || source_root.ends_with('/') {
+                    format!("{}{}", source_root, source)
+                } else {
+                    format!("{}/{}", source_root, source)
+                }
+            })
+            .collect();
+        Ok(SourceMap {
+            file: raw.file,
+            sources,
+            names: raw.names,
+            lines: parse_mappings(&raw.mappings)?,
+        })
+    }
+
+    pub fn from_file(path: &Path) -> Result<SourceMap> {
+        SourceMap::from_json(&read_file(path)?)
+            .map_err(|err| format_err!("Invalid source map {}: {}", path.to_string_lossy(), err))
+    }
+
+    /// The name of the generated file that this map belongs to, if the map says so.
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_ref().map(|file| file.as_str())
+    }
+
+    /// Find the original position of a position in the generated file.
+    /// Lines and columns start at 0.
+    pub fn lookup(&self, line: u32, column: u32) -> Option<OriginalPosition> {
+        let mappings = self.lines.get(line as usize)?;
+        let index = match mappings.binary_search_by_key(&column, |mapping| mapping.generated_column) {
+            Ok(index) => index,
+            // Use the closest mapping to the left.
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        let mapping = mappings[index];
+        Some(OriginalPosition {
+            source: self.sources.get(mapping.source as usize)?,
+            line: mapping.line,
+            column: mapping.column,
+            name: mapping.name.and_then(|name| self.names.get(name as usize)).map(|name| name.as_str()),
+        })
+    }
+}
+
+fn parse_mappings(mappings: &str) -> Result<Vec<Vec<Mapping>>> {
+    let mut lines = vec![];
+    // Everything but the generated column is relative to the previous segment in the whole map.
+    let (mut source, mut line, mut column, mut name) = (0i64, 0i64, 0i64, 0i64);
+    for generated_line in mappings.split(';') {
+        let mut segments = vec![];
+        let mut generated_column = 0i64;
+        for segment in generated_line.split(',').filter(|segment| !segment.is_empty()) {
+            let fields = decode_vlq(segment)?;
+            generated_column += fields[0];
+            // Segments with only a generated column do not map to anything.
+            if fields.len() < 4 {
+                continue;
+            }
+            source += fields[1];
+            line += fields[2];
+            column += fields[3];
+            let segment_name = if fields.len() > 4 {
+                name += fields[4];
+                Some(name as u32)
+            } else {
+                None
+            };
+            segments.push(Mapping {
+                generated_column: generated_column as u32,
+                source: source as u32,
+                line: line as u32,
+                column: column as u32,
+                name: segment_name,
+            });
+        }
+        segments.sort_by_key(|mapping| mapping.generated_column);
+        lines.push(segments);
+    }
+    Ok(lines)
+}
+
+/// Decode a base64 VLQ source map segment into its fields.
+fn decode_vlq(segment: &str) -> Result<Vec<i64>> {
+    let mut fields = vec![];
+    let mut value = 0i64;
+    let mut shift = 0;
+    for c in segment.chars() {
+        let digit = match c {
+            'A'...'Z' => c as i64 - 'A' as i64,
+            'a'...'z' => c as i64 - 'a' as i64 + 26,
+            '0'...'9' => c as i64 - '0' as i64 + 52,
+            '+' => 62,
+            '/' => 63,
+            _ => bail!("Invalid character {:?} in source map mappings", c),
+        };
+        value += (digit & 0b11111) << shift;
+        if digit & 0b100000 != 0 {
+            shift += 5;
+            continue;
+        }
+        // The lowest bit is the sign.
+        fields.push(if value & 1 == 1 { -(value >> 1) } else { value >> 1 });
+        value = 0;
+        shift = 0;
+    }
+    if shift != 0 {
+        bail!("Unterminated segment {:?} in source map mappings", segment);
+    }
+    if fields.is_empty() {
+        bail!("Empty segment in source map mappings");
+    }
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SourceMap, OriginalPosition, decode_vlq};
+
+    #[test]
+    fn decodes_vlq() {
+        assert_eq!(decode_vlq("AAAA").unwrap(), vec![0, 0, 0, 0]);
+        assert_eq!(decode_vlq("IAAID").unwrap(), vec![4, 0, 0, 4, -1]);
+        assert_eq!(decode_vlq("gB").unwrap(), vec![16]);
+        assert!(decode_vlq("g").is_err());
+    }
+
+    #[test]
+    fn looks_up_original_positions() {
+        let map = SourceMap::from_json(r#"{
+            "version": 3,
+            "file": "bundle.js",
+            "sourceRoot": "src",
+            "sources": ["a.js"],
+            "names": ["foo"],
+            "mappings": "AAAAA,IAAI;AACA"
+        }"#).unwrap();
+        assert_eq!(map.file(), Some("bundle.js"));
+        assert_eq!(map.lookup(0, 2), Some(OriginalPosition { source: "src/a.js", line: 0, column: 0, name: Some("foo") }));
+        assert_eq!(map.lookup(0, 5), Some(OriginalPosition { source: "src/a.js", line: 0, column: 4, name: None }));
+        assert_eq!(map.lookup(1, 0), Some(OriginalPosition { source: "src/a.js", line: 1, column: 4, name: None }));
+        assert_eq!(map.lookup(2, 0), None);
+    }
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use quicli::prelude::*;
+use serde_json::{self, Value};
+use emit::{write_if_changed, to_url_path};
+use graph::ModuleMap;
+use package::{package_name, read_package_json};
+
+/// The name of the index file in the sources directory.
+pub const SOURCES_INDEX_FILE: &str = "index.json";
+
+/// The package name for files that are not inside a node_modules folder and not inside a
+/// directory with a package.json that has a name.
+pub const APP_PACKAGE: &str = "app";
+
+/// A bundled module, copied to the sources directory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceEntry {
+    /// The package that contains the module.
+    pub package: String,
+    /// The version of the package, from its package.json.
+    pub version: Option<String>,
+    /// Where the module was loaded from.
+    pub original: PathBuf,
+    /// The path of the copy relative to the sources directory, with forward slashes.
+    pub path: String,
+}
+
+/// Lists the modules in the sources directory.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SourcesIndex {
+    pub files: Vec<SourceEntry>,
+}
+
+/// Copy the source of every bundled module, after transforms, to `dir`, so that the exact code
+/// that was shipped can be reviewed. Files are grouped by package and version, like
+/// `react@16.4.1/index.js`, and listed in an `index.json`. Files outside of node_modules are
+/// put in a directory named after the package.json in the current directory, or `app`.
+pub fn emit_sources(modules: &ModuleMap, dir: &Path) -> Result<SourcesIndex> {
+    let basedir = Path::new(".").canonicalize()?;
+    let app = PackageInfo::read(&basedir, APP_PACKAGE);
+    let mut index = SourcesIndex::default();
+    for record in modules.values() {
+        let original = record.file.path();
+        let (package, relative) = match package_root(original) {
+            Some((root, name)) => (PackageInfo::read(&root, &name), original.strip_prefix(&root)?.to_path_buf()),
+            None => (app.clone(), relative_to(original, &basedir)),
+        };
+        let path = Path::new(&package.dir_name()).join(&relative);
+        let target = dir.join(&path);
+        fs::create_dir_all(target.parent().unwrap())?;
+        write_if_changed(&target, record.file.source().as_bytes())?;
+        index.files.push(SourceEntry {
+            package: package.name,
+            version: package.version,
+            original: original.clone(),
+            path: to_url_path(&path),
+        });
+    }
+    index.files.sort_by(|a, b| a.path.cmp(&b.path));
+    write_if_changed(&dir.join(SOURCES_INDEX_FILE), serde_json::to_string_pretty(&index)?.as_bytes())?;
+    Ok(index)
+}
+
+/// The name, version and license of a package, from its package.json.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageInfo {
+    pub name: String,
+    pub version: Option<String>,
+    pub license: Option<String>,
+}
+
+impl PackageInfo {
+    /// Read the name, version and license of the package in `dir`. Uses `default_name` if
+    /// there is no package.json or it has no name.
+    pub fn read(dir: &Path, default_name: &str) -> PackageInfo {
+        let package = read_package_json(dir).ok();
+        let field = |name: &str| package.as_ref()
+            .and_then(|package| package.get(name))
+            .and_then(|value| value.as_str())
+            .map(|value| value.to_string());
+        PackageInfo {
+            name: field("name").unwrap_or_else(|| default_name.to_string()),
+            version: field("version"),
+            license: package.as_ref().and_then(license_field),
+        }
+    }
+
+    fn dir_name(&self) -> String {
+        match self.version {
+            Some(ref version) => format!("{}@{}", self.name, version),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// Read the license of a package.json. Old packages use `{ "type": "MIT" }` objects or a
+/// `licenses` array instead of an SPDX expression; those are turned into an expression.
+fn license_field(package: &Value) -> Option<String> {
+    let license_type = |license: &Value| match *license {
+        Value::String(ref license) => Some(license.clone()),
+        Value::Object(ref license) => license.get("type").and_then(|value| value.as_str()).map(|value| value.to_string()),
+        _ => None,
+    };
+    if let Some(license) = package.get("license") {
+        return license_type(license);
+    }
+    let licenses: Vec<String> = package.get("licenses")
+        .and_then(|licenses| licenses.as_array())
+        .map(|licenses| licenses.iter().filter_map(&license_type).collect())
+        .unwrap_or_default();
+    match licenses.len() {
+        0 => None,
+        1 => Some(licenses[0].clone()),
+        _ => Some(format!("({})", licenses.join(" OR "))),
+    }
+}
+
+/// Find the directory and name of the package that contains a file, if it is inside a
+/// node_modules folder.
+pub fn package_root(path: &Path) -> Option<(PathBuf, String)> {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let index = path.rfind("/node_modules/")?;
+    let start = index + "/node_modules/".len();
+    let name = package_name(&path[start..]).to_string();
+    Some((PathBuf::from(&path[..start + name.len()]), name))
+}
+
+/// The path of a file relative to `basedir`. Files outside of it keep their full path, without
+/// the root.
+pub fn relative_to(path: &Path, basedir: &Path) -> PathBuf {
+    path.strip_prefix(basedir)
+        .map(|relative| relative.to_path_buf())
+        .unwrap_or_else(|_| PathBuf::from(to_url_path(path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use serde_json;
+    use super::{package_root, relative_to, license_field};
+
+    #[test]
+    fn finds_package_roots() {
+        assert_eq!(package_root(Path::new("/app/node_modules/a/node_modules/@scope/b/lib/index.js")),
+                   Some((PathBuf::from("/app/node_modules/a/node_modules/@scope/b"), "@scope/b".to_string())));
+        assert_eq!(package_root(Path::new("/app/node_modules/react/index.js")),
+                   Some((PathBuf::from("/app/node_modules/react"), "react".to_string())));
+        assert_eq!(package_root(Path::new("/app/src/index.js")), None);
+    }
+
+    #[test]
+    fn reads_licenses() {
+        let license = |source: &str| license_field(&serde_json::from_str(source).unwrap());
+        assert_eq!(license(r#"{ "license": "MIT" }"#), Some("MIT".to_string()));
+        assert_eq!(license(r#"{ "license": { "type": "ISC" } }"#), Some("ISC".to_string()));
+        assert_eq!(license(r#"{ "licenses": [{ "type": "MIT" }, { "type": "Apache-2.0" }] }"#),
+                   Some("(MIT OR Apache-2.0)".to_string()));
+        assert_eq!(license(r#"{ "name": "a" }"#), None);
+    }
+
+    #[test]
+    fn makes_app_paths_relative() {
+        assert_eq!(relative_to(Path::new("/app/src/index.js"), Path::new("/app")), PathBuf::from("src/index.js"));
+        assert_eq!(relative_to(Path::new("/lib/shim.js"), Path::new("/app")), PathBuf::from("lib/shim.js"));
+    }
+}
@@ -0,0 +1,107 @@
+use sourcemap::SourceMap;
+
+/// A stack frame from a V8 (`at fn (file:1:2)`) or Firefox/Safari (`fn@file:1:2`) stack trace.
+#[derive(Debug, PartialEq)]
+struct Frame<'a> {
+    /// Whitespace before the frame.
+    indent: &'a str,
+    /// Whether the frame uses the `fn@file:1:2` format.
+    gecko: bool,
+    function: Option<&'a str>,
+    url: &'a str,
+    /// Line and column numbers start at 1, like in stack traces.
+    line: u32,
+    column: u32,
+}
+
+impl<'a> Frame<'a> {
+    fn parse(line: &'a str) -> Option<Frame<'a>> {
+        let trimmed = line.trim_left();
+        let indent = &line[..line.len() - trimmed.len()];
+        let trimmed = trimmed.trim_right();
+
+        let (gecko, function, location) = if trimmed.starts_with("at ") {
+            let rest = &trimmed["at ".len()..];
+            match rest.rfind(" (") {
+                Some(index) if rest.ends_with(')') => (false, Some(&rest[..index]), &rest[index + 2..rest.len() - 1]),
+                _ => (false, None, rest),
+            }
+        } else if let Some(index) = trimmed.rfind('@') {
+            let function = &trimmed[..index];
+            (true, if function.is_empty() { None } else { Some(function) }, &trimmed[index + 1..])
+        } else {
+            return None;
+        };
+
+        let mut parts = location.rsplitn(3, ':');
+        let column = parts.next()?.parse().ok()?;
+        let line = parts.next()?.parse().ok()?;
+        let url = parts.next()?;
+        Some(Frame { indent, gecko, function, url, line, column })
+    }
+
+    /// Check if the frame points into a generated file with the given name.
+    fn is_in(&self, file: &str) -> bool {
+        let url = self.url.split(|c| c == '?' || c == '#').next().unwrap_or(self.url);
+        url == file || url.ends_with(&format!("/{}", file))
+    }
+
+    fn to_string(&self, function: Option<&str>, url: &str, line: u32, column: u32) -> String {
+        match (self.gecko, function) {
+            (true, function) => format!("{}{}@{}:{}:{}", self.indent, function.unwrap_or(""), url, line, column),
+            (false, Some(function)) => format!("{}at {} ({}:{}:{})", self.indent, function, url, line, column),
+            (false, None) => format!("{}at {}:{}:{}", self.indent, url, line, column),
+        }
+    }
+}
+
+/// Rewrite the frames in a stack trace that point into generated files to their original
+/// source positions. `maps` pairs each source map with the name of the generated file it belongs to.
+/// Lines that are not stack frames, and frames that no map knows about, are kept as they are.
+pub fn symbolicate(trace: &str, maps: &[(String, SourceMap)]) -> String {
+    trace.lines()
+        .map(|line| symbolicate_line(line, maps).unwrap_or_else(|| line.to_string()))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn symbolicate_line(line: &str, maps: &[(String, SourceMap)]) -> Option<String> {
+    let frame = Frame::parse(line)?;
+    let &(_, ref map) = maps.iter().find(|&&(ref file, _)| frame.is_in(file))?;
+    let original = map.lookup(frame.line.checked_sub(1)?, frame.column.checked_sub(1)?)?;
+    let function = original.name.or(frame.function);
+    Some(frame.to_string(function, original.source, original.line + 1, original.column + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use sourcemap::SourceMap;
+    use super::{Frame, symbolicate};
+
+    #[test]
+    fn parses_frames() {
+        assert_eq!(Frame::parse("    at foo (https://example.com/bundle.js:1:5)"), Some(Frame {
+            indent: "    ", gecko: false, function: Some("foo"), url: "https://example.com/bundle.js", line: 1, column: 5,
+        }));
+        assert_eq!(Frame::parse("    at https://example.com/bundle.js:1:5"), Some(Frame {
+            indent: "    ", gecko: false, function: None, url: "https://example.com/bundle.js", line: 1, column: 5,
+        }));
+        assert_eq!(Frame::parse("foo@https://example.com/bundle.js:2:3"), Some(Frame {
+            indent: "", gecko: true, function: Some("foo"), url: "https://example.com/bundle.js", line: 2, column: 3,
+        }));
+        assert_eq!(Frame::parse("TypeError: x is undefined"), None);
+    }
+
+    #[test]
+    fn rewrites_frames() {
+        let map = SourceMap::from_json(r#"{
+            "version": 3,
+            "sources": ["src/a.js"],
+            "names": ["doThing"],
+            "mappings": "AAAAA,IAAI"
+        }"#).unwrap();
+        let maps = vec![("bundle.js".to_string(), map)];
+        let trace = "TypeError: x is undefined\n    at n (https://example.com/bundle.js:1:2)\n    at o (https://example.com/bundle.js?v=1:1:6)\n    at other.js:1:1";
+        assert_eq!(symbolicate(trace, &maps), "TypeError: x is undefined\n    at doThing (src/a.js:1:1)\n    at o (src/a.js:1:5)\n    at other.js:1:1");
+    }
+}
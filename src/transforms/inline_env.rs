@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+use easter::expr::Expr;
+use easter::punc::UnopTag;
+use estree_detect_requires::{Walker, Callbacks, Control, NodePath};
+use quicli::prelude::*;
+use serde_json;
+use graph::SourceFile;
+use loader::{Transform, parse_cjs};
+use super::Patch;
+
+/// Replace `process.env.NAME` with the value of an environment variable, like envify.
+/// Names that are not in `vars` are left alone, and so are `process.env.NAME` references that are
+/// assigned to, updated or deleted.
+pub struct InlineEnv {
+    vars: BTreeMap<String, String>,
+}
+
+impl InlineEnv {
+    pub fn new(vars: BTreeMap<String, String>) -> Self {
+        InlineEnv { vars }
+    }
+}
+
+impl Transform for InlineEnv {
+    fn transform(&self, file: SourceFile) -> Result<SourceFile> {
+        let patch = match file {
+            SourceFile::CJS { ast: Some(ref ast), .. } => Walker::new(ast, FindEnvReferences::new(&self.vars)).walk().patch,
+            _ => return Ok(file),
+        };
+        if patch.is_empty() {
+            return Ok(file);
+        }
+        parse_cjs(file.path().clone(), patch.apply(file.source()))
+    }
+}
+
+struct FindEnvReferences<'a> {
+    vars: &'a BTreeMap<String, String>,
+    patch: Patch,
+}
+
+impl<'a> FindEnvReferences<'a> {
+    fn new(vars: &'a BTreeMap<String, String>) -> Self {
+        FindEnvReferences { vars, patch: Patch::new() }
+    }
+}
+
+impl<'a> Callbacks for FindEnvReferences<'a> {
+    fn pre_expr(&mut self, expr: &Expr, _path: &NodePath) -> Control {
+        match *expr {
+            // Assignment and update targets are not expressions, so the walker never gets here
+            // for them, but the argument of `delete` is.
+            Expr::Unop(_, ref op, ref argument) if op.tag == UnopTag::Delete && env_name(argument).is_some() =>
+                return Control::SkipChildren,
+            Expr::Dot(Some(ref span), ..) => {
+                if let Some(value) = env_name(expr).and_then(|name| self.vars.get(name)) {
+                    self.patch.replace(span, &serde_json::to_string(value).unwrap());
+                }
+            },
+            _ => (),
+        }
+        Control::Continue
+    }
+}
+
+/// Get `NAME` if the expression is `process.env.NAME`.
+fn env_name(expr: &Expr) -> Option<&str> {
+    if let Expr::Dot(_, ref object, ref name) = *expr {
+        if let Expr::Dot(_, ref process, ref env) = **object {
+            if let Expr::Id(ref id) = **process {
+                if id.name.as_ref() == "process" && env.value == "env" {
+                    return Some(&name.value[..]);
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+    use loader::{Transform, parse_cjs};
+    use super::InlineEnv;
+
+    fn inline(source: &str) -> String {
+        let mut vars = BTreeMap::new();
+        vars.insert("NODE_ENV".to_string(), "production".to_string());
+        let file = parse_cjs(PathBuf::from("/a.js"), source.to_string()).unwrap();
+        InlineEnv::new(vars).transform(file).unwrap().source().to_string()
+    }
+
+    #[test]
+    fn replaces_references() {
+        assert_eq!(inline("if (process.env.NODE_ENV !== 'production') debug()"), "if (\"production\" !== 'production') debug()");
+        assert_eq!(inline("process.env.OTHER"), "process.env.OTHER");
+    }
+
+    #[test]
+    fn keeps_assignment_and_update_targets() {
+        let source = "process.env.NODE_ENV = 'test'; process.env.NODE_ENV += '!'; process.env.NODE_ENV++; delete process.env.NODE_ENV";
+        assert_eq!(inline(source), source);
+        assert_eq!(inline("process.env.NODE_ENV = process.env.NODE_ENV"), "process.env.NODE_ENV = \"production\"");
+    }
+}
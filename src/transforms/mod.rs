@@ -0,0 +1,9 @@
+mod inline_env;
+mod patch;
+mod strip_asserts;
+mod strip_debug;
+
+pub use self::inline_env::InlineEnv;
+pub use self::patch::Patch;
+pub use self::strip_asserts::StripAsserts;
+pub use self::strip_debug::{StripConsole, StripDebugger};
@@ -0,0 +1,50 @@
+use std::cmp::Reverse;
+use joker::track::Span;
+
+/// A set of replacements to make in a source string, at locations from its syntax tree.
+#[derive(Debug, Default)]
+pub struct Patch {
+    replacements: Vec<(u32, u32, String)>,
+}
+
+impl Patch {
+    pub fn new() -> Self {
+        Patch::default()
+    }
+
+    /// Replace the source code at `span`.
+    pub fn replace(&mut self, span: &Span, replacement: &str) -> () {
+        self.replacements.push((span.start.offset, span.end.offset, replacement.to_string()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.replacements.is_empty()
+    }
+
+    /// Apply the replacements to `source`.
+    /// If replacements overlap, only the outermost one is applied.
+    /// If two replacements cover the exact same source, the one that was added first is applied.
+    pub fn apply(mut self, source: &str) -> String {
+        self.replacements.sort_by_key(|&(start, end, _)| (start, Reverse(end)));
+
+        // Syntax tree offsets count characters, not bytes.
+        let byte_offsets: Vec<usize> = source.char_indices()
+            .map(|(index, _)| index)
+            .chain(Some(source.len()))
+            .collect();
+
+        let mut result = String::with_capacity(source.len());
+        let mut last = 0;
+        for (start, end, replacement) in self.replacements {
+            let (start, end) = (start as usize, end as usize);
+            if start < last {
+                continue;
+            }
+            result.push_str(&source[byte_offsets[last]..byte_offsets[start]]);
+            result.push_str(&replacement);
+            last = end;
+        }
+        result.push_str(&source[byte_offsets[last]..]);
+        result
+    }
+}
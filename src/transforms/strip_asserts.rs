@@ -0,0 +1,216 @@
+use std::collections::HashSet;
+use easter::decl::{Decl, Dtor};
+use easter::expr::{Expr, ExprListItem};
+use easter::id::Id;
+use easter::patt::Patt;
+use easter::stmt::Stmt;
+use estree_detect_requires::{Walker, Callbacks, Control, NodePath};
+use joker::track::Span;
+use quicli::prelude::*;
+use graph::SourceFile;
+use loader::{Transform, parse_cjs};
+use super::Patch;
+
+/// Modules that export an assert function.
+const ASSERT_MODULES: &[&str] = &["assert", "assert/", "power-assert"];
+
+/// Remove assertions, like unassertify.
+///
+/// Statements that call `assert(...)` or `assert.equal(...)` etc. are removed, where `assert`
+/// is the result of `require('assert')`. `console.assert(...)` statements are removed too.
+/// If nothing else uses the `assert` variable afterwards, its require() call is removed as well,
+/// so the assert module is not bundled. That is skipped in modules that use `eval` or `with`,
+/// because they may use the variable in ways that can not be seen.
+pub struct StripAsserts;
+
+impl Transform for StripAsserts {
+    fn transform(&self, file: SourceFile) -> Result<SourceFile> {
+        let patch = match file {
+            SourceFile::CJS { ast: Some(ref ast), .. } => {
+                let imports = Walker::new(ast, FindAssertImports::new()).walk();
+                Walker::new(ast, FindAssertCalls::new(&imports.names)).walk().patch
+            },
+            _ => return Ok(file),
+        };
+        if patch.is_empty() {
+            return Ok(file);
+        }
+
+        let file = parse_cjs(file.path().clone(), patch.apply(file.source()))?;
+        // Find the declarations again, because removing the calls moved the code after them.
+        let (imports, references) = match file {
+            SourceFile::CJS { ref dynamic_code, .. } if !dynamic_code.is_empty() => return Ok(file),
+            SourceFile::CJS { ast: Some(ref ast), .. } => {
+                let imports = Walker::new(ast, FindAssertImports::new()).walk();
+                let references = Walker::new(ast, CountReferences::new(&imports.names)).walk().count;
+                (imports, references)
+            },
+            _ => return Ok(file),
+        };
+        if imports.declarations.is_empty() || references > 0 {
+            return Ok(file);
+        }
+
+        let mut patch = Patch::new();
+        for span in &imports.declarations {
+            patch.replace(span, ";");
+        }
+        parse_cjs(file.path().clone(), patch.apply(file.source()))
+    }
+}
+
+/// Finds variables that are assigned `require('assert')`, and the statements that declare them.
+/// Only declarations of a single variable are considered, so they can be removed entirely.
+struct FindAssertImports {
+    names: HashSet<String>,
+    declarations: Vec<Span>,
+}
+
+impl FindAssertImports {
+    fn new() -> Self {
+        FindAssertImports { names: HashSet::new(), declarations: vec![] }
+    }
+}
+
+impl Callbacks for FindAssertImports {
+    fn pre_stmt(&mut self, stmt: &Stmt, _path: &NodePath) -> Control {
+        if let Stmt::Var(Some(span), ref dtors, _) = *stmt {
+            if dtors.len() == 1 {
+                if let Dtor::Simple(_, ref id, Some(ref init)) = dtors[0] {
+                    if is_assert_require(init) {
+                        self.names.insert(id.name.as_ref().to_string());
+                        self.declarations.push(span);
+                    }
+                }
+            }
+        }
+        Control::Continue
+    }
+
+    fn pre_decl(&mut self, decl: &Decl) -> Control {
+        match *decl {
+            Decl::Let(Some(span), ref dtors, _) if dtors.len() == 1 => {
+                if let Dtor::Simple(_, ref id, Some(ref init)) = dtors[0] {
+                    if is_assert_require(init) {
+                        self.names.insert(id.name.as_ref().to_string());
+                        self.declarations.push(span);
+                    }
+                }
+            },
+            Decl::Const(Some(span), ref dtors, _) if dtors.len() == 1 => {
+                if let Patt::Simple(ref id) = dtors[0].patt {
+                    if is_assert_require(&dtors[0].value) {
+                        self.names.insert(id.name.as_ref().to_string());
+                        self.declarations.push(span);
+                    }
+                }
+            },
+            _ => (),
+        }
+        Control::Continue
+    }
+}
+
+/// Finds statements that only call an assert function.
+struct FindAssertCalls<'a> {
+    names: &'a HashSet<String>,
+    patch: Patch,
+}
+
+impl<'a> FindAssertCalls<'a> {
+    fn new(names: &'a HashSet<String>) -> Self {
+        FindAssertCalls { names, patch: Patch::new() }
+    }
+}
+
+impl<'a> Callbacks for FindAssertCalls<'a> {
+    fn pre_stmt(&mut self, stmt: &Stmt, _path: &NodePath) -> Control {
+        if let Stmt::Expr(Some(ref span), ref expr, _) = *stmt {
+            if is_assert_call(expr, self.names) {
+                // Keep an empty statement, in case this is the body of an `if` or a loop.
+                self.patch.replace(span, ";");
+            }
+        }
+        Control::Continue
+    }
+}
+
+/// Counts references to a set of variables, including shorthand properties and assignment targets.
+struct CountReferences<'a> {
+    names: &'a HashSet<String>,
+    count: usize,
+}
+
+impl<'a> CountReferences<'a> {
+    fn new(names: &'a HashSet<String>) -> Self {
+        CountReferences { names, count: 0 }
+    }
+}
+
+impl<'a> Callbacks for CountReferences<'a> {
+    fn pre_id(&mut self, id: &Id, _path: &NodePath) -> Control {
+        if self.names.contains(id.name.as_ref()) {
+            self.count += 1;
+        }
+        Control::Continue
+    }
+}
+
+fn is_assert_require(expr: &Expr) -> bool {
+    if let Expr::Call(_, ref callee, ref args) = *expr {
+        if let Expr::Id(ref id) = **callee {
+            if id.name.as_ref() == "require" {
+                if let Some(&ExprListItem::Expr(Expr::String(_, ref val))) = args.first() {
+                    return ASSERT_MODULES.contains(&val.value.as_str());
+                }
+            }
+        }
+    }
+    false
+}
+
+fn is_assert_call(expr: &Expr, names: &HashSet<String>) -> bool {
+    if let Expr::Call(_, ref callee, _) = *expr {
+        match **callee {
+            // assert(x)
+            Expr::Id(ref id) => names.contains(id.name.as_ref()),
+            // assert.equal(x, y), console.assert(x)
+            Expr::Dot(_, ref object, ref key) => match **object {
+                Expr::Id(ref id) => {
+                    let name: &str = id.name.as_ref();
+                    names.contains(name) || (name == "console" && key.value == "assert")
+                },
+                _ => false,
+            },
+            _ => false,
+        }
+    } else {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use loader::{Transform, parse_cjs};
+    use super::StripAsserts;
+
+    fn strip(source: &str) -> String {
+        let file = parse_cjs(PathBuf::from("/a.js"), source.to_string()).unwrap();
+        StripAsserts.transform(file).unwrap().source().to_string()
+    }
+
+    #[test]
+    fn removes_the_declaration_after_earlier_calls() {
+        let source = strip("function check(x) { assert(x) }\nvar assert = require('assert')\ncheck(1)");
+        assert!(source.starts_with("function check(x) { ; }\n"));
+        assert!(!source.contains("require"));
+        assert!(source.ends_with("check(1)"));
+    }
+
+    #[test]
+    fn keeps_the_declaration_if_it_is_still_used() {
+        assert!(strip("var assert = require('assert')\nassert(1)\nmodule.exports = { assert }").contains("require('assert')"));
+        assert!(strip("var assert = require('assert')\nassert(1)\nassert = null").contains("require('assert')"));
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::HashSet;
+use easter::expr::Expr;
+use easter::stmt::Stmt;
+use estree_detect_requires::{Walker, Callbacks, Control, NodePath};
+use quicli::prelude::*;
+use graph::SourceFile;
+use loader::{Transform, parse_cjs};
+use super::Patch;
+
+/// Remove `console.*()` calls.
+///
+/// Calls in statement position are removed entirely. Calls whose value may be used,
+/// like in `x && console.log(y)`, are replaced by `void 0`.
+pub struct StripConsole {
+    keep: HashSet<String>,
+}
+
+impl StripConsole {
+    /// Remove all `console.*()` calls, except for the methods in `keep`,
+    /// eg. `vec!["error".to_string()]` to keep `console.error()`.
+    pub fn new(keep: Vec<String>) -> Self {
+        StripConsole { keep: keep.into_iter().collect() }
+    }
+}
+
+impl Transform for StripConsole {
+    fn transform(&self, file: SourceFile) -> Result<SourceFile> {
+        let patch = match file {
+            SourceFile::CJS { ast: Some(ref ast), .. } => Walker::new(ast, FindConsoleCalls::new(&self.keep)).walk().patch,
+            _ => return Ok(file),
+        };
+        if patch.is_empty() {
+            return Ok(file);
+        }
+        parse_cjs(file.path().clone(), patch.apply(file.source()))
+    }
+}
+
+/// Remove `debugger` statements.
+pub struct StripDebugger;
+
+impl Transform for StripDebugger {
+    fn transform(&self, file: SourceFile) -> Result<SourceFile> {
+        let patch = match file {
+            SourceFile::CJS { ast: Some(ref ast), .. } => Walker::new(ast, FindDebugger::new()).walk().patch,
+            _ => return Ok(file),
+        };
+        if patch.is_empty() {
+            return Ok(file);
+        }
+        parse_cjs(file.path().clone(), patch.apply(file.source()))
+    }
+}
+
+struct FindConsoleCalls<'a> {
+    keep: &'a HashSet<String>,
+    patch: Patch,
+}
+
+impl<'a> FindConsoleCalls<'a> {
+    fn new(keep: &'a HashSet<String>) -> Self {
+        FindConsoleCalls { keep, patch: Patch::new() }
+    }
+
+    fn is_console_call(&self, expr: &Expr) -> bool {
+        if let Expr::Call(_, ref callee, _) = *expr {
+            if let Expr::Dot(_, ref object, ref key) = **callee {
+                if let Expr::Id(ref id) = **object {
+                    return id.name.as_ref() == "console" && !self.keep.contains(&key.value);
+                }
+            }
+        }
+        false
+    }
+}
+
+impl<'a> Callbacks for FindConsoleCalls<'a> {
+    fn pre_stmt(&mut self, stmt: &Stmt, _path: &NodePath) -> Control {
+        if let Stmt::Expr(Some(ref span), ref expr, _) = *stmt {
+            if self.is_console_call(expr) {
+                // Keep an empty statement, in case this is the body of an `if` or a loop.
+                // This is added before the `void 0` replacement for the call expression
+                // itself, so it takes precedence.
+                self.patch.replace(span, ";");
+            }
+        }
+        Control::Continue
+    }
+
+    fn pre_expr(&mut self, expr: &Expr, _path: &NodePath) -> Control {
+        if let Expr::Call(Some(ref span), _, _) = *expr {
+            if self.is_console_call(expr) {
+                self.patch.replace(span, "void 0");
+            }
+        }
+        Control::Continue
+    }
+}
+
+struct FindDebugger {
+    patch: Patch,
+}
+
+impl FindDebugger {
+    fn new() -> Self {
+        FindDebugger { patch: Patch::new() }
+    }
+}
+
+impl Callbacks for FindDebugger {
+    fn pre_stmt(&mut self, stmt: &Stmt, _path: &NodePath) -> Control {
+        if let Stmt::Debugger(Some(ref span), _) = *stmt {
+            self.patch.replace(span, ";");
+        }
+        Control::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use loader::{Transform, parse_cjs};
+    use super::{StripConsole, StripDebugger};
+
+    fn strip<T: Transform>(transform: T, source: &str) -> String {
+        let file = parse_cjs(PathBuf::from("/a.js"), source).unwrap();
+        transform.transform(file).unwrap().source().to_string()
+    }
+
+    #[test]
+    fn removes_console_statements() {
+        assert_eq!(strip(StripConsole::new(vec![]), "console.log(1); a()\nconsole.warn(2)"), "; a()\n;");
+        assert_eq!(strip(StripConsole::new(vec![]), "if (a) console.log(1); else b()"), "if (a) ; else b()");
+        assert_eq!(strip(StripConsole::new(vec![]), "while (a()) console.log(1)"), "while (a()) ;");
+    }
+
+    #[test]
+    fn replaces_console_calls_in_expressions() {
+        assert_eq!(strip(StripConsole::new(vec![]), "x && console.log(y)"), "x && void 0");
+        assert_eq!(strip(StripConsole::new(vec![]), "a = console.log(1), b"), "a = void 0, b");
+    }
+
+    #[test]
+    fn keeps_allowed_console_methods() {
+        let keep = || StripConsole::new(vec!["error".to_string()]);
+        assert_eq!(strip(keep(), "console.error(err); console.log(1)"), "console.error(err); ;");
+        assert_eq!(strip(keep(), "log.info(1); console.info"), "log.info(1); console.info");
+    }
+
+    #[test]
+    fn removes_debugger_statements() {
+        assert_eq!(strip(StripDebugger, "debugger; a()"), "; a()");
+        assert_eq!(strip(StripDebugger, "if (a) debugger\nb()"), "if (a) ;\nb()");
+    }
+}
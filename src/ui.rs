@@ -0,0 +1,237 @@
+use std::collections::VecDeque;
+use std::env;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+use watch::WatchEvent;
+
+/// How many recent builds to show the duration and size of.
+const RECENT_BUILDS: usize = 5;
+/// How many warnings to keep. Older ones scroll away.
+const MAX_WARNINGS: usize = 200;
+/// The terminal height to use if `$LINES` is not set.
+const DEFAULT_HEIGHT: usize = 24;
+
+/// The state of the current or last build.
+#[derive(Debug, Clone, PartialEq)]
+enum Status {
+    Starting,
+    Building { changed: Option<PathBuf> },
+    Succeeded,
+    Failed(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct BuildSummary {
+    duration_ms: u64,
+    bytes: usize,
+    modules: usize,
+}
+
+/// A terminal dashboard for watch mode. It redraws the whole screen after every watch event,
+/// instead of appending log lines for every rebuild.
+#[derive(Debug)]
+pub struct WatchUi {
+    status: Status,
+    started: Option<Instant>,
+    recent: VecDeque<BuildSummary>,
+    warnings: VecDeque<String>,
+    /// Why the on-rebuild commands failed after the last build. The build itself still succeeded.
+    hook_error: Option<String>,
+}
+
+impl WatchUi {
+    pub fn new() -> WatchUi {
+        WatchUi {
+            status: Status::Starting,
+            started: None,
+            recent: VecDeque::new(),
+            warnings: VecDeque::new(),
+            hook_error: None,
+        }
+    }
+
+    /// Update the state for a watch event.
+    pub fn handle(&mut self, event: &WatchEvent) {
+        match *event {
+            WatchEvent::BuildStarted => {
+                self.started = Some(Instant::now());
+                // Warnings are about the current build, so the ones from earlier builds go.
+                // Diagnostics from the watcher that arrive during the build are kept.
+                self.warnings.clear();
+                self.hook_error = None;
+                let changed = match self.status {
+                    Status::Building { ref changed } => changed.clone(),
+                    _ => None,
+                };
+                self.status = Status::Building { changed };
+            },
+            WatchEvent::BuildFinished { result: Ok(ref build) } => {
+                let duration_ms = self.started.map_or(0, |started| {
+                    let elapsed = started.elapsed();
+                    elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_nanos() / 1_000_000)
+                });
+                self.recent.push_front(BuildSummary { duration_ms, bytes: build.bundle.len(), modules: build.files.len() });
+                self.recent.truncate(RECENT_BUILDS);
+                for warning in &build.warnings {
+                    self.warning(warning.clone());
+                }
+                self.status = Status::Succeeded;
+            },
+            WatchEvent::BuildFinished { result: Err(ref err) } => self.status = Status::Failed(err.to_string()),
+            WatchEvent::FileChanged(ref path) => self.status = Status::Building { changed: Some(path.clone()) },
+            WatchEvent::Diagnostic(ref message) => self.warning(message.clone()),
+        }
+    }
+
+    /// Show a problem that happened outside of the build itself, eg. while writing the bundle.
+    pub fn error(&mut self, message: String) {
+        self.status = Status::Failed(message);
+    }
+
+    /// Show that the on-rebuild commands failed, without marking the build as failed.
+    pub fn hook_error(&mut self, message: String) {
+        self.hook_error = Some(message);
+    }
+
+    fn warning(&mut self, message: String) {
+        self.warnings.push_back(message);
+        while self.warnings.len() > MAX_WARNINGS {
+            self.warnings.pop_front();
+        }
+    }
+
+    /// Render the screen as text, at most `height` lines. The newest warnings are shown if
+    /// they do not all fit.
+    pub fn render(&self, height: usize) -> String {
+        let mut lines = vec![];
+        lines.push(match self.status {
+            Status::Starting => "starting".to_string(),
+            Status::Building { changed: Some(ref path) } => format!("building: {} changed", path.to_string_lossy()),
+            Status::Building { changed: None } => "building".to_string(),
+            Status::Succeeded => match self.recent.front() {
+                Some(last) => format!("built in {}ms: {} in {} modules", last.duration_ms, format_bytes(last.bytes), last.modules),
+                None => "built".to_string(),
+            },
+            Status::Failed(_) => "build failed".to_string(),
+        });
+        if !self.recent.is_empty() {
+            let recent = self.recent.iter()
+                .map(|build| format!("{}ms ({})", build.duration_ms, format_bytes(build.bytes)))
+                .collect::<Vec<String>>();
+            lines.push(format!("recent builds: {}", recent.join(", ")));
+        }
+        lines.push(String::new());
+        if let Status::Failed(ref message) = self.status {
+            lines.extend(message.lines().map(|line| line.to_string()));
+            lines.push(String::new());
+        }
+        if let Some(ref message) = self.hook_error {
+            lines.extend(message.lines().map(|line| line.to_string()));
+            lines.push(String::new());
+        }
+        if !self.warnings.is_empty() {
+            lines.push(format!("{} warning{}:", self.warnings.len(), if self.warnings.len() == 1 { "" } else { "s" }));
+            let room = height.saturating_sub(lines.len());
+            let skip = self.warnings.len().saturating_sub(room);
+            lines.extend(self.warnings.iter().skip(skip).map(|warning| format!("  {}", warning)));
+        }
+        lines.truncate(height);
+        lines.join("\n") + "\n"
+    }
+
+    /// Clear the terminal and draw the screen on stderr.
+    pub fn draw(&self) -> io::Result<()> {
+        let height = env::var("LINES").ok()
+            .and_then(|lines| lines.parse().ok())
+            .unwrap_or(DEFAULT_HEIGHT);
+        let stderr = io::stderr();
+        let mut stderr = stderr.lock();
+        // Move to the top left and clear the screen.
+        write!(stderr, "\x1b[H\x1b[2J{}", self.render(height))?;
+        stderr.flush()
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    if bytes < 1000 {
+        format!("{} B", bytes)
+    } else if bytes < 1_000_000 {
+        format!("{:.1} kB", bytes as f64 / 1000.0)
+    } else {
+        format!("{:.2} MB", bytes as f64 / 1_000_000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::rc::Rc;
+    use pipeline::Pipeline;
+    use watch::{Build, WatchEvent};
+    use super::{WatchUi, format_bytes};
+
+    fn build(bytes: usize, warnings: Vec<&str>) -> WatchEvent {
+        WatchEvent::BuildFinished {
+            result: Ok(Build {
+                bundle: "x".repeat(bytes),
+                files: vec![PathBuf::from("a.js"), PathBuf::from("b.js")],
+                warnings: warnings.into_iter().map(|warning| warning.to_string()).collect(),
+                licenses: vec![],
+                pipeline: Rc::new(Pipeline::new()),
+            }),
+        }
+    }
+
+    #[test]
+    fn formats_bytes() {
+        assert_eq!(format_bytes(999), "999 B");
+        assert_eq!(format_bytes(34_200), "34.2 kB");
+        assert_eq!(format_bytes(1_250_000), "1.25 MB");
+    }
+
+    #[test]
+    fn shows_status_and_newest_warnings() {
+        let mut ui = WatchUi::new();
+        ui.handle(&WatchEvent::BuildStarted);
+        ui.handle(&build(1500, vec!["a", "b", "c"]));
+        let screen = ui.render(24);
+        assert!(screen.starts_with("built in "));
+        assert!(screen.contains("1.5 kB in 2 modules"));
+        assert!(screen.contains("3 warnings:\n  a\n  b\n  c\n"));
+        assert!(ui.render(5).ends_with("3 warnings:\n  c\n"));
+
+        ui.handle(&WatchEvent::FileChanged(PathBuf::from("src/a.js")));
+        ui.handle(&WatchEvent::BuildStarted);
+        assert!(ui.render(24).starts_with("building: src/a.js changed\n"));
+
+        ui.handle(&build(10, vec![]));
+        assert!(!ui.render(24).contains("warning"));
+        ui.error("could not write dist/app.js".to_string());
+        assert!(ui.render(24).starts_with("build failed\n"));
+    }
+
+    #[test]
+    fn keeps_diagnostics_from_during_the_build() {
+        let mut ui = WatchUi::new();
+        ui.handle(&WatchEvent::BuildStarted);
+        ui.handle(&WatchEvent::Diagnostic("could not watch src/gone.js".to_string()));
+        ui.handle(&build(10, vec!["a"]));
+        assert!(ui.render(24).contains("2 warnings:\n  could not watch src/gone.js\n  a\n"));
+        ui.handle(&WatchEvent::BuildStarted);
+        assert!(!ui.render(24).contains("warning"));
+    }
+
+    #[test]
+    fn shows_hook_failures_separately() {
+        let mut ui = WatchUi::new();
+        ui.handle(&WatchEvent::BuildStarted);
+        ui.handle(&build(10, vec![]));
+        ui.hook_error("on-rebuild command `false` failed: exit code: 1".to_string());
+        let screen = ui.render(24);
+        assert!(screen.starts_with("built in "));
+        assert!(screen.contains("\non-rebuild command `false` failed: exit code: 1\n"));
+        ui.handle(&WatchEvent::BuildStarted);
+        assert!(!ui.render(24).contains("on-rebuild"));
+    }
+}
@@ -0,0 +1,327 @@
+use std::collections::HashSet;
+use std::env;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc::channel;
+use std::time::Duration;
+use glob::Pattern;
+use notify::{self, Watcher, PollWatcher, RecursiveMode, DebouncedEvent};
+use quicli::prelude::*;
+use error::BundleError;
+use licenses::ModuleLicense;
+use pipeline::Pipeline;
+use path::{CaseSensitivity, NormalizedPath};
+
+/// How long to wait for more file changes before starting a rebuild.
+const DEBOUNCE_MS: u64 = 100;
+/// How often to check files for changes when polling, if no interval is given.
+pub const DEFAULT_POLL_INTERVAL_MS: u64 = 1000;
+
+/// The output of a single build.
+#[derive(Debug)]
+pub struct Build {
+    /// The bundle source.
+    pub bundle: String,
+    /// Files that were included in the bundle.
+    /// These are watched for changes.
+    pub files: Vec<PathBuf>,
+    /// Problems that did not stop the build.
+    pub warnings: Vec<String>,
+    /// The package and license of every bundled module, if they were requested.
+    pub licenses: Vec<ModuleLicense>,
+    /// The steps that were added to the build, for the ones after `Emit`.
+    pub pipeline: Rc<Pipeline>,
+}
+
+/// Events emitted while watching, so embedders can show their own UI.
+#[derive(Debug)]
+pub enum WatchEvent {
+    /// A build is about to start.
+    BuildStarted,
+    /// A build finished, successfully or not.
+    BuildFinished {
+        result: Result<Build>,
+    },
+    /// A watched file changed. A rebuild will start next.
+    FileChanged(PathBuf),
+    /// Something went wrong in the watcher itself, eg. a file could not be watched.
+    Diagnostic(String),
+}
+
+/// Files that the watcher does not watch or rebuild for, like the output directory, coverage
+/// reports and editor swap files.
+#[derive(Debug, Clone, Default)]
+pub struct WatchIgnore {
+    patterns: Vec<Pattern>,
+}
+
+impl WatchIgnore {
+    pub fn new() -> WatchIgnore {
+        WatchIgnore::default()
+    }
+
+    /// Ignore files matching these glob patterns, like `**/dist/**` or `**/*.swp`. Patterns are
+    /// matched against absolute paths with forward slashes, like `exclude` patterns.
+    pub fn with_patterns(mut self, patterns: Vec<Pattern>) -> Self {
+        self.patterns.extend(patterns);
+        self
+    }
+
+    /// Ignore the files that a .gitignore file lists. Does nothing if the file does not exist.
+    pub fn with_gitignore(mut self, path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(self);
+        }
+        let root = absolute(path.parent().unwrap_or_else(|| Path::new(".")));
+        let source = read_file(path)?;
+        self.patterns.extend(gitignore_patterns(&source, &root)
+            .map_err(|err| BundleError::config(format!("Invalid {}: {}", path.to_string_lossy(), err)))?);
+        Ok(self)
+    }
+
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let path = normalize(path);
+        self.patterns.iter().any(|pattern| pattern.matches(path.as_str()))
+    }
+}
+
+/// Check files for changes by looking at their modification times every so often, instead of
+/// relying on the operating system to report changes. This works on file systems where native
+/// watching does not, like Docker volumes and network drives, but it is slower.
+#[derive(Debug, Clone)]
+pub struct Polling {
+    interval: Duration,
+    paths: Vec<Pattern>,
+}
+
+impl Polling {
+    /// Poll every file, every `interval`.
+    pub fn new(interval: Duration) -> Polling {
+        Polling { interval, paths: vec![] }
+    }
+
+    /// Only poll files matching these glob patterns, like `/mnt/shared/**`. Other files are
+    /// watched natively. Patterns are matched like `WatchIgnore` patterns.
+    pub fn with_paths(mut self, paths: Vec<Pattern>) -> Self {
+        self.paths.extend(paths);
+        self
+    }
+
+    pub fn is_polled(&self, path: &Path) -> bool {
+        if self.paths.is_empty() {
+            return true;
+        }
+        let path = normalize(path);
+        self.paths.iter().any(|pattern| pattern.matches(path.as_str()))
+    }
+}
+
+/// Options for `watch`.
+#[derive(Debug, Clone, Default)]
+pub struct WatchOptions {
+    ignore: WatchIgnore,
+    polling: Option<Polling>,
+}
+
+impl WatchOptions {
+    pub fn new() -> WatchOptions {
+        WatchOptions::default()
+    }
+
+    /// Do not watch or rebuild for files that `ignore` matches.
+    pub fn with_ignore(mut self, ignore: WatchIgnore) -> Self {
+        self.ignore = ignore;
+        self
+    }
+
+    /// Poll some or all files for changes instead of watching them natively.
+    pub fn with_polling(mut self, polling: Polling) -> Self {
+        self.polling = Some(polling);
+        self
+    }
+
+    /// Whether `path` is polled. If it is not, it is watched natively.
+    fn polls(&self, path: &Path) -> bool {
+        self.polling.as_ref().map_or(false, |polling| polling.is_polled(path))
+    }
+}
+
+fn normalize(path: &Path) -> NormalizedPath {
+    NormalizedPath::new(&absolute(path), CaseSensitivity::platform_default())
+}
+
+/// Make a path absolute without touching the file system, because removed files must be
+/// matched too.
+fn absolute(path: &Path) -> PathBuf {
+    let path = if path.is_relative() {
+        env::current_dir().map(|dir| dir.join(path)).unwrap_or_else(|_| path.to_path_buf())
+    } else {
+        path.to_path_buf()
+    };
+    path.components().filter(|component| *component != Component::CurDir).collect()
+}
+
+/// Turn the lines of a .gitignore file into glob patterns. Patterns that contain a `/` are
+/// relative to `root`, the others match in any directory. Negated patterns (`!keep.js`) are
+/// not supported and skipped, so a little more may be ignored than git does.
+fn gitignore_patterns(source: &str, root: &Path) -> Result<Vec<Pattern>> {
+    let root = Pattern::escape(&NormalizedPath::new(root, CaseSensitivity::platform_default()).as_str().trim_right_matches('/'));
+    let mut patterns = vec![];
+    for line in source.lines() {
+        let line = line.trim_right();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+            continue;
+        }
+        let dir_only = line.ends_with('/');
+        let line = line.trim_right_matches('/');
+        let base = if line.contains('/') {
+            format!("{}/{}", root, line.trim_left_matches('/'))
+        } else {
+            format!("**/{}", line)
+        };
+        patterns.push(Pattern::new(&format!("{}/**", base))?);
+        if !dir_only {
+            patterns.push(Pattern::new(&base)?);
+        }
+    }
+    Ok(patterns)
+}
+
+/// Run `build` and run it again every time one of the files it included changes.
+/// `paths` are watched from the start, so that a rebuild also happens if the first build failed.
+/// Files that the ignore option matches are not watched, and changes to them do not start a
+/// rebuild. Progress is reported by calling `on_event`.
+///
+/// This only returns if the watcher stops working.
+pub fn watch<B, E>(paths: &[PathBuf], options: &WatchOptions, mut build: B, mut on_event: E) -> Result<()>
+    where B: FnMut() -> Result<Build>,
+          E: FnMut(&WatchEvent)
+{
+    let ignore = &options.ignore;
+    let (tx, rx) = channel();
+    let mut watcher = notify::watcher(tx.clone(), Duration::from_millis(DEBOUNCE_MS))?;
+    // Polled files report to the same channel, so events are handled the same way.
+    let mut poller = match options.polling {
+        Some(ref polling) => Some(PollWatcher::new(tx, polling.interval)?),
+        None => None,
+    };
+    let mut watched = HashSet::new();
+
+    let mut add_watch = |watched: &mut HashSet<PathBuf>, path: &PathBuf, on_event: &mut E| {
+        if !ignore.is_ignored(path) && watched.insert(path.clone()) {
+            let result = match poller {
+                Some(ref mut poller) if options.polls(path) => {
+                    poller.watch(path, RecursiveMode::NonRecursive)
+                },
+                _ => watcher.watch(path, RecursiveMode::NonRecursive),
+            };
+            if let Err(err) = result {
+                watched.remove(path);
+                on_event(&WatchEvent::Diagnostic(format!("could not watch {}: {}", path.to_string_lossy(), err)));
+            }
+        }
+    };
+
+    for path in paths {
+        add_watch(&mut watched, path, &mut on_event);
+    }
+
+    loop {
+        on_event(&WatchEvent::BuildStarted);
+        let result = build();
+        if let Ok(ref build) = result {
+            for path in &build.files {
+                add_watch(&mut watched, path, &mut on_event);
+            }
+        }
+        on_event(&WatchEvent::BuildFinished { result });
+
+        loop {
+            match rx.recv()? {
+                DebouncedEvent::Write(ref path) | DebouncedEvent::Create(ref path) |
+                DebouncedEvent::Remove(ref path) | DebouncedEvent::Rename(ref path, _) if ignore.is_ignored(path) => (),
+                DebouncedEvent::Write(path) | DebouncedEvent::Create(path) => {
+                    on_event(&WatchEvent::FileChanged(path));
+                    break;
+                },
+                // Editors that save by replacing the file remove the original one,
+                // so it has to be watched again after the rebuild.
+                DebouncedEvent::Remove(path) | DebouncedEvent::Rename(path, _) => {
+                    watched.remove(&path);
+                    on_event(&WatchEvent::FileChanged(path));
+                    break;
+                },
+                DebouncedEvent::Error(err, _) => on_event(&WatchEvent::Diagnostic(err.to_string())),
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::path::Path;
+    use std::time::Duration;
+    use glob::Pattern;
+    use super::{Polling, WatchIgnore, WatchOptions, gitignore_patterns};
+
+    #[test]
+    fn ignores_gitignored_files() {
+        let patterns = gitignore_patterns("
+# build output
+/dist/
+coverage/
+*.swp
+!keep.swp
+docs/generated
+", Path::new("/app")).unwrap();
+        let ignore = WatchIgnore::new().with_patterns(patterns);
+        assert!(ignore.is_ignored(Path::new("/app/dist/bundle.js")));
+        assert!(!ignore.is_ignored(Path::new("/app/src/dist/bundle.js")));
+        assert!(ignore.is_ignored(Path::new("/app/packages/a/coverage/index.html")));
+        assert!(!ignore.is_ignored(Path::new("/app/coverage")));
+        assert!(ignore.is_ignored(Path::new("/app/src/.index.js.swp")));
+        assert!(ignore.is_ignored(Path::new("/app/docs/generated")));
+        assert!(!ignore.is_ignored(Path::new("/app/src/docs/generated")));
+        assert!(!ignore.is_ignored(Path::new("/app/src/index.js")));
+    }
+
+    #[test]
+    fn polls_files_matching_the_poll_globs() {
+        let polling = Polling::new(Duration::from_millis(500))
+            .with_paths(vec![Pattern::new("/mnt/shared/**").unwrap(), Pattern::new("**/*.vue").unwrap()]);
+        assert!(polling.is_polled(Path::new("/mnt/shared/lib/index.js")));
+        assert!(polling.is_polled(Path::new("/app/src/App.vue")));
+        assert!(!polling.is_polled(Path::new("/mnt/other/index.js")));
+        assert!(!polling.is_polled(Path::new("/app/src/index.js")));
+
+        let cwd = env::current_dir().unwrap();
+        let polling = Polling::new(Duration::from_millis(500))
+            .with_paths(vec![Pattern::new(&format!("{}/shared/**", Pattern::escape(&cwd.to_string_lossy()))).unwrap()]);
+        assert!(polling.is_polled(Path::new("./shared/index.js")));
+        assert!(!polling.is_polled(Path::new("src/index.js")));
+    }
+
+    #[test]
+    fn polls_every_file_without_poll_globs() {
+        let polling = Polling::new(Duration::from_millis(500));
+        assert!(polling.is_polled(Path::new("/app/src/index.js")));
+        assert!(polling.is_polled(Path::new("src/index.js")));
+    }
+
+    #[test]
+    fn watches_other_files_natively() {
+        let path = Path::new("/app/src/index.js");
+        assert!(!WatchOptions::new().polls(path));
+        assert!(WatchOptions::new().with_polling(Polling::new(Duration::from_millis(500))).polls(path));
+
+        let options = WatchOptions::new().with_polling(Polling::new(Duration::from_millis(500))
+            .with_paths(vec![Pattern::new("/mnt/shared/**").unwrap()]));
+        assert!(options.polls(Path::new("/mnt/shared/index.js")));
+        assert!(!options.polls(path));
+    }
+}
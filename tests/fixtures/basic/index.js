@@ -0,0 +1,2 @@
+var greet = require('./greet')
+console.log(greet('fixtures'))
@@ -0,0 +1,4 @@
+// a.js is still running, so this gets its partial exports.
+var a = require('./a')
+exports.aDoneWhenRequired = a.done
+exports.done = true
@@ -0,0 +1,2 @@
+var a = require('./a')
+console.log(a.done, a.b.done, a.b.aDoneWhenRequired)
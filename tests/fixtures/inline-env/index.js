@@ -0,0 +1,2 @@
+console.log(process.env.FIXTURE_GREETING)
+console.log(typeof process.env.OTHER_GREETING)
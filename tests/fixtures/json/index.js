@@ -0,0 +1,2 @@
+var data = require('./data.json')
+console.log(data.name + ' ' + data.items.length)
@@ -0,0 +1 @@
+module.exports = function (a, b) { return a + b }
@@ -0,0 +1,13 @@
+(function () {
+var add$1 = {exports: {}};
+(function (module, exports) {
+module.exports = function (a, b) { return a + b }
+
+}).call(add$1.exports, add$1, add$1.exports);
+var main = {exports: {}};
+(function (module, exports) {
+var add = add$1.exports
+console.log(add(2, 3))
+
+}).call(main.exports, main, main.exports);
+})();
\ No newline at end of file
@@ -0,0 +1,2 @@
+var add = require('./add')
+console.log(add(2, 3))
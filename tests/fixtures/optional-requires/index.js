@@ -0,0 +1,11 @@
+var missing
+try {
+  missing = require('./missing')
+} catch (err) {
+  missing = err.code
+}
+console.log(missing)
+
+try {
+  console.log(require('./present'))
+} catch (err) {}
@@ -0,0 +1,83 @@
+(function (modules, cache, entry) {
+__bundle = (function () {
+  // Lets the host page add and replace modules after the bundle has loaded.
+  // Module factories are called like bundled modules: factory(require, module, exports).
+  return function registry(modules, cache) {
+    return {
+      register: function (id, factory, dependencies) {
+        modules[id] = [function (require, module, exports) {
+          factory.apply(this, arguments);
+        }, dependencies || {}];
+        delete cache[id];
+      },
+      // Modules that already required `id` keep their old copy.
+      invalidate: function (id) {
+        delete cache[id];
+      },
+      has: function (id) {
+        return Object.prototype.hasOwnProperty.call(modules, id);
+      }
+    };
+  };
+})()
+(modules, cache);
+_require = __bundle.require = (function () {
+  function outer(modules, cache, entry) {
+    var previousRequire = typeof require == 'function' && require;
+
+    function notFound(name) {
+      var err = new Error('Cannot find module \'' + name + '\'');
+      err.code = 'MODULE_NOT_FOUND';
+      return err;
+    }
+
+    function newRequire(name, jumped){
+      if(!cache[name]) {
+        if(!modules[name]) {
+          var currentRequire = typeof require == 'function' && require;
+          if (!jumped && currentRequire) return currentRequire(name, true);
+
+          if (previousRequire) return previousRequire(name, true);
+          throw notFound(name);
+        }
+        var m = cache[name] = {exports:{}};
+        modules[name][0].call(m.exports, function(x){
+          var id = modules[name][1][x];
+          // An optional dependency that was not found when bundling.
+          if (id === false) throw notFound(x);
+          return newRequire(id ? id : x);
+        },m,m.exports,outer,modules,cache,entry);
+      }
+      return cache[name].exports;
+    }
+    for(var i=0;i<entry.length;i++) newRequire(entry[i]);
+
+    return newRequire;
+  }
+
+  return outer;
+})()
+(modules, cache, entry);
+})({
+1:[function(require,module,exports){
+var greet = require('./greet')
+console.log(greet('entry'))
+
+// Like bundled modules, registered ones run with `exports` as `this`.
+__bundle.register('late', function (require, module, exports) {
+  this.greeting = require('./greet')('registered module')
+}, { './greet': 2 })
+
+// `__bundle.require` is set once the entry point has run.
+setTimeout(function () {
+  console.log(__bundle.require('late').greeting)
+  console.log(__bundle.has('late'), __bundle.has('missing'))
+})
+
+},{"./greet":2}],
+2:[function(require,module,exports){
+module.exports = function greet (name) {
+  return 'hello ' + name
+}
+
+},{}]},{},[1]);
\ No newline at end of file
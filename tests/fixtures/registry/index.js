@@ -0,0 +1,13 @@
+var greet = require('./greet')
+console.log(greet('entry'))
+
+// Like bundled modules, registered ones run with `exports` as `this`.
+__bundle.register('late', function (require, module, exports) {
+  this.greeting = require('./greet')('registered module')
+}, { './greet': 2 })
+
+// `__bundle.require` is set once the entry point has run.
+setTimeout(function () {
+  console.log(__bundle.require('late').greeting)
+  console.log(__bundle.has('late'), __bundle.has('missing'))
+})
@@ -0,0 +1,4 @@
+console.log('removed')
+debugger
+var logged = console.warn('removed') || 'replaced'
+console.info('kept', logged)